@@ -24,7 +24,6 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .with_port(4460) // Standard NTS-KE port
         .with_timeout(Duration::from_secs(5)) // 5 second timeout
         .with_max_retries(3) // Retry up to 3 times
-        .with_tls_verification(true) // Verify TLS certificates (default)
         .with_ntp_version(4); // Use NTPv4
 
     println!("Configuration:");
@@ -36,7 +35,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("  NTP version:    {}\n", config.ntp_version);
 
     // Create NTS client
-    let mut client = NtsClient::new(config);
+    let client = NtsClient::new(config);
 
     // Connect with error handling
     println!("Connecting to NTS server...");
@@ -5,7 +5,7 @@
 //!
 //! Run with: cargo run --example rkik_diagnostics --features tracing-subscriber
 
-use rkik_nts::{NtsClient, NtsClientConfig};
+use rkik_nts::{NtsClient, NtsClientConfig, SampleStatistics};
 use std::error::Error;
 use std::time::Duration;
 
@@ -25,9 +25,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Configure the NTS client
     let config = NtsClientConfig::new(server)
         .with_timeout(Duration::from_secs(10))
-        .with_max_retries(3);
+        .with_max_retries(3)
+        .with_ke_record_capture(true);
 
-    let mut client = NtsClient::new(config);
+    let client = NtsClient::new(config);
 
     // Phase 1: NTS Key Exchange Diagnostics
     println!("─────────────────────────────────────────");
@@ -41,12 +42,44 @@ async fn main() -> Result<(), Box<dyn Error>> {
             // Access NTS-KE diagnostic information
             if let Some(ke_info) = client.nts_ke_info() {
                 println!("NTS-KE Diagnostics:");
+                println!("  KE Server:       {}", ke_info.ke_server);
                 println!("  NTP Server:      {}", ke_info.ntp_server);
+                println!("  Redirect:        {}", ke_info.redirect().as_str());
                 println!("  AEAD Algorithm:  {}", ke_info.aead_algorithm);
+                println!("  NTP Version:     {}", ke_info.protocol_version());
                 println!("  KE Duration:     {:?}", ke_info.ke_duration());
                 println!("  Cookie Count:    {}", ke_info.cookie_count());
                 println!("  Cookie Sizes:    {:?} bytes", ke_info.cookie_sizes());
 
+                let timings = client.timings();
+                println!("\n  Phase Timings:");
+                println!("    DNS:           {:?}", timings.dns_resolution);
+                println!("    TCP connect:   {:?}", timings.tcp_connect);
+                println!("    TLS handshake: {:?}", timings.tls_handshake);
+                println!("    KE records:    {:?}", timings.ke_records);
+
+                let tls = ke_info.tls_details();
+                println!("\n  TLS Session:");
+                println!("    Version:       {:?}", tls.protocol_version);
+                println!("    Cipher Suite:  {:?}", tls.cipher_suite);
+                println!("    ALPN:          {:?}", tls.alpn_protocol);
+                println!("    Resumed:       {}", tls.session_resumed);
+                println!("    Certificates:  {}", tls.peer_certificates.len());
+                if let Some(leaf) = &tls.leaf_certificate {
+                    println!("    Subject:       {}", leaf.subject);
+                    println!("    Issuer:        {}", leaf.issuer);
+                    println!("    SANs:          {:?}", leaf.subject_alt_names);
+                    println!("    Not After:     {:?}", leaf.not_after);
+                    println!("    Expiring Soon: {}", tls.certificate_expiring);
+                }
+
+                if let Some(records) = ke_info.ke_records() {
+                    println!("\n  NTS-KE Records:");
+                    for record in records {
+                        println!("    {}", record);
+                    }
+                }
+
                 // Verbose mode: Show raw cookie data (first few bytes)
                 println!("\n  Cookies (hex preview):");
                 for (i, cookie) in ke_info.cookies_ref().iter().enumerate() {
@@ -86,11 +119,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
             println!("  System Time:     {:?}", time.system_time);
             println!("  Network Time:    {:?}", time.network_time);
             println!("  Offset:          {} ms", time.offset_signed());
-            println!("  Offset (abs):    {:?}", time.offset);
+            println!("  Offset (exact):  {}", time.clock_offset);
             println!("  Round-trip:      {:?}", time.round_trip_delay);
             println!("  Server:          {}", time.server);
             println!("  Authenticated:   {} ✓", time.authenticated);
 
+            println!("\nServer Quality:");
+            println!("  Stratum:         {}", time.server_info.stratum);
+            println!("  Leap Indicator:  {:?}", time.server_info.leap_indicator);
+            println!(
+                "  Reference ID:    {}",
+                time.server_info.reference_id_string()
+            );
+            println!("  Root Delay:      {:?}", time.server_info.root_delay);
+            println!("  Root Dispersion: {:?}", time.server_info.root_dispersion);
+
             println!("\nClock Status:");
             if time.is_ahead() {
                 println!("  ⚠  System clock is AHEAD by {} ms", time.offset_signed());
@@ -127,38 +170,29 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("Phase 3: Statistical Analysis (5 samples)");
     println!("─────────────────────────────────────────\n");
 
-    let mut offsets = Vec::new();
-    let mut rtts = Vec::new();
+    let mut samples = Vec::new();
 
     for i in 1..=5 {
         tokio::time::sleep(Duration::from_millis(200)).await;
 
         if let Ok(time) = client.get_time().await {
-            let offset = time.offset_signed();
-            let rtt = time.round_trip_delay.as_millis();
-            offsets.push(offset);
-            rtts.push(rtt);
-
-            println!("  Sample {}: offset={:+6} ms, RTT={:4} ms", i, offset, rtt);
+            println!(
+                "  Sample {}: offset={:+6} ms, RTT={:4} ms",
+                i,
+                time.offset_signed(),
+                time.round_trip_delay.as_millis()
+            );
+            samples.push(time);
         }
     }
 
-    if !offsets.is_empty() {
-        let avg_offset: i64 = offsets.iter().sum::<i64>() / offsets.len() as i64;
-        let avg_rtt: u128 = rtts.iter().sum::<u128>() / rtts.len() as u128;
-
-        let variance: i64 = offsets
-            .iter()
-            .map(|&x| (x - avg_offset).pow(2))
-            .sum::<i64>()
-            / offsets.len() as i64;
-        let std_dev = (variance as f64).sqrt();
-
+    if let Some(stats) = SampleStatistics::from_samples(&samples) {
         println!("\nStatistics:");
-        println!("  Average Offset:  {:+6} ms", avg_offset);
-        println!("  Std Deviation:   {:6.2} ms", std_dev);
-        println!("  Average RTT:     {:4} ms", avg_rtt);
-        println!("  Sample Count:    {}", offsets.len());
+        println!("  Average Offset:  {:+9.3} ms", stats.mean_offset * 1e3);
+        println!("  Std Deviation:   {:9.3} ms", stats.std_dev * 1e3);
+        println!("  Jitter:          {:9.3} ms", stats.jitter * 1e3);
+        println!("  Average RTT:     {:?}", stats.mean_delay);
+        println!("  Sample Count:    {}", stats.samples);
     }
 
     // Phase 4: Connection Status Summary
@@ -29,7 +29,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         let config = NtsClientConfig::new(server);
 
         // Create NTS client
-        let mut client = NtsClient::new(config);
+        let client = NtsClient::new(config);
 
         // Connect and perform NTS key exchange
         match client.connect().await {
@@ -48,21 +48,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         match client.get_time().await {
             Ok(time) => {
                 println!("✓ Time query successful!\n");
-                println!("  Network time:  {:?}", time.network_time);
-                println!("  System time:   {:?}", time.system_time);
-                println!("  Offset:        {:?}", time.offset);
-                println!("  Offset (ms):   {} ms", time.offset_signed());
-                println!("  Round-trip:    {:?}", time.round_trip_delay);
-                println!("  Authenticated: {}", time.authenticated);
-                println!("  Server:        {}", time.server);
-
-                if time.is_ahead() {
-                    println!("\n  ⚠ System clock is ahead of network time");
-                } else if time.is_behind() {
-                    println!("\n  ⚠ System clock is behind network time");
-                } else {
-                    println!("\n  ✓ System clock is synchronized");
-                }
+                println!("{}", time);
             }
             Err(e) => {
                 println!("✗ Failed to query time: {}", e);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rkik_nts::extension::parse_extension_fields;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_extension_fields(data);
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use std::time::UNIX_EPOCH;
+
+use libfuzzer_sys::fuzz_target;
+use rkik_nts::ntp_packet;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = ntp_packet::parse(data) {
+        assert_eq!(packet.extensions.len(), data.len() - ntp_packet::HEADER_LEN);
+        let _ = packet.kiss_code();
+        for timestamp in [
+            packet.reference_timestamp,
+            packet.origin_timestamp,
+            packet.receive_timestamp,
+            packet.transmit_timestamp,
+        ] {
+            ntp_packet::to_system_time(timestamp, UNIX_EPOCH);
+        }
+    }
+});
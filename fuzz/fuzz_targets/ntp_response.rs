@@ -0,0 +1,27 @@
+#![no_main]
+
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use libfuzzer_sys::fuzz_target;
+use rkik_nts::query::NtpQuery;
+
+// The first 16 bytes pick the local send and receive times, the rest is the
+// response. Half of the inputs answer the query, so that validation and the
+// offset math are reached.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 16 {
+        return;
+    }
+    let (times, response) = data.split_at(16);
+    let t1 = UNIX_EPOCH + Duration::from_nanos(u64::from_be_bytes(times[..8].try_into().unwrap()));
+    let t4 = UNIX_EPOCH + Duration::from_nanos(u64::from_be_bytes(times[8..].try_into().unwrap()));
+
+    let Ok(query) = NtpQuery::new("192.0.2.1:123".parse().unwrap(), 4, t1, None) else {
+        return;
+    };
+    let mut response = response.to_vec();
+    if response.len() >= 32 && times[0] & 1 == 1 {
+        response[24..32].copy_from_slice(&query.transmit());
+    }
+    let _ = query.parse_response(&response, t4, Instant::now());
+});
@@ -0,0 +1,150 @@
+#ifndef RKIK_NTS_H
+#define RKIK_NTS_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of a C API call.
+//
+// Besides `Ok`, `InvalidArgument` and `Panic`, each status corresponds to
+// an [`ErrorKind`].
+typedef enum RkikNtsStatus {
+  // The call succeeded.
+  RKIK_NTS_STATUS_OK = 0,
+  // A pointer was null or a string was not valid UTF-8.
+  RKIK_NTS_STATUS_INVALID_ARGUMENT = 1,
+  // The library panicked; this is a bug.
+  RKIK_NTS_STATUS_PANIC = 2,
+  // [`ErrorKind::Io`].
+  RKIK_NTS_STATUS_IO = 10,
+  // [`ErrorKind::Tls`].
+  RKIK_NTS_STATUS_TLS = 11,
+  // [`ErrorKind::KeyExchange`].
+  RKIK_NTS_STATUS_KEY_EXCHANGE = 12,
+  // [`ErrorKind::Protocol`].
+  RKIK_NTS_STATUS_PROTOCOL = 13,
+  // [`ErrorKind::InvalidResponse`].
+  RKIK_NTS_STATUS_INVALID_RESPONSE = 14,
+  // [`ErrorKind::Rejected`].
+  RKIK_NTS_STATUS_REJECTED = 15,
+  // [`ErrorKind::Timeout`].
+  RKIK_NTS_STATUS_TIMEOUT = 16,
+  // [`ErrorKind::Config`].
+  RKIK_NTS_STATUS_CONFIG = 17,
+  // [`ErrorKind::Unavailable`].
+  RKIK_NTS_STATUS_UNAVAILABLE = 18,
+  // [`ErrorKind::Dns`].
+  RKIK_NTS_STATUS_DNS = 19,
+  // [`ErrorKind::NotConnected`].
+  RKIK_NTS_STATUS_NOT_CONNECTED = 20,
+  // [`ErrorKind::CookieExhausted`].
+  RKIK_NTS_STATUS_COOKIE_EXHAUSTED = 21,
+  // [`ErrorKind::Replay`].
+  RKIK_NTS_STATUS_REPLAY = 22,
+  // [`ErrorKind::CertificateExpired`].
+  RKIK_NTS_STATUS_CERTIFICATE_EXPIRED = 23,
+  // [`ErrorKind::Authentication`].
+  RKIK_NTS_STATUS_AUTHENTICATION = 24,
+  // [`ErrorKind::KissOfDeath`].
+  RKIK_NTS_STATUS_KISS_OF_DEATH = 25,
+  // [`ErrorKind::NoMajority`].
+  RKIK_NTS_STATUS_NO_MAJORITY = 26,
+  // [`ErrorKind::CircuitOpen`].
+  RKIK_NTS_STATUS_CIRCUIT_OPEN = 27,
+  // [`ErrorKind::Other`].
+  RKIK_NTS_STATUS_OTHER = 28,
+} RkikNtsStatus;
+
+// A client that keeps its keys and cookies between queries.
+//
+// Created with [`rkik_nts_client_new`] and released with
+// [`rkik_nts_client_free`].
+typedef struct RkikNtsClient RkikNtsClient;
+
+// Result of a time query.
+typedef struct RkikNtsTime {
+  // Network time at the measurement, in whole seconds since the Unix
+  // epoch.
+  int64_t unix_seconds;
+  // Nanoseconds past `unix_seconds`, below one billion.
+  uint32_t unix_nanos;
+  // Offset of the system clock from the network time, in nanoseconds.
+  // Positive when the system clock is ahead.
+  int64_t offset_nanos;
+  // Round-trip delay to the server, in nanoseconds.
+  uint64_t round_trip_delay_nanos;
+  // Stratum of the server.
+  uint8_t stratum;
+  // Whether the response was authenticated with NTS.
+  bool authenticated;
+} RkikNtsTime;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Query the time from `server` once, with the default configuration.
+//
+// `server` is a host name or address, optionally with a port, as accepted
+// by [`NtsClientConfig::new`].
+//
+// # Safety
+//
+// `server` must be a valid NUL-terminated string and `out_result` must
+// point to writable memory for an [`RkikNtsTime`].
+enum RkikNtsStatus rkik_nts_query(const char *server, struct RkikNtsTime *out_result);
+
+// Query the time from `server` once, giving up on each step after
+// `timeout_ms` milliseconds, or the default timeout if 0.
+//
+// # Safety
+//
+// As for [`rkik_nts_query`].
+enum RkikNtsStatus rkik_nts_query_with(const char *server,
+                                       uint32_t timeout_ms,
+                                       struct RkikNtsTime *out_result);
+
+// Create a client for `server`, with `timeout_ms` as for
+// [`rkik_nts_query_with`].
+//
+// Returns null on failure. The client connects on its first query.
+//
+// # Safety
+//
+// `server` must be a valid NUL-terminated string.
+struct RkikNtsClient *rkik_nts_client_new(const char *server, uint32_t timeout_ms);
+
+// Query the time with `client`, performing a key exchange first if
+// needed.
+//
+// # Safety
+//
+// `client` must come from [`rkik_nts_client_new`] and not have been freed,
+// and `out_result` must point to writable memory for an [`RkikNtsTime`].
+enum RkikNtsStatus rkik_nts_client_get_time(const struct RkikNtsClient *client,
+                                            struct RkikNtsTime *out_result);
+
+// Release a client. Null is ignored.
+//
+// # Safety
+//
+// `client` must be null or come from [`rkik_nts_client_new`], and must not
+// be used afterwards.
+void rkik_nts_client_free(struct RkikNtsClient *client);
+
+// The message of the last failed call on this thread, or null.
+//
+// The string stays valid until the next call into the library on this
+// thread.
+const char *rkik_nts_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RKIK_NTS_H */
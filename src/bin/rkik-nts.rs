@@ -0,0 +1,326 @@
+//! `rkik-nts`: query NTS servers from the command line.
+//!
+//! ```text
+//! rkik-nts query time.cloudflare.com
+//! rkik-nts ke nts.netnod.se --json
+//! rkik-nts monitor time.cloudflare.com --interval 64
+//! rkik-nts compare time.cloudflare.com nts.netnod.se ptbtime1.ptb.de
+//! ```
+//!
+//! The exit code reflects the state of the local clock:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0 | The clock is within `--max-offset` of the server time, or the key exchange succeeded |
+//! | 1 | The query failed, or no server answered |
+//! | 2 | Invalid command line |
+//! | 3 | The clock is off by more than `--max-offset` |
+//!
+//! Built with the `cli` feature: `cargo install rkik-nts --features cli`.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use chrono::SecondsFormat;
+use clap::{Args, Parser, Subcommand};
+use rkik_nts::{Error, ErrorSummary, NtsClient, NtsClientConfig, TimeSnapshot};
+use serde_json::json;
+
+/// Exit code when the query failed.
+const EXIT_FAILURE: u8 = 1;
+
+/// Exit code when the clock offset exceeds `--max-offset`.
+const EXIT_OUT_OF_SYNC: u8 = 3;
+
+/// Query the time from NTS (Network Time Security) servers.
+#[derive(Parser)]
+#[command(name = "rkik-nts", version)]
+struct Cli {
+    #[command(flatten)]
+    options: Options,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Options {
+    /// Print JSON instead of text; one object per line for `monitor`.
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Timeout of the key exchange and of each query, in seconds.
+    #[arg(long, global = true, value_name = "SECONDS", default_value = "10", value_parser = parse_seconds)]
+    timeout: Duration,
+
+    /// Clock offset above which the clock is out of sync, in milliseconds.
+    #[arg(long, global = true, value_name = "MS", default_value = "128", value_parser = parse_millis)]
+    max_offset: Duration,
+
+    /// PEM file with CA certificates to trust in addition to the built-in
+    /// roots.
+    #[arg(long, global = true, value_name = "PATH")]
+    ca_file: Option<PathBuf>,
+
+    /// Log the key exchange and queries to stderr.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Query the time once.
+    Query {
+        /// NTS-KE server, as `host` or `host:port`.
+        server: String,
+    },
+
+    /// Perform the NTS key exchange only and show its outcome.
+    Ke {
+        /// NTS-KE server, as `host` or `host:port`.
+        server: String,
+    },
+
+    /// Query the time periodically.
+    Monitor {
+        /// NTS-KE server, as `host` or `host:port`.
+        server: String,
+
+        /// Time between queries, in seconds.
+        #[arg(long, value_name = "SECONDS", default_value = "16", value_parser = parse_seconds)]
+        interval: Duration,
+
+        /// Stop after this many samples.
+        #[arg(long, value_name = "N")]
+        count: Option<u64>,
+    },
+
+    /// Query several servers at once and compare their time.
+    Compare {
+        /// NTS-KE servers, as `host` or `host:port`.
+        #[arg(required = true)]
+        servers: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if cli.options.verbose {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_max_level(tracing::Level::DEBUG)
+            .init();
+    }
+
+    let options = &cli.options;
+    match &cli.command {
+        Command::Query { server } => query(options, server).await,
+        Command::Ke { server } => key_exchange(options, server).await,
+        Command::Monitor {
+            server,
+            interval,
+            count,
+        } => monitor(options, server, *interval, *count).await,
+        Command::Compare { servers } => compare(options, servers).await,
+    }
+}
+
+async fn query(options: &Options, server: &str) -> ExitCode {
+    let client = NtsClient::new(options.config(server));
+    let result = async {
+        client.connect().await?;
+        client.get_time().await
+    }
+    .await;
+    match result {
+        Ok(time) => {
+            if options.json {
+                println!("{}", options.time_json(server, &time));
+            } else {
+                println!("{time}");
+            }
+            options.exit_code(&time)
+        }
+        Err(e) => {
+            options.print_error(server, &e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+async fn key_exchange(options: &Options, server: &str) -> ExitCode {
+    let client = NtsClient::new(options.config(server));
+    if let Err(e) = client.connect().await {
+        options.print_error(server, &e);
+        return ExitCode::from(EXIT_FAILURE);
+    }
+    let info = client.nts_ke_info().expect("connected");
+    if options.json {
+        println!("{}", json!({ "server": server, "key_exchange": &*info }));
+    } else {
+        println!("{info}");
+    }
+    ExitCode::SUCCESS
+}
+
+async fn monitor(
+    options: &Options,
+    server: &str,
+    interval: Duration,
+    count: Option<u64>,
+) -> ExitCode {
+    let client = NtsClient::new(options.config(server));
+    let mut samples = client.time_stream(interval);
+    let mut status = ExitCode::from(EXIT_FAILURE);
+    let mut taken = 0;
+    while count.map_or(true, |count| taken < count) {
+        let Some(sample) = samples.next().await else {
+            break;
+        };
+        taken += 1;
+        status = match sample {
+            Ok(time) => {
+                if options.json {
+                    println!("{}", options.time_json(server, &time));
+                } else {
+                    println!(
+                        "{}  offset {:+10.3} ms  delay {:8.3} ms  stratum {}",
+                        time.network_datetime()
+                            .to_rfc3339_opts(SecondsFormat::Millis, true),
+                        time.clock_offset.as_secs_f64() * 1e3,
+                        time.round_trip_delay.as_secs_f64() * 1e3,
+                        time.server_info.stratum,
+                    );
+                }
+                options.exit_code(&time)
+            }
+            Err(e) => {
+                options.print_error(server, &e);
+                ExitCode::from(EXIT_FAILURE)
+            }
+        };
+    }
+    status
+}
+
+async fn compare(options: &Options, servers: &[String]) -> ExitCode {
+    let tasks: Vec<_> = servers
+        .iter()
+        .map(|server| {
+            let client = NtsClient::new(options.config(server));
+            tokio::spawn(async move {
+                client.connect().await?;
+                client.get_time().await
+            })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.expect("query task panicked"));
+    }
+
+    let offsets: Vec<f64> = results
+        .iter()
+        .flatten()
+        .map(|time| time.clock_offset.as_secs_f64() * 1e3)
+        .collect();
+    let spread = offsets.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        - offsets.iter().copied().fold(f64::INFINITY, f64::min);
+
+    if options.json {
+        let entries: Vec<_> = servers
+            .iter()
+            .zip(&results)
+            .map(|(server, result)| match result {
+                Ok(time) => options.time_json(server, time),
+                Err(e) => json!({ "server": server, "error": ErrorSummary::from(e) }),
+            })
+            .collect();
+        let spread = (!offsets.is_empty()).then_some(spread);
+        println!("{}", json!({ "servers": entries, "spread_ms": spread }));
+    } else {
+        let width = servers.iter().map(String::len).max().unwrap_or(0).max(6);
+        println!(
+            "{:<width$}  {:>13}  {:>11}  STRATUM",
+            "SERVER", "OFFSET", "DELAY"
+        );
+        for (server, result) in servers.iter().zip(&results) {
+            match result {
+                Ok(time) => println!(
+                    "{:<width$}  {:>+10.3} ms  {:>8.3} ms  {}",
+                    server,
+                    time.clock_offset.as_secs_f64() * 1e3,
+                    time.round_trip_delay.as_secs_f64() * 1e3,
+                    time.server_info.stratum,
+                ),
+                Err(e) => println!("{server:<width$}  error: {e}"),
+            }
+        }
+        if offsets.len() > 1 {
+            println!("\nSpread: {spread:.3} ms");
+        }
+    }
+
+    let answered: Vec<&TimeSnapshot> = results.iter().flatten().collect();
+    if answered.is_empty() {
+        ExitCode::from(EXIT_FAILURE)
+    } else if answered.iter().any(|time| !options.in_sync(time)) {
+        ExitCode::from(EXIT_OUT_OF_SYNC)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+impl Options {
+    fn config(&self, server: &str) -> NtsClientConfig {
+        let config = NtsClientConfig::new(server).with_timeout(self.timeout);
+        match &self.ca_file {
+            Some(path) => config.with_ca_file(path),
+            None => config,
+        }
+    }
+
+    fn in_sync(&self, time: &TimeSnapshot) -> bool {
+        time.clock_offset.abs() <= self.max_offset
+    }
+
+    fn exit_code(&self, time: &TimeSnapshot) -> ExitCode {
+        if self.in_sync(time) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(EXIT_OUT_OF_SYNC)
+        }
+    }
+
+    fn time_json(&self, server: &str, time: &TimeSnapshot) -> serde_json::Value {
+        json!({ "server": server, "time": time, "in_sync": self.in_sync(time) })
+    }
+
+    /// Report `error`: as JSON on stdout, or as text on stderr.
+    fn print_error(&self, server: &str, error: &Error) {
+        if self.json {
+            println!(
+                "{}",
+                json!({ "server": server, "error": ErrorSummary::from(error) })
+            );
+        } else {
+            eprintln!("{server}: {error}");
+        }
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_millis(value: &str) -> Result<Duration, String> {
+    let millis: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(millis / 1e3).map_err(|e| e.to_string())
+}
@@ -0,0 +1,161 @@
+//! Temporary blacklisting of failing servers.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// When a failing server is blacklisted, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BlacklistPolicy {
+    /// Consecutive failures after which a server is blacklisted (default: 3).
+    /// A Kiss-o'-Death `DENY` or `RSTR` blacklists the server immediately.
+    pub max_failures: u32,
+
+    /// How long a server stays blacklisted (default: 5 minutes).
+    pub duration: Duration,
+}
+
+impl Default for BlacklistPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            duration: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// A currently blacklisted server, for diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BlacklistEntry {
+    /// The NTS-KE server hostname.
+    pub server: String,
+
+    /// Time until the server is tried again.
+    pub remaining: Duration,
+}
+
+/// Failure counts and blacklist state for a set of servers.
+#[derive(Debug, Default)]
+pub(crate) struct Blacklist {
+    policy: BlacklistPolicy,
+    servers: HashMap<String, ServerHealth>,
+}
+
+#[derive(Debug, Default)]
+struct ServerHealth {
+    consecutive_failures: u32,
+    until: Option<Instant>,
+}
+
+impl Blacklist {
+    pub(crate) fn new(policy: BlacklistPolicy) -> Self {
+        Self {
+            policy,
+            servers: HashMap::new(),
+        }
+    }
+
+    /// Whether `server` is blacklisted at `now`.
+    pub(crate) fn contains(&self, server: &str, now: Instant) -> bool {
+        self.servers
+            .get(server)
+            .and_then(|health| health.until)
+            .is_some_and(|until| until > now)
+    }
+
+    /// Record a failure, blacklisting `server` once it failed
+    /// `max_failures` times in a row.
+    pub(crate) fn record_failure(&mut self, server: &str, now: Instant) {
+        let health = self.servers.entry(server.to_string()).or_default();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.policy.max_failures {
+            health.consecutive_failures = 0;
+            health.until = Some(now + self.policy.duration);
+        }
+    }
+
+    /// Blacklist `server` immediately, e.g. after a Kiss-o'-Death `DENY`.
+    pub(crate) fn ban(&mut self, server: &str, now: Instant) {
+        let health = self.servers.entry(server.to_string()).or_default();
+        health.consecutive_failures = 0;
+        health.until = Some(now + self.policy.duration);
+    }
+
+    /// Record a success, clearing the failure count of `server`.
+    pub(crate) fn record_success(&mut self, server: &str) {
+        self.servers.remove(server);
+    }
+
+    /// Servers blacklisted at `now`, sorted by hostname.
+    pub(crate) fn entries(&self, now: Instant) -> Vec<BlacklistEntry> {
+        let mut entries: Vec<_> = self
+            .servers
+            .iter()
+            .filter_map(|(server, health)| {
+                let until = health.until.filter(|&until| until > now)?;
+                Some(BlacklistEntry {
+                    server: server.clone(),
+                    remaining: until - now,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.server.cmp(&b.server));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blacklist_after_repeated_failures() {
+        let now = Instant::now();
+        let mut blacklist = Blacklist::new(BlacklistPolicy {
+            max_failures: 2,
+            duration: Duration::from_secs(60),
+        });
+
+        blacklist.record_failure("a.example", now);
+        assert!(!blacklist.contains("a.example", now));
+        blacklist.record_failure("a.example", now);
+        assert!(blacklist.contains("a.example", now));
+        assert!(!blacklist.contains("b.example", now));
+
+        assert_eq!(
+            blacklist.entries(now + Duration::from_secs(10)),
+            [BlacklistEntry {
+                server: "a.example".to_string(),
+                remaining: Duration::from_secs(50),
+            }]
+        );
+
+        // Expired entries are neither blacklisted nor reported
+        let later = now + Duration::from_secs(60);
+        assert!(!blacklist.contains("a.example", later));
+        assert!(blacklist.entries(later).is_empty());
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let now = Instant::now();
+        let mut blacklist = Blacklist::new(BlacklistPolicy {
+            max_failures: 2,
+            duration: Duration::from_secs(60),
+        });
+
+        blacklist.record_failure("a.example", now);
+        blacklist.record_success("a.example");
+        blacklist.record_failure("a.example", now);
+        assert!(!blacklist.contains("a.example", now));
+
+        blacklist.ban("a.example", now);
+        assert!(blacklist.contains("a.example", now));
+        blacklist.record_success("a.example");
+        assert!(!blacklist.contains("a.example", now));
+    }
+}
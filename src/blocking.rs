@@ -0,0 +1,213 @@
+//! Blocking NTS client, for programs without an async runtime.
+//!
+//! [`NtsClient`] drives the async client on the calling thread. Key
+//! exchanges and NTP queries use std sockets, and no Tokio runtime is
+//! started, so the client fits CLI tools and scripts.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use rkik_nts::blocking::NtsClient;
+//! use rkik_nts::NtsClientConfig;
+//!
+//! # fn main() -> Result<(), rkik_nts::Error> {
+//! let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+//! client.connect()?;
+//! let time = client.get_time()?;
+//! println!("Offset: {}", time.clock_offset);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use crate::config::{NtsClientConfig, QueryOptions};
+use crate::error::Result;
+use crate::health::HealthStatus;
+use crate::resolver::StdResolver;
+use crate::runtime::{Runtime, SleepFuture};
+use crate::transport::StdConnector;
+use crate::types::{NtsKeResult, TimeSnapshot};
+
+/// How often pending timers and sockets are checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A blocking NTS client.
+///
+/// The same client as [`crate::NtsClient`], with blocking methods. Every
+/// call runs to completion on the calling thread; clones share the
+/// connection.
+#[derive(Clone)]
+pub struct NtsClient {
+    inner: crate::NtsClient,
+}
+
+impl NtsClient {
+    /// Create a new blocking NTS client with the given configuration.
+    pub fn new(config: NtsClientConfig) -> Self {
+        let connector = Arc::new(StdConnector::new(&config));
+        Self {
+            inner: crate::NtsClient::with_io(
+                config,
+                Arc::new(StdResolver),
+                connector,
+                Arc::new(PolledRuntime),
+            ),
+        }
+    }
+
+    /// Connect to the NTS server and perform key exchange.
+    ///
+    /// See [`crate::NtsClient::connect`].
+    pub fn connect(&self) -> Result<()> {
+        block_on(self.inner.connect())
+    }
+
+    /// Query the current time from the NTS-secured NTP server.
+    ///
+    /// See [`crate::NtsClient::get_time`].
+    pub fn get_time(&self) -> Result<TimeSnapshot> {
+        block_on(self.inner.get_time())
+    }
+
+    /// Query the time with per-query options.
+    ///
+    /// See [`crate::NtsClient::get_time_with`].
+    pub fn get_time_with(&self, options: &QueryOptions) -> Result<TimeSnapshot> {
+        block_on(self.inner.get_time_with(options))
+    }
+
+    /// Drop the current keys and perform a new key exchange.
+    pub fn reconnect(&self) -> Result<()> {
+        block_on(self.inner.reconnect())
+    }
+
+    /// Check if the client is connected.
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    /// Get the NTP server address, if connected.
+    pub fn ntp_server(&self) -> Option<SocketAddr> {
+        self.inner.ntp_server()
+    }
+
+    /// Get the result of the last key exchange, if connected.
+    pub fn nts_ke_info(&self) -> Option<Arc<NtsKeResult>> {
+        self.inner.nts_ke_info()
+    }
+
+    /// Get the health of the client.
+    ///
+    /// See [`crate::NtsClient::health`].
+    pub fn health(&self) -> HealthStatus {
+        self.inner.health()
+    }
+
+    /// The client configuration.
+    pub fn config(&self) -> &NtsClientConfig {
+        self.inner.config()
+    }
+}
+
+impl std::fmt::Debug for NtsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NtsClient")
+            .field("server", &self.inner.config().nts_ke_server)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+/// Poll `future` to completion on the current thread.
+///
+/// Besides waking on its waker, the future is polled every
+/// [`POLL_INTERVAL`], which is what drives [`PolledRuntime`] timers.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park_timeout(POLL_INTERVAL);
+    }
+}
+
+/// Timers checked on every poll of [`block_on`], and a thread per blocking
+/// task, or the calling thread on WASI.
+struct PolledRuntime;
+
+impl Runtime for PolledRuntime {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let deadline = Instant::now() + duration;
+        Box::pin(std::future::poll_fn(move |_| {
+            if Instant::now() >= deadline {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        #[cfg(not(target_os = "wasi"))]
+        std::thread::spawn(task);
+        #[cfg(target_os = "wasi")]
+        task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::test_util::{MockBehavior, MockServer};
+
+    #[test]
+    fn test_blocking_client() {
+        let server = MockServer::start().unwrap();
+        let client = NtsClient::new(server.client_config().with_max_retries(0));
+        assert!(!client.is_connected());
+
+        client.connect().unwrap();
+        assert_eq!(client.ntp_server(), Some(server.ntp_addr()));
+        for _ in 0..3 {
+            let time = client.get_time().unwrap();
+            assert!(time.authenticated);
+            assert!(time.round_trip_delay < Duration::from_secs(1));
+        }
+        client.reconnect().unwrap();
+        assert!(client.nts_ke_info().unwrap().cookie_count() > 0);
+    }
+
+    #[test]
+    fn test_blocking_client_timeout() {
+        let server =
+            MockServer::start_with(MockBehavior::new().with_ntp_delay(Duration::from_millis(300)))
+                .unwrap();
+        let client = NtsClient::new(
+            server
+                .client_config()
+                .with_query_timeout(Duration::from_millis(100))
+                .with_max_retries(0),
+        );
+        client.connect().unwrap();
+        let start = Instant::now();
+        assert!(matches!(client.get_time(), Err(Error::Timeout)));
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,114 @@
+//! Capability probing for NTS servers.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Outcome of probing a single capability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CapabilityStatus {
+    /// The capability was exercised successfully.
+    Supported,
+
+    /// The capability was exercised and failed.
+    Failed(String),
+
+    /// The capability was not exercised because a prerequisite failed.
+    Skipped,
+
+    /// The client does not implement this capability yet.
+    Unimplemented,
+}
+
+impl CapabilityStatus {
+    /// Check if the capability was exercised successfully.
+    pub fn is_supported(&self) -> bool {
+        matches!(self, CapabilityStatus::Supported)
+    }
+}
+
+/// Per-server report of which features work in the current environment.
+///
+/// Produced by [`NtsClient::capabilities`](crate::NtsClient::capabilities).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CapabilityReport {
+    /// The NTS-KE server that was probed.
+    pub server: String,
+
+    /// NTS key exchange over TLS.
+    pub key_exchange: CapabilityStatus,
+
+    /// NTP time query after key exchange.
+    pub time_query: CapabilityStatus,
+
+    /// Receiving fresh cookies in NTP responses.
+    pub cookie_refresh: CapabilityStatus,
+
+    /// NTP interleaved mode.
+    pub interleaved: CapabilityStatus,
+
+    /// NTP version 5.
+    pub ntpv5: CapabilityStatus,
+
+    /// Kernel receive timestamps.
+    pub kernel_timestamps: CapabilityStatus,
+}
+
+impl CapabilityReport {
+    /// Create a report for `server` with nothing probed yet.
+    pub(crate) fn new(server: impl Into<String>) -> Self {
+        Self {
+            server: server.into(),
+            key_exchange: CapabilityStatus::Skipped,
+            time_query: CapabilityStatus::Skipped,
+            cookie_refresh: CapabilityStatus::Unimplemented,
+            interleaved: CapabilityStatus::Unimplemented,
+            ntpv5: CapabilityStatus::Unimplemented,
+            kernel_timestamps: CapabilityStatus::Unimplemented,
+        }
+    }
+
+    /// List all capabilities as `(name, status)` pairs, e.g. for printing a matrix.
+    pub fn entries(&self) -> Vec<(&'static str, &CapabilityStatus)> {
+        vec![
+            ("key_exchange", &self.key_exchange),
+            ("time_query", &self.time_query),
+            ("cookie_refresh", &self.cookie_refresh),
+            ("interleaved", &self.interleaved),
+            ("ntpv5", &self.ntpv5),
+            ("kernel_timestamps", &self.kernel_timestamps),
+        ]
+    }
+
+    /// Check if the essential capabilities (key exchange and time query) work.
+    pub fn is_usable(&self) -> bool {
+        self.key_exchange.is_supported() && self.time_query.is_supported()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_report_defaults() {
+        let report = CapabilityReport::new("time.example.com");
+        assert_eq!(report.server, "time.example.com");
+        assert_eq!(report.key_exchange, CapabilityStatus::Skipped);
+        assert_eq!(report.interleaved, CapabilityStatus::Unimplemented);
+        assert!(!report.is_usable());
+        assert_eq!(report.entries().len(), 6);
+    }
+
+    #[test]
+    fn test_is_usable() {
+        let mut report = CapabilityReport::new("time.example.com");
+        report.key_exchange = CapabilityStatus::Supported;
+        report.time_query = CapabilityStatus::Failed("timeout".to_string());
+        assert!(!report.is_usable());
+
+        report.time_query = CapabilityStatus::Supported;
+        assert!(report.is_usable());
+    }
+}
@@ -0,0 +1,200 @@
+//! Circuit breaker for repeated time query failures.
+
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// When the circuit breaker opens, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CircuitBreakerPolicy {
+    /// Consecutive failed queries after which the circuit opens.
+    pub failure_threshold: u32,
+
+    /// How long queries fail fast before a trial query is allowed.
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+/// State of the circuit breaker around `get_time()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Queries are sent normally.
+    Closed {
+        /// Failed queries since the last success.
+        consecutive_failures: u32,
+    },
+
+    /// Queries fail fast with [`Error::CircuitOpen`] until the cool-down
+    /// ends.
+    Open {
+        /// When the next trial query is allowed.
+        until: Instant,
+    },
+
+    /// The cool-down ended: the next query is a trial. It closes the circuit
+    /// on success and reopens it on failure.
+    HalfOpen,
+}
+
+/// Circuit breaker state machine.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    policy: Option<CircuitBreakerPolicy>,
+    state: CircuitState,
+}
+
+impl CircuitBreaker {
+    /// Create a closed circuit breaker. Without a policy it never opens.
+    pub(crate) fn new(policy: Option<CircuitBreakerPolicy>) -> Self {
+        Self {
+            policy,
+            state: CircuitState::Closed {
+                consecutive_failures: 0,
+            },
+        }
+    }
+
+    /// Current state at `now`.
+    pub(crate) fn state(&self, now: Instant) -> CircuitState {
+        match self.state {
+            CircuitState::Open { until } if until <= now => CircuitState::HalfOpen,
+            state => state,
+        }
+    }
+
+    /// Check whether a query may be sent at `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::CircuitOpen`] while the circuit is open.
+    pub(crate) fn check(&mut self, now: Instant) -> Result<()> {
+        self.state = self.state(now);
+        match self.state {
+            CircuitState::Open { until } => Err(Error::CircuitOpen {
+                retry_after: until - now,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a successful query, closing the circuit.
+    pub(crate) fn record_success(&mut self) {
+        self.state = CircuitState::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    /// Record a failed query at `now`, opening the circuit once the failure
+    /// threshold is reached or if a trial query failed.
+    ///
+    /// Returns `true` if the circuit opened.
+    pub(crate) fn record_failure(&mut self, now: Instant) -> bool {
+        let Some(policy) = self.policy else {
+            return false;
+        };
+        let failures = match self.state {
+            CircuitState::Closed {
+                consecutive_failures,
+            } => consecutive_failures + 1,
+            CircuitState::HalfOpen => policy.failure_threshold,
+            CircuitState::Open { .. } => return false,
+        };
+        if failures >= policy.failure_threshold {
+            self.state = CircuitState::Open {
+                until: now + policy.cool_down,
+            };
+            true
+        } else {
+            self.state = CircuitState::Closed {
+                consecutive_failures: failures,
+            };
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(Some(CircuitBreakerPolicy {
+            failure_threshold: 2,
+            cool_down: Duration::from_secs(10),
+        }))
+    }
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+
+        assert!(!breaker.record_failure(now));
+        assert!(breaker.check(now).is_ok());
+        assert!(breaker.record_failure(now));
+
+        match breaker.check(now + Duration::from_secs(4)) {
+            Err(Error::CircuitOpen { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(6))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_half_open_trial() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        breaker.record_failure(now);
+        breaker.record_failure(now);
+
+        // A failed trial reopens the circuit immediately
+        let later = now + Duration::from_secs(10);
+        assert!(breaker.check(later).is_ok());
+        assert_eq!(breaker.state(later), CircuitState::HalfOpen);
+        assert!(breaker.record_failure(later));
+        assert!(breaker.check(later).is_err());
+
+        // A successful trial closes it
+        let later = later + Duration::from_secs(10);
+        assert!(breaker.check(later).is_ok());
+        breaker.record_success();
+        assert_eq!(
+            breaker.state(later),
+            CircuitState::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let now = Instant::now();
+        let mut breaker = breaker();
+        breaker.record_failure(now);
+        breaker.record_success();
+        assert!(!breaker.record_failure(now));
+    }
+
+    #[test]
+    fn test_disabled_never_opens() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::new(None);
+        for _ in 0..100 {
+            assert!(!breaker.record_failure(now));
+        }
+        assert!(breaker.check(now).is_ok());
+    }
+}
@@ -1,16 +1,48 @@
 //! High-level NTS client implementation.
 
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
-use tokio::net::UdpSocket;
-use tokio::time::timeout;
-use tracing::{debug, info};
+use futures_channel::oneshot;
+use futures_util::future::{select, Either};
+use tracing::field::{display, Empty};
+use tracing::{debug, info, instrument, warn, Span};
 
-use crate::config::NtsClientConfig;
+use crate::blacklist::{Blacklist, BlacklistEntry};
+use crate::capabilities::{CapabilityReport, CapabilityStatus};
+use crate::circuit::{CircuitBreaker, CircuitState};
+use crate::config::{NtsClientConfig, QueryOptions};
+use crate::cookies::{CookieStore, MemoryCookieStore};
+use crate::diagnostics::{
+    CookieReport, DiagnosticsReport, KeyExchangeReport, SnapshotReport, TimingsReport, TlsReport,
+    DIAGNOSTICS_SCHEMA_VERSION,
+};
 use crate::error::{Error, Result};
-use crate::nts_ke::perform_nts_ke;
-use crate::types::{NtsKeResult, TimeSnapshot};
+use crate::events::{ClientEvent, EventHandler, COOKIE_LOW_WATERMARK};
+use crate::extension::parse_extension_fields;
+use crate::health::HealthStatus;
+use crate::leap;
+use crate::metrics::MetricsSink;
+use crate::nts_ke::{perform_nts_ke, TlsSessions};
+#[cfg(feature = "pcap")]
+use crate::pcap::PcapWriter;
+use crate::query::{NtpQuery, PendingQueries};
+use crate::resolver::Resolver;
+use crate::retry::{with_retries, within, ExponentialBackoff};
+use crate::runtime::{self, Runtime};
+use crate::sink::SampleSink;
+use crate::stats::ServerStats;
+use crate::stream::{needs_rekey, TimeStream};
+use crate::time_source::{Clock, SystemClock};
+use crate::transport::{Connector, NtpTransport};
+use crate::types::{
+    DebugSnapshot, FilteredTime, NtsKeResult, NtsKeys, PacketCapture, RateLimitState, TimeSnapshot,
+    TimingBreakdown,
+};
 
 /// A high-level NTS (Network Time Security) client.
 ///
@@ -24,7 +56,7 @@ use crate::types::{NtsKeResult, TimeSnapshot};
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ///     let config = NtsClientConfig::new("time.cloudflare.com");
-///     let mut client = NtsClient::new(config);
+///     let client = NtsClient::new(config);
 ///
 ///     // Connect and perform NTS key exchange
 ///     client.connect().await?;
@@ -32,15 +64,155 @@ use crate::types::{NtsKeResult, TimeSnapshot};
 ///     // Get the current time
 ///     let time = client.get_time().await?;
 ///     println!("Network time: {:?}", time.network_time);
-///     println!("Offset: {:?}", time.offset);
+///     println!("Offset: {}", time.clock_offset);
 ///
 ///     Ok(())
 /// }
 /// ```
+///
+/// # Concurrency
+///
+/// `NtsClient` is `Send + Sync` and all its methods take `&self`. Cloning is
+/// cheap: clones share the connection, cookies, rate limiting, blacklist and
+/// circuit breaker state, so a client can be handed to every request handler
+/// of an axum or tonic service.
+///
+/// - Concurrent `get_time()` calls run in parallel over the same NTP socket.
+///   Each request is identified by its transmit timestamp field, and every
+///   response is routed to the call that sent the matching request.
+/// - `connect()`, `reconnect()` and automatic connection are serialized; a
+///   query started during a reconnect completes on the previous connection.
+/// - Kiss-o'-Death `RATE` spacing applies to the client as a whole: each
+///   call reserves the next free slot.
+///
+/// ```no_run
+/// use rkik_nts::{NtsClient, NtsClientConfig};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// client.connect().await?;
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let client = client.clone();
+///         tokio::spawn(async move { client.get_time().await })
+///     })
+///     .collect();
+/// for handle in handles {
+///     println!("Offset: {}", handle.await??.clock_offset);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
 pub struct NtsClient {
+    inner: Arc<ClientInner>,
+}
+
+/// State shared by all clones of an [`NtsClient`].
+struct ClientInner {
     config: NtsClientConfig,
-    nts_state: Option<NtsKeResult>,
-    socket: Option<UdpSocket>,
+    connection: RwLock<Option<Arc<Connection>>>,
+    connecting: futures_util::lock::Mutex<()>,
+    resolver: Arc<dyn Resolver>,
+    connector: Arc<dyn Connector>,
+    clock: Arc<dyn Clock>,
+    runtime: Arc<dyn Runtime>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    sample_sinks: Vec<Arc<dyn SampleSink>>,
+    event_handlers: Vec<EventHandler>,
+    pacing: Mutex<Pacing>,
+    timings: Mutex<TimingBreakdown>,
+    server_stats: Mutex<HashMap<String, ServerStats>>,
+    history: Mutex<VecDeque<TimeSnapshot>>,
+    last_snapshot: Mutex<Option<TimeSnapshot>>,
+    last_packets: Mutex<Option<PacketCapture>>,
+    #[cfg(feature = "pcap")]
+    pcap: Option<Arc<PcapWriter>>,
+    tls_sessions: TlsSessions,
+    cookie_store: Arc<dyn CookieStore>,
+    ke_rotation: AtomicUsize,
+    /// Whether the client was ever connected, to tell re-keying apart.
+    keyed: AtomicBool,
+    /// Failed queries since the last successful one.
+    consecutive_failures: AtomicU32,
+    /// Whether the last query failed in a way only a new key exchange fixes.
+    rekey_needed: AtomicBool,
+    blacklist: Mutex<Blacklist>,
+    circuit: Mutex<CircuitBreaker>,
+}
+
+/// An established association with an NTP server.
+struct Connection {
+    transport: Box<dyn NtpTransport>,
+    /// Queries awaiting a response, by transmit timestamp field.
+    pending: Mutex<PendingQueries<oneshot::Sender<Response>>>,
+    nts_state: Arc<NtsKeResult>,
+    /// The NTS-KE server the keys were negotiated with, if any.
+    bound_server: Option<String>,
+}
+
+/// A received datagram and its receive timestamp (T4).
+type Response = (Vec<u8>, SystemTime);
+
+impl Connection {
+    /// Register a query so that responses to it received by other queries
+    /// are routed to `sender`. The registration ends when the guard drops.
+    fn register(&self, transmit: [u8; 8], sender: oneshot::Sender<Response>) -> Registration<'_> {
+        lock(&self.pending).insert(transmit, sender);
+        Registration {
+            connection: self,
+            transmit,
+        }
+    }
+
+    /// Hand a response to the query it answers. Returns `false` if no
+    /// pending query matches its origin timestamp.
+    fn route(&self, data: &[u8], t4: SystemTime) -> bool {
+        match lock(&self.pending).take_answered(data) {
+            Some(sender) => sender.send((data.to_vec(), t4)).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Registration of a pending query, removed on drop.
+struct Registration<'a> {
+    connection: &'a Connection,
+    transmit: [u8; 8],
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        lock(&self.connection.pending).remove(&self.transmit);
+    }
+}
+
+/// Spacing of queries as requested by Kiss-o'-Death `RATE` packets.
+#[derive(Debug, Default)]
+struct Pacing {
+    rate_limit: RateLimitState,
+    last_query: Option<Instant>,
+}
+
+impl Pacing {
+    /// Reserve the next query slot at or after `now`, returning how long to
+    /// wait before sending.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let next = self
+            .last_query
+            .map(|last| last + self.rate_limit.min_interval)
+            .filter(|&next| next > now)
+            .unwrap_or(now);
+        self.last_query = Some(next);
+        next - now
+    }
+}
+
+/// Lock `mutex`, ignoring poisoning: the state it guards stays consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
 }
 
 impl NtsClient {
@@ -49,56 +221,317 @@ impl NtsClient {
     /// # Arguments
     ///
     /// * `config` - Configuration for the NTS client.
+    #[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
     pub fn new(config: NtsClientConfig) -> Self {
-        Self {
+        NtsClientBuilder {
+            config,
+            ..NtsClientBuilder::default()
+        }
+        .assemble()
+        .expect("the runtime features provide a default runtime, connector and resolver")
+    }
+
+    /// Like [`new`](Self::new), with the given resolver, connector and
+    /// runtime.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn with_io(
+        config: NtsClientConfig,
+        resolver: Arc<dyn Resolver>,
+        connector: Arc<dyn Connector>,
+        runtime: Arc<dyn Runtime>,
+    ) -> Self {
+        NtsClientBuilder {
             config,
-            nts_state: None,
-            socket: None,
+            resolver: Some(resolver),
+            connector: Some(connector),
+            runtime: Some(runtime),
+            ..NtsClientBuilder::default()
         }
+        .assemble()
+        .expect("the runtime, connector and resolver are given")
+    }
+
+    fn from_inner(inner: ClientInner) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Create a builder for an NTS client.
+    ///
+    /// Unlike [`NtsClient::new`], the builder validates the configuration
+    /// when [`NtsClientBuilder::build`] is called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::NtsClient;
+    ///
+    /// let client = NtsClient::builder()
+    ///     .with_server("time.cloudflare.com")
+    ///     .on_event(|event| println!("{:?}", event))
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(!client.is_connected());
+    /// ```
+    pub fn builder() -> NtsClientBuilder {
+        NtsClientBuilder::default()
     }
 
     /// Connect to the NTS server and perform key exchange.
     ///
-    /// This must be called before querying time.
+    /// This must be called before querying time. Retryable failures are
+    /// retried according to the configured retry policy. If the
+    /// key exchange still fails, the configured fallback servers are tried
+    /// in order; see [`bound_server`](Self::bound_server). Blacklisted
+    /// servers are skipped unless all servers are blacklisted.
     ///
     /// # Errors
     ///
-    /// Returns an error if the configuration is invalid or the key exchange fails.
-    pub async fn connect(&mut self) -> Result<()> {
-        info!("Connecting to NTS server: {}", self.config.nts_ke_server);
+    /// Returns an error if the configuration is invalid or the key exchange
+    /// fails with every server. The error of the last server is returned.
+    /// Fails with [`Error::Timeout`] if the configured total deadline passes.
+    pub async fn connect(&self) -> Result<()> {
+        let _connecting = self.inner.connecting.lock().await;
+        self.connect_locked().await
+    }
+
+    /// Connect unless already connected, e.g. by a concurrent query.
+    async fn ensure_connected(&self) -> Result<()> {
+        let _connecting = self.inner.connecting.lock().await;
+        if self.is_connected() {
+            return Ok(());
+        }
+        debug!("Not connected, performing key exchange before the first query");
+        self.connect_locked().await
+    }
+
+    /// Body of [`connect`](Self::connect), called with `connecting` held.
+    #[instrument(
+        name = "nts.connect",
+        skip_all,
+        fields(
+            server = %self.inner.config.nts_ke_server,
+            bound_server = Empty,
+            ntp_server = Empty,
+            aead = Empty
+        )
+    )]
+    async fn connect_locked(&self) -> Result<()> {
+        info!(
+            "Connecting to NTS server: {}",
+            self.inner.config.nts_ke_server
+        );
+        let deadline = self.deadline();
 
         // Validate configuration
-        self.config.validate()?;
+        self.inner.config.validate()?;
+
+        // Index 0 is the primary server, n is fallback server n - 1
+        let now = self.inner.clock.instant();
+        let names: Vec<&str> = std::iter::once(&self.inner.config.nts_ke_server)
+            .chain(&self.inner.config.fallback_servers)
+            .map(String::as_str)
+            .collect();
+        let mut candidates: Vec<usize> = {
+            let blacklist = lock(&self.inner.blacklist);
+            (0..names.len())
+                .filter(|&i| !blacklist.contains(names[i], now))
+                .collect()
+        };
+        if candidates.is_empty() {
+            debug!("All NTS servers are blacklisted, trying them anyway");
+            candidates = (0..names.len()).collect();
+        } else if candidates.len() < names.len() {
+            debug!("Skipping blacklisted NTS servers: {:?}", self.blacklist());
+        }
 
-        // Perform NTS key exchange
-        let nts_result = perform_nts_ke(&self.config).await?;
+        let mut candidates = candidates.into_iter().peekable();
+        let (server, nts_result) = loop {
+            let attempt = candidates.next().expect("at least one candidate server");
+            let config = match attempt {
+                0 => Cow::Borrowed(&self.inner.config),
+                n => {
+                    let fallback = &self.inner.config.fallback_servers[n - 1];
+                    info!("Trying fallback NTS server: {}", fallback);
+                    Cow::Owned(self.inner.config.for_fallback(fallback))
+                }
+            };
+
+            // Perform NTS key exchange, starting each attempt with the next
+            // resolved address
+            let resolver = self.inner.resolver.as_ref();
+            let connector = &self.inner.connector;
+            let sessions = &self.inner.tls_sessions;
+            let rotation = &self.inner.ke_rotation;
+            let runtime = self.inner.runtime.as_ref();
+            let policy = config.effective_retry_policy();
+            let result = within(
+                runtime,
+                self.inner.clock.as_ref(),
+                deadline,
+                with_retries("NTS-KE", policy.as_ref(), runtime, || {
+                    let current = rotation.fetch_add(1, Ordering::Relaxed);
+                    perform_nts_ke(
+                        &config,
+                        resolver,
+                        connector,
+                        sessions,
+                        current,
+                        runtime,
+                        &self.inner.clock,
+                    )
+                }),
+            )
+            .await;
+            match result {
+                Ok(result) => {
+                    lock(&self.inner.blacklist).record_success(&config.nts_ke_server);
+                    break (config.nts_ke_server.clone(), result);
+                }
+                Err(e) => {
+                    lock(&self.inner.blacklist)
+                        .record_failure(&config.nts_ke_server, self.inner.clock.instant());
+                    if let Some(metrics) = &self.inner.metrics {
+                        metrics.record_key_exchange_failure(&e);
+                    }
+                    self.emit(&ClientEvent::KeyExchangeFailed(&e));
+                    let expired = deadline.is_some_and(|d| d <= self.inner.clock.instant());
+                    if candidates.peek().is_none() || expired {
+                        return Err(e);
+                    }
+                    warn!("NTS server {} failed: {}", config.nts_ke_server, e);
+                }
+            }
+        };
+
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.record_key_exchange(nts_result.ke_duration());
+        }
+        *lock(&self.inner.timings) = nts_result.timings.clone();
+        #[cfg(feature = "pcap")]
+        if let Some(pcap) = &self.inner.pcap {
+            if let Err(e) = pcap.write_key_exchange(&server, &nts_result, self.inner.clock.now()) {
+                warn!("Failed to write key exchange to pcap capture: {}", e);
+            }
+        }
 
         info!(
-            "NTS key exchange successful. NTP server: {}",
-            nts_result.ntp_server
+            "NTS key exchange with {} successful. NTP server: {}",
+            server, nts_result.ntp_server
         );
+        let span = Span::current();
+        span.record("bound_server", display(&server));
+        span.record("ntp_server", display(nts_result.ntp_server));
+        span.record("aead", display(&nts_result.aead_algorithm));
 
-        // Create UDP socket for NTP queries
-        // Choose bind address based on server's address family
-        let bind_addr = if nts_result.ntp_server.is_ipv6() {
-            "[::]:0"
-        } else {
-            "0.0.0.0:0"
-        };
-        let socket = UdpSocket::bind(bind_addr).await?;
-        socket.connect(nts_result.ntp_server).await?;
+        self.attach(nts_result, Some(server)).await
+    }
+
+    /// Connect using pre-shared NTS keys and cookies, skipping key exchange.
+    ///
+    /// This is meant for testing and for deployments where keys are
+    /// provisioned out of band. The keys and cookies must have been issued
+    /// for `ntp_server`; no TLS connection is made.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration is invalid, no cookie is
+    /// provided, or the UDP socket cannot be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig, NtsKeys};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32])?;
+    /// let cookies = vec![vec![0u8; 64]];
+    ///
+    /// let client = NtsClient::new(NtsClientConfig::new("time.example.com"));
+    /// client
+    ///     .connect_with_keys("192.0.2.1:123".parse()?, keys, cookies)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_with_keys(
+        &self,
+        ntp_server: SocketAddr,
+        keys: NtsKeys,
+        cookies: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        self.inner.config.validate()?;
+        if cookies.is_empty() {
+            return Err(Error::CookieExhausted { server: ntp_server });
+        }
 
-        self.socket = Some(socket);
-        self.nts_state = Some(nts_result);
+        info!("Using pre-shared NTS keys for NTP server: {}", ntp_server);
+        *lock(&self.inner.timings) = TimingBreakdown::default();
+        self.attach(
+            NtsKeResult::from_fixed_keys(ntp_server, keys, cookies),
+            None,
+        )
+        .await
+    }
+
+    /// Open the transport for NTP queries and store the NTS state.
+    ///
+    /// Cookies left in the cookie store by the replaced connection are
+    /// dropped, as they are bound to its keys. Cookies of other clients
+    /// sharing the store are kept.
+    async fn attach(&self, nts_result: NtsKeResult, bound_server: Option<String>) -> Result<()> {
+        let transport = self
+            .inner
+            .connector
+            .connect_ntp(nts_result.ntp_server)
+            .await?;
+
+        let ntp_server = nts_result.ntp_server;
+        if let Some(previous) = self.connection() {
+            self.inner
+                .cookie_store
+                .clear(previous.nts_state.association());
+        }
+        self.inner
+            .cookie_store
+            .put(nts_result.association(), nts_result.cookies.clone());
+        let connection = Connection {
+            transport,
+            pending: Mutex::default(),
+            nts_state: Arc::new(nts_result),
+            bound_server,
+        };
+        *self
+            .inner
+            .connection
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(connection));
+        self.inner.rekey_needed.store(false, Ordering::Relaxed);
+        self.emit(&ClientEvent::Connected { ntp_server });
+        if self.inner.keyed.swap(true, Ordering::Relaxed) {
+            self.emit(&ClientEvent::Rekeyed { ntp_server });
+        }
 
         Ok(())
     }
 
     /// Query the current time from the NTS-secured NTP server.
     ///
+    /// Retryable failures (such as timeouts) are retried according to the
+    /// configured retry policy (by default up to `max_retries` times with
+    /// exponential backoff).
+    ///
+    /// If the server previously answered with a Kiss-o'-Death `RATE` packet,
+    /// this waits until the minimum query interval has elapsed.
+    ///
     /// # Errors
     ///
-    /// Returns an error if not connected or if the time query fails.
+    /// Returns [`Error::NotConnected`] if not connected (unless
+    /// [`auto_connect`](NtsClientConfig::auto_connect) is enabled, in which
+    /// case key exchange errors are returned), an error if the time query fails,
+    /// [`Error::CircuitOpen`] while a configured circuit breaker is open, and
+    /// [`Error::Timeout`] if the configured total deadline passes.
     ///
     /// # Examples
     ///
@@ -106,146 +539,2516 @@ impl NtsClient {
     /// # use rkik_nts::{NtsClient, NtsClientConfig};
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// let mut client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
     /// client.connect().await?;
     /// let time = client.get_time().await?;
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn get_time(&mut self) -> Result<TimeSnapshot> {
-        let socket = self
-            .socket
-            .as_ref()
-            .ok_or_else(|| Error::Other("Not connected. Call connect() first.".to_string()))?;
+    pub async fn get_time(&self) -> Result<TimeSnapshot> {
+        self.get_time_with(&QueryOptions::default()).await
+    }
+
+    /// Take `n` samples in a row and keep the one with the minimum round-trip
+    /// delay, like the NTP clock filter.
+    ///
+    /// The raw samples are returned as well, see
+    /// [`FilteredTime::median_offset`] for an alternative choice. Samples that
+    /// fail with a retryable error are counted in
+    /// [`FilteredTime::failures`]; any other error is returned at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if `n` is zero, the error of the last
+    /// sample if all samples failed, and otherwise the same errors as
+    /// [`get_time`](Self::get_time).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// client.connect().await?;
+    /// let filtered = client.get_time_filtered(8).await?;
+    /// println!(
+    ///     "Offset: {} ms (delay {:?}, {} samples)",
+    ///     filtered.chosen.offset_signed(),
+    ///     filtered.chosen.round_trip_delay,
+    ///     filtered.samples.len()
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_time_filtered(&self, n: usize) -> Result<FilteredTime> {
+        if n == 0 {
+            return Err(Error::InvalidConfig(
+                "At least one sample is required".to_string(),
+            ));
+        }
+        let mut samples = Vec::with_capacity(n);
+        let mut last_error = None;
+        for _ in 0..n {
+            match self.get_time().await {
+                Ok(snapshot) => samples.push(snapshot),
+                Err(e) if e.is_retryable() || matches!(e, Error::RetriesExhausted { .. }) => {
+                    debug!("Filtered sample failed: {}", e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let failures = n - samples.len();
+        match FilteredTime::from_samples(samples, failures) {
+            Some(filtered) => Ok(filtered),
+            None => Err(last_error.unwrap_or(Error::Timeout)),
+        }
+    }
+
+    /// Query the current time, overriding the timeout, retry count or
+    /// validation thresholds of the configuration for this call only.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`get_time`](Self::get_time).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig, QueryOptions};
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// client.connect().await?;
+    ///
+    /// let probe = QueryOptions::new()
+    ///     .with_timeout(Duration::from_millis(500))
+    ///     .with_max_retries(0);
+    /// let time = client.get_time_with(&probe).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_time_with(&self, options: &QueryOptions) -> Result<TimeSnapshot> {
+        self.query_with(options).await.map(|(snapshot, _)| snapshot)
+    }
+
+    /// Query the current time and decode the extension fields of the
+    /// response, for protocol-level troubleshooting.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`get_time`](Self::get_time), and
+    /// [`Error::InvalidResponse`] if the extension fields are malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// client.connect().await?;
+    /// let debug = client.get_time_debug().await?;
+    /// for field in &debug.extension_fields {
+    ///     println!("0x{:04x}: {:?}", field.field_type(), field);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_time_debug(&self) -> Result<DebugSnapshot> {
+        let (snapshot, response) = self.query_with(&QueryOptions::default()).await?;
+        Ok(DebugSnapshot {
+            snapshot,
+            extension_fields: parse_extension_fields(&response)?,
+        })
+    }
+
+    /// Query the current time, returning the snapshot and the raw response.
+    #[instrument(
+        name = "nts.get_time",
+        skip_all,
+        fields(server = Empty, rtt_ms = Empty, offset_ms = Empty)
+    )]
+    async fn query_with(&self, options: &QueryOptions) -> Result<(TimeSnapshot, Vec<u8>)> {
+        lock(&self.inner.circuit).check(self.inner.clock.instant())?;
+        // The deadline covers the key exchange made on the first query
+        let deadline = self.deadline();
+        if self.inner.config.auto_connect && !self.is_connected() {
+            within(
+                self.inner.runtime.as_ref(),
+                self.inner.clock.as_ref(),
+                deadline,
+                self.ensure_connected(),
+            )
+            .await?;
+        }
 
-        let nts_state = self.nts_state.as_ref().ok_or_else(|| {
-            Error::Other("No NTS state available. Call connect() first.".to_string())
-        })?;
+        let mut waited = Ok(());
+        let wait = lock(&self.inner.pacing).reserve(self.inner.clock.instant());
+        if !wait.is_zero() {
+            debug!("Rate limited, waiting {:?} before querying", wait);
+            waited = within(
+                self.inner.runtime.as_ref(),
+                self.inner.clock.as_ref(),
+                deadline,
+                async {
+                    self.inner.runtime.sleep(wait).await;
+                    Ok(())
+                },
+            )
+            .await;
+        }
 
-        // Create NTP request packet
-        let request = self.create_ntp_request()?;
+        let result = match waited {
+            Ok(()) => {
+                let policy = match options.max_retries {
+                    Some(retries) => Arc::new(ExponentialBackoff::new(retries)),
+                    None => self.inner.config.effective_retry_policy(),
+                };
+                let runtime = self.inner.runtime.as_ref();
+                within(
+                    runtime,
+                    self.inner.clock.as_ref(),
+                    deadline,
+                    with_retries("NTP query", policy.as_ref(), runtime, || {
+                        self.query_time(options)
+                    }),
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
 
-        // Send request
-        debug!("Sending NTP request");
-        socket.send(&request).await?;
+        match &result {
+            Ok((snapshot, round_trip, _)) => {
+                let span = Span::current();
+                span.record("server", display(&snapshot.server));
+                span.record("rtt_ms", round_trip.as_secs_f64() * 1e3);
+                span.record("offset_ms", snapshot.clock_offset.as_secs_f64() * 1e3);
+                lock(&self.inner.circuit).record_success();
+                self.inner.consecutive_failures.store(0, Ordering::Relaxed);
+                self.inner.rekey_needed.store(false, Ordering::Relaxed);
+                lock(&self.inner.timings).ntp_round_trip = Some(*round_trip);
+                if let Some(window) = self.inner.config.stats_window {
+                    lock(&self.inner.server_stats)
+                        .entry(snapshot.server.clone())
+                        .or_insert_with(|| ServerStats::new(window))
+                        .record(snapshot);
+                }
+                *lock(&self.inner.last_snapshot) = Some(snapshot.clone());
+                if let Some(capacity) = self.inner.config.history_capacity {
+                    let mut history = lock(&self.inner.history);
+                    if history.len() == capacity {
+                        history.pop_front();
+                    }
+                    history.push_back(snapshot.clone());
+                }
+                if let Some(metrics) = &self.inner.metrics {
+                    metrics.record_query(snapshot);
+                    metrics.record_cookies_remaining(self.cookies_remaining());
+                }
+                for sink in &self.inner.sample_sinks {
+                    if let Err(e) = sink.record(snapshot) {
+                        warn!("Failed to record sample: {}", e);
+                    }
+                }
+                self.emit(&ClientEvent::TimeReceived(snapshot));
+            }
+            Err(e) => {
+                self.inner
+                    .consecutive_failures
+                    .fetch_add(1, Ordering::Relaxed);
+                self.inner
+                    .rekey_needed
+                    .store(needs_rekey(e), Ordering::Relaxed);
+                if lock(&self.inner.circuit).record_failure(self.inner.clock.instant()) {
+                    warn!("Too many failed queries, opening circuit breaker");
+                }
+                if let Error::KissOfDeath { code } = e {
+                    self.emit(&ClientEvent::KissOfDeath { code });
+                    if code == "RATE" {
+                        let mut pacing = lock(&self.inner.pacing);
+                        pacing.rate_limit.record_rate_kiss();
+                        warn!(
+                            "Server requested rate reduction, minimum interval is now {:?}",
+                            pacing.rate_limit.min_interval
+                        );
+                    } else if code == "DENY" || code == "RSTR" {
+                        if let Some(server) = self.bound_server() {
+                            warn!("NTS server {} denied access, blacklisting it", server);
+                            lock(&self.inner.blacklist).ban(&server, self.inner.clock.instant());
+                        }
+                    }
+                }
+                if let Some(metrics) = &self.inner.metrics {
+                    metrics.record_query_failure(e);
+                }
+                self.emit(&ClientEvent::QueryFailed(e));
+            }
+        }
 
-        // Receive response with timeout
-        let mut buf = vec![0u8; 1024];
-        let len = timeout(self.config.timeout, socket.recv(&mut buf))
-            .await
-            .map_err(|_| Error::Timeout)??;
+        result.map(|(snapshot, _, response)| (snapshot, response))
+    }
+
+    /// Perform a single NTP query without retrying.
+    ///
+    /// Returns the snapshot, the wall-clock duration of the exchange and the
+    /// raw response.
+    #[instrument(name = "nts.query", skip_all, fields(address = Empty, query_id = Empty))]
+    async fn query_time(
+        &self,
+        options: &QueryOptions,
+    ) -> Result<(TimeSnapshot, Duration, Vec<u8>)> {
+        let connection = self.connection().ok_or(Error::NotConnected)?;
+        let nts_state = &connection.nts_state;
+        if nts_state.protocol_version() >= 5 {
+            return Err(Error::Protocol(
+                "NTPv5 time queries are not supported yet".to_string(),
+            ));
+        }
+
+        // Each request spends a cookie, and asks for enough new ones to
+        // refill the store
+        let server = nts_state.ntp_server;
+        let association = nts_state.association();
+        let cookie = self
+            .inner
+            .cookie_store
+            .take(association)
+            .ok_or(Error::CookieExhausted { server })?;
+        let remaining = self.inner.cookie_store.len(association);
+        if remaining < COOKIE_LOW_WATERMARK {
+            self.emit(&ClientEvent::CookiesLow { remaining });
+        }
+        let placeholders = MAX_RESPONSE_COOKIES.saturating_sub(remaining + 1);
+        let query =
+            self.create_ntp_request(server)?
+                .with_nts(&nts_state.keys, &cookie, placeholders)?;
+        let request = query.request();
+        let span = Span::current();
+        span.record("address", display(nts_state.ntp_server));
+        span.record(
+            "query_id",
+            display(format_args!(
+                "{:016x}",
+                u64::from_be_bytes(query.transmit())
+            )),
+        );
+
+        // Send request. Concurrent queries read from the same socket, so
+        // our response may be received and routed to us by another query.
+        let (sender, mut routed) = oneshot::channel();
+        let _registration = connection.register(query.transmit(), sender);
+        debug!("Sending NTP request");
+        let sent_at = self.inner.clock.instant();
+        if self.inner.config.capture_packets {
+            *lock(&self.inner.last_packets) = Some(PacketCapture {
+                server: nts_state.ntp_server,
+                sent_at: self.inner.clock.now(),
+                request: request.to_vec(),
+                response: None,
+                received_at: None,
+            });
+        }
+        #[cfg(feature = "pcap")]
+        self.write_pcap(&connection, self.inner.clock.now(), true, request);
+        connection.transport.send(request).await?;
 
-        buf.truncate(len);
+        // Receive responses until one answers our request, or the timeout
+        // expires. One spare byte reveals datagrams larger than expected.
+        let size = response_buffer_size(
+            nts_state.cookie_sizes().into_iter().max().unwrap_or(0),
+            self.inner.config.max_packet_size,
+        );
+        let mut buf = vec![0u8; size + 1];
+        let query_timeout = options
+            .timeout
+            .unwrap_or_else(|| self.inner.config.effective_query_timeout());
+        let (buf, t4) = runtime::timeout(self.inner.runtime.as_ref(), query_timeout, async {
+            loop {
+                let received = match select(&mut routed, connection.transport.recv(&mut buf)).await
+                {
+                    Either::Left((response, _)) => {
+                        return response.map_err(|_| {
+                            Error::Other("Pending NTP query was replaced".to_string())
+                        });
+                    }
+                    Either::Right((received, _)) => received,
+                };
+                let (len, t4) = received?;
+                let t4 = self.inner.clock.receive_time(t4);
+                if len > size {
+                    if query.matches(&buf[..len]) {
+                        self.capture_response(&query, &buf[..len], t4);
+                        #[cfg(feature = "pcap")]
+                        self.write_pcap(&connection, t4, false, &buf[..len]);
+                        return Err(Error::ResponseTruncated { size });
+                    }
+                    warn!("Dropping NTP response larger than {} bytes", size);
+                    continue;
+                }
+                if query.matches(&buf[..len]) {
+                    buf.truncate(len);
+                    return Ok::<_, Error>((buf, t4));
+                }
+                if !connection.route(&buf[..len], t4) {
+                    warn!("Dropping NTP response with mismatched origin timestamp");
+                }
+            }
+        })
+        .await
+        .ok_or(Error::Timeout)??;
+        let round_trip = self
+            .inner
+            .clock
+            .instant()
+            .saturating_duration_since(sent_at);
+        self.capture_response(&query, &buf, t4);
+        #[cfg(feature = "pcap")]
+        self.write_pcap(&connection, t4, false, &buf);
 
         // Parse response
-        debug!("Received {} bytes, parsing NTP response", len);
-        let time_snapshot = self.parse_ntp_response(&buf, nts_state)?;
+        debug!("Received {} bytes, parsing NTP response", buf.len());
+        let (mut time_snapshot, cookies) = self.parse_ntp_response(&buf, &query, t4)?;
+        self.inner.cookie_store.put(association, cookies);
+        leap::apply(&mut time_snapshot, self.inner.config.leap_second_handling)?;
+        self.check_limits(&time_snapshot, options)?;
 
-        Ok(time_snapshot)
+        Ok((time_snapshot, round_trip, buf))
     }
 
-    /// Check if the client is connected and ready to query time.
-    pub fn is_connected(&self) -> bool {
-        self.socket.is_some() && self.nts_state.is_some()
-    }
+    /// Probe which capabilities work against the configured server.
+    ///
+    /// Performs a key exchange (if not already connected) and a single time
+    /// query, recording the outcome of each step instead of returning early.
+    /// Features this client does not implement yet are reported as
+    /// [`CapabilityStatus::Unimplemented`].
+    pub async fn capabilities(&self) -> CapabilityReport {
+        let mut report = CapabilityReport::new(self.inner.config.nts_ke_server.clone());
 
-    /// Get the NTP server address being used.
-    pub fn ntp_server(&self) -> Option<SocketAddr> {
-        self.nts_state.as_ref().map(|s| s.ntp_server)
+        if !self.is_connected() {
+            if let Err(e) = self.connect().await {
+                report.key_exchange = CapabilityStatus::Failed(e.to_string());
+                return report;
+            }
+        }
+        report.key_exchange = CapabilityStatus::Supported;
+
+        report.time_query = match self.get_time().await {
+            Ok(_) => CapabilityStatus::Supported,
+            Err(e) => CapabilityStatus::Failed(e.to_string()),
+        };
+
+        if cfg!(all(feature = "kernel-timestamps", target_os = "linux")) {
+            let enabled = self
+                .connection()
+                .is_some_and(|c| c.transport.kernel_timestamps());
+            report.kernel_timestamps = if enabled {
+                CapabilityStatus::Supported
+            } else {
+                CapabilityStatus::Failed("SO_TIMESTAMPNS could not be enabled".to_string())
+            };
+        }
+
+        report
     }
 
-    /// Get a reference to the NTS key exchange result for diagnostic purposes.
-    ///
-    /// This provides access to NTS-KE negotiation details including:
-    /// - AEAD algorithm
-    /// - Cookie count and sizes
-    /// - Key exchange duration
+    /// Get the NTS-KE server hostname the client is connected through.
     ///
-    /// Returns `None` if not connected.
-    pub fn nts_ke_info(&self) -> Option<&NtsKeResult> {
-        self.nts_state.as_ref()
+    /// This is the primary server or one of the fallback servers. Returns
+    /// `None` if not connected, or if the client was connected without a
+    /// key exchange.
+    pub fn bound_server(&self) -> Option<String> {
+        self.connection()?.bound_server.clone()
     }
 
-    /// Reconnect and perform a fresh NTS key exchange.
+    /// Get the NTS-KE server address used for the current key exchange.
     ///
-    /// This can be useful if the connection has been idle for a long time
-    /// or if the server has rotated keys.
-    pub async fn reconnect(&mut self) -> Result<()> {
-        debug!("Reconnecting to NTS server");
-        self.socket = None;
-        self.nts_state = None;
-        self.connect().await
+    /// When the hostname resolves to several addresses, each key exchange
+    /// starts with the next one. Returns `None` if not connected.
+    pub fn ke_server(&self) -> Option<SocketAddr> {
+        self.connection().map(|c| c.nts_state.ke_server)
     }
 
-    fn create_ntp_request(&self) -> Result<Vec<u8>> {
-        // Create a basic NTP client request packet
-        // This is a simplified version - in production, you'd use the full ntp-proto capabilities
+    /// Get the NTS-KE servers currently blacklisted after repeated failures
+    /// or a Kiss-o'-Death `DENY`, for diagnostic purposes.
+    pub fn blacklist(&self) -> Vec<BlacklistEntry> {
+        lock(&self.inner.blacklist).entries(self.inner.clock.instant())
+    }
+
+    /// Get the state of the circuit breaker around `get_time()`.
+    pub fn circuit_state(&self) -> CircuitState {
+        lock(&self.inner.circuit).state(self.inner.clock.instant())
+    }
 
-        let mut packet = vec![0u8; 48]; // Minimum NTP packet size
+    /// Get the client configuration.
+    pub fn config(&self) -> &NtsClientConfig {
+        &self.inner.config
+    }
 
-        // LI (2 bits) = 0, VN (3 bits) = 4, Mode (3 bits) = 3 (client)
-        packet[0] = 0x23; // 0b00_100_011
+    /// Check if the client is connected and ready to query time.
+    pub fn is_connected(&self) -> bool {
+        self.connection().is_some()
+    }
 
-        // Poll interval
-        packet[2] = 6;
+    /// Get the NTP server address being used.
+    pub fn ntp_server(&self) -> Option<SocketAddr> {
+        self.connection().map(|c| c.nts_state.ntp_server)
+    }
 
-        // Transmit timestamp (current time)
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| Error::Other(format!("System time error: {}", e)))?;
+    /// Get the duration of each connection and query phase for diagnostic purposes.
+    ///
+    /// Key exchange phases are updated by `connect()`, the NTP round trip by
+    /// each successful `get_time()`.
+    pub fn timings(&self) -> TimingBreakdown {
+        lock(&self.inner.timings).clone()
+    }
 
-        let ntp_time = now.as_secs() + 2_208_988_800; // NTP epoch offset
-        let frac = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    /// Get the rolling offset and round-trip statistics of each server
+    /// queried, by server address.
+    ///
+    /// Empty unless [`stats_window`](NtsClientConfig::stats_window) is set.
+    pub fn server_stats(&self) -> HashMap<String, ServerStats> {
+        lock(&self.inner.server_stats).clone()
+    }
 
-        packet[40..48].copy_from_slice(&ntp_time.to_be_bytes());
-        packet[44..48].copy_from_slice(&(frac as u32).to_be_bytes());
+    /// Get the result of the last successful time query.
+    pub fn last_snapshot(&self) -> Option<TimeSnapshot> {
+        lock(&self.inner.last_snapshot).clone()
+    }
 
-        Ok(packet)
+    /// Get the request and response bytes of the last query, successful or
+    /// not, e.g. to hex-dump a response that failed to parse.
+    ///
+    /// Returns `None` unless [`NtsClientConfig::with_packet_capture`] is
+    /// enabled, or before the first query.
+    pub fn last_packets(&self) -> Option<PacketCapture> {
+        lock(&self.inner.last_packets).clone()
     }
 
-    fn parse_ntp_response(&self, data: &[u8], nts_state: &NtsKeResult) -> Result<TimeSnapshot> {
-        if data.len() < 48 {
-            return Err(Error::InvalidResponse("NTP packet too small".to_string()));
+    /// Collect the key exchange, TLS, timing and cookie state and the last
+    /// snapshot in a report with a stable schema, e.g. to embed in JSON
+    /// output.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// client.connect().await?;
+    /// client.get_time().await?;
+    /// let report = client.diagnostics_report();
+    /// if let Some(ke) = &report.key_exchange {
+    ///     println!("{} via {}", ke.aead_algorithm, ke.ntp_server);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn diagnostics_report(&self) -> DiagnosticsReport {
+        let connection = self.connection();
+        let remaining = self.cookies_remaining();
+        DiagnosticsReport {
+            schema_version: DIAGNOSTICS_SCHEMA_VERSION,
+            server: self.inner.config.nts_ke_server.clone(),
+            connected: connection.is_some(),
+            key_exchange: connection
+                .as_ref()
+                .map(|c| KeyExchangeReport::new(&c.nts_state, c.bound_server.clone())),
+            tls: connection
+                .as_ref()
+                .filter(|c| c.bound_server.is_some())
+                .map(|c| TlsReport::from(c.nts_state.tls_details())),
+            timings: TimingsReport::from(&self.timings()),
+            cookies: CookieReport {
+                remaining,
+                low: connection.is_some() && remaining < COOKIE_LOW_WATERMARK,
+                initial_sizes: connection
+                    .as_ref()
+                    .map(|c| c.nts_state.cookie_sizes())
+                    .unwrap_or_default(),
+            },
+            last_snapshot: self.last_snapshot().as_ref().map(SnapshotReport::from),
         }
+    }
 
-        // Extract transmit timestamp from server (bytes 40-47)
-        // NTP timestamp is: 4 bytes seconds + 4 bytes fraction
-        let tx_secs = u32::from_be_bytes([data[40], data[41], data[42], data[43]]);
-        let tx_frac = u32::from_be_bytes([data[44], data[45], data[46], data[47]]);
+    /// Get the health of the client, e.g. for a `/healthz` endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use rkik_nts::{NtsClient, NtsClientConfig};
+    ///
+    /// # async fn example() -> Result<(), rkik_nts::Error> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// client.connect().await?;
+    /// client.get_time().await?;
+    ///
+    /// let health = client.health();
+    /// let status = if health.is_healthy(Duration::from_secs(300)) { 200 } else { 503 };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn health(&self) -> HealthStatus {
+        let connected = self.is_connected();
+        let cookies_remaining = self.cookies_remaining();
+        HealthStatus {
+            connected,
+            last_sync_age: lock(&self.inner.last_snapshot)
+                .as_ref()
+                .map(|snapshot| snapshot.age(self.inner.clock.as_ref())),
+            consecutive_failures: self.inner.consecutive_failures.load(Ordering::Relaxed),
+            cookies_remaining,
+            rekey_overdue: connected
+                && (cookies_remaining == 0 || self.inner.rekey_needed.load(Ordering::Relaxed)),
+        }
+    }
 
-        // Convert NTP timestamp to Unix timestamp
-        // NTP epoch is 1900-01-01, Unix epoch is 1970-01-01
-        // Difference is 2208988800 seconds
-        let unix_secs = tx_secs.wrapping_sub(2_208_988_800) as u64;
-        let nanos = ((tx_frac as u64) * 1_000_000_000) >> 32;
+    /// Get the most recent successful snapshots, oldest first.
+    ///
+    /// Empty unless [`history_capacity`](NtsClientConfig::history_capacity)
+    /// is set.
+    pub fn history(&self) -> Vec<TimeSnapshot> {
+        lock(&self.inner.history).iter().cloned().collect()
+    }
 
-        let network_time = UNIX_EPOCH
-            + std::time::Duration::from_secs(unix_secs)
-            + std::time::Duration::from_nanos(nanos);
-        let system_time = SystemTime::now();
+    /// Get the number of unused NTS cookies for the current NTP server.
+    ///
+    /// Returns 0 if not connected.
+    pub fn cookies_remaining(&self) -> usize {
+        self.connection().map_or(0, |connection| {
+            self.inner
+                .cookie_store
+                .len(connection.nts_state.association())
+        })
+    }
 
-        // Calculate offset using abs_diff to avoid potential panics
-        // This handles both positive and negative time differences safely
-        let offset = system_time
-            .duration_since(network_time)
-            .unwrap_or_else(|e| e.duration());
+    /// Get the current rate-limiting state for diagnostic purposes.
+    ///
+    /// The minimum query interval grows each time the server answers with a
+    /// Kiss-o'-Death `RATE` packet.
+    pub fn rate_limit(&self) -> RateLimitState {
+        lock(&self.inner.pacing).rate_limit
+    }
 
-        // For now, we'll use a simple round-trip delay estimation
-        let round_trip_delay = self.config.timeout / 10;
+    /// Get a reference to the NTS key exchange result for diagnostic purposes.
+    ///
+    /// This provides access to NTS-KE negotiation details including:
+    /// - AEAD algorithm
+    /// - Cookie count and sizes
+    /// - Key exchange duration
+    ///
+    /// Returns `None` if not connected.
+    pub fn nts_ke_info(&self) -> Option<Arc<NtsKeResult>> {
+        self.connection().map(|c| Arc::clone(&c.nts_state))
+    }
 
-        Ok(TimeSnapshot {
-            system_time,
-            network_time,
-            offset,
-            round_trip_delay,
-            server: nts_state.ntp_server.to_string(),
-            authenticated: true, // NTS provides authentication
-        })
+    fn connection(&self) -> Option<Arc<Connection>> {
+        self.inner
+            .connection
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
-}
 
-impl Drop for NtsClient {
-    fn drop(&mut self) {
-        debug!("NtsClient dropped");
+    /// Save the negotiated NTS keys and the unused cookies to `path`.
+    ///
+    /// A later [`restore_state`](Self::restore_state) resumes querying the
+    /// NTP server without a new key exchange, e.g. after a daemon restart.
+    /// The file contains secret keys; on Unix it is created readable by its
+    /// owner only. Use [`save_state_encrypted`](Self::save_state_encrypted)
+    /// to encrypt it at rest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if not connected, if no unused cookie is left, or if
+    /// the file cannot be written.
+    #[cfg(feature = "persistence")]
+    pub fn save_state(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.save_state_inner(path.as_ref(), None)
+    }
+
+    /// Save the NTS state like [`save_state`](Self::save_state), encrypted
+    /// with `key` using ChaCha20-Poly1305.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if not connected, if no unused cookie is left, or if
+    /// the file cannot be written.
+    #[cfg(feature = "persistence")]
+    pub fn save_state_encrypted(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        key: &[u8; 32],
+    ) -> Result<()> {
+        self.save_state_inner(path.as_ref(), Some(key))
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save_state_inner(&self, path: &std::path::Path, key: Option<&[u8; 32]>) -> Result<()> {
+        let state = self.nts_ke_info().ok_or(Error::NotConnected)?;
+        // Only cookies that were never sent may be used again
+        let cookies = self.inner.cookie_store.snapshot(state.association());
+        if cookies.is_empty() {
+            return Err(Error::CookieExhausted {
+                server: state.ntp_server,
+            });
+        }
+        crate::state::save(path, &state, cookies, key)?;
+        debug!("Saved NTS state to {}", path.display());
+        Ok(())
+    }
+
+    /// Restore NTS state saved by [`save_state`](Self::save_state) and
+    /// connect without a new key exchange.
+    ///
+    /// Cookies expire when the server rotates its keys. If queries fail
+    /// after restoring, call [`reconnect`](Self::reconnect).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, if it is
+    /// encrypted, or if the UDP socket cannot be created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rkik_nts::{NtsClient, NtsClientConfig};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+    /// if client.restore_state("/var/lib/rkik/nts.json").await.is_err() {
+    ///     client.connect().await?;
+    ///     client.save_state("/var/lib/rkik/nts.json")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "persistence")]
+    pub async fn restore_state(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.restore_state_inner(path.as_ref(), None).await
+    }
+
+    /// Restore NTS state saved by
+    /// [`save_state_encrypted`](Self::save_state_encrypted).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, decrypted or parsed, or
+    /// if the UDP socket cannot be created.
+    #[cfg(feature = "persistence")]
+    pub async fn restore_state_encrypted(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        key: &[u8; 32],
+    ) -> Result<()> {
+        self.restore_state_inner(path.as_ref(), Some(key)).await
+    }
+
+    #[cfg(feature = "persistence")]
+    async fn restore_state_inner(
+        &self,
+        path: &std::path::Path,
+        key: Option<&[u8; 32]>,
+    ) -> Result<()> {
+        self.inner.config.validate()?;
+        let nts_result = crate::state::load(path, key)?;
+        info!(
+            "Restored NTS state from {}. NTP server: {}",
+            path.display(),
+            nts_result.ntp_server
+        );
+        *lock(&self.inner.timings) = TimingBreakdown::default();
+        self.attach(nts_result, None).await
+    }
+
+    /// Take a time sample every `period`, connecting and re-keying as
+    /// needed. See [`TimeStream`].
+    ///
+    /// The stream shares this client's state, like a clone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn time_stream(&self, period: Duration) -> TimeStream {
+        TimeStream::new(self.clone(), Arc::clone(&self.inner.runtime), period)
+    }
+
+    /// Connect if needed and return a [`ConnectedNtsClient`], whose queries
+    /// cannot fail with [`Error::NotConnected`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of [`connect`](Self::connect) if the client was not
+    /// connected and the key exchange fails.
+    pub async fn into_connected(self) -> Result<ConnectedNtsClient> {
+        self.ensure_connected().await?;
+        Ok(ConnectedNtsClient { client: self })
+    }
+
+    /// Reconnect and perform a fresh NTS key exchange.
+    ///
+    /// This can be useful if the connection has been idle for a long time
+    /// or if the server has rotated keys. TLS sessions from earlier key
+    /// exchanges are resumed when the server allows it, which saves a full
+    /// handshake.
+    ///
+    /// The current connection stays in use until the new key exchange
+    /// succeeds, and is kept if it fails.
+    pub async fn reconnect(&self) -> Result<()> {
+        debug!("Reconnecting to NTS server");
+        let _connecting = self.inner.connecting.lock().await;
+        self.connect_locked().await
+    }
+
+    /// Deadline for the current call from the configured total budget.
+    fn deadline(&self) -> Option<Instant> {
+        self.inner
+            .config
+            .total_deadline
+            .map(|budget| self.inner.clock.instant() + budget)
+    }
+
+    fn emit(&self, event: &ClientEvent<'_>) {
+        for handler in &self.inner.event_handlers {
+            handler(event);
+        }
+    }
+
+    fn create_ntp_request(&self, server: SocketAddr) -> Result<NtpQuery> {
+        let nonce = self
+            .inner
+            .config
+            .transmit_nonce
+            .then(rand::random::<[u8; 8]>);
+        NtpQuery::new(
+            server,
+            self.inner.config.ntp_version,
+            self.inner.clock.now(),
+            nonce,
+        )
+    }
+
+    /// Record `response` in the packet capture if it answers the last
+    /// query sent.
+    fn capture_response(&self, query: &NtpQuery, response: &[u8], received_at: SystemTime) {
+        if let Some(capture) = lock(&self.inner.last_packets).as_mut() {
+            if capture.request.get(40..48) == Some(&query.transmit()[..]) {
+                capture.response = Some(response.to_vec());
+                capture.received_at = Some(received_at);
+            }
+        }
+    }
+
+    /// Record an NTP packet sent or received on `connection` at `time` in
+    /// the pcap capture, if one is attached.
+    #[cfg(feature = "pcap")]
+    fn write_pcap(&self, connection: &Connection, time: SystemTime, sent: bool, packet: &[u8]) {
+        if let Some(pcap) = &self.inner.pcap {
+            let server = connection.nts_state.ntp_server;
+            let local = connection
+                .transport
+                .local_addr()
+                .unwrap_or_else(|_| SocketAddr::new(server.ip(), 0));
+            let (src, dst) = if sent {
+                (local, server)
+            } else {
+                (server, local)
+            };
+            if let Err(e) = pcap.write_udp(time, src, dst, packet) {
+                warn!("Failed to write NTP packet to pcap capture: {}", e);
+            }
+        }
+    }
+
+    /// Parse a server response to `query`, returning the snapshot and the
+    /// new cookies.
+    ///
+    /// `t4` is the time the response was received, read from the local clock.
+    fn parse_ntp_response(
+        &self,
+        data: &[u8],
+        query: &NtpQuery,
+        t4: SystemTime,
+    ) -> Result<(TimeSnapshot, Vec<Vec<u8>>)> {
+        let (mut snapshot, cookies) =
+            query.parse_response_with_cookies(data, t4, self.inner.clock.instant())?;
+        snapshot.bootstrap = self
+            .nts_ke_info()
+            .is_some_and(|state| state.tls.validity_ignored);
+        Ok((snapshot, cookies))
+    }
+
+    /// Reject snapshots beyond the root distance and offset thresholds of
+    /// `options`, or of the configuration.
+    fn check_limits(&self, snapshot: &TimeSnapshot, options: &QueryOptions) -> Result<()> {
+        if let Some(max) = options
+            .max_root_distance
+            .or(self.inner.config.max_root_distance)
+        {
+            let distance = snapshot.root_distance();
+            if distance > max {
+                return Err(Error::RootDistanceExceeded { distance, max });
+            }
+        }
+
+        if let Some(max) = options.max_offset.or(self.inner.config.max_offset) {
+            if snapshot.clock_offset.abs() > max {
+                return Err(Error::ImplausibleTime {
+                    offset: snapshot.clock_offset.abs(),
+                    max,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Smallest receive buffer, enough for any response without NTS.
+const MIN_RESPONSE_BUFFER: usize = 1024;
+
+/// Most cookies an NTS response carries: one replacing the cookie sent,
+/// plus one per placeholder (RFC 8915, section 5.7).
+const MAX_RESPONSE_COOKIES: usize = 8;
+
+/// Receive buffer size for an NTS response carrying cookies of `cookie_len`
+/// bytes, capped at `max_packet_size`.
+///
+/// The response holds the 48-byte header, a Unique Identifier extension
+/// field and an NTS Authenticator whose ciphertext contains the cookies.
+fn response_buffer_size(cookie_len: usize, max_packet_size: usize) -> usize {
+    const HEADER: usize = 48;
+    const UNIQUE_ID_FIELD: usize = 4 + 32;
+    // Field header, nonce and ciphertext lengths, 16-byte nonce and tag
+    const AUTHENTICATOR_OVERHEAD: usize = 4 + 4 + 16 + 16;
+
+    let cookie_field = 4 + ((cookie_len + 3) & !3);
+    let expected =
+        HEADER + UNIQUE_ID_FIELD + AUTHENTICATOR_OVERHEAD + MAX_RESPONSE_COOKIES * cookie_field;
+    expected.max(MIN_RESPONSE_BUFFER).min(max_packet_size)
+}
+
+/// An [`NtsClient`] that is known to be connected.
+///
+/// Created with [`ConnectedNtsClient::connect`] or
+/// [`NtsClient::into_connected`]. Unlike [`NtsClient`], it has no way to
+/// drop its connection: [`reconnect`](Self::reconnect) keeps the current
+/// keys until a new key exchange succeeds. Clones share state like clones of
+/// [`NtsClient`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::{ConnectedNtsClient, NtsClientConfig};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = ConnectedNtsClient::connect(NtsClientConfig::new("time.cloudflare.com")).await?;
+/// println!("NTP server: {}", client.ntp_server());
+/// let time = client.get_time().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ConnectedNtsClient {
+    client: NtsClient,
+}
+
+impl ConnectedNtsClient {
+    /// Create a client with `config` and perform the key exchange.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`NtsClient::connect`].
+    #[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+    pub async fn connect(config: NtsClientConfig) -> Result<Self> {
+        NtsClient::new(config).into_connected().await
+    }
+
+    /// Query the current time. See [`NtsClient::get_time`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the time query fails.
+    pub async fn get_time(&self) -> Result<TimeSnapshot> {
+        self.client.get_time().await
+    }
+
+    /// Query the current time with per-call options. See
+    /// [`NtsClient::get_time_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the time query fails.
+    pub async fn get_time_with(&self, options: &QueryOptions) -> Result<TimeSnapshot> {
+        self.client.get_time_with(options).await
+    }
+
+    /// Perform a fresh key exchange, e.g. after the server rotated its keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key exchange fails, in which case the
+    /// current connection is kept.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.client.connect().await
+    }
+
+    /// Get the NTP server address being used.
+    pub fn ntp_server(&self) -> SocketAddr {
+        self.connection().nts_state.ntp_server
+    }
+
+    /// Get the NTS key exchange result, for diagnostic purposes.
+    pub fn nts_ke_info(&self) -> Arc<NtsKeResult> {
+        Arc::clone(&self.connection().nts_state)
+    }
+
+    /// Get the NTS-KE server hostname the client is connected through, or
+    /// `None` if it was connected without a key exchange.
+    pub fn bound_server(&self) -> Option<String> {
+        self.client.bound_server()
+    }
+
+    /// Get the number of unused NTS cookies for the NTP server.
+    pub fn cookies_remaining(&self) -> usize {
+        self.client.cookies_remaining()
+    }
+
+    /// Get the duration of each connection and query phase.
+    pub fn timings(&self) -> TimingBreakdown {
+        self.client.timings()
+    }
+
+    /// Get the client configuration.
+    pub fn config(&self) -> &NtsClientConfig {
+        self.client.config()
+    }
+
+    /// Return the underlying dynamically checked client.
+    pub fn into_inner(self) -> NtsClient {
+        self.client
+    }
+
+    fn connection(&self) -> Arc<Connection> {
+        self.client
+            .connection()
+            .expect("a ConnectedNtsClient is never disconnected")
+    }
+}
+
+/// Builder for [`NtsClient`].
+///
+/// Created with [`NtsClient::builder`].
+#[derive(Default)]
+pub struct NtsClientBuilder {
+    config: NtsClientConfig,
+    resolver: Option<Arc<dyn Resolver>>,
+    connector: Option<Arc<dyn Connector>>,
+    clock: Option<Arc<dyn Clock>>,
+    runtime: Option<Arc<dyn Runtime>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    sample_sinks: Vec<Arc<dyn SampleSink>>,
+    event_handlers: Vec<EventHandler>,
+    cookie_store: Option<Arc<dyn CookieStore>>,
+    #[cfg(feature = "pcap")]
+    pcap: Option<Arc<PcapWriter>>,
+}
+
+impl NtsClientBuilder {
+    /// Use the given configuration.
+    pub fn with_config(mut self, config: NtsClientConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the NTS-KE server, optionally with a port as accepted by
+    /// [`NtsClientConfig::new`].
+    pub fn with_server(mut self, server: impl Into<String>) -> Self {
+        self.config = self.config.with_server(server);
+        self
+    }
+
+    /// Use pre-resolved addresses for the NTS-KE server instead of DNS.
+    ///
+    /// The configured hostname is still used for TLS certificate validation.
+    /// See [`NtsClientConfig::with_ke_addr`].
+    pub fn with_resolved_addrs(mut self, addrs: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.config.ke_addrs = addrs.into_iter().collect();
+        self
+    }
+
+    /// Use a custom resolver for the NTS-KE server hostname.
+    pub fn with_resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Open NTS-KE and NTP connections through the given connector instead
+    /// of sockets with the configured options.
+    pub fn with_connector(mut self, connector: impl Connector + 'static) -> Self {
+        self.connector = Some(Arc::new(connector));
+        self
+    }
+
+    /// Read the time from the given clock instead of the system clock.
+    ///
+    /// The clock provides the request and receive timestamps the offset is
+    /// computed from, and the monotonic time used for round trips, pacing,
+    /// the circuit breaker and the blacklist.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Run timers and the blocking key exchange on the given runtime
+    /// instead of Tokio.
+    ///
+    /// To use the client without a Tokio runtime, also register a
+    /// [`Connector`] and a [`Resolver`] built on the other runtime's I/O.
+    pub fn with_runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Some(Arc::new(runtime));
+        self
+    }
+
+    /// Send measurements to the given metrics sink.
+    pub fn with_metrics(mut self, metrics: impl MetricsSink + 'static) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Record every successful sample to the given sink, for example a
+    /// [`CsvSink`](crate::sink::CsvSink). Can be called several times.
+    pub fn with_sample_sink(mut self, sink: impl SampleSink + 'static) -> Self {
+        self.sample_sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Keep NTS cookies in the given store instead of in memory.
+    ///
+    /// Pass an [`Arc`] to share one store between several clients.
+    pub fn with_cookie_store(mut self, store: impl CookieStore + 'static) -> Self {
+        self.cookie_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Record NTP packets and key exchanges to the given pcapng capture.
+    #[cfg(feature = "pcap")]
+    pub fn with_pcap_writer(mut self, writer: PcapWriter) -> Self {
+        self.pcap = Some(Arc::new(writer));
+        self
+    }
+
+    /// Register a handler invoked for every [`ClientEvent`].
+    pub fn on_event(mut self, handler: impl Fn(&ClientEvent<'_>) + Send + Sync + 'static) -> Self {
+        self.event_handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// Register a handler invoked with each successful time sample.
+    pub fn on_resync(self, handler: impl Fn(&TimeSnapshot) + Send + Sync + 'static) -> Self {
+        self.on_event(move |event| {
+            if let ClientEvent::TimeReceived(snapshot) = event {
+                handler(snapshot);
+            }
+        })
+    }
+
+    /// Register a handler invoked with the number of unused cookies when it
+    /// falls under [`COOKIE_LOW_WATERMARK`].
+    pub fn on_cookie_low(self, handler: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_event(move |event| {
+            if let ClientEvent::CookiesLow { remaining } = event {
+                handler(*remaining);
+            }
+        })
+    }
+
+    /// Register a handler invoked with the new NTP server each time a key
+    /// exchange replaces earlier keys.
+    pub fn on_rekey(self, handler: impl Fn(SocketAddr) + Send + Sync + 'static) -> Self {
+        self.on_event(move |event| {
+            if let ClientEvent::Rekeyed { ntp_server } = event {
+                handler(*ntp_server);
+            }
+        })
+    }
+
+    /// Register a handler invoked with the kiss code of each Kiss-o'-Death
+    /// packet.
+    pub fn on_kod(self, handler: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_event(move |event| {
+            if let ClientEvent::KissOfDeath { code } = event {
+                handler(code);
+            }
+        })
+    }
+
+    /// Register a handler invoked with each failed key exchange or time
+    /// query.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::NtsClient;
+    ///
+    /// let client = NtsClient::builder()
+    ///     .with_server("time.cloudflare.com")
+    ///     .on_error(|error| eprintln!("NTS failure: {}", error))
+    ///     .on_kod(|code| eprintln!("Kiss-o'-Death: {}", code))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn on_error(self, handler: impl Fn(&Error) + Send + Sync + 'static) -> Self {
+        self.on_event(move |event| {
+            if let ClientEvent::KeyExchangeFailed(error) | ClientEvent::QueryFailed(error) = event {
+                handler(error);
+            }
+        })
+    }
+
+    /// Validate the configuration and build the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the configuration is invalid, or
+    /// if no runtime, connector or resolver was given and none of the
+    /// `tokio`, `smol` and `async-std` features provides a default.
+    pub fn build(self) -> Result<NtsClient> {
+        self.config.validate()?;
+        self.assemble()
+    }
+
+    /// Build the client without validating the configuration, filling in
+    /// the default resolver, connector, clock and runtime.
+    fn assemble(self) -> Result<NtsClient> {
+        let missing = |what: &str| {
+            Error::InvalidConfig(format!(
+                "No {} given, and no runtime feature provides a default",
+                what
+            ))
+        };
+        let resolver = match self.resolver {
+            Some(resolver) => resolver,
+            None => default_resolver().ok_or_else(|| missing("resolver"))?,
+        };
+        let connector = match self.connector {
+            Some(connector) => connector,
+            None => default_connector(&self.config).ok_or_else(|| missing("connector"))?,
+        };
+        let runtime = match self.runtime {
+            Some(runtime) => runtime,
+            None => default_runtime().ok_or_else(|| missing("runtime"))?,
+        };
+        Ok(NtsClient::from_inner(ClientInner {
+            connection: RwLock::new(None),
+            connecting: futures_util::lock::Mutex::new(()),
+            resolver,
+            connector,
+            clock: self
+                .clock
+                .unwrap_or_else(|| Arc::new(SystemClock) as Arc<dyn Clock>),
+            runtime,
+            metrics: self.metrics,
+            sample_sinks: self.sample_sinks,
+            event_handlers: self.event_handlers,
+            pacing: Mutex::default(),
+            timings: Mutex::default(),
+            server_stats: Mutex::default(),
+            history: Mutex::default(),
+            last_snapshot: Mutex::default(),
+            last_packets: Mutex::default(),
+            #[cfg(feature = "pcap")]
+            pcap: self.pcap,
+            tls_sessions: TlsSessions::default(),
+            cookie_store: self
+                .cookie_store
+                .unwrap_or_else(|| Arc::new(MemoryCookieStore::new()) as Arc<dyn CookieStore>),
+            ke_rotation: AtomicUsize::new(0),
+            keyed: AtomicBool::new(false),
+            consecutive_failures: AtomicU32::new(0),
+            rekey_needed: AtomicBool::new(false),
+            blacklist: Mutex::new(Blacklist::new(self.config.blacklist)),
+            circuit: Mutex::new(CircuitBreaker::new(self.config.circuit_breaker)),
+            config: self.config,
+        }))
+    }
+}
+
+/// The resolver used when the builder is given none: Tokio's with the
+/// `tokio` feature, otherwise a lookup on the `blocking` thread pool.
+#[allow(unreachable_code)]
+fn default_resolver() -> Option<Arc<dyn Resolver>> {
+    #[cfg(feature = "tokio")]
+    return Some(Arc::new(crate::resolver::SystemResolver));
+    #[cfg(any(feature = "smol", feature = "async-std"))]
+    return Some(Arc::new(crate::resolver::BlockingResolver));
+    None
+}
+
+/// The connector used when the builder is given none: sockets driven by
+/// Tokio with the `tokio` feature, otherwise by async-io.
+#[allow(unreachable_code)]
+fn default_connector(config: &NtsClientConfig) -> Option<Arc<dyn Connector>> {
+    #[cfg(any(feature = "tokio", target_os = "wasi"))]
+    return Some(Arc::new(crate::transport::SocketConnector::new(config)));
+    #[cfg(any(feature = "smol", feature = "async-std"))]
+    return Some(Arc::new(crate::transport::AsyncIoConnector::new(config)));
+    let _ = config;
+    None
+}
+
+/// The runtime used when the builder is given none, by priority: Tokio,
+/// smol, async-std.
+#[allow(unreachable_code)]
+fn default_runtime() -> Option<Arc<dyn Runtime>> {
+    #[cfg(feature = "tokio")]
+    return Some(Arc::new(crate::runtime::TokioRuntime));
+    #[cfg(feature = "smol")]
+    return Some(Arc::new(crate::runtime::SmolRuntime));
+    #[cfg(feature = "async-std")]
+    return Some(Arc::new(crate::runtime::AsyncStdRuntime));
+    None
+}
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        debug!("NtsClient dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cookies::Association;
+    use crate::error::ErrorKind;
+    use crate::ntp_packet::encode_ntp_timestamp;
+    use crate::test_util::SimulatedClock;
+    use crate::transport::{KeTransport, TransportFuture, UdpTransport};
+    use crate::types::SignedDuration;
+    use std::time::UNIX_EPOCH;
+    use tokio::net::UdpSocket;
+
+    fn test_client(version: u8) -> NtsClient {
+        NtsClient::new(NtsClientConfig::new("test.server.com").with_ntp_version(version))
+    }
+
+    fn test_server() -> SocketAddr {
+        "127.0.0.1:123".parse().unwrap()
+    }
+
+    fn test_query(t1: SystemTime) -> NtpQuery {
+        NtpQuery::new(test_server(), 4, t1, None).unwrap()
+    }
+
+    /// Build an NTPv4 server response answering a request sent at `t1`.
+    fn test_response(t1: SystemTime, t2: SystemTime, t3: SystemTime) -> Vec<u8> {
+        let mut response = vec![0u8; 48];
+        response[0] = 0x24; // VN = 4, mode = 4 (server)
+        response[1] = 2; // stratum
+        response[24..32].copy_from_slice(&encode_ntp_timestamp(t1).unwrap());
+        response[32..40].copy_from_slice(&encode_ntp_timestamp(t2).unwrap());
+        response[40..48].copy_from_slice(&encode_ntp_timestamp(t3).unwrap());
+        response
+    }
+
+    /// The keys the test clients are connected with.
+    fn test_keys() -> NtsKeys {
+        NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap()
+    }
+
+    /// Wrap the server response `header` in an NTS response to `request`,
+    /// authenticated with the [`test_keys`].
+    fn nts_answer(request: &[u8], header: &[u8]) -> Vec<u8> {
+        crate::test_util::nts_response(request, header, &test_keys()).unwrap()
+    }
+
+    /// Spawn a server answering each request like a server whose clock
+    /// matches ours.
+    async fn spawn_echo_server() -> SocketAddr {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            while let Ok((len, peer)) = server.recv_from(&mut buf).await {
+                let now = SystemTime::now();
+                let mut response = test_response(now, now, now);
+                response[24..32].copy_from_slice(&buf[40..48]);
+                let response = nts_answer(&buf[..len], &response);
+                let _ = server.send_to(&response, peer).await;
+            }
+        });
+        server_addr
+    }
+
+    #[test]
+    fn test_builder_validates_config() {
+        assert!(NtsClient::builder().build().is_err());
+        assert!(NtsClient::builder()
+            .with_config(NtsClientConfig::new("test.server.com").with_ntp_version(2))
+            .build()
+            .is_err());
+
+        let client = NtsClient::builder()
+            .with_server("test.server.com")
+            .with_resolved_addrs([test_server()])
+            .build()
+            .unwrap();
+        assert_eq!(client.inner.config.ke_addrs, vec![test_server()]);
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_connect_with_keys() {
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = test_client(4);
+
+        let result = client
+            .connect_with_keys(test_server(), keys.clone(), Vec::new())
+            .await;
+        assert!(
+            matches!(result, Err(Error::CookieExhausted { server }) if server == test_server())
+        );
+        assert!(!client.is_connected());
+
+        client
+            .connect_with_keys(test_server(), keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+        assert!(client.is_connected());
+        let state = client.nts_ke_info().unwrap();
+        assert_eq!(state.ntp_server, test_server());
+        assert_eq!(state.aead_algorithm, "AEAD_AES_SIV_CMAC_256");
+        assert_eq!(state.cookie_count(), 1);
+        assert_eq!(client.cookies_remaining(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_tries_fallback_servers() {
+        use crate::resolver::ResolveFuture;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct FailingResolver(Mutex<Vec<String>>);
+
+        impl Resolver for Arc<FailingResolver> {
+            fn resolve<'a>(&'a self, host: &'a str, _port: u16) -> ResolveFuture<'a> {
+                self.0.lock().unwrap().push(host.to_string());
+                Box::pin(async { Err(Error::ServerUnavailable("no DNS".to_string())) })
+            }
+        }
+
+        let resolver = Arc::new(FailingResolver::default());
+        let failures = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&failures);
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("primary.example")
+                    .with_max_retries(0)
+                    .with_fallback_servers(["a.example", "b.example"]),
+            )
+            .with_resolver(Arc::clone(&resolver))
+            .on_event(move |event| {
+                if let ClientEvent::KeyExchangeFailed(_) = event {
+                    *counter.lock().unwrap() += 1;
+                }
+            })
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            client.connect().await,
+            Err(Error::ServerUnavailable(_))
+        ));
+        assert_eq!(
+            *resolver.0.lock().unwrap(),
+            ["primary.example", "a.example", "b.example"]
+        );
+        assert_eq!(*failures.lock().unwrap(), 3);
+        assert!(client.bound_server().is_none());
+        // Every attempt starts with the next address
+        assert_eq!(client.inner.ke_rotation.load(Ordering::Relaxed), 3);
+
+        // Blacklisted servers are skipped
+        resolver.0.lock().unwrap().clear();
+        lock(&client.inner.blacklist).ban("primary.example", Instant::now());
+        assert!(client.connect().await.is_err());
+        assert_eq!(*resolver.0.lock().unwrap(), ["a.example", "b.example"]);
+        assert_eq!(client.blacklist()[0].server, "primary.example");
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast() {
+        use crate::circuit::CircuitBreakerPolicy;
+
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com")
+                .with_max_retries(0)
+                .with_circuit_breaker(CircuitBreakerPolicy {
+                    failure_threshold: 2,
+                    cool_down: Duration::from_secs(60),
+                }),
+        );
+
+        // Not connected, so every query fails
+        assert!(matches!(client.get_time().await, Err(Error::NotConnected)));
+        assert!(matches!(client.get_time().await, Err(Error::NotConnected)));
+        assert!(matches!(client.circuit_state(), CircuitState::Open { .. }));
+        assert!(matches!(
+            client.get_time().await,
+            Err(Error::CircuitOpen { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        use crate::test_util::{MockBehavior, MockNtpResponse, MockServer};
+
+        let server = MockServer::start().unwrap();
+        let client = NtsClient::new(server.client_config().with_max_retries(0));
+        let health = client.health();
+        assert!(!health.connected && !health.rekey_overdue);
+        assert_eq!(health.last_sync_age, None);
+
+        client.connect().await.unwrap();
+        client.get_time().await.unwrap();
+        let health = client.health();
+        assert!(health.is_healthy(Duration::from_secs(60)));
+        assert!(health.last_sync_age.unwrap() < Duration::from_secs(1));
+        assert!(health.cookies_remaining > 0);
+
+        server.set_behavior(MockBehavior::new().with_ntp_response(MockNtpResponse::NtsNak));
+        assert!(client.get_time().await.is_err());
+        assert!(client.get_time().await.is_err());
+        let health = client.health();
+        assert_eq!(health.consecutive_failures, 2);
+        assert!(health.rekey_overdue);
+        assert!(health.last_sync_age.is_some());
+
+        server.set_behavior(MockBehavior::new());
+        client.reconnect().await.unwrap();
+        assert!(!client.health().rekey_overdue);
+        client.get_time().await.unwrap();
+        assert_eq!(client.health().consecutive_failures, 0);
+
+        // Forged responses do not call for a new key exchange by
+        // themselves, but each spends a cookie
+        server.set_behavior(MockBehavior::new().with_ntp_response(MockNtpResponse::BadMac));
+        let cookies = client.cookies_remaining();
+        for remaining in (0..cookies).rev() {
+            assert!(!client.health().rekey_overdue);
+            let err = client.get_time().await.unwrap_err();
+            assert!(matches!(err, Error::AuthenticationFailed(_)), "{:?}", err);
+            assert_eq!(client.health().cookies_remaining, remaining);
+        }
+        assert!(client.health().rekey_overdue);
+    }
+
+    #[tokio::test]
+    async fn test_auto_connect_on_first_query() {
+        let config = NtsClientConfig::new("test.server.com")
+            .with_ke_addr("127.0.0.1:9".parse().unwrap())
+            .with_max_retries(0);
+
+        // Without auto-connect the query fails without a key exchange
+        let client = NtsClient::new(config.clone());
+        assert!(matches!(client.get_time().await, Err(Error::NotConnected)));
+        assert_eq!(client.inner.ke_rotation.load(Ordering::Relaxed), 0);
+
+        // With it, the key exchange is attempted and its error returned
+        let client = NtsClient::new(config.with_auto_connect(true));
+        let result = client.get_time().await;
+        assert!(result.is_err() && !matches!(result, Err(Error::NotConnected)));
+        assert_eq!(client.inner.ke_rotation.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_total_deadline_covers_auto_connect() {
+        use crate::test_util::{MockBehavior, MockServer};
+
+        // A key exchange that hangs
+        let server =
+            MockServer::start_with(MockBehavior::new().with_ke_delay(Duration::from_secs(5)))
+                .unwrap();
+        let config = |server: &MockServer| {
+            server
+                .client_config()
+                .with_timeout(Duration::from_secs(10))
+                .with_max_retries(0)
+                .with_auto_connect(true)
+                .with_total_deadline(Duration::from_secs(1))
+        };
+        let client = NtsClient::new(config(&server));
+        let start = Instant::now();
+        assert!(matches!(client.get_time().await, Err(Error::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(3));
+
+        // A slow key exchange leaves the query only the rest of the budget
+        let server = MockServer::start_with(
+            MockBehavior::new()
+                .with_ke_delay(Duration::from_millis(600))
+                .with_ntp_delay(Duration::from_millis(600)),
+        )
+        .unwrap();
+        let client = NtsClient::new(config(&server));
+        assert!(matches!(client.get_time().await, Err(Error::Timeout)));
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_total_deadline_bounds_retries() {
+        use crate::retry::FixedBackoff;
+
+        // Each attempt fails quickly, but the backoff exceeds the budget
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com")
+                .with_ke_addr("127.0.0.1:9".parse().unwrap())
+                .with_retry_policy(FixedBackoff {
+                    max_retries: 10,
+                    delay: Duration::from_secs(10),
+                })
+                .with_total_deadline(Duration::from_millis(200)),
+        );
+
+        let start = Instant::now();
+        assert!(matches!(client.connect().await, Err(Error::Timeout)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_pacing_spaces_reserved_queries() {
+        let now = Instant::now();
+        let mut pacing = Pacing::default();
+        assert_eq!(pacing.reserve(now), Duration::ZERO);
+        assert_eq!(pacing.reserve(now), Duration::ZERO);
+
+        pacing.rate_limit.record_rate_kiss();
+        let interval = pacing.rate_limit.min_interval;
+        assert_eq!(pacing.reserve(now), interval);
+        assert_eq!(pacing.reserve(now), interval * 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_queries_share_client() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NtsClient>();
+
+        let server_addr = spawn_echo_server().await;
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = test_client(4);
+        client
+            .clone()
+            .connect_with_keys(server_addr, keys, vec![vec![0xAB; 64]; 8])
+            .await
+            .unwrap();
+        assert!(client.is_connected());
+
+        assert!(client.server_stats().is_empty());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.get_time().await })
+            })
+            .collect();
+        for handle in handles {
+            let snapshot = handle.await.unwrap().unwrap();
+            assert_eq!(snapshot.server, server_addr.to_string());
+        }
+        assert!(client.timings().ntp_round_trip.is_some());
+    }
+
+    #[test]
+    fn test_response_buffer_size() {
+        assert_eq!(response_buffer_size(0, 2048), MIN_RESPONSE_BUFFER);
+        assert_eq!(response_buffer_size(100, 2048), MIN_RESPONSE_BUFFER);
+        assert_eq!(response_buffer_size(120, 2048), 48 + 36 + 40 + 8 * 124);
+        assert_eq!(response_buffer_size(121, 2048), 48 + 36 + 40 + 8 * 128);
+        assert_eq!(response_buffer_size(400, 2048), 2048);
+        assert_eq!(response_buffer_size(0, 512), 512);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_response() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            while let Ok((_, peer)) = server.recv_from(&mut buf).await {
+                let now = SystemTime::now();
+                let mut response = test_response(now, now, now);
+                response[24..32].copy_from_slice(&buf[40..48]);
+                response.resize(1500, 0);
+                let _ = server.send_to(&response, peer).await;
+            }
+        });
+
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com")
+                .with_max_packet_size(1024)
+                .with_max_retries(0)
+                .with_packet_capture(true),
+        );
+        assert!(client.last_packets().is_none());
+        client
+            .connect_with_keys(server_addr, keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+
+        let err = client.get_time().await.unwrap_err();
+        assert!(
+            matches!(err, Error::ResponseTruncated { size: 1024 }),
+            "{:?}",
+            err
+        );
+
+        let packets = client.last_packets().unwrap();
+        assert_eq!(packets.server, server_addr);
+        // Header, Unique Identifier, cookie, 7 placeholders, authenticator
+        assert_eq!(packets.request.len(), 48 + 36 + 8 * 68 + 40);
+        assert_eq!(packets.response.unwrap()[24..32], packets.request[40..48]);
+    }
+
+    #[tokio::test]
+    async fn test_get_time_debug() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            while let Ok((len, peer)) = server.recv_from(&mut buf).await {
+                let now = SystemTime::now();
+                let mut response = test_response(now, now, now);
+                response[24..32].copy_from_slice(&buf[40..48]);
+                let response = nts_answer(&buf[..len], &response);
+                let _ = server.send_to(&response, peer).await;
+            }
+        });
+
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = test_client(4);
+        client
+            .connect_with_keys(server_addr, keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+
+        let debug = client.get_time_debug().await.unwrap();
+        assert_eq!(debug.snapshot.server, server_addr.to_string());
+        assert!(debug.snapshot.authenticated);
+        assert!(client.last_packets().is_none());
+        assert!(matches!(
+            debug.extension_fields[..],
+            [
+                crate::ExtensionField::UniqueIdentifier { ref id },
+                crate::ExtensionField::NtsAuthenticator { .. },
+            ] if id.len() == 32
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_time_filtered() {
+        let client = NtsClient::new(NtsClientConfig::new("test.server.com").with_stats_window(3));
+        assert!(matches!(
+            client.get_time_filtered(0).await,
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            client.get_time_filtered(3).await,
+            Err(Error::NotConnected)
+        ));
+
+        let server_addr = spawn_echo_server().await;
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        client
+            .connect_with_keys(server_addr, keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+
+        let filtered = client.get_time_filtered(4).await.unwrap();
+        assert_eq!(filtered.samples.len(), 4);
+        assert_eq!(filtered.failures, 0);
+        let min_delay = filtered.samples.iter().map(|s| s.round_trip_delay).min();
+        assert_eq!(Some(filtered.chosen.round_trip_delay), min_delay);
+
+        let stats = client.server_stats();
+        assert_eq!(stats[&server_addr.to_string()].offset.len(), 3);
+        assert!(client.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_recent_samples() {
+        let client = NtsClient::new(NtsClientConfig::new("test.server.com").with_history(2));
+        let report = client.diagnostics_report();
+        assert!(!report.connected && report.key_exchange.is_none() && !report.cookies.low);
+
+        let server_addr = spawn_echo_server().await;
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        client
+            .connect_with_keys(server_addr, keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+
+        let mut taken = Vec::new();
+        for _ in 0..3 {
+            taken.push(client.get_time().await.unwrap().system_time);
+        }
+        let history: Vec<_> = client.history().iter().map(|s| s.system_time).collect();
+        assert_eq!(history, taken[1..]);
+
+        let report = client.diagnostics_report();
+        assert!(report.connected);
+        assert!(report.tls.is_none());
+        let key_exchange = report.key_exchange.unwrap();
+        assert_eq!(key_exchange.ntp_server, server_addr.to_string());
+        assert_eq!(key_exchange.redirect, "not_redirected");
+        assert_eq!(report.cookies.initial_sizes, vec![64]);
+        assert!(report.last_snapshot.is_some());
+        assert_eq!(client.last_snapshot().unwrap().system_time, taken[2]);
+    }
+
+    #[tokio::test]
+    async fn test_responses_routed_to_pending_query() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let connection = Connection {
+            transport: Box::new(UdpTransport::new(socket)),
+            pending: Mutex::default(),
+            nts_state: Arc::new(NtsKeResult::from_fixed_keys(test_server(), keys, vec![])),
+            bound_server: None,
+        };
+
+        let now = SystemTime::now();
+        let query = test_query(now);
+        let response = test_response(now, now, now);
+        let (sender, mut receiver) = oneshot::channel();
+        {
+            let _registration = connection.register(query.transmit(), sender);
+            assert!(connection.route(&response, now));
+            assert_eq!(receiver.try_recv().unwrap().unwrap().0, response);
+        }
+
+        // Unknown or expired queries are not routed
+        let (sender, _receiver) = oneshot::channel();
+        drop(connection.register(query.transmit(), sender));
+        assert!(!connection.route(&response, now));
+        assert!(!connection.route(&response[..20], now));
+    }
+
+    #[tokio::test]
+    async fn test_event_hooks() {
+        // Answer the first request with a Kiss-o'-Death DENY, the others normally
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let mut first = true;
+            while let Ok((len, peer)) = server.recv_from(&mut buf).await {
+                let now = SystemTime::now();
+                let mut response = test_response(now, now, now);
+                response[24..32].copy_from_slice(&buf[40..48]);
+                if std::mem::take(&mut first) {
+                    response[1] = 0;
+                    response[12..16].copy_from_slice(b"DENY");
+                }
+                let response = nts_answer(&buf[..len], &response);
+                let _ = server.send_to(&response, peer).await;
+            }
+        });
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (a, b, c, d, e) = (
+            Arc::clone(&log),
+            Arc::clone(&log),
+            Arc::clone(&log),
+            Arc::clone(&log),
+            Arc::clone(&log),
+        );
+        let client = NtsClient::builder()
+            .with_config(NtsClientConfig::new("test.server.com"))
+            .on_resync(move |_| a.lock().unwrap().push("resync".to_string()))
+            .on_cookie_low(move |n| b.lock().unwrap().push(format!("cookie_low {}", n)))
+            .on_rekey(move |_| c.lock().unwrap().push("rekey".to_string()))
+            .on_kod(move |code| d.lock().unwrap().push(format!("kod {}", code)))
+            .on_error(move |_| e.lock().unwrap().push("error".to_string()))
+            .build()
+            .unwrap();
+
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        for _ in 0..2 {
+            client
+                .connect_with_keys(server_addr, keys.clone(), vec![vec![0xAB; 64]; 2])
+                .await
+                .unwrap();
+        }
+        assert!(client.get_time().await.is_err());
+        client.get_time().await.unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "rekey",
+                "cookie_low 1",
+                "kod DENY",
+                "error",
+                "cookie_low 0",
+                "resync"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connected_client_keeps_connection() {
+        let config = NtsClientConfig::new("test.server.com")
+            .with_ke_addr("127.0.0.1:9".parse().unwrap())
+            .with_max_retries(0);
+        assert!(ConnectedNtsClient::connect(config.clone()).await.is_err());
+
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = NtsClient::new(config);
+        client
+            .connect_with_keys(test_server(), keys, vec![vec![0xAB; 64]])
+            .await
+            .unwrap();
+        let client = client.into_connected().await.unwrap();
+        assert_eq!(client.ntp_server(), test_server());
+
+        // A failed reconnect keeps the previous keys
+        assert!(client.reconnect().await.is_err());
+        assert_eq!(client.ntp_server(), test_server());
+        assert_eq!(client.cookies_remaining(), 1);
+
+        // Also when a clone sharing the connection reconnects
+        let shared = client.clone().into_inner();
+        assert!(shared.reconnect().await.is_err());
+        assert!(shared.is_connected());
+        assert_eq!(client.ntp_server(), test_server());
+        assert_eq!(client.nts_ke_info().ntp_server, test_server());
+    }
+
+    #[tokio::test]
+    async fn test_shared_cookie_store() {
+        // Two clients share a store and an NTP server, each with its own keys
+        let store = Arc::new(MemoryCookieStore::new());
+        let client = || {
+            NtsClient::builder()
+                .with_server("test.server.com")
+                .with_cookie_store(Arc::clone(&store))
+                .build()
+                .unwrap()
+        };
+        let keys = |key: u8| {
+            NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![key; 32], vec![key; 32]).unwrap()
+        };
+        let (first, second) = (client(), client());
+        first
+            .connect_with_keys(test_server(), keys(0), vec![vec![0xAB; 64], vec![0xAC; 64]])
+            .await
+            .unwrap();
+        second
+            .connect_with_keys(test_server(), keys(1), vec![vec![0xCD; 64]])
+            .await
+            .unwrap();
+        assert_eq!(first.cookies_remaining(), 2);
+        assert_eq!(second.cookies_remaining(), 1);
+
+        let association = first.nts_ke_info().unwrap().association();
+        assert_eq!(store.take(association), Some(vec![0xAB; 64]));
+        assert_eq!(first.cookies_remaining(), 1);
+
+        // A new connection drops only the client's own cookies
+        first
+            .connect_with_keys(test_server(), keys(2), vec![vec![0xEF; 64]])
+            .await
+            .unwrap();
+        assert!(store.is_empty(association));
+        assert_eq!(first.cookies_remaining(), 1);
+        let association = second.nts_ke_info().unwrap().association();
+        assert_eq!(store.take(association), Some(vec![0xCD; 64]));
+    }
+
+    #[test]
+    fn test_request_encodes_configured_version() {
+        let query = test_client(3).create_ntp_request(test_server()).unwrap();
+        assert_eq!(query.request()[0], 0x1B); // 0b00_011_011
+
+        let query = test_client(4).create_ntp_request(test_server()).unwrap();
+        assert_eq!(query.request()[0], 0x23); // 0b00_100_011
+    }
+
+    #[test]
+    fn test_transmit_nonce() {
+        let client = test_client(4);
+        let first = client.create_ntp_request(test_server()).unwrap();
+        let second = client.create_ntp_request(test_server()).unwrap();
+        assert_eq!(&first.request()[40..48], &first.transmit()[..]);
+        assert_ne!(first.transmit(), second.transmit());
+
+        let client =
+            NtsClient::new(NtsClientConfig::new("test.server.com").with_transmit_nonce(false));
+        let query = client.create_ntp_request(test_server()).unwrap();
+        assert_eq!(query.transmit(), encode_ntp_timestamp(query.t1()).unwrap());
+    }
+
+    #[test]
+    fn test_root_distance_threshold() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let t4 = base + Duration::from_millis(100);
+        let mut response = test_response(base, base, base);
+        response[4..8].copy_from_slice(&0x0000_8000u32.to_be_bytes()); // 0.5 s root delay
+        response[8..12].copy_from_slice(&0x0000_4000u32.to_be_bytes()); // 0.25 s root dispersion
+
+        // 0.25 + 0.25 + 0.05 = 0.55 s
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com").with_max_root_distance(Duration::from_secs(1)),
+        );
+        let (snapshot, _) = client
+            .parse_ntp_response(&response, &test_query(base), t4)
+            .unwrap();
+        assert_eq!(snapshot.root_distance(), Duration::from_millis(550));
+        assert!(client
+            .check_limits(&snapshot, &QueryOptions::default())
+            .is_ok());
+
+        let strict = QueryOptions::new().with_max_root_distance(Duration::from_millis(500));
+        let result = client.check_limits(&snapshot, &strict);
+        assert!(matches!(result, Err(Error::RootDistanceExceeded { .. })));
+
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com")
+                .with_max_root_distance(Duration::from_millis(500)),
+        );
+        let result = client.check_limits(&snapshot, &QueryOptions::default());
+        assert!(matches!(result, Err(Error::RootDistanceExceeded { .. })));
+    }
+
+    #[test]
+    fn test_implausible_offset_rejected() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let server_time = base + Duration::from_secs(3 * 3600);
+        let response = test_response(base, server_time, server_time);
+
+        let client = NtsClient::new(
+            NtsClientConfig::new("test.server.com").with_max_offset(Duration::from_secs(3600)),
+        );
+        let (snapshot, _) = client
+            .parse_ntp_response(&response, &test_query(base), base)
+            .unwrap();
+        let result = client.check_limits(&snapshot, &QueryOptions::default());
+        assert!(matches!(result, Err(Error::ImplausibleTime { .. })));
+
+        let client = test_client(4);
+        assert!(client
+            .check_limits(&snapshot, &QueryOptions::default())
+            .is_ok());
+        let lenient = QueryOptions::new().with_max_offset(Duration::from_secs(4 * 3600));
+        assert!(client.check_limits(&snapshot, &lenient).is_ok());
+    }
+
+    /// Decides which datagrams answer the `n`th request sent.
+    type Script = dyn Fn(usize, &[u8]) -> Vec<Vec<u8>> + Send + Sync;
+
+    /// An in-memory NTP transport delivering the datagrams chosen by a
+    /// script, to simulate loss, corruption and reordering.
+    struct ScriptedTransport {
+        script: Arc<Script>,
+        sent: AtomicUsize,
+        outbox: tokio::sync::mpsc::UnboundedSender<Vec<u8>>,
+        inbox: tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>>,
+    }
+
+    impl NtpTransport for ScriptedTransport {
+        fn send<'a>(&'a self, packet: &'a [u8]) -> TransportFuture<'a, ()> {
+            let n = self.sent.fetch_add(1, Ordering::Relaxed);
+            for datagram in (self.script)(n, packet) {
+                let _ = self.outbox.send(datagram);
+            }
+            Box::pin(async { Ok(()) })
+        }
+
+        fn recv<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SystemTime)> {
+            Box::pin(async move {
+                let datagram = self.inbox.lock().await.recv().await.unwrap();
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                Ok((len, SystemTime::now()))
+            })
+        }
+
+        fn local_addr(&self) -> std::io::Result<SocketAddr> {
+            Ok("127.0.0.1:40000".parse().unwrap())
+        }
+    }
+
+    /// Connects NTP queries to a [`ScriptedTransport`], and key exchanges
+    /// to a stream replaying `ke_response`.
+    struct ScriptedConnector {
+        script: Arc<Script>,
+        ke_response: Vec<u8>,
+    }
+
+    impl ScriptedConnector {
+        fn new(script: impl Fn(usize, &[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static) -> Self {
+            Self {
+                script: Arc::new(script),
+                ke_response: Vec::new(),
+            }
+        }
+    }
+
+    impl Connector for ScriptedConnector {
+        fn connect_ke<'a>(
+            &'a self,
+            _server: SocketAddr,
+        ) -> TransportFuture<'a, Box<dyn KeTransport>> {
+            let stream = ReplayStream(std::io::Cursor::new(self.ke_response.clone()));
+            Box::pin(async move { Ok(Box::new(stream) as Box<dyn KeTransport>) })
+        }
+
+        fn connect_ntp<'a>(
+            &'a self,
+            _server: SocketAddr,
+        ) -> TransportFuture<'a, Box<dyn NtpTransport>> {
+            let (outbox, inbox) = tokio::sync::mpsc::unbounded_channel();
+            let transport = ScriptedTransport {
+                script: self.script.clone(),
+                sent: AtomicUsize::new(0),
+                outbox,
+                inbox: tokio::sync::Mutex::new(inbox),
+            };
+            Box::pin(async move { Ok(Box::new(transport) as Box<dyn NtpTransport>) })
+        }
+    }
+
+    /// A stream that discards writes and replays fixed bytes.
+    struct ReplayStream(std::io::Cursor<Vec<u8>>);
+
+    impl std::io::Read for ReplayStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl std::io::Write for ReplayStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A response to `request` from a server whose clock matches ours.
+    fn answer(request: &[u8]) -> Vec<u8> {
+        answer_at(request, SystemTime::now())
+    }
+
+    /// A response to `request` received and sent at `server_time`.
+    fn answer_at(request: &[u8], server_time: SystemTime) -> Vec<u8> {
+        let mut response = test_response(server_time, server_time, server_time);
+        response[24..32].copy_from_slice(&request[40..48]);
+        nts_answer(request, &response)
+    }
+
+    async fn scripted_client(connector: ScriptedConnector, config: NtsClientConfig) -> NtsClient {
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = NtsClient::builder()
+            .with_config(config)
+            .with_connector(connector)
+            .build()
+            .unwrap();
+        client
+            .connect_with_keys(test_server(), keys, vec![vec![0xAB; 64]; 8])
+            .await
+            .unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_lost_and_late_responses() {
+        // The first response is lost and arrives late, just before the
+        // response to the retry
+        let late = Arc::new(Mutex::new(None));
+        let connector = ScriptedConnector::new(move |n, request| {
+            let mut late = lock(&late);
+            match n {
+                0 => {
+                    *late = Some(answer(request));
+                    vec![]
+                }
+                _ => late.take().into_iter().chain([answer(request)]).collect(),
+            }
+        });
+        let config = NtsClientConfig::new("test.server.com")
+            .with_query_timeout(Duration::from_millis(50))
+            .with_max_retries(1);
+        let client = scripted_client(connector, config).await;
+
+        client.get_time().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reordered_responses() {
+        // Responses to concurrent queries arrive in reverse order
+        let held = Arc::new(Mutex::new(None));
+        let connector = ScriptedConnector::new(move |n, request| {
+            let mut held = lock(&held);
+            if n % 2 == 0 {
+                *held = Some(answer(request));
+                vec![]
+            } else {
+                [answer(request)].into_iter().chain(held.take()).collect()
+            }
+        });
+        let client = scripted_client(connector, NtsClientConfig::new("test.server.com")).await;
+
+        let (first, second) = tokio::join!(client.get_time(), client.get_time());
+        first.unwrap();
+        second.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_responses() {
+        let connector = ScriptedConnector::new(|n, request| {
+            let now = SystemTime::now();
+            let mut response = test_response(now, now, now);
+            response[24..32].copy_from_slice(&request[40..48]);
+            match n {
+                0 => response[0] = 0x23, // mode 3 (client)
+                _ => response[24] ^= 0xff,
+            }
+            vec![nts_answer(request, &response)]
+        });
+        let config = NtsClientConfig::new("test.server.com")
+            .with_query_timeout(Duration::from_millis(50))
+            .with_max_retries(0);
+        let client = scripted_client(connector, config).await;
+
+        assert!(matches!(
+            client.get_time().await,
+            Err(Error::InvalidMode(3))
+        ));
+        // A corrupted origin timestamp does not match the query
+        assert!(matches!(client.get_time().await, Err(Error::Timeout)));
+    }
+
+    /// A cookie store counting the cookies taken from it.
+    #[derive(Default)]
+    struct CountingStore {
+        cookies: MemoryCookieStore,
+        taken: AtomicUsize,
+    }
+
+    impl CookieStore for CountingStore {
+        fn put(&self, association: Association, cookies: Vec<Vec<u8>>) {
+            self.cookies.put(association, cookies)
+        }
+
+        fn take(&self, association: Association) -> Option<Vec<u8>> {
+            self.taken.fetch_add(1, Ordering::Relaxed);
+            self.cookies.take(association)
+        }
+
+        fn snapshot(&self, association: Association) -> Vec<Vec<u8>> {
+            self.cookies.snapshot(association)
+        }
+
+        fn len(&self, association: Association) -> usize {
+            self.cookies.len(association)
+        }
+
+        fn clear(&self, association: Association) {
+            self.cookies.clear(association)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queries_consume_cookies() {
+        // The first request is lost, the second is answered with a cookie
+        // per cookie and placeholder sent
+        let placeholders = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&placeholders);
+        let connector = ScriptedConnector::new(move |n, request| {
+            let fields = crate::extension::decode_fields(&request[48..], true).unwrap();
+            let count = fields
+                .iter()
+                .filter(|(_, field)| {
+                    matches!(field, crate::ExtensionField::NtsCookiePlaceholder { .. })
+                })
+                .count();
+            lock(&sent).push(count);
+            match n {
+                0 => vec![],
+                _ => vec![answer(request)],
+            }
+        });
+        let store = Arc::new(CountingStore::default());
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("test.server.com")
+                    .with_query_timeout(Duration::from_millis(50))
+                    .with_max_retries(0),
+            )
+            .with_connector(connector)
+            .with_cookie_store(Arc::clone(&store))
+            .build()
+            .unwrap();
+        client
+            .connect_with_keys(test_server(), test_keys(), vec![vec![0xAB; 64]; 3])
+            .await
+            .unwrap();
+
+        assert!(matches!(client.get_time().await, Err(Error::Timeout)));
+        assert_eq!(store.taken.load(Ordering::Relaxed), 1);
+        assert_eq!(client.cookies_remaining(), 2);
+
+        client.get_time().await.unwrap();
+        assert_eq!(store.taken.load(Ordering::Relaxed), 2);
+        assert_eq!(client.cookies_remaining(), MAX_RESPONSE_COOKIES);
+        assert_eq!(*lock(&placeholders), [5, 6]);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_save_state_keeps_unused_cookies() {
+        let connector = ScriptedConnector::new(|_, request| vec![answer(request)]);
+        let client = NtsClient::builder()
+            .with_server("test.server.com")
+            .with_connector(connector)
+            .build()
+            .unwrap();
+        let cookies = vec![vec![0xA1; 64], vec![0xA2; 64], vec![0xA3; 64]];
+        client
+            .connect_with_keys(test_server(), test_keys(), cookies)
+            .await
+            .unwrap();
+        client.get_time().await.unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("rkik-nts-cookies-{}.json", std::process::id()));
+        client.save_state(&path).unwrap();
+        let restored = NtsClient::new(NtsClientConfig::new("test.server.com"));
+        restored.restore_state(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The spent cookie is gone, the cookies of the response were added
+        let restored = restored.nts_ke_info().unwrap();
+        assert_eq!(restored.cookies.len(), MAX_RESPONSE_COOKIES);
+        assert_eq!(restored.cookies[..2], [vec![0xA2; 64], vec![0xA3; 64]]);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_low_on_consumption() {
+        // Lost requests spend their cookie without getting new ones
+        let connector = ScriptedConnector::new(|_, _| vec![]);
+        let low = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&low);
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("test.server.com")
+                    .with_query_timeout(Duration::from_millis(20))
+                    .with_max_retries(0),
+            )
+            .with_connector(connector)
+            .on_cookie_low(move |remaining| lock(&seen).push(remaining))
+            .build()
+            .unwrap();
+        client
+            .connect_with_keys(test_server(), test_keys(), vec![vec![0xAB; 64]; 3])
+            .await
+            .unwrap();
+
+        for remaining in (0..3).rev() {
+            assert!(matches!(client.get_time().await, Err(Error::Timeout)));
+            assert_eq!(client.cookies_remaining(), remaining);
+        }
+        assert_eq!(*lock(&low), [1, 0]);
+        assert!(client.diagnostics_report().cookies.low);
+        assert!(matches!(
+            client.get_time().await,
+            Err(Error::CookieExhausted { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_key_exchange_over_scripted_transport() {
+        let mut connector = ScriptedConnector::new(|_, _| vec![]);
+        connector.ke_response = b"HTTP/1.1 400 Bad Request\r\n\r\n".to_vec();
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("test.server.com")
+                    .with_ke_addr(test_server())
+                    .with_max_retries(0),
+            )
+            .with_connector(connector)
+            .build()
+            .unwrap();
+
+        let err = client.connect().await.unwrap_err();
+        assert!(matches!(err, Error::NotNtsKe { .. }), "{:?}", err);
+        assert_eq!(err.kind(), ErrorKind::KeyExchange);
+    }
+
+    /// A runtime without Tokio: a thread per timer and per blocking task.
+    struct ThreadRuntime;
+
+    impl Runtime for ThreadRuntime {
+        fn sleep(&self, duration: Duration) -> crate::runtime::SleepFuture {
+            let (sender, receiver) = oneshot::channel::<()>();
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                let _ = sender.send(());
+            });
+            Box::pin(async move {
+                let _ = receiver.await;
+            })
+        }
+
+        fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+            std::thread::spawn(task);
+        }
+    }
+
+    /// Poll `future` to completion on the current thread, outside any Tokio
+    /// runtime.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::task::Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn test_custom_runtime_without_tokio() {
+        // The first request is lost, so the query times out and is retried
+        let connector = ScriptedConnector::new(|n, request| match n {
+            0 => vec![],
+            _ => vec![answer(request)],
+        });
+        let config = NtsClientConfig::new("test.server.com")
+            .with_query_timeout(Duration::from_millis(50))
+            .with_max_retries(1);
+        let client = NtsClient::builder()
+            .with_config(config)
+            .with_connector(connector)
+            .with_runtime(ThreadRuntime)
+            .build()
+            .unwrap();
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        block_on(async {
+            client
+                .connect_with_keys(test_server(), keys, vec![vec![0xAB; 64]; 2])
+                .await
+                .unwrap();
+            client.get_time().await.unwrap();
+        });
+
+        // The key exchange runs on the runtime's blocking threads
+        let mut connector = ScriptedConnector::new(|_, _| vec![]);
+        connector.ke_response = b"HTTP/1.1 400 Bad Request\r\n\r\n".to_vec();
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("test.server.com")
+                    .with_ke_addr(test_server())
+                    .with_max_retries(0),
+            )
+            .with_connector(connector)
+            .with_runtime(ThreadRuntime)
+            .build()
+            .unwrap();
+        let err = block_on(client.connect()).unwrap_err();
+        assert!(matches!(err, Error::NotNtsKe { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_offset_with_simulated_clock() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = SimulatedClock::new(start);
+        let local = clock.clone();
+        let connector = ScriptedConnector::new(move |n, request| {
+            // The server is 250 ms ahead. The local clock steps 1 s forward
+            // while the second request is in flight.
+            let response = answer_at(request, local.now() + Duration::from_millis(250));
+            if n == 1 {
+                local.step(SignedDuration::from_nanos(1_000_000_000));
+            }
+            vec![response]
+        });
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let client = NtsClient::builder()
+            .with_config(NtsClientConfig::new("test.server.com"))
+            .with_connector(connector)
+            .with_clock(clock.clone())
+            .build()
+            .unwrap();
+        client
+            .connect_with_keys(test_server(), keys, vec![vec![0xAB; 64]; 2])
+            .await
+            .unwrap();
+
+        let snapshot = client.get_time().await.unwrap();
+        assert_eq!(snapshot.offset_nanos(), -250_000_000);
+        assert_eq!(snapshot.system_time, start);
+        assert_eq!(snapshot.network_time, start + Duration::from_millis(250));
+        assert_eq!(snapshot.round_trip_delay, Duration::ZERO);
+
+        // T4 is 1 s after T1: half of it is attributed to the path delay
+        let snapshot = client.get_time().await.unwrap();
+        assert_eq!(snapshot.offset_nanos(), 250_000_000);
+        assert_eq!(snapshot.system_time, start + Duration::from_secs(1));
+        assert_eq!(snapshot.network_time, start + Duration::from_millis(750));
+        assert_eq!(snapshot.round_trip_delay, Duration::from_secs(1));
+        assert_eq!(client.timings().ntp_round_trip, Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_total_deadline_on_injected_clock() {
+        use crate::resolver::ResolveFuture;
+
+        /// A resolver whose lookups take 2 s on the simulated clock.
+        struct SlowResolver(SimulatedClock, Mutex<Vec<String>>);
+
+        impl Resolver for Arc<SlowResolver> {
+            fn resolve<'a>(&'a self, host: &'a str, _port: u16) -> ResolveFuture<'a> {
+                self.0.advance(Duration::from_secs(2));
+                lock(&self.1).push(host.to_string());
+                Box::pin(async { Err(Error::ServerUnavailable("no DNS".to_string())) })
+            }
+        }
+
+        let clock = SimulatedClock::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        let resolver = Arc::new(SlowResolver(clock.clone(), Mutex::default()));
+        let client = NtsClient::builder()
+            .with_config(
+                NtsClientConfig::new("primary.example")
+                    .with_max_retries(0)
+                    .with_fallback_servers(["a.example"])
+                    .with_total_deadline(Duration::from_secs(1)),
+            )
+            .with_resolver(Arc::clone(&resolver))
+            .with_clock(clock)
+            .build()
+            .unwrap();
+
+        // The budget is spent on the simulated clock after the first server
+        assert!(client.connect().await.is_err());
+        assert_eq!(*lock(&resolver.1), ["primary.example"]);
     }
 }
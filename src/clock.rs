@@ -0,0 +1,249 @@
+//! System clock adjustment from time snapshots.
+//!
+//! Small offsets are slewed (the clock is sped up or slowed down until the
+//! offset is absorbed), larger ones are stepped. Both require privileges:
+//! `CAP_SYS_TIME` on Linux, `SeSystemtimePrivilege` on Windows.
+
+use std::time::{Duration, SystemTime};
+
+use crate::error::Result;
+use crate::types::TimeSnapshot;
+
+/// Offsets up to this value are slewed, larger ones are stepped (the
+/// `ntpd` default).
+pub const DEFAULT_STEP_THRESHOLD: Duration = Duration::from_millis(128);
+
+/// Maximum slew rate, in parts per million (the Linux kernel limit).
+const MAX_SLEW_PPM: u64 = 500;
+
+/// How the system clock was corrected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockAdjustment {
+    /// The clock was set to the network time at once.
+    Stepped,
+    /// The clock rate was changed until the offset is absorbed, which takes
+    /// `duration`.
+    Slewed {
+        /// Time needed to absorb the offset at the maximum slew rate.
+        duration: Duration,
+    },
+}
+
+/// Correct the system clock by the offset of `snapshot`, using
+/// [`DEFAULT_STEP_THRESHOLD`].
+///
+/// # Errors
+///
+/// Returns [`Error::Io`](crate::Error::Io) if the operating system refuses
+/// the adjustment (usually for lack of privileges), or
+/// [`Error::Other`](crate::Error::Other) on platforms other than Linux and
+/// Windows.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::clock::{apply_offset, ClockAdjustment};
+/// use rkik_nts::{NtsClient, NtsClientConfig};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// client.connect().await?;
+/// let time = client.get_time().await?;
+/// match apply_offset(&time)? {
+///     ClockAdjustment::Stepped => println!("Clock stepped"),
+///     ClockAdjustment::Slewed { duration } => println!("Slewing for {:?}", duration),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn apply_offset(snapshot: &TimeSnapshot) -> Result<ClockAdjustment> {
+    apply_offset_with(snapshot, DEFAULT_STEP_THRESHOLD)
+}
+
+/// Correct the system clock by the offset of `snapshot`, slewing offsets up
+/// to `step_threshold` and stepping larger ones. A zero threshold always
+/// steps.
+///
+/// # Errors
+///
+/// Same as [`apply_offset`].
+pub fn apply_offset_with(
+    snapshot: &TimeSnapshot,
+    step_threshold: Duration,
+) -> Result<ClockAdjustment> {
+    let offset = -snapshot.offset_nanos();
+    let adjustment = plan(offset, step_threshold);
+    match adjustment {
+        ClockAdjustment::Stepped => {
+            let now = SystemTime::now();
+            let magnitude = Duration::from_nanos(offset.unsigned_abs() as u64);
+            let target = if offset >= 0 {
+                now + magnitude
+            } else {
+                now - magnitude
+            };
+            sys::step(target)?;
+        }
+        ClockAdjustment::Slewed { duration } => sys::slew(offset, duration)?,
+    }
+    Ok(adjustment)
+}
+
+/// Decide how to correct an offset of `offset` nanoseconds.
+fn plan(offset: i128, step_threshold: Duration) -> ClockAdjustment {
+    let magnitude = offset.unsigned_abs();
+    if magnitude > step_threshold.as_nanos() {
+        ClockAdjustment::Stepped
+    } else {
+        let nanos = magnitude * 1_000_000 / MAX_SLEW_PPM as u128;
+        ClockAdjustment::Slewed {
+            duration: Duration::from_nanos(nanos as u64),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::error::{Error, Result};
+
+    pub(super) fn step(target: SystemTime) -> Result<()> {
+        let since_epoch = target
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Other(format!("System time error: {}", e)))?;
+        let ts = libc::timespec {
+            tv_sec: since_epoch.as_secs() as libc::time_t,
+            tv_nsec: since_epoch.subsec_nanos() as _,
+        };
+        // SAFETY: ts is a valid timespec for the duration of the call.
+        if unsafe { libc::clock_settime(libc::CLOCK_REALTIME, &ts) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn slew(offset: i128, _duration: Duration) -> Result<()> {
+        // SAFETY: timex is a plain C struct for which all-zeroes is valid.
+        let mut tx: libc::timex = unsafe { std::mem::zeroed() };
+        tx.modes = libc::ADJ_OFFSET_SINGLESHOT;
+        tx.offset = (offset / 1_000) as libc::c_long;
+        // SAFETY: tx is a valid, initialized timex.
+        if unsafe { libc::adjtimex(&mut tx) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::io;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+    use windows_sys::Win32::System::SystemInformation::{
+        GetSystemTimeAdjustment, SetSystemTime, SetSystemTimeAdjustment,
+    };
+    use windows_sys::Win32::System::Time::FileTimeToSystemTime;
+
+    use crate::error::{Error, Result};
+
+    /// 100ns intervals between 1601-01-01 and the Unix epoch.
+    const FILETIME_UNIX_OFFSET: u64 = 116_444_736_000_000_000;
+
+    pub(super) fn step(target: SystemTime) -> Result<()> {
+        let since_epoch = target
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Other(format!("System time error: {}", e)))?;
+        let ticks = (since_epoch.as_nanos() / 100) as u64 + FILETIME_UNIX_OFFSET;
+        let file_time = FILETIME {
+            dwLowDateTime: ticks as u32,
+            dwHighDateTime: (ticks >> 32) as u32,
+        };
+        // SAFETY: SYSTEMTIME is a plain C struct for which all-zeroes is valid.
+        let mut system_time: SYSTEMTIME = unsafe { std::mem::zeroed() };
+        // SAFETY: both pointers are valid for the duration of the calls.
+        unsafe {
+            if FileTimeToSystemTime(&file_time, &mut system_time) == 0
+                || SetSystemTime(&system_time) == 0
+            {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the clock at about the maximum slew rate until the offset is
+    /// absorbed, then restore the previous adjustment from a background
+    /// thread.
+    pub(super) fn slew(offset: i128, _duration: Duration) -> Result<()> {
+        let (mut adjustment, mut increment, mut disabled) = (0u32, 0u32, 0i32);
+        // SAFETY: all pointers are valid for the duration of the calls.
+        let duration = unsafe {
+            if GetSystemTimeAdjustment(&mut adjustment, &mut increment, &mut disabled) == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            // The adjustment is added every clock tick, in 100ns units
+            let delta = (increment as u64 * super::MAX_SLEW_PPM / 1_000_000).max(1) as u32;
+            let slewed = if offset >= 0 {
+                increment + delta
+            } else {
+                increment - delta
+            };
+            if SetSystemTimeAdjustment(slewed, 0) == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let nanos = offset.unsigned_abs() * increment as u128 / delta as u128;
+            Duration::from_nanos(nanos as u64)
+        };
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            // SAFETY: plain value arguments.
+            unsafe { SetSystemTimeAdjustment(adjustment, disabled) };
+        });
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod sys {
+    use std::time::{Duration, SystemTime};
+
+    use crate::error::{Error, Result};
+
+    pub(super) fn step(_target: SystemTime) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn slew(_offset: i128, _duration: Duration) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn unsupported() -> Error {
+        Error::Other("Clock adjustment is not supported on this platform".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_steps_large_offsets() {
+        let threshold = DEFAULT_STEP_THRESHOLD;
+        assert_eq!(plan(200_000_000, threshold), ClockAdjustment::Stepped);
+        assert_eq!(plan(-200_000_000, threshold), ClockAdjustment::Stepped);
+        assert_eq!(plan(1, Duration::ZERO), ClockAdjustment::Stepped);
+
+        // 10ms at 500ppm takes 20s
+        assert_eq!(
+            plan(-10_000_000, threshold),
+            ClockAdjustment::Slewed {
+                duration: Duration::from_secs(20)
+            }
+        );
+    }
+}
@@ -1,14 +1,103 @@
 //! Configuration for NTS client.
 
-use std::net::SocketAddr;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
+pub use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::blacklist::BlacklistPolicy;
+use crate::circuit::CircuitBreakerPolicy;
+use crate::leap::LeapSecondHandling;
+use crate::proxy::ProxyConfig;
+use crate::retry::{ExponentialBackoff, RetryPolicy};
+
+/// Size of an NTP packet without extension fields.
+const MIN_PACKET_SIZE: usize = 48;
+
+/// IP address family selection for resolved server addresses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AddressFamily {
+    /// Use addresses in the order returned by the resolver.
+    #[default]
+    Any,
+
+    /// Try IPv4 addresses first, then IPv6.
+    PreferIpv4,
+
+    /// Try IPv6 addresses first, then IPv4.
+    PreferIpv6,
+
+    /// Only use IPv4 addresses.
+    OnlyIpv4,
+
+    /// Only use IPv6 addresses.
+    OnlyIpv6,
+}
+
+impl AddressFamily {
+    /// Filter and order `addrs` according to this preference.
+    ///
+    /// The relative order of addresses within a family is preserved.
+    pub fn apply(self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            AddressFamily::Any => {}
+            AddressFamily::PreferIpv4 => addrs.sort_by_key(|a| a.is_ipv6()),
+            AddressFamily::PreferIpv6 => addrs.sort_by_key(|a| a.is_ipv4()),
+            AddressFamily::OnlyIpv4 => addrs.retain(|a| a.is_ipv4()),
+            AddressFamily::OnlyIpv6 => addrs.retain(|a| a.is_ipv6()),
+        }
+        addrs
+    }
+}
+
+/// How to treat an NTS-KE response that sends the client to a different NTP
+/// server than the NTS-KE server (RFC 8915, section 4.1.7).
+///
+/// A response redirects when it names another host, or an NTP port other
+/// than 123. The outcome is reported by [`NtsKeResult::redirect`](crate::NtsKeResult::redirect).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RedirectPolicy {
+    /// Follow any redirect.
+    #[default]
+    Permissive,
+
+    /// Reject redirects; the NTP server must be the NTS-KE server.
+    Strict,
+
+    /// Only follow redirects to these hostnames or IP addresses.
+    Allowlist(Vec<String>),
+}
+
+/// A client certificate chain and private key for mutual TLS.
+#[derive(Debug)]
+pub struct ClientAuth {
+    /// Certificate chain, leaf certificate first.
+    pub cert_chain: Vec<CertificateDer<'static>>,
+
+    /// Private key matching the leaf certificate.
+    pub private_key: PrivateKeyDer<'static>,
+}
+
+impl Clone for ClientAuth {
+    fn clone(&self) -> Self {
+        Self {
+            cert_chain: self.cert_chain.clone(),
+            private_key: self.private_key.clone_key(),
+        }
+    }
+}
+
 /// Configuration for an NTS client.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct NtsClientConfig {
     /// The NTS key exchange server hostname.
     pub nts_ke_server: String,
@@ -16,21 +105,243 @@ pub struct NtsClientConfig {
     /// The NTS key exchange server port (default: 4460).
     pub nts_ke_port: u16,
 
+    /// Optional: TLS server name (SNI and certificate validation).
+    /// If None, `nts_ke_server` is used.
+    pub tls_server_name: Option<String>,
+
+    /// Pre-resolved NTS-KE server addresses.
+    /// If non-empty, DNS resolution of `nts_ke_server` is skipped.
+    pub ke_addrs: Vec<SocketAddr>,
+
+    /// NTS-KE server hostnames tried in order when the primary server fails.
+    pub fallback_servers: Vec<String>,
+
+    /// When servers that keep failing are temporarily skipped.
+    pub blacklist: BlacklistPolicy,
+
+    /// Optional: Make `get_time()` fail fast after repeated failures.
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+
     /// Timeout for network operations.
+    /// Used when `ke_timeout` or `query_timeout` is not set.
     pub timeout: Duration,
 
+    /// Optional: Timeout for the NTS key exchange (TCP connect and TLS handshake).
+    pub ke_timeout: Option<Duration>,
+
+    /// Optional: Timeout for a single NTP query round trip.
+    pub query_timeout: Option<Duration>,
+
     /// Maximum number of retry attempts for failed operations.
+    /// Used when `retry_policy` is not set.
     pub max_retries: u32,
 
+    /// Optional: Wall-clock budget for a whole `connect()` or `get_time()`
+    /// call, including all retries and phases.
+    pub total_deadline: Option<Duration>,
+
+    /// Optional: Retry policy for key exchanges and time queries.
+    /// If None, exponential backoff with `max_retries` retries is used.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub retry_policy: Option<Arc<dyn RetryPolicy>>,
+
+    /// Whether `get_time()` performs the key exchange on first use instead
+    /// of failing when the client is not connected (default: false).
+    pub auto_connect: bool,
+
     /// Whether to verify the server's TLS certificate.
+    ///
+    /// Disabling verification requires the `insecure` feature, unless SPKI
+    /// pins are configured.
     pub verify_tls_cert: bool,
 
+    /// Whether to check that the certificate matches the server name
+    /// (default: true). The chain is still verified when disabled.
+    pub verify_hostname: bool,
+
+    /// Accept NTS-KE certificates outside their validity period when the
+    /// system clock is wrong (default: false). Snapshots are then marked as
+    /// bootstrap measurements.
+    pub tolerate_clock_skew: bool,
+
+    /// Additional trusted root certificates for NTS-KE, on top of the
+    /// platform's trust store.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub root_certificates: Vec<CertificateDer<'static>>,
+
+    /// Optional: PEM file with additional trusted root certificates.
+    pub ca_file: Option<PathBuf>,
+
+    /// SHA-256 hashes of trusted SubjectPublicKeyInfos.
+    /// If non-empty, the NTS-KE server's certificate chain must also contain
+    /// a matching key. Requires `verify_tls_cert`.
+    pub spki_pins: Vec<[u8; 32]>,
+
+    /// Optional: Client certificate to present to the NTS-KE server.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub client_auth: Option<ClientAuth>,
+
+    /// Optional: Warn when the NTS-KE server certificate expires within this
+    /// window (default: 30 days).
+    pub cert_expiry_warning: Option<Duration>,
+
+    /// Whether to keep the decoded records of the server's NTS-KE response
+    /// (default: false). See [`NtsKeResult::ke_records`](crate::NtsKeResult::ke_records).
+    pub capture_ke_records: bool,
+
+    /// Optional: Local address to bind the NTS-KE and NTP sockets to.
+    pub bind_address: Option<SocketAddr>,
+
+    /// Optional: Network interface to bind the NTS-KE and NTP sockets to
+    /// (`SO_BINDTODEVICE`, Linux only).
+    pub interface: Option<String>,
+
+    /// Optional: DSCP value (0-63) for outgoing NTS-KE and NTP packets.
+    pub dscp: Option<u8>,
+
+    /// Optional: IP TTL (IPv4) or hop limit (IPv6) for NTP packets.
+    pub ttl: Option<u32>,
+
+    /// Optional: SOCKS5 proxy for the NTS-KE connection. NTP queries are
+    /// still sent directly over UDP.
+    pub proxy: Option<ProxyConfig>,
+
+    /// Address family preference for the NTS-KE and NTP server addresses.
+    pub address_family: AddressFamily,
+
+    /// Delay before racing the next resolved NTS-KE address while a TCP
+    /// connection attempt is still pending (Happy Eyeballs, RFC 8305).
+    /// If None, addresses are tried one after another (default: 250ms).
+    pub connection_attempt_delay: Option<Duration>,
+
     /// Optional: Specific NTP server address to use after key exchange.
     /// If None, uses the server provided during NTS-KE.
     pub ntp_server: Option<SocketAddr>,
 
+    /// NTP servers the NTS-KE server must not direct the client to, as
+    /// hostnames or IP addresses.
+    pub denied_servers: Vec<String>,
+
+    /// Whether to follow NTS-KE responses that name another NTP server
+    /// (default: [`RedirectPolicy::Permissive`]).
+    pub redirect_policy: RedirectPolicy,
+
     /// NTP version to use (default: 4).
     pub ntp_version: u8,
+
+    /// Optional: Minimum NTP version the NTS-KE server must negotiate (4 or 5).
+    /// A lower version fails with [`Error::ProtocolDowngrade`](crate::Error::ProtocolDowngrade).
+    pub min_protocol_version: Option<u8>,
+
+    /// Send a random nonce instead of the local clock in the request's
+    /// transmit timestamp (default: true).
+    ///
+    /// This follows draft-ietf-ntp-data-minimization and avoids leaking the
+    /// client's clock, which could be used for fingerprinting.
+    pub transmit_nonce: bool,
+
+    /// Largest NTP response accepted, in bytes (default: 2048). Larger
+    /// responses fail with [`Error::ResponseTruncated`](crate::Error::ResponseTruncated).
+    pub max_packet_size: usize,
+
+    /// Optional: Maximum acceptable root distance.
+    /// Responses from servers further from their reference are rejected.
+    pub max_root_distance: Option<Duration>,
+
+    /// Optional: Maximum plausible clock offset.
+    /// Measurements with a larger offset are treated as bogus.
+    pub max_offset: Option<Duration>,
+
+    /// What to do with measurements taken around an announced leap second
+    /// (default: [`LeapSecondHandling::Report`]).
+    pub leap_second_handling: LeapSecondHandling,
+
+    /// Optional: Number of samples kept in the per-server rolling
+    /// statistics returned by
+    /// [`NtsClient::server_stats`](crate::NtsClient::server_stats).
+    /// Disabled by default.
+    pub stats_window: Option<usize>,
+
+    /// Optional: Number of recent snapshots kept and returned by
+    /// [`NtsClient::history`](crate::NtsClient::history). Disabled by
+    /// default.
+    pub history_capacity: Option<usize>,
+
+    /// Keep the request and response bytes of the last query, returned by
+    /// [`NtsClient::last_packets`](crate::NtsClient::last_packets)
+    /// (default: false).
+    pub capture_packets: bool,
+}
+
+/// Per-call overrides for [`NtsClient::get_time_with`](crate::NtsClient::get_time_with).
+///
+/// Unset options fall back to the client configuration.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::QueryOptions;
+/// use std::time::Duration;
+///
+/// // A quick probe: one attempt, short timeout
+/// let probe = QueryOptions::new()
+///     .with_timeout(Duration::from_millis(500))
+///     .with_max_retries(0);
+///
+/// // A careful measurement: strict quality thresholds
+/// let careful = QueryOptions::new()
+///     .with_max_retries(5)
+///     .with_max_root_distance(Duration::from_millis(100));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct QueryOptions {
+    /// Optional: Timeout for a single NTP query round trip, overriding
+    /// `query_timeout`.
+    pub timeout: Option<Duration>,
+
+    /// Optional: Maximum number of retries with exponential backoff,
+    /// overriding the client's retry policy.
+    pub max_retries: Option<u32>,
+
+    /// Optional: Maximum acceptable root distance, overriding
+    /// `max_root_distance`.
+    pub max_root_distance: Option<Duration>,
+
+    /// Optional: Maximum plausible clock offset, overriding `max_offset`.
+    pub max_offset: Option<Duration>,
+}
+
+impl QueryOptions {
+    /// Create options that override nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timeout for a single query round trip.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of retries.
+    pub fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Set the maximum acceptable root distance.
+    pub fn with_max_root_distance(mut self, max: Duration) -> Self {
+        self.max_root_distance = Some(max);
+        self
+    }
+
+    /// Set the maximum plausible clock offset.
+    pub fn with_max_offset(mut self, max: Duration) -> Self {
+        self.max_offset = Some(max);
+        self
+    }
 }
 
 impl Default for NtsClientConfig {
@@ -38,11 +349,47 @@ impl Default for NtsClientConfig {
         Self {
             nts_ke_server: String::new(),
             nts_ke_port: 4460, // Standard NTS-KE port
+            tls_server_name: None,
+            ke_addrs: Vec::new(),
+            fallback_servers: Vec::new(),
+            blacklist: BlacklistPolicy::default(),
+            circuit_breaker: None,
             timeout: Duration::from_secs(10),
+            ke_timeout: None,
+            query_timeout: None,
             max_retries: 3,
+            retry_policy: None,
+            total_deadline: None,
+            auto_connect: false,
             verify_tls_cert: true,
+            verify_hostname: true,
+            tolerate_clock_skew: false,
+            root_certificates: Vec::new(),
+            ca_file: None,
+            spki_pins: Vec::new(),
+            client_auth: None,
+            cert_expiry_warning: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            capture_ke_records: false,
+            bind_address: None,
+            interface: None,
+            dscp: None,
+            ttl: None,
+            proxy: None,
+            address_family: AddressFamily::Any,
+            connection_attempt_delay: Some(Duration::from_millis(250)),
             ntp_server: None,
+            denied_servers: Vec::new(),
+            redirect_policy: RedirectPolicy::Permissive,
             ntp_version: 4,
+            min_protocol_version: None,
+            transmit_nonce: true,
+            max_packet_size: 2048,
+            max_root_distance: None,
+            max_offset: None,
+            leap_second_handling: LeapSecondHandling::Report,
+            stats_window: None,
+            history_capacity: None,
+            capture_packets: false,
         }
     }
 }
@@ -52,7 +399,9 @@ impl NtsClientConfig {
     ///
     /// # Arguments
     ///
-    /// * `server` - The hostname or IP address of the NTS-KE server.
+    /// * `server` - The hostname or IP address of the NTS-KE server,
+    ///   optionally with a port as `host:port`, `[ipv6]:port` or
+    ///   `nts://host:port`.
     ///
     /// # Examples
     ///
@@ -60,65 +409,841 @@ impl NtsClientConfig {
     /// use rkik_nts::config::NtsClientConfig;
     ///
     /// let config = NtsClientConfig::new("time.cloudflare.com");
+    /// assert_eq!(config.nts_ke_port, 4460);
+    ///
+    /// let config = NtsClientConfig::new("nts://[2001:db8::1]:4461");
+    /// assert_eq!(config.nts_ke_server, "2001:db8::1");
+    /// assert_eq!(config.nts_ke_port, 4461);
     /// ```
     pub fn new(server: impl Into<String>) -> Self {
-        Self {
-            nts_ke_server: server.into(),
-            ..Default::default()
+        Self::default().with_server(server)
+    }
+
+    /// Set the NTS-KE server, in any of the forms accepted by
+    /// [`new`](Self::new). A port in `server` replaces the configured one.
+    pub fn with_server(mut self, server: impl Into<String>) -> Self {
+        let (host, port) = parse_server(&server.into());
+        self.nts_ke_server = host;
+        if let Some(port) = port {
+            self.nts_ke_port = port;
         }
+        self
     }
 
     /// Set the NTS-KE server port.
+    ///
+    /// Servers that share port 443 with HTTPS work like any other: the
+    /// client offers only the `ntske/1` ALPN protocol, and a server that
+    /// answers with HTTP instead fails with
+    /// [`Error::NotNtsKe`](crate::Error::NotNtsKe).
     pub fn with_port(mut self, port: u16) -> Self {
         self.nts_ke_port = port;
         self
     }
 
+    /// Set the TLS server name independently of the host connected to.
+    ///
+    /// Useful when connecting to a load balancer address while validating
+    /// the certificate against the canonical server name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("192.0.2.10")
+    ///     .with_tls_server_name("time.example.com");
+    /// assert_eq!(config.effective_tls_server_name(), "time.example.com");
+    /// ```
+    pub fn with_tls_server_name(mut self, name: impl Into<String>) -> Self {
+        self.tls_server_name = Some(name.into());
+        self
+    }
+
+    /// Get the name used for SNI and certificate validation.
+    pub fn effective_tls_server_name(&self) -> &str {
+        self.tls_server_name
+            .as_deref()
+            .unwrap_or(&self.nts_ke_server)
+    }
+
+    /// Connect to the NTS-KE server at a pre-resolved address, skipping DNS.
+    ///
+    /// Can be called several times to add fallback addresses. The configured
+    /// hostname is still used for TLS certificate validation, which solves
+    /// the time/DNS bootstrap problem where DNSSEC needs a correct clock.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_ke_addr("162.159.200.1:4460".parse().unwrap());
+    /// ```
+    pub fn with_ke_addr(mut self, addr: SocketAddr) -> Self {
+        self.ke_addrs.push(addr);
+        self
+    }
+
+    /// Set NTS-KE servers to try in order when the primary server fails to
+    /// connect or authenticate.
+    ///
+    /// Fallback servers use the same port and settings as the primary server,
+    /// except that [`tls_server_name`](Self::tls_server_name) and
+    /// [`ke_addrs`](Self::ke_addrs) only apply to the primary server.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_fallback_servers(vec!["nts.ntp.se", "ntppool1.time.nl"]);
+    /// ```
+    pub fn with_fallback_servers(
+        mut self,
+        servers: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.fallback_servers = servers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set when servers that keep failing are temporarily skipped.
+    ///
+    /// Blacklisted servers are skipped by `connect()` as long as another
+    /// primary or fallback server is available.
+    pub fn with_blacklist_policy(mut self, policy: BlacklistPolicy) -> Self {
+        self.blacklist = policy;
+        self
+    }
+
+    /// Enable a circuit breaker around `get_time()`.
+    ///
+    /// After `failure_threshold` consecutive failed queries, `get_time()`
+    /// fails immediately with [`Error::CircuitOpen`](crate::Error::CircuitOpen)
+    /// for the cool-down period instead of waiting for timeouts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::{CircuitBreakerPolicy, NtsClientConfig};
+    /// use std::time::Duration;
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_circuit_breaker(CircuitBreakerPolicy {
+    ///         failure_threshold: 3,
+    ///         cool_down: Duration::from_secs(60),
+    ///     });
+    /// ```
+    pub fn with_circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.circuit_breaker = Some(policy);
+        self
+    }
+
+    /// Configuration for the fallback NTS-KE server `server`.
+    pub(crate) fn for_fallback(&self, server: &str) -> Self {
+        Self {
+            nts_ke_server: server.to_string(),
+            tls_server_name: None,
+            ke_addrs: Vec::new(),
+            fallback_servers: Vec::new(),
+            ..self.clone()
+        }
+    }
+
     /// Set the timeout duration.
     pub fn with_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
     }
 
+    /// Set the timeout for the NTS key exchange, overriding `timeout`.
+    pub fn with_ke_timeout(mut self, timeout: Duration) -> Self {
+        self.ke_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for NTP queries, overriding `timeout`.
+    pub fn with_query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Get the timeout applied to the NTS key exchange.
+    pub fn effective_ke_timeout(&self) -> Duration {
+        self.ke_timeout.unwrap_or(self.timeout)
+    }
+
+    /// Get the timeout applied to NTP queries.
+    pub fn effective_query_timeout(&self) -> Duration {
+        self.query_timeout.unwrap_or(self.timeout)
+    }
+
     /// Set the maximum number of retries.
     pub fn with_max_retries(mut self, retries: u32) -> Self {
         self.max_retries = retries;
         self
     }
 
+    /// Bound the total duration of a `connect()` or `get_time()` call,
+    /// including retries, backoff delays and rate-limit waits.
+    ///
+    /// When the budget runs out, the call fails with
+    /// [`Error::Timeout`](crate::Error::Timeout). Per-attempt timeouts still
+    /// apply within the budget.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::NtsClientConfig;
+    /// use std::time::Duration;
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_max_retries(5)
+    ///     .with_total_deadline(Duration::from_secs(3));
+    /// ```
+    pub fn with_total_deadline(mut self, deadline: Duration) -> Self {
+        self.total_deadline = Some(deadline);
+        self
+    }
+
+    /// Connect on the first `get_time()` call instead of requiring an
+    /// explicit `connect()`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rkik_nts::{NtsClient, NtsClientConfig};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = NtsClientConfig::new("time.cloudflare.com").with_auto_connect(true);
+    /// let mut client = NtsClient::new(config);
+    /// let time = client.get_time().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_auto_connect(mut self, enabled: bool) -> Self {
+        self.auto_connect = enabled;
+        self
+    }
+
+    /// Set the retry policy for key exchanges and time queries, overriding
+    /// `max_retries`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::retry::ExponentialBackoff;
+    /// use rkik_nts::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_retry_policy(ExponentialBackoff::new(5).with_jitter(true));
+    /// ```
+    pub fn with_retry_policy(mut self, policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Get the retry policy to use.
+    pub(crate) fn effective_retry_policy(&self) -> Arc<dyn RetryPolicy> {
+        self.retry_policy
+            .clone()
+            .unwrap_or_else(|| Arc::new(ExponentialBackoff::new(self.max_retries)))
+    }
+
     /// Set whether to verify TLS certificates.
+    ///
+    /// Only available with the `insecure` feature. Without verification any
+    /// server can impersonate the NTS-KE server; use it for testing only.
+    #[cfg(feature = "insecure")]
     pub fn with_tls_verification(mut self, verify: bool) -> Self {
         self.verify_tls_cert = verify;
         self
     }
 
+    /// Set whether the certificate must match the server name.
+    ///
+    /// When disabled, the certificate chain is still validated against the
+    /// trusted roots, but a certificate issued for a different name is
+    /// accepted. Meant for lab setups; the certificate needs a
+    /// subjectAltName for the chain check.
+    pub fn with_hostname_verification(mut self, verify: bool) -> Self {
+        self.verify_hostname = verify;
+        self
+    }
+
+    /// Set whether to tolerate a wrong system clock during certificate validation.
+    ///
+    /// Without network time, a badly wrong clock makes every certificate look
+    /// expired or not yet valid. When enabled, such a certificate is accepted
+    /// if it passes all other checks, a warning is logged and the resulting
+    /// [`TimeSnapshot::bootstrap`](crate::TimeSnapshot::bootstrap) is set.
+    pub fn with_clock_skew_tolerance(mut self, tolerate: bool) -> Self {
+        self.tolerate_clock_skew = tolerate;
+        self
+    }
+
+    /// Trust additional root certificates when verifying the NTS-KE server.
+    ///
+    /// Useful for private deployments whose servers are signed by an internal
+    /// CA. The platform's trust store is still consulted.
+    pub fn with_root_certificates(
+        mut self,
+        certs: impl IntoIterator<Item = CertificateDer<'static>>,
+    ) -> Self {
+        self.root_certificates.extend(certs);
+        self
+    }
+
+    /// Trust the root certificates in a PEM file when verifying the NTS-KE server.
+    ///
+    /// The file is read on every key exchange.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("nts.internal.example")
+    ///     .with_ca_file("/etc/ssl/internal-ca.pem");
+    /// ```
+    pub fn with_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_file = Some(path.into());
+        self
+    }
+
+    /// Pin the NTS-KE server to a SHA-256 SubjectPublicKeyInfo hash.
+    ///
+    /// Can be called several times; the connection is accepted if the leaf
+    /// or any intermediate certificate matches one of the pins. Pins are
+    /// checked in addition to regular certificate verification, which must
+    /// stay enabled: the chain is still validated against the trusted roots.
+    ///
+    /// The hash of a certificate's key can be computed with:
+    ///
+    /// ```text
+    /// openssl x509 -in cert.pem -pubkey -noout \
+    ///     | openssl pkey -pubin -outform der | openssl dgst -sha256
+    /// ```
+    pub fn with_spki_pin(mut self, sha256: [u8; 32]) -> Self {
+        self.spki_pins.push(sha256);
+        self
+    }
+
+    /// Authenticate to the NTS-KE server with a client certificate.
+    ///
+    /// Required by private NTS-KE servers that use mutual TLS.
+    pub fn with_client_auth(
+        mut self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        private_key: PrivateKeyDer<'static>,
+    ) -> Self {
+        self.client_auth = Some(ClientAuth {
+            cert_chain,
+            private_key,
+        });
+        self
+    }
+
+    /// Set the window for the certificate expiry warning.
+    ///
+    /// Pass `None` to disable the check. See
+    /// [`TlsDetails::certificate_expiring`](crate::TlsDetails::certificate_expiring).
+    pub fn with_cert_expiry_warning(mut self, window: Option<Duration>) -> Self {
+        self.cert_expiry_warning = window;
+        self
+    }
+
+    /// Set whether to keep the records of the server's NTS-KE response.
+    ///
+    /// The records show exactly what the server advertised, including
+    /// warnings and record types this crate ignores. Decoding them requires
+    /// the TLS traffic secret of the session, which is held in memory for
+    /// the duration of the key exchange only.
+    pub fn with_ke_record_capture(mut self, capture: bool) -> Self {
+        self.capture_ke_records = capture;
+        self
+    }
+
+    /// Bind the NTS-KE and NTP sockets to a local address.
+    ///
+    /// Useful on multi-homed hosts to choose the outgoing address.
+    pub fn with_bind_address(mut self, addr: SocketAddr) -> Self {
+        self.bind_address = Some(addr);
+        self
+    }
+
+    /// Bind the NTS-KE and NTP sockets to a network interface (e.g. `eth0`).
+    ///
+    /// Only supported on Linux; validation fails on other platforms.
+    pub fn with_interface(mut self, interface: impl Into<String>) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Set the DSCP value for outgoing packets.
+    ///
+    /// Time traffic is commonly marked as network control (CS6, 48) so QoS
+    /// policies can prioritize it.
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+
+    /// Set the TTL / hop limit for NTP packets.
+    ///
+    /// A small value restricts queries to nearby servers, e.g. 1 for on-link only.
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Connect to the NTS-KE server through a SOCKS5 proxy.
+    ///
+    /// For hosts that cannot reach port 4460 directly. Only the key exchange
+    /// is proxied: the NTP server must still be reachable over UDP. The
+    /// NTS-KE hostname is resolved locally, and a custom
+    /// [`Connector`](crate::Connector) does not use the proxy.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Set the address family preference.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::{AddressFamily, NtsClientConfig};
+    ///
+    /// let config = NtsClientConfig::new("time.cloudflare.com")
+    ///     .with_address_family(AddressFamily::PreferIpv6);
+    /// ```
+    pub fn with_address_family(mut self, family: AddressFamily) -> Self {
+        self.address_family = family;
+        self
+    }
+
+    /// Set the delay before racing the next NTS-KE address.
+    ///
+    /// Pass `None` to try addresses strictly one after another.
+    pub fn with_connection_attempt_delay(mut self, delay: Option<Duration>) -> Self {
+        self.connection_attempt_delay = delay;
+        self
+    }
+
     /// Set a specific NTP server to use.
     pub fn with_ntp_server(mut self, server: SocketAddr) -> Self {
         self.ntp_server = Some(server);
         self
     }
 
+    /// Refuse key exchanges that direct the client to one of these NTP
+    /// servers.
+    ///
+    /// Entries are hostnames, compared case-insensitively with the server
+    /// record of the NTS-KE response, or IP addresses, which are also
+    /// compared with the resolved NTP server address. A match fails the key
+    /// exchange with [`Error::ServerDenied`](crate::Error::ServerDenied).
+    /// The list is also sent to the NTS-KE server, so that an NTS pool can
+    /// pick another server.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    ///
+    /// let config = NtsClientConfig::new("nts.pool.example")
+    ///     .with_denied_servers(vec!["ntp1.pool.example".to_string()]);
+    /// assert_eq!(config.denied_servers, ["ntp1.pool.example"]);
+    /// ```
+    pub fn with_denied_servers(
+        mut self,
+        servers: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.denied_servers = servers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set whether to follow NTS-KE responses that name another NTP server.
+    ///
+    /// A rejected redirect fails the key exchange with
+    /// [`Error::RedirectRejected`](crate::Error::RedirectRejected).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::{NtsClientConfig, RedirectPolicy};
+    ///
+    /// let config = NtsClientConfig::new("nts.example.com").with_redirect_policy(
+    ///     RedirectPolicy::Allowlist(vec!["ntp.example.com".to_string()]),
+    /// );
+    /// ```
+    pub fn with_redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
     /// Set the NTP version.
     pub fn with_ntp_version(mut self, version: u8) -> Self {
         self.ntp_version = version;
         self
     }
 
-    /// Validate the configuration.
-    pub(crate) fn validate(&self) -> crate::error::Result<()> {
+    /// Require a minimum negotiated NTP version.
+    ///
+    /// Requiring 5 offers only NTPv5 during the key exchange, so the server
+    /// cannot silently fall back to NTPv4. Note that NTPv5 time queries are
+    /// not implemented yet.
+    pub fn with_min_protocol_version(mut self, version: u8) -> Self {
+        self.min_protocol_version = Some(version);
+        self
+    }
+
+    /// Set whether to use a random nonce as the request transmit timestamp.
+    pub fn with_transmit_nonce(mut self, enabled: bool) -> Self {
+        self.transmit_nonce = enabled;
+        self
+    }
+
+    /// Set the largest NTP response accepted, in bytes.
+    ///
+    /// Receive buffers are sized for the NTS response expected from the
+    /// negotiated cookie length, up to this limit. Raise it for servers
+    /// with unusually large cookies.
+    pub fn with_max_packet_size(mut self, size: usize) -> Self {
+        self.max_packet_size = size;
+        self
+    }
+
+    /// Set the maximum acceptable root distance.
+    ///
+    /// Root distance is `root_delay / 2 + root_dispersion + round_trip_delay / 2`
+    /// and bounds the error of the server's time.
+    pub fn with_max_root_distance(mut self, max: Duration) -> Self {
+        self.max_root_distance = Some(max);
+        self
+    }
+
+    /// Set the maximum plausible clock offset.
+    ///
+    /// Queries measuring a larger offset fail with
+    /// [`Error::ImplausibleTime`](crate::Error::ImplausibleTime) instead of
+    /// returning a snapshot.
+    pub fn with_max_offset(mut self, max: Duration) -> Self {
+        self.max_offset = Some(max);
+        self
+    }
+
+    /// Set what to do with measurements taken around an announced leap
+    /// second.
+    ///
+    /// Either way, the announcement is reported by
+    /// [`TimeSnapshot::leap_indicator`](crate::TimeSnapshot::leap_indicator).
+    pub fn with_leap_second_handling(mut self, handling: LeapSecondHandling) -> Self {
+        self.leap_second_handling = handling;
+        self
+    }
+
+    /// Maintain rolling offset and round-trip statistics per server over the
+    /// last `window` samples.
+    pub fn with_stats_window(mut self, window: usize) -> Self {
+        self.stats_window = Some(window);
+        self
+    }
+
+    /// Keep the last `capacity` successful snapshots, for trend displays and
+    /// later analysis.
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history_capacity = Some(capacity);
+        self
+    }
+
+    /// Keep the raw request and response of the last query, for hex dumps
+    /// and bug reports when a response fails to parse.
+    pub fn with_packet_capture(mut self, capture: bool) -> Self {
+        self.capture_packets = capture;
+        self
+    }
+
+    /// Check the configuration and return every problem found, so that
+    /// they can all be fixed at once. An empty list means the configuration
+    /// is valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rkik_nts::config::NtsClientConfig;
+    /// use std::time::Duration;
+    ///
+    /// let config = NtsClientConfig::new("")
+    ///     .with_port(0)
+    ///     .with_timeout(Duration::ZERO);
+    /// let fields: Vec<_> = config.validation_errors().iter().map(|e| e.field).collect();
+    /// assert_eq!(fields, ["nts_ke_server", "nts_ke_port", "timeout"]);
+    /// ```
+    pub fn validation_errors(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
         if self.nts_ke_server.is_empty() {
-            return Err(crate::error::Error::InvalidConfig(
-                "NTS-KE server hostname is required".to_string(),
+            errors.push(ConfigError::new(
+                "nts_ke_server",
+                "NTS-KE server hostname is required",
+            ));
+        } else if self.nts_ke_server.contains('/')
+            || (self.nts_ke_server.contains(':') && self.nts_ke_server.parse::<Ipv6Addr>().is_err())
+        {
+            errors.push(ConfigError::new(
+                "nts_ke_server",
+                format!("Invalid NTS-KE server: {}", self.nts_ke_server),
+            ));
+        }
+
+        if self.nts_ke_port == 0 {
+            errors.push(ConfigError::new("nts_ke_port", "NTS-KE port must not be 0"));
+        }
+
+        if self.timeout.is_zero() {
+            errors.push(ConfigError::new(
+                "timeout",
+                "Timeout must be greater than zero",
+            ));
+        }
+
+        if self.ke_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            errors.push(ConfigError::new(
+                "ke_timeout",
+                "Key exchange timeout must be greater than zero",
+            ));
+        }
+
+        if self.query_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            errors.push(ConfigError::new(
+                "query_timeout",
+                "Query timeout must be greater than zero",
+            ));
+        }
+
+        if self.fallback_servers.iter().any(String::is_empty) {
+            errors.push(ConfigError::new(
+                "fallback_servers",
+                "Fallback server hostnames must not be empty",
+            ));
+        }
+
+        if self.max_packet_size < MIN_PACKET_SIZE {
+            errors.push(ConfigError::new(
+                "max_packet_size",
+                format!(
+                    "Maximum packet size must be at least {} bytes",
+                    MIN_PACKET_SIZE
+                ),
+            ));
+        }
+
+        if self.denied_servers.iter().any(String::is_empty) {
+            errors.push(ConfigError::new(
+                "denied_servers",
+                "Denied server names must not be empty",
+            ));
+        }
+
+        if let RedirectPolicy::Allowlist(servers) = &self.redirect_policy {
+            if servers.iter().any(String::is_empty) {
+                errors.push(ConfigError::new(
+                    "redirect_policy",
+                    "Allowed redirect targets must not be empty",
+                ));
+            }
+        }
+
+        if self.blacklist.max_failures == 0 {
+            errors.push(ConfigError::new(
+                "blacklist",
+                "Blacklist failure threshold must be at least 1",
+            ));
+        }
+
+        if self
+            .circuit_breaker
+            .is_some_and(|policy| policy.failure_threshold == 0)
+        {
+            errors.push(ConfigError::new(
+                "circuit_breaker",
+                "Circuit breaker failure threshold must be at least 1",
+            ));
+        }
+
+        if self.stats_window == Some(0) {
+            errors.push(ConfigError::new(
+                "stats_window",
+                "Statistics window must hold at least one sample",
+            ));
+        }
+
+        if self.history_capacity == Some(0) {
+            errors.push(ConfigError::new(
+                "history_capacity",
+                "History must hold at least one sample",
             ));
         }
 
         if self.ntp_version < 3 || self.ntp_version > 4 {
-            return Err(crate::error::Error::InvalidConfig(
-                "NTP version must be 3 or 4".to_string(),
+            errors.push(ConfigError::new(
+                "ntp_version",
+                "NTP version must be 3 or 4",
+            ));
+        }
+
+        if !self.verify_tls_cert {
+            if !cfg!(feature = "insecure") {
+                errors.push(ConfigError::new(
+                    "verify_tls_cert",
+                    "Disabling TLS certificate verification requires the `insecure` feature",
+                ));
+            }
+            if !self.root_certificates.is_empty() || self.ca_file.is_some() {
+                errors.push(ConfigError::new(
+                    "verify_tls_cert",
+                    "Trusted root certificates are ignored when TLS certificate verification \
+                     is disabled",
+                ));
+            }
+            if !self.spki_pins.is_empty() {
+                errors.push(ConfigError::new(
+                    "spki_pins",
+                    "SPKI pins require TLS certificate verification",
+                ));
+            }
+        }
+
+        if let Some(name) = &self.tls_server_name {
+            if rustls::pki_types::ServerName::try_from(name.as_str()).is_err() {
+                errors.push(ConfigError::new(
+                    "tls_server_name",
+                    format!("Invalid TLS server name: {}", name),
+                ));
+            }
+        }
+
+        if self
+            .min_protocol_version
+            .is_some_and(|version| !(4..=5).contains(&version))
+        {
+            errors.push(ConfigError::new(
+                "min_protocol_version",
+                "Minimum protocol version must be 4 or 5",
+            ));
+        }
+
+        if self.dscp.is_some_and(|dscp| dscp > 63) {
+            errors.push(ConfigError::new(
+                "dscp",
+                "DSCP value must be between 0 and 63",
+            ));
+        }
+
+        if self.ttl.is_some_and(|ttl| ttl == 0 || ttl > 255) {
+            errors.push(ConfigError::new("ttl", "TTL must be between 1 and 255"));
+        }
+
+        if let Some(message) = self.proxy.as_ref().and_then(ProxyConfig::validation_error) {
+            errors.push(ConfigError::new("proxy", message));
+        }
+
+        if self.interface.is_some()
+            && !cfg!(any(
+                target_os = "android",
+                target_os = "fuchsia",
+                target_os = "linux"
+            ))
+        {
+            errors.push(ConfigError::new(
+                "interface",
+                "Binding to an interface is only supported on Linux",
             ));
         }
 
-        Ok(())
+        // WASI sockets are created through std, which offers neither
+        if cfg!(target_os = "wasi") {
+            if self.dscp.is_some() {
+                errors.push(ConfigError::new("dscp", "DSCP is not supported on WASI"));
+            }
+            if self.bind_address.is_some() {
+                errors.push(ConfigError::new(
+                    "bind_address",
+                    "Binding to a local address is not supported on WASI",
+                ));
+            }
+        }
+
+        errors
+    }
+
+    /// Validate the configuration, reporting every problem in a single
+    /// [`Error::InvalidConfig`](crate::Error::InvalidConfig).
+    pub(crate) fn validate(&self) -> crate::error::Result<()> {
+        let errors = self.validation_errors();
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        Err(crate::error::Error::InvalidConfig(messages.join("; ")))
+    }
+}
+
+/// A problem found by [`NtsClientConfig::validation_errors`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct ConfigError {
+    /// Name of the offending configuration field.
+    pub field: &'static str,
+
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+/// Split an NTS-KE server given as `host`, `host:port`, `[ipv6]:port` or
+/// `nts://host:port` into its host and optional port.
+///
+/// Unparseable ports are left in the host, which then fails validation.
+fn parse_server(server: &str) -> (String, Option<u16>) {
+    let server = match server.get(..6) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("nts://") => &server[6..],
+        _ => server,
+    };
+    let server = server.strip_suffix('/').unwrap_or(server);
+
+    if let Some(rest) = server.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            if after.is_empty() {
+                return (host.to_string(), None);
+            }
+            if let Some(port) = after.strip_prefix(':').and_then(|p| p.parse().ok()) {
+                return (host.to_string(), Some(port));
+            }
+        }
+        return (server.to_string(), None);
+    }
+
+    // A bare IPv6 address contains several colons and no port
+    match server.split_once(':') {
+        Some((host, port)) if !port.contains(':') => match port.parse() {
+            Ok(port) => (host.to_string(), Some(port)),
+            Err(_) => (server.to_string(), None),
+        },
+        _ => (server.to_string(), None),
     }
 }
 
@@ -133,10 +1258,58 @@ mod tests {
         assert_eq!(config.nts_ke_port, 4460);
         assert_eq!(config.ntp_version, 4);
         assert!(config.verify_tls_cert);
+        assert!(config.transmit_nonce);
+        assert!(!config.auto_connect);
+        assert_eq!(config.stats_window, None);
+        assert_eq!(config.history_capacity, None);
         // Default config with empty server should fail validation
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_server_with_port() {
+        let config = NtsClientConfig::new("time.example.com:1234");
+        assert_eq!(config.nts_ke_server, "time.example.com");
+        assert_eq!(config.nts_ke_port, 1234);
+
+        let config = NtsClientConfig::new("nts://time.example.com:4461/");
+        assert_eq!(config.nts_ke_server, "time.example.com");
+        assert_eq!(config.nts_ke_port, 4461);
+
+        let config = NtsClientConfig::new("NTS://time.example.com");
+        assert_eq!(config.nts_ke_server, "time.example.com");
+        assert_eq!(config.nts_ke_port, 4460);
+
+        let config = NtsClientConfig::new("[2001:db8::1]:4461");
+        assert_eq!(config.nts_ke_server, "2001:db8::1");
+        assert_eq!(config.nts_ke_port, 4461);
+
+        let config = NtsClientConfig::new("2001:db8::1");
+        assert_eq!(config.nts_ke_server, "2001:db8::1");
+        assert_eq!(config.nts_ke_port, 4460);
+        assert!(config.validate().is_ok());
+
+        // A port in the server replaces an earlier one, not a later one
+        let config = NtsClientConfig::new("a.example:1").with_port(2);
+        assert_eq!(config.nts_ke_port, 2);
+        let config = NtsClientConfig::default()
+            .with_port(2)
+            .with_server("a.example:1");
+        assert_eq!(config.nts_ke_port, 1);
+
+        for invalid in [
+            "time.example.com:http",
+            "[2001:db8::1",
+            "nts://a.example/path",
+        ] {
+            assert!(
+                NtsClientConfig::new(invalid).validate().is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
     #[test]
     fn test_builder_pattern() {
         let config = NtsClientConfig::new("custom.server.com")
@@ -164,6 +1337,32 @@ mod tests {
             .contains("hostname is required"));
     }
 
+    #[test]
+    fn test_validation_errors_are_aggregated() {
+        let config = NtsClientConfig::new("time.example.com")
+            .with_port(0)
+            .with_ntp_version(2)
+            .with_query_timeout(Duration::ZERO);
+        let fields: Vec<_> = config
+            .validation_errors()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, ["nts_ke_port", "query_timeout", "ntp_version"]);
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("port must not be 0; "), "{}", message);
+        assert!(
+            message.contains("NTP version must be 3 or 4"),
+            "{}",
+            message
+        );
+
+        assert!(NtsClientConfig::new("time.example.com")
+            .validation_errors()
+            .is_empty());
+    }
+
     #[test]
     fn test_invalid_ntp_version() {
         let config = NtsClientConfig {
@@ -189,8 +1388,126 @@ mod tests {
     }
 
     #[test]
+    fn test_separate_timeouts() {
+        let config = NtsClientConfig::new("test.server.com").with_timeout(Duration::from_secs(5));
+        assert_eq!(config.effective_ke_timeout(), Duration::from_secs(5));
+        assert_eq!(config.effective_query_timeout(), Duration::from_secs(5));
+
+        let config = config
+            .with_ke_timeout(Duration::from_secs(30))
+            .with_query_timeout(Duration::from_millis(500));
+        assert_eq!(config.effective_ke_timeout(), Duration::from_secs(30));
+        assert_eq!(config.effective_query_timeout(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_address_family_apply() {
+        let v4: SocketAddr = "192.0.2.1:123".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:123".parse().unwrap();
+        let addrs = vec![v6, v4];
+
+        assert_eq!(AddressFamily::Any.apply(addrs.clone()), vec![v6, v4]);
+        assert_eq!(AddressFamily::PreferIpv4.apply(addrs.clone()), vec![v4, v6]);
+        assert_eq!(AddressFamily::PreferIpv6.apply(addrs.clone()), vec![v6, v4]);
+        assert_eq!(AddressFamily::OnlyIpv4.apply(addrs.clone()), vec![v4]);
+        assert_eq!(AddressFamily::OnlyIpv6.apply(addrs), vec![v6]);
+    }
+
+    #[test]
+    fn test_dscp_validation() {
+        assert!(NtsClientConfig::new("test.server.com")
+            .with_dscp(48)
+            .validate()
+            .is_ok());
+        assert!(NtsClientConfig::new("test.server.com")
+            .with_dscp(64)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_fallback_servers() {
+        let config = NtsClientConfig::new("primary.example")
+            .with_tls_server_name("name.example")
+            .with_ke_addr("192.0.2.1:4460".parse().unwrap())
+            .with_port(4461)
+            .with_fallback_servers(["a.example", "b.example"]);
+        assert_eq!(config.fallback_servers, ["a.example", "b.example"]);
+        assert!(config.validate().is_ok());
+
+        let fallback = config.for_fallback("a.example");
+        assert_eq!(fallback.nts_ke_server, "a.example");
+        assert_eq!(fallback.effective_tls_server_name(), "a.example");
+        assert!(fallback.ke_addrs.is_empty());
+        assert!(fallback.fallback_servers.is_empty());
+        assert_eq!(fallback.nts_ke_port, 4461);
+
+        assert!(config.with_fallback_servers([""]).validate().is_err());
+    }
+
+    #[test]
+    fn test_tls_server_name() {
+        let config = NtsClientConfig::new("192.0.2.10");
+        assert_eq!(config.effective_tls_server_name(), "192.0.2.10");
+
+        let config = config.with_tls_server_name("time.example.com");
+        assert_eq!(config.effective_tls_server_name(), "time.example.com");
+        assert!(config.validate().is_ok());
+
+        assert!(NtsClientConfig::new("192.0.2.10")
+            .with_tls_server_name("not a hostname!")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_min_protocol_version_validation() {
+        let config = NtsClientConfig::new("test.server.com");
+        assert!(config
+            .clone()
+            .with_min_protocol_version(4)
+            .validate()
+            .is_ok());
+        assert!(config
+            .clone()
+            .with_min_protocol_version(5)
+            .validate()
+            .is_ok());
+        assert!(config.with_min_protocol_version(3).validate().is_err());
+    }
+
+    #[test]
+    fn test_ttl_validation() {
+        let config = NtsClientConfig::new("test.server.com");
+        assert!(config.clone().with_ttl(1).validate().is_ok());
+        assert!(config.clone().with_ttl(0).validate().is_err());
+        assert!(config.with_ttl(256).validate().is_err());
+    }
+
+    #[test]
+    fn test_disabled_verification_requires_insecure_feature() {
+        let mut config = NtsClientConfig::new("test.server.com");
+        config.verify_tls_cert = false;
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "insecure"));
+
+        // Pins are no substitute for chain verification
+        let errors = config.with_spki_pin([0; 32]).validation_errors();
+        assert!(errors
+            .iter()
+            .any(|e| e.field == "spki_pins" && e.message.contains("verification")));
+    }
+
+    #[test]
+    #[cfg(feature = "insecure")]
     fn test_tls_verification_disable() {
         let config = NtsClientConfig::new("test.server.com").with_tls_verification(false);
         assert!(!config.verify_tls_cert);
+        assert!(config.validate().is_ok());
+
+        // Trusted roots and disabled verification contradict each other
+        let config = config.with_ca_file("/etc/ssl/internal-ca.pem");
+        let errors = config.validation_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "verify_tls_cert");
     }
 }
@@ -0,0 +1,184 @@
+//! Pluggable storage for NTS cookies.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::types::NtsKeys;
+
+/// The key exchange a set of NTS cookies belongs to.
+///
+/// Cookies only authenticate with the keys negotiated alongside them, so
+/// the cookies of each key exchange are stored apart, even when several
+/// clients sharing a store use the same NTP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Association {
+    server: SocketAddr,
+    key_id: [u8; 8],
+}
+
+impl Association {
+    /// Association of the cookies for `server` issued with `keys`.
+    pub(crate) fn new(server: SocketAddr, keys: &NtsKeys) -> Self {
+        // A truncated hash tells keys apart without revealing them
+        let digest = ring::digest::digest(&ring::digest::SHA256, keys.c2s());
+        let mut key_id = [0; 8];
+        key_id.copy_from_slice(&digest.as_ref()[..8]);
+        Self { server, key_id }
+    }
+
+    /// The NTP server the cookies are sent to.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Fingerprint of the keys the cookies were issued with.
+    pub fn key_id(&self) -> [u8; 8] {
+        self.key_id
+    }
+}
+
+/// Stores the NTS cookies an [`NtsClient`](crate::NtsClient) received for
+/// each key exchange, see [`Association`].
+///
+/// Each cookie may only be sent once (RFC 8915, section 5.7), so cookies are
+/// handed out with [`take`](CookieStore::take) and removed from the store.
+/// Implement this trait to back the store with persistent storage or a
+/// keychain, or to observe cookie consumption. Wrapping a store in an
+/// [`Arc`] lets several clients share it.
+pub trait CookieStore: Send + Sync {
+    /// Add cookies received for `association`.
+    fn put(&self, association: Association, cookies: Vec<Vec<u8>>);
+
+    /// Remove and return one cookie for `association`, if any is left.
+    fn take(&self, association: Association) -> Option<Vec<u8>>;
+
+    /// The cookies stored for `association`, in the order they are handed
+    /// out, without removing them. Used to save them, see
+    /// [`NtsClient::save_state`](crate::NtsClient::save_state).
+    fn snapshot(&self, association: Association) -> Vec<Vec<u8>>;
+
+    /// Number of cookies stored for `association`.
+    fn len(&self, association: Association) -> usize;
+
+    /// Whether no cookie is stored for `association`.
+    fn is_empty(&self, association: Association) -> bool {
+        self.len(association) == 0
+    }
+
+    /// Remove all cookies stored for `association`, e.g. after a new key
+    /// exchange replaced its keys.
+    fn clear(&self, association: Association);
+}
+
+impl<S: CookieStore + ?Sized> CookieStore for Arc<S> {
+    fn put(&self, association: Association, cookies: Vec<Vec<u8>>) {
+        (**self).put(association, cookies)
+    }
+
+    fn take(&self, association: Association) -> Option<Vec<u8>> {
+        (**self).take(association)
+    }
+
+    fn snapshot(&self, association: Association) -> Vec<Vec<u8>> {
+        (**self).snapshot(association)
+    }
+
+    fn len(&self, association: Association) -> usize {
+        (**self).len(association)
+    }
+
+    fn clear(&self, association: Association) {
+        (**self).clear(association)
+    }
+}
+
+/// The default cookie store, kept in memory.
+///
+/// Cookies are handed out oldest first.
+#[derive(Debug, Default)]
+pub struct MemoryCookieStore {
+    cookies: Mutex<HashMap<Association, VecDeque<Vec<u8>>>>,
+}
+
+impl MemoryCookieStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Association, VecDeque<Vec<u8>>>> {
+        self.cookies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CookieStore for MemoryCookieStore {
+    fn put(&self, association: Association, cookies: Vec<Vec<u8>>) {
+        self.lock().entry(association).or_default().extend(cookies);
+    }
+
+    fn take(&self, association: Association) -> Option<Vec<u8>> {
+        self.lock().get_mut(&association)?.pop_front()
+    }
+
+    fn snapshot(&self, association: Association) -> Vec<Vec<u8>> {
+        self.lock()
+            .get(&association)
+            .map_or_else(Vec::new, |cookies| cookies.iter().cloned().collect())
+    }
+
+    fn len(&self, association: Association) -> usize {
+        self.lock().get(&association).map_or(0, VecDeque::len)
+    }
+
+    fn clear(&self, association: Association) {
+        self.lock().remove(&association);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn association(port: u16, key: u8) -> Association {
+        let keys =
+            NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![key; 32], vec![key; 32]).unwrap();
+        Association::new(SocketAddr::from(([192, 0, 2, 1], port)), &keys)
+    }
+
+    #[test]
+    fn test_memory_store_is_fifo_per_association() {
+        let store = MemoryCookieStore::new();
+        let first = association(123, 0);
+        let other_keys = association(123, 1);
+        let other_server = association(124, 0);
+        assert_ne!(first, other_keys);
+        store.put(first, vec![vec![1], vec![2]]);
+        store.put(other_keys, vec![vec![8]]);
+        store.put(other_server, vec![vec![9]]);
+        store.put(first, vec![vec![3]]);
+
+        assert_eq!(store.len(first), 3);
+        assert_eq!(store.snapshot(first), [vec![1], vec![2], vec![3]]);
+        assert_eq!(store.take(first), Some(vec![1]));
+        assert_eq!(store.take(first), Some(vec![2]));
+        assert_eq!(store.take(first), Some(vec![3]));
+        assert_eq!(store.take(first), None);
+        assert!(store.is_empty(first));
+        assert_eq!(store.len(other_keys), 1);
+        assert_eq!(store.len(other_server), 1);
+
+        store.clear(other_server);
+        assert!(store.is_empty(other_server));
+        assert_eq!(store.take(other_keys), Some(vec![8]));
+    }
+
+    #[test]
+    fn test_shared_store() {
+        let store = Arc::new(MemoryCookieStore::new());
+        let other = Arc::clone(&store);
+        store.put(association(123, 0), vec![vec![1]]);
+        assert_eq!(other.take(association(123, 0)), Some(vec![1]));
+        assert!(store.is_empty(association(123, 0)));
+    }
+}
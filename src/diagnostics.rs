@@ -0,0 +1,293 @@
+//! Machine-readable diagnostics.
+//!
+//! The report uses plain units (milliseconds, Unix seconds, strings) rather
+//! than library types, so its serialized form stays stable across releases.
+//! Fields are only added; anything else bumps [`DIAGNOSTICS_SCHEMA_VERSION`].
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::types::{NtsKeResult, TimeSnapshot, TimingBreakdown, TlsDetails};
+
+/// Version of the [`DiagnosticsReport`] schema.
+pub const DIAGNOSTICS_SCHEMA_VERSION: u32 = 1;
+
+/// State of a client for diagnostics, returned by
+/// [`NtsClient::diagnostics_report`](crate::NtsClient::diagnostics_report).
+///
+/// With the `serde` feature, serialize it with any serde format, e.g.
+/// `serde_json::to_string(&report)`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DiagnosticsReport {
+    /// Always [`DIAGNOSTICS_SCHEMA_VERSION`].
+    pub schema_version: u32,
+
+    /// Configured NTS-KE server hostname.
+    pub server: String,
+
+    /// Whether the client holds NTS keys.
+    pub connected: bool,
+
+    /// The current key exchange, `None` if not connected.
+    pub key_exchange: Option<KeyExchangeReport>,
+
+    /// TLS session of the current key exchange, `None` if not connected or
+    /// connected without a key exchange.
+    pub tls: Option<TlsReport>,
+
+    /// Duration of each connection and query phase.
+    pub timings: TimingsReport,
+
+    /// Cookie state for the current NTP server.
+    pub cookies: CookieReport,
+
+    /// The last successful time query, if any.
+    pub last_snapshot: Option<SnapshotReport>,
+}
+
+/// Outcome of a key exchange.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyExchangeReport {
+    /// NTS-KE hostname the client is bound to (primary or fallback).
+    pub bound_server: Option<String>,
+
+    /// NTS-KE server address.
+    pub ke_address: String,
+
+    /// NTP server address negotiated for time queries.
+    pub ntp_server: String,
+
+    /// How a redirect to another NTP server was handled:
+    /// `not_redirected`, `followed` or `allowed`.
+    pub redirect: String,
+
+    /// Negotiated AEAD algorithm.
+    pub aead_algorithm: String,
+
+    /// Negotiated NTP version.
+    pub protocol_version: u8,
+
+    /// Duration of the key exchange, in milliseconds.
+    pub duration_ms: f64,
+}
+
+/// TLS session details of a key exchange.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TlsReport {
+    /// Negotiated TLS version, if known.
+    pub protocol_version: Option<String>,
+
+    /// Negotiated cipher suite, if known.
+    pub cipher_suite: Option<String>,
+
+    /// ALPN protocol selected by the server, if any.
+    pub alpn_protocol: Option<String>,
+
+    /// Whether an earlier TLS session was resumed.
+    pub session_resumed: bool,
+
+    /// Subject of the server's leaf certificate.
+    pub certificate_subject: Option<String>,
+
+    /// Issuer of the server's leaf certificate.
+    pub certificate_issuer: Option<String>,
+
+    /// End of the leaf certificate validity period, in Unix seconds.
+    pub certificate_not_after: Option<f64>,
+
+    /// Whether the leaf certificate expires soon.
+    pub certificate_expiring: bool,
+
+    /// Whether the certificate validity period was ignored.
+    pub validity_ignored: bool,
+}
+
+/// Phase durations, in milliseconds. Phases that did not run are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimingsReport {
+    /// DNS resolution of the NTS-KE server.
+    pub dns_resolution_ms: Option<f64>,
+
+    /// TCP connection to the NTS-KE server.
+    pub tcp_connect_ms: Option<f64>,
+
+    /// TLS handshake.
+    pub tls_handshake_ms: Option<f64>,
+
+    /// NTS-KE records exchange.
+    pub ke_records_ms: Option<f64>,
+
+    /// Last NTP request/response exchange.
+    pub ntp_round_trip_ms: Option<f64>,
+}
+
+/// Cookie state.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CookieReport {
+    /// Unused cookies for the current NTP server.
+    pub remaining: usize,
+
+    /// Whether few enough cookies remain that a
+    /// [`ClientEvent::CookiesLow`](crate::ClientEvent::CookiesLow) is emitted.
+    pub low: bool,
+
+    /// Sizes in bytes of the cookies received in the key exchange.
+    pub initial_sizes: Vec<usize>,
+}
+
+/// A time measurement.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnapshotReport {
+    /// System time of the measurement, in Unix seconds.
+    pub system_time: f64,
+
+    /// Network time of the measurement, in Unix seconds.
+    pub network_time: f64,
+
+    /// Offset in milliseconds, positive when the system clock is ahead.
+    pub offset_ms: f64,
+
+    /// Round-trip delay, in milliseconds.
+    pub round_trip_ms: f64,
+
+    /// Root distance, in milliseconds.
+    pub root_distance_ms: f64,
+
+    /// NTP server address.
+    pub server: String,
+
+    /// Whether the response was authenticated.
+    pub authenticated: bool,
+
+    /// Server stratum.
+    pub stratum: u8,
+
+    /// Leap indicator, e.g. `NoWarning`.
+    pub leap_indicator: String,
+
+    /// Reference identifier, formatted for display.
+    pub reference_id: String,
+}
+
+impl KeyExchangeReport {
+    pub(crate) fn new(result: &NtsKeResult, bound_server: Option<String>) -> Self {
+        Self {
+            bound_server,
+            ke_address: result.ke_server.to_string(),
+            ntp_server: result.ntp_server.to_string(),
+            redirect: result.redirect().as_str().to_string(),
+            aead_algorithm: result.aead_algorithm.clone(),
+            protocol_version: result.protocol_version(),
+            duration_ms: millis(result.ke_duration()),
+        }
+    }
+}
+
+impl From<&TlsDetails> for TlsReport {
+    fn from(tls: &TlsDetails) -> Self {
+        let leaf = tls.leaf_certificate.as_ref();
+        Self {
+            protocol_version: tls.protocol_version.clone(),
+            cipher_suite: tls.cipher_suite.clone(),
+            alpn_protocol: tls.alpn_protocol.clone(),
+            session_resumed: tls.session_resumed,
+            certificate_subject: leaf.map(|c| c.subject.clone()),
+            certificate_issuer: leaf.map(|c| c.issuer.clone()),
+            certificate_not_after: leaf.map(|c| unix_secs(c.not_after)),
+            certificate_expiring: tls.certificate_expiring,
+            validity_ignored: tls.validity_ignored,
+        }
+    }
+}
+
+impl From<&TimingBreakdown> for TimingsReport {
+    fn from(timings: &TimingBreakdown) -> Self {
+        Self {
+            dns_resolution_ms: timings.dns_resolution.map(millis),
+            tcp_connect_ms: timings.tcp_connect.map(millis),
+            tls_handshake_ms: timings.tls_handshake.map(millis),
+            ke_records_ms: timings.ke_records.map(millis),
+            ntp_round_trip_ms: timings.ntp_round_trip.map(millis),
+        }
+    }
+}
+
+impl From<&TimeSnapshot> for SnapshotReport {
+    fn from(snapshot: &TimeSnapshot) -> Self {
+        let info = &snapshot.server_info;
+        Self {
+            system_time: unix_secs(snapshot.system_time),
+            network_time: unix_secs(snapshot.network_time),
+            offset_ms: snapshot.clock_offset.as_secs_f64() * 1e3,
+            round_trip_ms: millis(snapshot.round_trip_delay),
+            root_distance_ms: millis(snapshot.root_distance()),
+            server: snapshot.server.clone(),
+            authenticated: snapshot.authenticated,
+            stratum: info.stratum,
+            leap_indicator: format!("{:?}", info.leap_indicator),
+            reference_id: info.reference_id_string(),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    signed_secs(UNIX_EPOCH, time)
+}
+
+/// Signed seconds from `from` to `to`.
+fn signed_secs(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ServerInfo;
+
+    #[test]
+    fn test_snapshot_report_units() {
+        let system_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let snapshot = TimeSnapshot {
+            round_trip_delay: Duration::from_millis(20),
+            server_info: ServerInfo {
+                stratum: 2,
+                reference_id: [192, 0, 2, 1],
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(
+                system_time,
+                crate::types::SignedDuration::from_nanos(-5_000_000),
+            )
+        };
+
+        let report = SnapshotReport::from(&snapshot);
+        assert_eq!(report.system_time, 1_700_000_000.0);
+        assert!((report.offset_ms + 5.0).abs() < 1e-6);
+        assert!((report.round_trip_ms - 20.0).abs() < 1e-9);
+        assert!((report.root_distance_ms - 10.0).abs() < 1e-9);
+        assert_eq!(report.leap_indicator, "NoWarning");
+        assert_eq!(report.reference_id, "192.0.2.1");
+
+        let timings = TimingsReport::from(&TimingBreakdown {
+            tcp_connect: Some(Duration::from_micros(1500)),
+            ..Default::default()
+        });
+        assert_eq!(timings.tcp_connect_ms, Some(1.5));
+        assert_eq!(timings.dns_resolution_ms, None);
+    }
+}
@@ -0,0 +1,231 @@
+//! Clock drift estimation from successive time samples.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use crate::types::TimeSnapshot;
+
+/// Estimates the frequency error of the system clock.
+///
+/// Fits a least-squares line through the offsets of the last `window`
+/// snapshots against their system time. The slope is the rate at which the
+/// system clock gains (positive) or loses (negative) time relative to the
+/// network time.
+///
+/// Offsets follow the sign convention of [`TimeSnapshot::offset_signed`]:
+/// positive when the system clock is ahead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rkik_nts::{DriftEstimator, NtsClient, NtsClientConfig};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// let mut estimator = DriftEstimator::new(16);
+/// let mut samples = client.time_stream(Duration::from_secs(64));
+/// while let Some(Ok(time)) = samples.next().await {
+///     estimator.add(&time);
+///     if let Some(estimate) = estimator.estimate() {
+///         println!("Drift: {:.2} ppm", estimate.drift_ppm);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DriftEstimator {
+    window: usize,
+    /// System time and signed offset in seconds of each sample.
+    samples: VecDeque<(SystemTime, f64)>,
+}
+
+/// Result of a drift estimation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftEstimate {
+    /// Frequency error in parts per million. Positive when the system clock
+    /// runs fast.
+    pub drift_ppm: f64,
+
+    /// Standard error of `drift_ppm`, or `None` with only two samples.
+    /// About 95% of the time, the true drift is within twice this value.
+    pub std_error_ppm: Option<f64>,
+
+    /// Number of samples the estimate is based on.
+    pub samples: usize,
+
+    /// Fitted offset at `reference`, in seconds.
+    offset_at_reference: f64,
+
+    /// System time of the oldest sample.
+    reference: SystemTime,
+}
+
+impl DriftEstimator {
+    /// Create an estimator keeping the last `window` samples (at least 2).
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(2),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add a sample, evicting the oldest one if the window is full.
+    pub fn add(&mut self, snapshot: &TimeSnapshot) {
+        let offset = snapshot.clock_offset.as_secs_f64();
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((snapshot.system_time, offset));
+    }
+
+    /// Number of samples currently in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Whether no sample was added since creation or the last
+    /// [`clear`](Self::clear).
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Drop all samples, e.g. after the system clock was stepped.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Estimate the drift, or `None` with fewer than two samples or if all
+    /// samples were taken at the same time.
+    pub fn estimate(&self) -> Option<DriftEstimate> {
+        let n = self.samples.len();
+        let &(reference, _) = self.samples.front()?;
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|&(time, offset)| (seconds_between(reference, time), offset))
+            .collect();
+
+        let count = n as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / count;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / count;
+        let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        if n < 2 || sxx <= 0.0 {
+            return None;
+        }
+
+        let slope = sxy / sxx;
+        let intercept = mean_y - slope * mean_x;
+        let std_error = (n > 2).then(|| {
+            let residuals: f64 = points
+                .iter()
+                .map(|p| (p.1 - (intercept + slope * p.0)).powi(2))
+                .sum();
+            (residuals / (count - 2.0) / sxx).sqrt()
+        });
+
+        Some(DriftEstimate {
+            drift_ppm: slope * 1e6,
+            std_error_ppm: std_error.map(|e| e * 1e6),
+            samples: n,
+            offset_at_reference: intercept,
+            reference,
+        })
+    }
+}
+
+impl Default for DriftEstimator {
+    /// An estimator keeping the last 8 samples, the size of the NTP clock
+    /// filter.
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+impl DriftEstimate {
+    /// Predicted offset of the system clock at `at`, in seconds, positive
+    /// when the system clock is ahead.
+    pub fn predicted_offset(&self, at: SystemTime) -> f64 {
+        self.offset_at_reference + self.drift_ppm / 1e6 * seconds_between(self.reference, at)
+    }
+
+    /// Time for the offset to change by `tolerance` at the estimated rate,
+    /// e.g. to choose the next polling interval. `None` without drift.
+    pub fn time_to_drift(&self, tolerance: Duration) -> Option<Duration> {
+        let rate = (self.drift_ppm / 1e6).abs();
+        (rate > 0.0).then(|| Duration::from_secs_f64(tolerance.as_secs_f64() / rate))
+    }
+}
+
+/// Signed seconds from `from` to `to`.
+fn seconds_between(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SignedDuration;
+    use std::time::UNIX_EPOCH;
+
+    fn snapshot(system_time: SystemTime, offset_secs: f64) -> TimeSnapshot {
+        let offset = SignedDuration::from_nanos((offset_secs * 1e9).round() as i128);
+        TimeSnapshot::for_test(system_time, offset)
+    }
+
+    #[test]
+    fn test_estimates_linear_drift() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut estimator = DriftEstimator::new(8);
+        assert!(estimator.estimate().is_none());
+
+        // 10ppm fast, starting 1ms behind
+        for i in 0..5 {
+            let elapsed = 64.0 * i as f64;
+            estimator.add(&snapshot(
+                start + Duration::from_secs_f64(elapsed),
+                -0.001 + elapsed * 10e-6,
+            ));
+        }
+
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimate.samples, 5);
+        assert!((estimate.drift_ppm - 10.0).abs() < 1e-3);
+        assert!(estimate.std_error_ppm.unwrap() < 1e-3);
+
+        let predicted = estimate.predicted_offset(start + Duration::from_secs(1000));
+        assert!((predicted - 0.009).abs() < 1e-6);
+
+        let to_drift = estimate.time_to_drift(Duration::from_millis(1)).unwrap();
+        assert!((to_drift.as_secs_f64() - 100.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_window_evicts_oldest() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut estimator = DriftEstimator::new(2);
+
+        // An outlier followed by two samples without drift
+        estimator.add(&snapshot(start, 1.0));
+        estimator.add(&snapshot(start + Duration::from_secs(10), 0.0));
+        estimator.add(&snapshot(start + Duration::from_secs(20), 0.0));
+
+        let estimate = estimator.estimate().unwrap();
+        assert_eq!(estimator.len(), 2);
+        assert_eq!(estimate.drift_ppm, 0.0);
+        assert_eq!(estimate.std_error_ppm, None);
+        assert_eq!(estimate.time_to_drift(Duration::from_millis(1)), None);
+
+        // Samples taken at the same time carry no drift information
+        estimator.clear();
+        estimator.add(&snapshot(start, 0.0));
+        estimator.add(&snapshot(start, 0.5));
+        assert!(estimator.estimate().is_none());
+    }
+}
@@ -1,8 +1,14 @@
 //! Error types for the NTS client library.
 
 use std::io;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
 use thiserror::Error;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// Result type for NTS operations.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -14,12 +20,24 @@ pub enum Error {
     Io(#[from] io::Error),
 
     /// TLS/connection error during NTS key exchange.
-    #[error("TLS error: {0}")]
-    Tls(String),
+    #[error("TLS error: {message}")]
+    Tls {
+        /// What failed.
+        message: String,
+        /// The underlying rustls or certificate error, if any.
+        #[source]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
 
     /// NTS key exchange failed.
-    #[error("NTS key exchange failed: {0}")]
-    KeyExchange(String),
+    #[error("NTS key exchange failed: {message}")]
+    KeyExchange {
+        /// What failed.
+        message: String,
+        /// The underlying error, if any.
+        #[source]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
 
     /// NTP protocol error.
     #[error("NTP protocol error: {0}")]
@@ -29,6 +47,90 @@ pub enum Error {
     #[error("Invalid server response: {0}")]
     InvalidResponse(String),
 
+    /// The response was not sent in server mode (4).
+    #[error("Invalid server response: unexpected mode {0}")]
+    InvalidMode(u8),
+
+    /// The response stratum is outside the valid range 1-15.
+    #[error("Invalid server response: stratum {0} out of range")]
+    InvalidStratum(u8),
+
+    /// The response did not fit the receive buffer, see
+    /// [`NtsClientConfig::max_packet_size`](crate::NtsClientConfig::max_packet_size).
+    #[error("Invalid server response: truncated to {size} bytes")]
+    ResponseTruncated {
+        /// Size of the receive buffer.
+        size: usize,
+    },
+
+    /// The server reported that its clock is not synchronized.
+    #[error("Invalid server response: server clock is unsynchronized")]
+    ServerUnsynchronized,
+
+    /// The server's root distance exceeds the configured maximum.
+    #[error("Root distance {distance:?} exceeds maximum {max:?}")]
+    RootDistanceExceeded {
+        /// Measured root distance.
+        distance: std::time::Duration,
+        /// Configured maximum.
+        max: std::time::Duration,
+    },
+
+    /// The measured offset exceeds the configured plausibility limit.
+    #[error("Implausible time: offset {offset:?} exceeds maximum {max:?}")]
+    ImplausibleTime {
+        /// Measured absolute offset.
+        offset: std::time::Duration,
+        /// Configured maximum.
+        max: std::time::Duration,
+    },
+
+    /// The measurement was taken too close to an announced leap second, see
+    /// [`LeapSecondHandling::Reject`](crate::leap::LeapSecondHandling::Reject).
+    #[error("Measurement too close to the leap second at {at:?}")]
+    LeapSecond {
+        /// Time of the leap second.
+        at: std::time::SystemTime,
+    },
+
+    /// The server negotiated an older protocol version than required.
+    #[error(
+        "Protocol downgrade: server negotiated NTPv{negotiated}, but NTPv{required} is required"
+    )]
+    ProtocolDowngrade {
+        /// NTP version negotiated by the server.
+        negotiated: u8,
+        /// Configured minimum version.
+        required: u8,
+    },
+
+    /// The NTS-KE server directed the client to a denied NTP server, see
+    /// [`NtsClientConfig::with_denied_servers`](crate::NtsClientConfig::with_denied_servers).
+    #[error("NTS-KE server directed the client to denied NTP server {server}")]
+    ServerDenied {
+        /// The NTP server named in the key exchange response.
+        server: String,
+    },
+
+    /// The NTS-KE server redirected the client to another NTP server, which
+    /// the [`RedirectPolicy`](crate::RedirectPolicy) does not allow.
+    #[error("NTS-KE redirect to {server}:{port} rejected by redirect policy")]
+    RedirectRejected {
+        /// The NTP server named in the key exchange response.
+        server: String,
+        /// The NTP port named in the key exchange response.
+        port: u16,
+    },
+
+    /// The server at the NTS-KE address does not speak NTS-KE, e.g. an
+    /// HTTPS server sharing port 443 that answered with HTTP or refused the
+    /// `ntske/1` ALPN protocol.
+    #[error("Server does not speak NTS-KE: {reason}")]
+    NotNtsKe {
+        /// What the server did instead.
+        reason: String,
+    },
+
     /// Timeout occurred during operation.
     #[error("Operation timed out")]
     Timeout,
@@ -41,18 +143,323 @@ pub enum Error {
     #[error("Server unreachable: {0}")]
     ServerUnavailable(String),
 
+    /// A server hostname could not be resolved.
+    #[error("DNS resolution of {host} failed: {reason}")]
+    Dns {
+        /// The hostname that was looked up.
+        host: String,
+        /// Why the lookup failed.
+        reason: String,
+    },
+
+    /// The client has no NTS keys; call `connect()` first.
+    #[error("Not connected. Call connect() first.")]
+    NotConnected,
+
+    /// No NTS cookies are left for the NTP server, so a new key exchange is
+    /// needed.
+    #[error("No NTS cookies left for {server}")]
+    CookieExhausted {
+        /// The NTP server the cookies were issued for.
+        server: SocketAddr,
+    },
+
+    /// A response's origin timestamp does not echo the request's transmit
+    /// timestamp: it is stale, spoofed or replayed.
+    #[error(
+        "Possible replay: response origin {received:016x} does not match request {expected:016x}"
+    )]
+    ReplayDetected {
+        /// Transmit timestamp field of the request.
+        expected: u64,
+        /// Origin timestamp field of the response.
+        received: u64,
+    },
+
+    /// The NTS-KE server's certificate is expired or not yet valid.
+    ///
+    /// If the system clock may be wrong, see
+    /// [`NtsClientConfig::with_clock_skew_tolerance`](crate::NtsClientConfig::with_clock_skew_tolerance).
+    #[error("NTS-KE server certificate is outside its validity period")]
+    CertificateExpired {
+        /// End of the certificate validity period, if known.
+        not_after: Option<SystemTime>,
+    },
+
     /// Authentication failed.
     #[error("Authentication failed: {0}")]
     AuthenticationFailed(String),
 
+    /// The server sent a Kiss-o'-Death packet.
+    #[error("Kiss-o'-Death received: {code}")]
+    KissOfDeath {
+        /// The four-character kiss code (e.g. `RATE`, `DENY`).
+        code: String,
+    },
+
+    /// Not enough servers of a pool agree on the time.
+    #[error("No majority agreement: {agreeing} of {total} servers agree")]
+    NoMajority {
+        /// Size of the largest group of agreeing servers.
+        agreeing: usize,
+        /// Number of servers queried.
+        total: usize,
+    },
+
+    /// Too many consecutive queries failed; queries fail fast until the
+    /// circuit breaker's cool-down ends.
+    #[error("Circuit open after repeated failures, retry in {retry_after:?}")]
+    CircuitOpen {
+        /// Time until the next query is allowed.
+        retry_after: std::time::Duration,
+    },
+
+    /// Operation failed after exhausting all retry attempts.
+    #[error("{source} (after {attempts} attempts)")]
+    RetriesExhausted {
+        /// Total number of attempts made, including the first one.
+        attempts: u32,
+        /// The error returned by the last attempt.
+        #[source]
+        source: Box<Error>,
+    },
+
     /// Generic error.
     #[error("{0}")]
     Other(String),
 }
 
+impl Error {
+    /// Whether the operation that produced this error may succeed if retried.
+    ///
+    /// Timeouts, transient socket errors and DNS failures are retryable.
+    /// Certificate, configuration and protocol errors are not, and neither
+    /// is [`Error::RetriesExhausted`], as the retries already happened.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout | Error::ServerUnavailable(_) | Error::Dns { .. } => true,
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the server could not be authenticated: a TLS or certificate
+    /// failure, a failed NTS authentication, or a replayed response.
+    ///
+    /// These point at a misconfiguration or an attack rather than a network
+    /// problem. Retried errors report the last attempt.
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Error::Tls { .. }
+            | Error::CertificateExpired { .. }
+            | Error::AuthenticationFailed(_)
+            | Error::ReplayDetected { .. } => true,
+            Error::RetriesExhausted { source, .. } => source.is_auth_failure(),
+            _ => false,
+        }
+    }
+
+    /// The category of this error. Retried errors report the category of
+    /// the last attempt.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(_) => ErrorKind::Io,
+            Error::Tls { .. } => ErrorKind::Tls,
+            Error::KeyExchange { .. } | Error::NotNtsKe { .. } => ErrorKind::KeyExchange,
+            Error::Protocol(_) | Error::ProtocolDowngrade { .. } => ErrorKind::Protocol,
+            Error::InvalidResponse(_)
+            | Error::InvalidMode(_)
+            | Error::InvalidStratum(_)
+            | Error::ResponseTruncated { .. }
+            | Error::ServerUnsynchronized => ErrorKind::InvalidResponse,
+            Error::RootDistanceExceeded { .. }
+            | Error::ImplausibleTime { .. }
+            | Error::LeapSecond { .. }
+            | Error::ServerDenied { .. }
+            | Error::RedirectRejected { .. } => ErrorKind::Rejected,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::InvalidConfig(_) => ErrorKind::Config,
+            Error::ServerUnavailable(_) => ErrorKind::Unavailable,
+            Error::Dns { .. } => ErrorKind::Dns,
+            Error::NotConnected => ErrorKind::NotConnected,
+            Error::CookieExhausted { .. } => ErrorKind::CookieExhausted,
+            Error::ReplayDetected { .. } => ErrorKind::Replay,
+            Error::CertificateExpired { .. } => ErrorKind::CertificateExpired,
+            Error::AuthenticationFailed(_) => ErrorKind::Authentication,
+            Error::KissOfDeath { .. } => ErrorKind::KissOfDeath,
+            Error::NoMajority { .. } => ErrorKind::NoMajority,
+            Error::CircuitOpen { .. } => ErrorKind::CircuitOpen,
+            Error::RetriesExhausted { source, .. } => source.kind(),
+            Error::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Category of an [`Error`], returned by [`Error::kind`].
+///
+/// [`as_str`](Self::as_str) gives a short, stable label such as `timeout`
+/// or `kiss_of_death`, suitable for metrics labels and machine-readable
+/// reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// [`Error::Io`].
+    Io,
+    /// [`Error::Tls`].
+    Tls,
+    /// [`Error::KeyExchange`] and [`Error::NotNtsKe`].
+    KeyExchange,
+    /// [`Error::Protocol`] and [`Error::ProtocolDowngrade`].
+    Protocol,
+    /// [`Error::InvalidResponse`], [`Error::InvalidMode`],
+    /// [`Error::InvalidStratum`], [`Error::ResponseTruncated`] and
+    /// [`Error::ServerUnsynchronized`].
+    InvalidResponse,
+    /// [`Error::RootDistanceExceeded`], [`Error::ImplausibleTime`],
+    /// [`Error::LeapSecond`], [`Error::ServerDenied`] and
+    /// [`Error::RedirectRejected`].
+    Rejected,
+    /// [`Error::Timeout`].
+    Timeout,
+    /// [`Error::InvalidConfig`].
+    Config,
+    /// [`Error::ServerUnavailable`].
+    Unavailable,
+    /// [`Error::Dns`].
+    Dns,
+    /// [`Error::NotConnected`].
+    NotConnected,
+    /// [`Error::CookieExhausted`].
+    CookieExhausted,
+    /// [`Error::ReplayDetected`].
+    Replay,
+    /// [`Error::CertificateExpired`].
+    CertificateExpired,
+    /// [`Error::AuthenticationFailed`].
+    Authentication,
+    /// [`Error::KissOfDeath`].
+    KissOfDeath,
+    /// [`Error::NoMajority`].
+    NoMajority,
+    /// [`Error::CircuitOpen`].
+    CircuitOpen,
+    /// [`Error::Other`].
+    Other,
+}
+
+impl ErrorKind {
+    /// The label of this kind, e.g. `timeout`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Tls => "tls",
+            ErrorKind::KeyExchange => "key_exchange",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::InvalidResponse => "invalid_response",
+            ErrorKind::Rejected => "rejected",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Config => "config",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::Dns => "dns",
+            ErrorKind::NotConnected => "not_connected",
+            ErrorKind::CookieExhausted => "cookie_exhausted",
+            ErrorKind::Replay => "replay",
+            ErrorKind::CertificateExpired => "certificate_expired",
+            ErrorKind::Authentication => "authentication",
+            ErrorKind::KissOfDeath => "kiss_of_death",
+            ErrorKind::NoMajority => "no_majority",
+            ErrorKind::CircuitOpen => "circuit_open",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A serializable summary of an [`Error`], for reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ErrorSummary {
+    /// Label of the error's [`ErrorKind`].
+    pub kind: String,
+
+    /// The error message.
+    pub message: String,
+}
+
+impl From<&Error> for ErrorSummary {
+    fn from(error: &Error) -> Self {
+        Self {
+            kind: error.kind().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl Error {
+    /// A TLS error for `message`, caused by `source`.
+    pub(crate) fn tls(
+        message: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Error::Tls {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    /// A key exchange error for `message`, caused by `source`.
+    pub(crate) fn key_exchange(
+        message: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Error::KeyExchange {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    /// Convert a rustls error, describing it as `message` unless it is a
+    /// certificate validity error.
+    pub(crate) fn from_rustls(message: &str, err: rustls::Error) -> Self {
+        use rustls::CertificateError;
+
+        match err {
+            rustls::Error::InvalidCertificate(CertificateError::ExpiredContext {
+                not_after,
+                ..
+            }) => Error::CertificateExpired {
+                not_after: Some(
+                    SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(not_after.as_secs()),
+                ),
+            },
+            rustls::Error::InvalidCertificate(
+                CertificateError::Expired
+                | CertificateError::NotValidYet
+                | CertificateError::NotValidYetContext { .. },
+            ) => Error::CertificateExpired { not_after: None },
+            err => Error::tls(message, err),
+        }
+    }
+}
+
 impl From<rustls::Error> for Error {
     fn from(err: rustls::Error) -> Self {
-        Error::Tls(err.to_string())
+        Error::from_rustls("connection failed", err)
     }
 }
 
@@ -60,6 +467,56 @@ impl From<rustls::Error> for Error {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_error_summary() {
+        let error = Error::RetriesExhausted {
+            attempts: 3,
+            source: Box::new(Error::Timeout),
+        };
+        let summary = ErrorSummary::from(&error);
+        assert_eq!(summary.kind, "timeout");
+        assert_eq!(summary.message, "Operation timed out (after 3 attempts)");
+        assert_eq!(
+            Error::KissOfDeath {
+                code: "RATE".to_string()
+            }
+            .kind(),
+            ErrorKind::KissOfDeath
+        );
+        assert_eq!(Error::NotConnected.kind(), ErrorKind::NotConnected);
+    }
+
+    #[test]
+    fn test_expired_certificate() {
+        use rustls::pki_types::UnixTime;
+        use rustls::CertificateError;
+
+        let error = Error::from(rustls::Error::InvalidCertificate(
+            CertificateError::ExpiredContext {
+                time: UnixTime::since_unix_epoch(std::time::Duration::from_secs(2_000)),
+                not_after: UnixTime::since_unix_epoch(std::time::Duration::from_secs(1_000)),
+            },
+        ));
+        assert!(matches!(
+            error,
+            Error::CertificateExpired { not_after: Some(t) }
+                if t == SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000)
+        ));
+        assert_eq!(error.kind().as_str(), "certificate_expired");
+
+        let error = Error::from(rustls::Error::InvalidCertificate(
+            CertificateError::UnknownIssuer,
+        ));
+        assert!(matches!(error, Error::Tls { .. }));
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(matches!(
+            source.downcast_ref::<rustls::Error>(),
+            Some(rustls::Error::InvalidCertificate(
+                CertificateError::UnknownIssuer
+            ))
+        ));
+    }
+
     #[test]
     fn test_error_display() {
         let err = Error::Timeout;
@@ -70,6 +527,60 @@ mod tests {
 
         let err = Error::ServerUnavailable("server down".to_string());
         assert_eq!(err.to_string(), "Server unreachable: server down");
+
+        let err = Error::InvalidStratum(16);
+        assert_eq!(
+            err.to_string(),
+            "Invalid server response: stratum 16 out of range"
+        );
+
+        let err = Error::ProtocolDowngrade {
+            negotiated: 4,
+            required: 5,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Protocol downgrade: server negotiated NTPv4, but NTPv5 is required"
+        );
+        assert!(!err.is_retryable());
+
+        let err = Error::ServerDenied {
+            server: "ntp1.pool.example".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "NTS-KE server directed the client to denied NTP server ntp1.pool.example"
+        );
+        assert_eq!(err.kind(), ErrorKind::Rejected);
+        assert!(!err.is_retryable());
+
+        let err = Error::RedirectRejected {
+            server: "ntp.example".to_string(),
+            port: 123,
+        };
+        assert_eq!(
+            err.to_string(),
+            "NTS-KE redirect to ntp.example:123 rejected by redirect policy"
+        );
+        assert_eq!(err.kind(), ErrorKind::Rejected);
+
+        let err = Error::CircuitOpen {
+            retry_after: std::time::Duration::from_secs(3),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Circuit open after repeated failures, retry in 3s"
+        );
+        assert!(!err.is_retryable());
+
+        let err = Error::NoMajority {
+            agreeing: 1,
+            total: 3,
+        };
+        assert_eq!(
+            err.to_string(),
+            "No majority agreement: 1 of 3 servers agree"
+        );
     }
 
     #[test]
@@ -79,6 +590,57 @@ mod tests {
         assert!(matches!(err, Error::Io(_)));
     }
 
+    #[test]
+    fn test_retryable_classification() {
+        assert!(Error::Timeout.is_retryable());
+        assert!(Error::ServerUnavailable("dns".to_string()).is_retryable());
+        assert!(Error::Io(io::Error::from(io::ErrorKind::WouldBlock)).is_retryable());
+        assert!(!Error::Io(io::Error::from(io::ErrorKind::PermissionDenied)).is_retryable());
+        assert!(!Error::Tls {
+            message: "bad certificate".to_string(),
+            source: None,
+        }
+        .is_retryable());
+        assert!(!Error::InvalidConfig("bad".to_string()).is_retryable());
+        assert!(Error::Dns {
+            host: "time.example.com".to_string(),
+            reason: "no addresses resolved".to_string(),
+        }
+        .is_retryable());
+        assert!(!Error::CertificateExpired { not_after: None }.is_retryable());
+    }
+
+    #[test]
+    fn test_auth_failure_classification() {
+        assert!(Error::Tls {
+            message: "bad certificate".to_string(),
+            source: None,
+        }
+        .is_auth_failure());
+        assert!(Error::RetriesExhausted {
+            attempts: 2,
+            source: Box::new(Error::ReplayDetected {
+                expected: 1,
+                received: 2
+            }),
+        }
+        .is_auth_failure());
+        assert!(!Error::Timeout.is_auth_failure());
+        assert!(!Error::KissOfDeath {
+            code: "DENY".to_string()
+        }
+        .is_auth_failure());
+    }
+
+    #[test]
+    fn test_retries_exhausted_display() {
+        let err = Error::RetriesExhausted {
+            attempts: 4,
+            source: Box::new(Error::Timeout),
+        };
+        assert_eq!(err.to_string(), "Operation timed out (after 4 attempts)");
+    }
+
     #[test]
     fn test_error_is_send_sync() {
         fn assert_send_sync<T: Send + Sync>() {}
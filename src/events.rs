@@ -0,0 +1,57 @@
+//! Client events and handlers.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::types::TimeSnapshot;
+
+/// An event emitted by [`NtsClient`](crate::NtsClient) as its state changes.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ClientEvent<'a> {
+    /// NTS key exchange succeeded and the client is ready to query time.
+    Connected {
+        /// The NTP server negotiated during key exchange.
+        ntp_server: SocketAddr,
+    },
+
+    /// Keys and cookies from an earlier connection were replaced, e.g. by
+    /// [`reconnect`](crate::NtsClient::reconnect). Emitted after
+    /// [`Connected`](Self::Connected).
+    Rekeyed {
+        /// The NTP server negotiated during key exchange.
+        ntp_server: SocketAddr,
+    },
+
+    /// A time query succeeded.
+    TimeReceived(&'a TimeSnapshot),
+
+    /// NTS key exchange failed.
+    KeyExchangeFailed(&'a Error),
+
+    /// A time query failed.
+    QueryFailed(&'a Error),
+
+    /// The server answered with a Kiss-o'-Death packet. Emitted before
+    /// [`QueryFailed`](Self::QueryFailed).
+    KissOfDeath {
+        /// The kiss code, e.g. `RATE` or `DENY`.
+        code: &'a str,
+    },
+
+    /// Fewer than [`COOKIE_LOW_WATERMARK`] unused cookies are left after a
+    /// query spent one; a new key exchange will be needed soon unless the
+    /// response brings new cookies.
+    CookiesLow {
+        /// Unused cookies for the NTP server.
+        remaining: usize,
+    },
+}
+
+/// Number of unused cookies under which [`ClientEvent::CookiesLow`] is
+/// emitted.
+pub const COOKIE_LOW_WATERMARK: usize = 2;
+
+/// Callback invoked for every [`ClientEvent`].
+pub type EventHandler = Arc<dyn Fn(&ClientEvent<'_>) + Send + Sync>;
@@ -0,0 +1,197 @@
+//! Export of negotiated NTS state for external NTP clients.
+//!
+//! This module is only available with the `export-keys` feature. It lets
+//! rkik-nts act as a key exchange broker: the key exchange runs here, and the
+//! resulting keys and cookies are handed to another process (for example an
+//! embedded C NTP client) that sends the authenticated NTP requests itself.
+//!
+//! # Wire format
+//!
+//! [`NtsMaterial::to_bytes`] produces the following layout. All integers are
+//! big-endian.
+//!
+//! | Field | Size | Description |
+//! |-------|------|-------------|
+//! | magic | 4 | ASCII `NTSM` |
+//! | version | 1 | Format version, currently `1` |
+//! | family | 1 | `4` for IPv4, `6` for IPv6 |
+//! | address | 4 or 16 | NTP server address |
+//! | port | 2 | NTP server port |
+//! | aead | 2 | AEAD algorithm identifier from the IANA registry |
+//! | key length | 2 | Length `n` of each key in bytes |
+//! | c2s key | n | Client-to-server key |
+//! | s2c key | n | Server-to-client key |
+//! | cookie count | 2 | Number of cookies that follow |
+//! | cookies | ... | Each cookie as a 2-byte length followed by its bytes |
+//!
+//! The exported keys are secrets. Anyone holding them can forge NTP responses
+//! for this association until the cookies expire.
+
+use std::net::{IpAddr, SocketAddr};
+
+use zeroize::Zeroizing;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::types::{NtsKeResult, NtsKeys};
+
+/// Magic bytes at the start of the binary export format.
+const MAGIC: &[u8; 4] = b"NTSM";
+
+/// Current version of the binary export format.
+const FORMAT_VERSION: u8 = 1;
+
+/// Negotiated NTS state, ready to be handed to another NTP client.
+///
+/// Key bytes are zeroed when dropped and never printed by `Debug`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NtsMaterial {
+    /// NTP server the keys and cookies were issued for.
+    pub ntp_server: SocketAddr,
+    /// AEAD algorithm identifier from the IANA registry.
+    pub aead_algorithm: u16,
+    /// Client-to-server key.
+    pub c2s_key: Zeroizing<Vec<u8>>,
+    /// Server-to-client key.
+    pub s2c_key: Zeroizing<Vec<u8>>,
+    /// Cookies to place in NTS Cookie extension fields, one per request.
+    pub cookies: Vec<Vec<u8>>,
+}
+
+impl NtsMaterial {
+    fn new(ntp_server: SocketAddr, keys: &NtsKeys, cookies: Vec<Vec<u8>>) -> Self {
+        Self {
+            ntp_server,
+            aead_algorithm: keys.aead_algorithm(),
+            c2s_key: Zeroizing::new(keys.c2s().to_vec()),
+            s2c_key: Zeroizing::new(keys.s2c().to_vec()),
+            cookies,
+        }
+    }
+
+    /// Encode the material in the binary format documented in the
+    /// [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Protocol`] if there are more than 65535 cookies or a
+    /// cookie is longer than 65535 bytes.
+    pub fn to_bytes(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let mut out = Zeroizing::new(Vec::with_capacity(
+            64 + 2 * self.c2s_key.len() + self.cookies.iter().map(|c| c.len() + 2).sum::<usize>(),
+        ));
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        match self.ntp_server.ip() {
+            IpAddr::V4(ip) => {
+                out.push(4);
+                out.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                out.push(6);
+                out.extend_from_slice(&ip.octets());
+            }
+        }
+        out.extend_from_slice(&self.ntp_server.port().to_be_bytes());
+        out.extend_from_slice(&self.aead_algorithm.to_be_bytes());
+        out.extend_from_slice(&length_prefix(self.c2s_key.len(), "key")?);
+        out.extend_from_slice(&self.c2s_key);
+        out.extend_from_slice(&self.s2c_key);
+        out.extend_from_slice(&length_prefix(self.cookies.len(), "cookie count")?);
+        for cookie in &self.cookies {
+            out.extend_from_slice(&length_prefix(cookie.len(), "cookie")?);
+            out.extend_from_slice(cookie);
+        }
+        Ok(out)
+    }
+}
+
+impl std::fmt::Debug for NtsMaterial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NtsMaterial")
+            .field("ntp_server", &self.ntp_server)
+            .field("aead_algorithm", &self.aead_algorithm)
+            .field("c2s_key", &"[redacted]")
+            .field("s2c_key", &"[redacted]")
+            .field("cookies", &self.cookies.len())
+            .finish()
+    }
+}
+
+fn length_prefix(len: usize, what: &str) -> Result<[u8; 2]> {
+    u16::try_from(len)
+        .map(u16::to_be_bytes)
+        .map_err(|_| Error::Protocol(format!("NTS material {} too large: {}", what, len)))
+}
+
+impl NtsKeResult {
+    /// Export the negotiated keys and cookies for use by another NTP client.
+    ///
+    /// See the [`export`](crate::export) module for the binary format.
+    pub fn export_material(&self) -> NtsMaterial {
+        NtsMaterial::new(self.ntp_server, &self.keys, self.cookies.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_material() -> NtsMaterial {
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![1; 32], vec![2; 32]).unwrap();
+        let result = NtsKeResult::from_fixed_keys(
+            "192.0.2.1:123".parse().unwrap(),
+            keys,
+            vec![vec![0xAA; 3], vec![0xBB; 2]],
+        );
+        result.export_material()
+    }
+
+    #[test]
+    fn test_export_material() {
+        let material = test_material();
+        assert_eq!(material.aead_algorithm, 15);
+        assert_eq!(*material.c2s_key, vec![1; 32]);
+        assert_eq!(*material.s2c_key, vec![2; 32]);
+        assert_eq!(material.cookies.len(), 2);
+        assert!(!format!("{:?}", material).contains("[1,"));
+    }
+
+    #[test]
+    fn test_to_bytes_layout() {
+        let bytes = test_material().to_bytes().unwrap();
+
+        let mut expected = b"NTSM\x01\x04".to_vec();
+        expected.extend_from_slice(&[192, 0, 2, 1, 0, 123, 0, 15, 0, 32]);
+        expected.extend_from_slice(&[1; 32]);
+        expected.extend_from_slice(&[2; 32]);
+        expected.extend_from_slice(&[0, 2, 0, 3, 0xAA, 0xAA, 0xAA, 0, 2, 0xBB, 0xBB]);
+        assert_eq!(*bytes, expected);
+    }
+
+    #[test]
+    fn test_to_bytes_ipv6() {
+        let mut material = test_material();
+        material.ntp_server = "[2001:db8::1]:4123".parse().unwrap();
+        let bytes = material.to_bytes().unwrap();
+        assert_eq!(bytes[5], 6);
+        assert_eq!(
+            bytes[6..22],
+            "2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets()
+        );
+        assert_eq!(bytes[22..24], 4123u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_to_bytes_rejects_oversized_cookie() {
+        let mut material = test_material();
+        material.cookies.push(vec![0; 70_000]);
+        assert!(matches!(material.to_bytes(), Err(Error::Protocol(_))));
+    }
+}
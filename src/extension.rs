@@ -0,0 +1,312 @@
+//! NTP extension fields (RFC 7822) and the NTS fields defined in RFC 8915.
+//!
+//! [`parse_extension_fields`] decodes the fields following the 48-byte NTP
+//! header, for protocol-level troubleshooting with
+//! [`NtsClient::get_time_debug`](crate::NtsClient::get_time_debug).
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::ntp_packet::HEADER_LEN;
+
+/// Lengths of a legacy MAC following the extension fields (RFC 7822, section 7.5).
+const LEGACY_MAC_LENS: [usize; 2] = [20, 24];
+
+/// Smallest extension field when no MAC follows (RFC 7822, section 7.5.1.4).
+const MIN_FIELD_LEN: usize = 16;
+
+const TYPE_UNIQUE_IDENTIFIER: u16 = 0x0104;
+const TYPE_NTS_COOKIE: u16 = 0x0204;
+const TYPE_NTS_COOKIE_PLACEHOLDER: u16 = 0x0304;
+const TYPE_NTS_AUTHENTICATOR: u16 = 0x0404;
+
+/// An NTP extension field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ExtensionField {
+    /// Unique Identifier (0x0104), echoed from the request.
+    UniqueIdentifier {
+        /// The identifier.
+        id: Vec<u8>,
+    },
+
+    /// NTS Cookie (0x0204). Only sent in the clear in requests; responses
+    /// carry new cookies inside the authenticator.
+    NtsCookie {
+        /// The cookie.
+        cookie: Vec<u8>,
+    },
+
+    /// NTS Cookie Placeholder (0x0304), requesting an additional cookie.
+    NtsCookiePlaceholder {
+        /// Size of the placeholder body in bytes.
+        length: usize,
+    },
+
+    /// NTS Authenticator and Encrypted Extension Fields (0x0404).
+    NtsAuthenticator {
+        /// The AEAD nonce.
+        nonce: Vec<u8>,
+        /// The AEAD ciphertext, including any encrypted extension fields.
+        ciphertext: Vec<u8>,
+    },
+
+    /// A field type this crate does not interpret.
+    Unknown {
+        /// Field type.
+        field_type: u16,
+        /// Field body, including any padding.
+        value: Vec<u8>,
+    },
+}
+
+impl ExtensionField {
+    /// The field type of the extension field.
+    pub fn field_type(&self) -> u16 {
+        match self {
+            ExtensionField::UniqueIdentifier { .. } => TYPE_UNIQUE_IDENTIFIER,
+            ExtensionField::NtsCookie { .. } => TYPE_NTS_COOKIE,
+            ExtensionField::NtsCookiePlaceholder { .. } => TYPE_NTS_COOKIE_PLACEHOLDER,
+            ExtensionField::NtsAuthenticator { .. } => TYPE_NTS_AUTHENTICATOR,
+            ExtensionField::Unknown { field_type, .. } => *field_type,
+        }
+    }
+
+    /// Append the field to `out`, zero-padded to a multiple of 4 bytes and
+    /// to the 16 bytes RFC 7822 requires of fields outside the authenticator.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&self.field_type().to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        match self {
+            ExtensionField::UniqueIdentifier { id } => out.extend_from_slice(id),
+            ExtensionField::NtsCookie { cookie } => out.extend_from_slice(cookie),
+            ExtensionField::NtsCookiePlaceholder { length } => out.resize(out.len() + length, 0),
+            ExtensionField::NtsAuthenticator { nonce, ciphertext } => {
+                out.extend_from_slice(&(nonce.len() as u16).to_be_bytes());
+                out.extend_from_slice(&(ciphertext.len() as u16).to_be_bytes());
+                out.extend_from_slice(nonce);
+                out.resize(out.len() + padded(nonce.len()) - nonce.len(), 0);
+                out.extend_from_slice(ciphertext);
+            }
+            ExtensionField::Unknown { value, .. } => out.extend_from_slice(value),
+        }
+        let len = padded(out.len() - start).max(MIN_FIELD_LEN);
+        out.resize(start + len, 0);
+        out[start + 2..start + 4].copy_from_slice(&(len as u16).to_be_bytes());
+    }
+
+    fn decode(field_type: u16, body: &[u8]) -> Result<Self> {
+        Ok(match field_type {
+            TYPE_UNIQUE_IDENTIFIER => ExtensionField::UniqueIdentifier { id: body.to_vec() },
+            TYPE_NTS_COOKIE => ExtensionField::NtsCookie {
+                cookie: body.to_vec(),
+            },
+            TYPE_NTS_COOKIE_PLACEHOLDER => {
+                ExtensionField::NtsCookiePlaceholder { length: body.len() }
+            }
+            TYPE_NTS_AUTHENTICATOR => {
+                let invalid =
+                    || Error::InvalidResponse("Malformed NTS authenticator field".to_string());
+                if body.len() < 4 {
+                    return Err(invalid());
+                }
+                let nonce_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let ciphertext_len = u16::from_be_bytes([body[2], body[3]]) as usize;
+                let nonce_start = 4;
+                let ciphertext_start = nonce_start + padded(nonce_len);
+                let nonce = body
+                    .get(nonce_start..nonce_start + nonce_len)
+                    .ok_or_else(invalid)?;
+                let ciphertext = body
+                    .get(ciphertext_start..ciphertext_start + ciphertext_len)
+                    .ok_or_else(invalid)?;
+                ExtensionField::NtsAuthenticator {
+                    nonce: nonce.to_vec(),
+                    ciphertext: ciphertext.to_vec(),
+                }
+            }
+            _ => ExtensionField::Unknown {
+                field_type,
+                value: body.to_vec(),
+            },
+        })
+    }
+}
+
+/// `len` rounded up to a multiple of 4.
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Parse the extension fields of an NTP packet.
+///
+/// `packet` is the whole packet including its 48-byte header. A legacy MAC
+/// after the extension fields is skipped.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidResponse`] if the packet is shorter than the
+/// header, or a field is truncated, not a multiple of 4 bytes long or
+/// malformed.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::extension::{parse_extension_fields, ExtensionField};
+///
+/// let mut packet = vec![0u8; 48];
+/// packet.extend_from_slice(&[0x01, 0x04, 0x00, 0x08, 1, 2, 3, 4]);
+/// assert_eq!(
+///     parse_extension_fields(&packet)?,
+///     vec![ExtensionField::UniqueIdentifier { id: vec![1, 2, 3, 4] }]
+/// );
+/// # Ok::<(), rkik_nts::Error>(())
+/// ```
+pub fn parse_extension_fields(packet: &[u8]) -> Result<Vec<ExtensionField>> {
+    let data = packet
+        .get(HEADER_LEN..)
+        .ok_or_else(|| Error::InvalidResponse("NTP packet too small".to_string()))?;
+    let fields = decode_fields(data, true)?;
+    Ok(fields.into_iter().map(|(_, field)| field).collect())
+}
+
+/// Decode the extension fields in `data`, with their offsets in `data`.
+///
+/// A legacy MAC at the end is skipped if `mac_allowed`; the plaintext of an
+/// NTS authenticator never holds one.
+pub(crate) fn decode_fields(
+    mut data: &[u8],
+    mac_allowed: bool,
+) -> Result<Vec<(usize, ExtensionField)>> {
+    let total = data.len();
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        if mac_allowed && LEGACY_MAC_LENS.contains(&data.len()) {
+            break;
+        }
+        if data.len() < 4 {
+            return Err(Error::InvalidResponse(format!(
+                "Truncated extension field header ({} bytes left)",
+                data.len()
+            )));
+        }
+        let field_type = u16::from_be_bytes([data[0], data[1]]);
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        if len < 4 || len % 4 != 0 || len > data.len() {
+            return Err(Error::InvalidResponse(format!(
+                "Invalid length {} of extension field 0x{:04x}",
+                len, field_type
+            )));
+        }
+        let offset = total - data.len();
+        fields.push((offset, ExtensionField::decode(field_type, &data[4..len])?));
+        data = &data[len..];
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(field_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut field = field_type.to_be_bytes().to_vec();
+        field.extend_from_slice(&((4 + body.len()) as u16).to_be_bytes());
+        field.extend_from_slice(body);
+        field
+    }
+
+    #[test]
+    fn test_parse_nts_response_fields() {
+        let mut authenticator = vec![0, 2, 0, 5];
+        authenticator.extend_from_slice(&[9, 9, 0, 0]); // nonce, padded
+        authenticator.extend_from_slice(&[1, 2, 3, 4, 5, 0, 0, 0]); // ciphertext, padded
+
+        let mut packet = vec![0u8; HEADER_LEN];
+        packet.extend(field(TYPE_UNIQUE_IDENTIFIER, &[7; 32]));
+        packet.extend(field(TYPE_NTS_AUTHENTICATOR, &authenticator));
+        packet.extend(field(0x1234, &[1, 2, 3, 4]));
+        packet.extend(field(TYPE_NTS_COOKIE_PLACEHOLDER, &[0; 100]));
+        packet.extend([0u8; 20]); // legacy MAC
+
+        let fields = parse_extension_fields(&packet).unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                ExtensionField::UniqueIdentifier { id: vec![7; 32] },
+                ExtensionField::NtsAuthenticator {
+                    nonce: vec![9, 9],
+                    ciphertext: vec![1, 2, 3, 4, 5],
+                },
+                ExtensionField::Unknown {
+                    field_type: 0x1234,
+                    value: vec![1, 2, 3, 4],
+                },
+                ExtensionField::NtsCookiePlaceholder { length: 100 },
+            ]
+        );
+        assert_eq!(fields[1].field_type(), TYPE_NTS_AUTHENTICATOR);
+        assert!(parse_extension_fields(&packet[..HEADER_LEN])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_malformed_fields() {
+        let header = [0u8; HEADER_LEN];
+        for fields in [
+            vec![0x01, 0x04],                                         // truncated header
+            vec![0x01, 0x04, 0x00, 0x06, 1, 2],                       // length not a multiple of 4
+            vec![0x01, 0x04, 0x00, 0x10, 1, 2, 3, 4],                 // length past the end
+            vec![0x01, 0x04, 0x00, 0x00],                             // zero length
+            field(TYPE_NTS_AUTHENTICATOR, &[0, 8, 0, 0, 1, 2, 3, 4]), // short nonce
+        ] {
+            let packet = [&header[..], &fields].concat();
+            assert!(
+                matches!(
+                    parse_extension_fields(&packet),
+                    Err(Error::InvalidResponse(_))
+                ),
+                "{:?}",
+                fields
+            );
+        }
+        assert!(parse_extension_fields(&header[..40]).is_err());
+    }
+
+    #[test]
+    fn test_encode_fields() {
+        let fields = vec![
+            ExtensionField::UniqueIdentifier { id: vec![7; 32] },
+            ExtensionField::NtsCookie { cookie: vec![1; 5] },
+            ExtensionField::NtsCookiePlaceholder { length: 5 },
+            ExtensionField::NtsAuthenticator {
+                nonce: vec![9; 16],
+                ciphertext: vec![3; 17],
+            },
+        ];
+        let mut packet = vec![0u8; HEADER_LEN];
+        for field in &fields {
+            field.encode(&mut packet);
+        }
+        // Short fields are padded to 16 bytes, the others to 4
+        assert_eq!(packet.len(), HEADER_LEN + 36 + 16 + 16 + (8 + 16 + 20));
+
+        let mut decoded = parse_extension_fields(&packet).unwrap();
+        assert_eq!(
+            decoded.remove(1),
+            ExtensionField::NtsCookie {
+                cookie: [vec![1; 5], vec![0; 7]].concat()
+            }
+        );
+        assert_eq!(
+            decoded.remove(1),
+            ExtensionField::NtsCookiePlaceholder { length: 12 }
+        );
+        assert_eq!(decoded, [fields[0].clone(), fields[3].clone()]);
+    }
+}
@@ -0,0 +1,460 @@
+//! C API, for linking the client into C and C++ programs.
+//!
+//! Built on the [`blocking`](crate::blocking) client, so no async runtime is
+//! needed on the C side. The crate is built as a Rust library only; build
+//! the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`, or the
+//! static one with `--crate-type staticlib`, and include
+//! `include/rkik_nts.h`, which is generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/rkik_nts.h`.
+//!
+//! ```c
+//! #include <stdio.h>
+//! #include "rkik_nts.h"
+//!
+//! int main(void) {
+//!     RkikNtsTime time;
+//!     if (rkik_nts_query("time.cloudflare.com", &time) != RKIK_NTS_STATUS_OK) {
+//!         fprintf(stderr, "query failed: %s\n", rkik_nts_last_error());
+//!         return 1;
+//!     }
+//!     printf("offset: %lld ns\n", (long long)time.offset_nanos);
+//!     return 0;
+//! }
+//! ```
+//!
+//! Functions return an [`RkikNtsStatus`]; on failure the message is
+//! available from [`rkik_nts_last_error`] on the same thread. Panics are
+//! caught at the boundary and reported as [`RkikNtsStatus::Panic`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::time::{Duration, SystemTime};
+
+use crate::blocking::NtsClient;
+use crate::config::NtsClientConfig;
+use crate::error::{Error, ErrorKind};
+use crate::types::TimeSnapshot;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Outcome of a C API call.
+///
+/// Besides `Ok`, `InvalidArgument` and `Panic`, each status corresponds to
+/// an [`ErrorKind`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RkikNtsStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer was null or a string was not valid UTF-8.
+    InvalidArgument = 1,
+    /// The library panicked; this is a bug.
+    Panic = 2,
+    /// [`ErrorKind::Io`].
+    Io = 10,
+    /// [`ErrorKind::Tls`].
+    Tls = 11,
+    /// [`ErrorKind::KeyExchange`].
+    KeyExchange = 12,
+    /// [`ErrorKind::Protocol`].
+    Protocol = 13,
+    /// [`ErrorKind::InvalidResponse`].
+    InvalidResponse = 14,
+    /// [`ErrorKind::Rejected`].
+    Rejected = 15,
+    /// [`ErrorKind::Timeout`].
+    Timeout = 16,
+    /// [`ErrorKind::Config`].
+    Config = 17,
+    /// [`ErrorKind::Unavailable`].
+    Unavailable = 18,
+    /// [`ErrorKind::Dns`].
+    Dns = 19,
+    /// [`ErrorKind::NotConnected`].
+    NotConnected = 20,
+    /// [`ErrorKind::CookieExhausted`].
+    CookieExhausted = 21,
+    /// [`ErrorKind::Replay`].
+    Replay = 22,
+    /// [`ErrorKind::CertificateExpired`].
+    CertificateExpired = 23,
+    /// [`ErrorKind::Authentication`].
+    Authentication = 24,
+    /// [`ErrorKind::KissOfDeath`].
+    KissOfDeath = 25,
+    /// [`ErrorKind::NoMajority`].
+    NoMajority = 26,
+    /// [`ErrorKind::CircuitOpen`].
+    CircuitOpen = 27,
+    /// [`ErrorKind::Other`].
+    Other = 28,
+}
+
+impl From<ErrorKind> for RkikNtsStatus {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::Io => RkikNtsStatus::Io,
+            ErrorKind::Tls => RkikNtsStatus::Tls,
+            ErrorKind::KeyExchange => RkikNtsStatus::KeyExchange,
+            ErrorKind::Protocol => RkikNtsStatus::Protocol,
+            ErrorKind::InvalidResponse => RkikNtsStatus::InvalidResponse,
+            ErrorKind::Rejected => RkikNtsStatus::Rejected,
+            ErrorKind::Timeout => RkikNtsStatus::Timeout,
+            ErrorKind::Config => RkikNtsStatus::Config,
+            ErrorKind::Unavailable => RkikNtsStatus::Unavailable,
+            ErrorKind::Dns => RkikNtsStatus::Dns,
+            ErrorKind::NotConnected => RkikNtsStatus::NotConnected,
+            ErrorKind::CookieExhausted => RkikNtsStatus::CookieExhausted,
+            ErrorKind::Replay => RkikNtsStatus::Replay,
+            ErrorKind::CertificateExpired => RkikNtsStatus::CertificateExpired,
+            ErrorKind::Authentication => RkikNtsStatus::Authentication,
+            ErrorKind::KissOfDeath => RkikNtsStatus::KissOfDeath,
+            ErrorKind::NoMajority => RkikNtsStatus::NoMajority,
+            ErrorKind::CircuitOpen => RkikNtsStatus::CircuitOpen,
+            ErrorKind::Other => RkikNtsStatus::Other,
+        }
+    }
+}
+
+/// Result of a time query.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RkikNtsTime {
+    /// Network time at the measurement, in whole seconds since the Unix
+    /// epoch.
+    pub unix_seconds: i64,
+    /// Nanoseconds past `unix_seconds`, below one billion.
+    pub unix_nanos: u32,
+    /// Offset of the system clock from the network time, in nanoseconds.
+    /// Positive when the system clock is ahead.
+    pub offset_nanos: i64,
+    /// Round-trip delay to the server, in nanoseconds.
+    pub round_trip_delay_nanos: u64,
+    /// Stratum of the server.
+    pub stratum: u8,
+    /// Whether the response was authenticated with NTS.
+    pub authenticated: bool,
+}
+
+impl From<&TimeSnapshot> for RkikNtsTime {
+    fn from(snapshot: &TimeSnapshot) -> Self {
+        let (unix_seconds, unix_nanos) =
+            match snapshot.network_time.duration_since(SystemTime::UNIX_EPOCH) {
+                Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+                Err(e) => {
+                    // Round down, keeping the nanoseconds positive
+                    let d = e.duration();
+                    match d.subsec_nanos() {
+                        0 => (-(d.as_secs() as i64), 0),
+                        nanos => (-(d.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                    }
+                }
+            };
+        let clamp = |nanos: i128| nanos.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        Self {
+            unix_seconds,
+            unix_nanos,
+            offset_nanos: clamp(snapshot.offset_nanos()),
+            round_trip_delay_nanos: u64::try_from(snapshot.round_trip_delay.as_nanos())
+                .unwrap_or(u64::MAX),
+            stratum: snapshot.server_info.stratum,
+            authenticated: snapshot.authenticated,
+        }
+    }
+}
+
+/// A client that keeps its keys and cookies between queries.
+///
+/// Created with [`rkik_nts_client_new`] and released with
+/// [`rkik_nts_client_free`].
+pub struct RkikNtsClient {
+    client: NtsClient,
+}
+
+/// Query the time from `server` once, with the default configuration.
+///
+/// `server` is a host name or address, optionally with a port, as accepted
+/// by [`NtsClientConfig::new`].
+///
+/// # Safety
+///
+/// `server` must be a valid NUL-terminated string and `out_result` must
+/// point to writable memory for an [`RkikNtsTime`].
+#[no_mangle]
+pub unsafe extern "C" fn rkik_nts_query(
+    server: *const c_char,
+    out_result: *mut RkikNtsTime,
+) -> RkikNtsStatus {
+    rkik_nts_query_with(server, 0, out_result)
+}
+
+/// Query the time from `server` once, giving up on each step after
+/// `timeout_ms` milliseconds, or the default timeout if 0.
+///
+/// # Safety
+///
+/// As for [`rkik_nts_query`].
+#[no_mangle]
+pub unsafe extern "C" fn rkik_nts_query_with(
+    server: *const c_char,
+    timeout_ms: u32,
+    out_result: *mut RkikNtsTime,
+) -> RkikNtsStatus {
+    guard(|| {
+        let config = config(server, timeout_ms)?;
+        let out_result = out_ptr(out_result)?;
+        let time = NtsClient::new(config).get_time()?;
+        *out_result = RkikNtsTime::from(&time);
+        Ok(())
+    })
+}
+
+/// Create a client for `server`, with `timeout_ms` as for
+/// [`rkik_nts_query_with`].
+///
+/// Returns null on failure. The client connects on its first query.
+///
+/// # Safety
+///
+/// `server` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rkik_nts_client_new(
+    server: *const c_char,
+    timeout_ms: u32,
+) -> *mut RkikNtsClient {
+    let mut client = ptr::null_mut();
+    guard(|| {
+        let config = config(server, timeout_ms)?;
+        config.validate()?;
+        client = Box::into_raw(Box::new(RkikNtsClient {
+            client: NtsClient::new(config),
+        }));
+        Ok(())
+    });
+    client
+}
+
+/// Query the time with `client`, performing a key exchange first if
+/// needed.
+///
+/// # Safety
+///
+/// `client` must come from [`rkik_nts_client_new`] and not have been freed,
+/// and `out_result` must point to writable memory for an [`RkikNtsTime`].
+#[no_mangle]
+pub unsafe extern "C" fn rkik_nts_client_get_time(
+    client: *const RkikNtsClient,
+    out_result: *mut RkikNtsTime,
+) -> RkikNtsStatus {
+    guard(|| {
+        let client = client.as_ref().ok_or(FfiError::Null("client"))?;
+        let out_result = out_ptr(out_result)?;
+        let time = client.client.get_time()?;
+        *out_result = RkikNtsTime::from(&time);
+        Ok(())
+    })
+}
+
+/// Release a client. Null is ignored.
+///
+/// # Safety
+///
+/// `client` must be null or come from [`rkik_nts_client_new`], and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rkik_nts_client_free(client: *mut RkikNtsClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// The message of the last failed call on this thread, or null.
+///
+/// The string stays valid until the next call into the library on this
+/// thread.
+#[no_mangle]
+pub extern "C" fn rkik_nts_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Why a C API call failed.
+enum FfiError {
+    /// A null pointer argument, by name.
+    Null(&'static str),
+    /// A string argument that is not UTF-8, by name.
+    NotUtf8(&'static str),
+    Client(Error),
+}
+
+impl From<Error> for FfiError {
+    fn from(error: Error) -> Self {
+        FfiError::Client(error)
+    }
+}
+
+/// Run `call`, recording its error for [`rkik_nts_last_error`].
+fn guard(call: impl FnOnce() -> std::result::Result<(), FfiError>) -> RkikNtsStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => (RkikNtsStatus::Ok, None),
+        Ok(Err(FfiError::Null(name))) => (
+            RkikNtsStatus::InvalidArgument,
+            Some(format!("{name} is null")),
+        ),
+        Ok(Err(FfiError::NotUtf8(name))) => (
+            RkikNtsStatus::InvalidArgument,
+            Some(format!("{name} is not valid UTF-8")),
+        ),
+        Ok(Err(FfiError::Client(error))) => (error.kind().into(), Some(error.to_string())),
+        Err(_) => (RkikNtsStatus::Panic, Some("panic in rkik-nts".to_string())),
+    };
+    LAST_ERROR.with(|last| {
+        // Messages never contain NUL bytes
+        *last.borrow_mut() = message.and_then(|message| CString::new(message).ok());
+    });
+    status
+}
+
+/// The configuration for `server` with `timeout_ms`.
+///
+/// # Safety
+///
+/// `server` must be null or a valid NUL-terminated string.
+unsafe fn config(
+    server: *const c_char,
+    timeout_ms: u32,
+) -> std::result::Result<NtsClientConfig, FfiError> {
+    if server.is_null() {
+        return Err(FfiError::Null("server"));
+    }
+    let server = CStr::from_ptr(server)
+        .to_str()
+        .map_err(|_| FfiError::NotUtf8("server"))?;
+    let config = NtsClientConfig::new(server).with_auto_connect(true);
+    Ok(match timeout_ms {
+        0 => config,
+        ms => config.with_timeout(Duration::from_millis(ms.into())),
+    })
+}
+
+/// `out` as a reference, if not null.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn out_ptr<'a, T>(out: *mut T) -> std::result::Result<&'a mut T, FfiError> {
+    out.as_mut().ok_or(FfiError::Null("out_result"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::MockServer;
+    use crate::types::{ServerInfo, SignedDuration};
+
+    fn last_error() -> Option<String> {
+        let message = rkik_nts_last_error();
+        (!message.is_null()).then(|| {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    #[test]
+    fn test_client_get_time() {
+        let server = MockServer::start().unwrap();
+        let client = Box::into_raw(Box::new(RkikNtsClient {
+            client: NtsClient::new(server.client_config().with_auto_connect(true)),
+        }));
+        let mut time = RkikNtsTime::default();
+        for _ in 0..2 {
+            let status = unsafe { rkik_nts_client_get_time(client, &mut time) };
+            assert_eq!(status, RkikNtsStatus::Ok);
+            assert_eq!(last_error(), None);
+            assert!(time.authenticated);
+            assert!(time.unix_seconds > 1_700_000_000);
+            assert!(time.round_trip_delay_nanos < 1_000_000_000);
+        }
+        unsafe { rkik_nts_client_free(client) };
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let mut time = RkikNtsTime::default();
+        let status = unsafe { rkik_nts_query(ptr::null(), &mut time) };
+        assert_eq!(status, RkikNtsStatus::InvalidArgument);
+        assert_eq!(last_error().as_deref(), Some("server is null"));
+
+        let server = CString::new("127.0.0.1").unwrap();
+        let status = unsafe { rkik_nts_query(server.as_ptr(), ptr::null_mut()) };
+        assert_eq!(status, RkikNtsStatus::InvalidArgument);
+        assert_eq!(last_error().as_deref(), Some("out_result is null"));
+
+        let invalid = CString::new(vec![0xff, 0xfe]).unwrap();
+        let client = unsafe { rkik_nts_client_new(invalid.as_ptr(), 0) };
+        assert!(client.is_null());
+        assert_eq!(last_error().as_deref(), Some("server is not valid UTF-8"));
+
+        let status = unsafe { rkik_nts_client_get_time(ptr::null(), &mut time) };
+        assert_eq!(status, RkikNtsStatus::InvalidArgument);
+        unsafe { rkik_nts_client_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_client_errors() {
+        let server = CString::new("invalid/host").unwrap();
+        let client = unsafe { rkik_nts_client_new(server.as_ptr(), 0) };
+        assert!(client.is_null());
+        assert!(last_error().unwrap().contains("Invalid NTS-KE server"));
+
+        // Nothing listens on port 1
+        let server = CString::new("127.0.0.1:1").unwrap();
+        let mut time = RkikNtsTime::default();
+        let status = unsafe { rkik_nts_query_with(server.as_ptr(), 500, &mut time) };
+        assert_ne!(status, RkikNtsStatus::Ok);
+        assert!(last_error().is_some());
+    }
+
+    fn snapshot(network_time: SystemTime) -> TimeSnapshot {
+        TimeSnapshot {
+            round_trip_delay: Duration::from_millis(20),
+            server: "[::1]:123".to_string(),
+            server_info: ServerInfo {
+                stratum: 2,
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(
+                network_time + Duration::from_millis(5),
+                SignedDuration::from_nanos(5_000_000),
+            )
+        }
+    }
+
+    #[test]
+    fn test_time_conversion() {
+        let time = RkikNtsTime::from(&snapshot(
+            SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+        ));
+        assert_eq!(
+            (time.unix_seconds, time.unix_nanos),
+            (1_700_000_000, 123_456_789)
+        );
+        assert_eq!(time.offset_nanos, 5_000_000);
+        assert_eq!(time.round_trip_delay_nanos, 20_000_000);
+        assert_eq!(time.stratum, 2);
+
+        let time = RkikNtsTime::from(&snapshot(
+            SystemTime::UNIX_EPOCH - Duration::new(1, 250_000_000),
+        ));
+        assert_eq!((time.unix_seconds, time.unix_nanos), (-2, 750_000_000));
+    }
+}
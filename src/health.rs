@@ -0,0 +1,86 @@
+//! Client health for liveness and readiness checks.
+
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Health of a client, returned by
+/// [`NtsClient::health`](crate::NtsClient::health).
+///
+/// Meant to back a service's `/healthz` endpoint: with the `serde` feature,
+/// serialize it as the response body, and use [`is_healthy`] for the status
+/// code.
+///
+/// [`is_healthy`]: HealthStatus::is_healthy
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HealthStatus {
+    /// Whether the client holds NTS keys.
+    pub connected: bool,
+
+    /// Time since the last successful query, `None` if no query succeeded
+    /// yet.
+    pub last_sync_age: Option<Duration>,
+
+    /// Failed queries since the last successful one.
+    pub consecutive_failures: u32,
+
+    /// Unused NTS cookies for the current NTP server.
+    pub cookies_remaining: usize,
+
+    /// Whether a new key exchange is needed before queries can succeed:
+    /// the cookies ran out, or the last query failed in a way only fresh
+    /// keys fix, such as a Kiss-o'-Death `NTSN`.
+    pub rekey_overdue: bool,
+}
+
+impl HealthStatus {
+    /// Check if the client is connected, does not need a new key exchange,
+    /// and synced successfully within `max_sync_age`.
+    pub fn is_healthy(&self, max_sync_age: Duration) -> bool {
+        self.connected
+            && !self.rekey_overdue
+            && self.last_sync_age.is_some_and(|age| age <= max_sync_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy() -> HealthStatus {
+        HealthStatus {
+            connected: true,
+            last_sync_age: Some(Duration::from_secs(10)),
+            consecutive_failures: 0,
+            cookies_remaining: 7,
+            rekey_overdue: false,
+        }
+    }
+
+    #[test]
+    fn test_is_healthy() {
+        let max_age = Duration::from_secs(60);
+        assert!(healthy().is_healthy(max_age));
+        assert!(!healthy().is_healthy(Duration::from_secs(5)));
+
+        let never_synced = HealthStatus {
+            last_sync_age: None,
+            ..healthy()
+        };
+        assert!(!never_synced.is_healthy(max_age));
+
+        let disconnected = HealthStatus {
+            connected: false,
+            ..healthy()
+        };
+        assert!(!disconnected.is_healthy(max_age));
+
+        let overdue = HealthStatus {
+            rekey_overdue: true,
+            ..healthy()
+        };
+        assert!(!overdue.is_healthy(max_age));
+    }
+}
@@ -0,0 +1,135 @@
+//! Capture of the records of an NTS-KE response.
+//!
+//! The key exchange keeps the application data the server sent, as
+//! decrypted by rustls, and the records are decoded again here to show
+//! what the server actually sent, unknown records included.
+
+use crate::types::NtsKeRecord;
+
+const RECORD_END_OF_MESSAGE: u16 = 0;
+const RECORD_NEXT_PROTOCOL: u16 = 1;
+const RECORD_ERROR: u16 = 2;
+const RECORD_WARNING: u16 = 3;
+const RECORD_AEAD_ALGORITHM: u16 = 4;
+const RECORD_NEW_COOKIE: u16 = 5;
+const RECORD_SERVER: u16 = 6;
+const RECORD_PORT: u16 = 7;
+
+/// Parse NTS-KE records up to and including End of Message.
+///
+/// Returns None if the response ends before End of Message.
+pub(crate) fn parse_records(mut data: &[u8]) -> Option<Vec<NtsKeRecord>> {
+    fn u16_list(body: &[u8]) -> Vec<u16> {
+        body.chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    let mut records = Vec::new();
+    while data.len() >= 4 {
+        let header = u16::from_be_bytes([data[0], data[1]]);
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let body = data.get(4..4 + len)?;
+        data = &data[4 + len..];
+
+        let critical = header & 0x8000 != 0;
+        let record_type = header & 0x7fff;
+        let record = match (record_type, body.len()) {
+            (RECORD_END_OF_MESSAGE, _) => NtsKeRecord::EndOfMessage,
+            (RECORD_NEXT_PROTOCOL, _) => NtsKeRecord::NextProtocol {
+                protocol_ids: u16_list(body),
+            },
+            (RECORD_ERROR, 2) => NtsKeRecord::Error {
+                code: u16::from_be_bytes([body[0], body[1]]),
+            },
+            (RECORD_WARNING, 2) => NtsKeRecord::Warning {
+                code: u16::from_be_bytes([body[0], body[1]]),
+            },
+            (RECORD_AEAD_ALGORITHM, _) => NtsKeRecord::AeadAlgorithm {
+                critical,
+                algorithm_ids: u16_list(body),
+            },
+            (RECORD_NEW_COOKIE, length) => NtsKeRecord::NewCookie { length },
+            (RECORD_SERVER, _) => NtsKeRecord::Server {
+                critical,
+                name: String::from_utf8_lossy(body).into_owned(),
+            },
+            (RECORD_PORT, 2) => NtsKeRecord::Port {
+                critical,
+                port: u16::from_be_bytes([body[0], body[1]]),
+            },
+            _ => NtsKeRecord::Unknown {
+                record_type,
+                critical,
+                data: body.to_vec(),
+            },
+        };
+
+        let end = record == NtsKeRecord::EndOfMessage;
+        records.push(record);
+        if end {
+            return Some(records);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let records = hex(concat!(
+            "80010002", // Next Protocol (critical): NTPv4
+            "0000",
+            "80040002000f",   // AEAD Algorithm (critical): 15
+            "00050003abcdef", // New Cookie
+            "00060004",       // Server
+            "74657374",
+            "00070002", // Port
+            "007b",
+            "40040001", // unknown record type
+            "01",
+            "80000000", // End of Message
+        ));
+
+        assert_eq!(
+            parse_records(&records).unwrap(),
+            vec![
+                NtsKeRecord::NextProtocol {
+                    protocol_ids: vec![0]
+                },
+                NtsKeRecord::AeadAlgorithm {
+                    critical: true,
+                    algorithm_ids: vec![15]
+                },
+                NtsKeRecord::NewCookie { length: 3 },
+                NtsKeRecord::Server {
+                    critical: false,
+                    name: "test".to_string()
+                },
+                NtsKeRecord::Port {
+                    critical: false,
+                    port: 123
+                },
+                NtsKeRecord::Unknown {
+                    record_type: 0x4004,
+                    critical: false,
+                    data: vec![1]
+                },
+                NtsKeRecord::EndOfMessage,
+            ]
+        );
+
+        // A response cut off before End of Message
+        assert!(parse_records(&records[..records.len() - 4]).is_none());
+    }
+}
@@ -0,0 +1,650 @@
+//! Leap second announcements.
+//!
+//! Servers announce a leap second through the leap indicator of their
+//! responses during the month it occurs in; the leap second itself is
+//! inserted or deleted at the end of the last day of the month, UTC (RFC
+//! 5905, section 7.3). [`LeapSecondHandling`] decides what the client does
+//! with measurements taken around it, and [`LeapSmearDetector`] spots
+//! servers that smear leap seconds instead.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::ntp_packet::unix_seconds;
+use crate::types::{LeapIndicator, SignedDuration, TimeSnapshot};
+
+/// What the client does with measurements taken around an announced leap
+/// second, set with
+/// [`NtsClientConfig::with_leap_second_handling`](crate::NtsClientConfig::with_leap_second_handling).
+///
+/// The announcement itself is always reported by
+/// [`TimeSnapshot::leap_indicator`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LeapSecondHandling {
+    /// Report network times as measured.
+    #[default]
+    Report,
+
+    /// Apply the announced leap second to network times extrapolated past
+    /// it: the network time of a measurement straddling an inserted leap
+    /// second is set back by one second, and forward for a deleted one. The
+    /// clock offset is adjusted to match.
+    Apply,
+
+    /// Reject measurements whose network time lies within this distance of
+    /// an announced leap second with
+    /// [`Error::LeapSecond`](crate::Error::LeapSecond), as clocks around a
+    /// leap second often disagree by up to a second.
+    Reject(Duration),
+}
+
+/// The time of the next possible leap second after `time`: midnight UTC at
+/// the start of the following month.
+///
+/// An inserted leap second (23:59:60) ends at this instant, a deleted one
+/// (23:59:59) would have started one second before it.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use rkik_nts::leap::next_leap_second;
+///
+/// // 2016-12-31T12:00:00Z precedes the leap second ending at 2017-01-01
+/// let time = UNIX_EPOCH + Duration::from_secs(1_483_185_600);
+/// assert_eq!(
+///     next_leap_second(time),
+///     UNIX_EPOCH + Duration::from_secs(1_483_228_800)
+/// );
+/// ```
+pub fn next_leap_second(time: SystemTime) -> SystemTime {
+    let (year, month, _) = civil_from_days(unix_seconds(time).div_euclid(86_400));
+    let (year, month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let next = days_from_civil(year, month, 1) * 86_400;
+    if next >= 0 {
+        UNIX_EPOCH + Duration::from_secs(next as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(next.unsigned_abs())
+    }
+}
+
+/// Apply `handling` to `snapshot`.
+pub(crate) fn apply(snapshot: &mut TimeSnapshot, handling: LeapSecondHandling) -> Result<()> {
+    let Some(leap) = snapshot.next_leap_second() else {
+        return Ok(());
+    };
+
+    match handling {
+        LeapSecondHandling::Report => {}
+        LeapSecondHandling::Apply => {
+            let second = Duration::from_secs(1);
+            match snapshot.leap_indicator() {
+                // The network clock repeats the second ending at the leap...
+                LeapIndicator::InsertSecond if snapshot.network_time >= leap => {
+                    snapshot.network_time -= second;
+                }
+                // ...or skips the one before it
+                LeapIndicator::DeleteSecond if snapshot.network_time >= leap - second => {
+                    snapshot.network_time += second;
+                }
+                _ => return Ok(()),
+            }
+            snapshot.clock_offset =
+                SignedDuration::between(snapshot.network_time, snapshot.system_time);
+            #[allow(deprecated)]
+            {
+                snapshot.offset = snapshot.clock_offset.abs();
+            }
+        }
+        LeapSecondHandling::Reject(window) => {
+            if SignedDuration::between(leap, snapshot.network_time).abs() <= window {
+                return Err(Error::LeapSecond { at: leap });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Longest window analysed by default: the 24-hour smears used by large
+/// public NTP services.
+const DEFAULT_SMEAR_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Slowest smear rate detected: 1 s over 48 hours, about 5.8 ppm.
+const MIN_SMEAR_PPM: f64 = 5.0;
+
+/// Fastest smear rate detected: 1 s over 500 s.
+const MAX_SMEAR_PPM: f64 = 2_000.0;
+
+/// Smallest offset the smear must accumulate over the samples, to tell it
+/// apart from noise.
+const MIN_SMEAR_OFFSET: f64 = 0.05;
+
+/// Fewest sample pairs within the window needed for a detection.
+const MIN_SMEAR_PAIRS: usize = 4;
+
+/// Largest share of the smeared offset a single pair of samples may
+/// account for.
+const MAX_STEP_SHARE: f64 = 0.25;
+
+/// Flags servers that appear to smear leap seconds.
+///
+/// A smearing server spreads a leap second over several hours (commonly 24)
+/// by running its clock slow or fast, instead of inserting or deleting a
+/// second at the end of the month. Measured against a clock that does not
+/// smear, its offset drifts steadily around the leap: about 11.6 ppm for a
+/// 24-hour smear. Synchronizing from a mix of smearing and non-smearing
+/// servers leaves the client up to half a second off.
+///
+/// Feed the detector the snapshots of one server. It looks for a month
+/// boundary (the only time a leap second can occur) around which the offset
+/// drifts consistently faster than the baseline drift of the local clock,
+/// measured away from the boundary. Without samples outside the window
+/// there is no baseline, and nothing is detected. If the local clock itself
+/// smears, every non-smearing server is flagged instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::{LeapSmearDetector, NtsClient, NtsClientConfig};
+///
+/// # async fn run() -> rkik_nts::Result<()> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.google.com").with_history(4096));
+/// // ... query the server regularly around the end of June or December ...
+///
+/// let mut detector = LeapSmearDetector::default();
+/// for snapshot in client.history() {
+///     detector.add(&snapshot);
+/// }
+/// if let Some(smear) = detector.detect() {
+///     println!(
+///         "{} smears the leap second: {:.1} ppm over {:?}",
+///         client.config().nts_ke_server,
+///         smear.rate_ppm,
+///         smear.smear_duration()
+///     );
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LeapSmearDetector {
+    capacity: usize,
+    window: Duration,
+    /// System time, network time and signed offset in seconds of each
+    /// sample.
+    samples: VecDeque<(SystemTime, SystemTime, f64)>,
+}
+
+/// A leap second smear detected by [`LeapSmearDetector`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeapSmear {
+    /// The month boundary the smear is around.
+    pub leap: SystemTime,
+
+    /// Rate at which the server's clock drifts from the local clock's
+    /// baseline, in parts per million. Positive when the server runs slow,
+    /// as when smearing an inserted leap second.
+    pub rate_ppm: f64,
+
+    /// Offset accumulated by the smear over the samples in the window.
+    pub accumulated: SignedDuration,
+
+    /// Number of samples within the window.
+    pub samples: usize,
+}
+
+impl LeapSmear {
+    /// The kind of leap second being smeared: an inserted second slows the
+    /// server's clock, a deleted one speeds it up.
+    pub fn leap_indicator(&self) -> LeapIndicator {
+        if self.rate_ppm >= 0.0 {
+            LeapIndicator::InsertSecond
+        } else {
+            LeapIndicator::DeleteSecond
+        }
+    }
+
+    /// The time the server takes to smear one second at the detected rate.
+    pub fn smear_duration(&self) -> Duration {
+        Duration::from_secs_f64(1e6 / self.rate_ppm.abs())
+    }
+}
+
+impl LeapSmearDetector {
+    /// Create a detector keeping the last `capacity` samples (at least 2),
+    /// looking for smears within 24 hours of a month boundary.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(2),
+            window: DEFAULT_SMEAR_WINDOW,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Look for smears within `window` of a month boundary, on either side.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Add a sample, evicting the oldest one if the detector is full.
+    pub fn add(&mut self, snapshot: &TimeSnapshot) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((
+            snapshot.system_time,
+            snapshot.network_time,
+            snapshot.clock_offset.as_secs_f64(),
+        ));
+    }
+
+    /// Number of samples currently kept.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Whether no sample was added since creation or the last
+    /// [`clear`](Self::clear).
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Drop all samples.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Look for a smear, returning the most pronounced one found.
+    pub fn detect(&self) -> Option<LeapSmear> {
+        let mut samples: Vec<_> = self.samples.iter().copied().collect();
+        samples.sort_by_key(|&(system_time, _, _)| system_time);
+
+        // The month boundary nearest to each sample is a candidate
+        let mut boundaries: Vec<SystemTime> = samples
+            .iter()
+            .map(|&(_, network_time, _)| nearest_month_boundary(network_time))
+            .collect();
+        boundaries.dedup();
+
+        boundaries
+            .into_iter()
+            .filter_map(|leap| self.smear_around(&samples, leap))
+            .max_by(|a, b| a.accumulated.abs().cmp(&b.accumulated.abs()))
+    }
+
+    /// Check the time-ordered `samples` for a smear around `leap`.
+    fn smear_around(
+        &self,
+        samples: &[(SystemTime, SystemTime, f64)],
+        leap: SystemTime,
+    ) -> Option<LeapSmear> {
+        let window = self.window.as_secs_f64();
+        let from_leap = |time: SystemTime| SignedDuration::between(leap, time).as_secs_f64();
+        let inside_window = |network_time: SystemTime| from_leap(network_time).abs() <= window;
+
+        // Consecutive pairs on the same side of the boundary by both clocks,
+        // give or take the leap second itself: pairs straddling it may
+        // include a leap second step
+        let mut inside = Vec::new();
+        let (mut outside_dy, mut outside_dx) = (0.0, 0.0);
+        for pair in samples.windows(2) {
+            let (t0, n0, y0) = pair[0];
+            let (t1, n1, y1) = pair[1];
+            let dx = SignedDuration::between(t0, t1).as_secs_f64();
+            let first = from_leap(t0).min(from_leap(n0));
+            let last = from_leap(t1).max(from_leap(n1));
+            if dx <= 0.0 || (first < 1.0 && last >= -1.0) {
+                continue;
+            }
+            match (inside_window(n0), inside_window(n1)) {
+                (true, true) => inside.push((dx, y1 - y0)),
+                (false, false) => {
+                    outside_dx += dx;
+                    outside_dy += y1 - y0;
+                }
+                _ => {}
+            }
+        }
+        if inside.len() < MIN_SMEAR_PAIRS || outside_dx <= 0.0 {
+            return None;
+        }
+
+        // Offset changes in excess of the baseline drift of the local clock
+        let baseline = outside_dy / outside_dx;
+        let excess: Vec<(f64, f64)> = inside
+            .iter()
+            .map(|&(dx, dy)| (dx, dy - baseline * dx))
+            .collect();
+        let accumulated: f64 = excess.iter().map(|&(_, e)| e).sum();
+        if accumulated.abs() < MIN_SMEAR_OFFSET {
+            return None;
+        }
+
+        // A smear accumulates steadily, not in a few steps
+        let largest = excess.iter().map(|&(_, e)| e.abs()).fold(0.0, f64::max);
+        if largest > MAX_STEP_SHARE * accumulated.abs() {
+            return None;
+        }
+
+        // The smear rate, over the time in which the central 90% of the
+        // offset accumulated
+        let (mut elapsed, mut sum) = (0.0, 0.0);
+        let (mut start, mut end) = (None, None);
+        for &(dx, e) in &excess {
+            elapsed += dx;
+            sum += e / accumulated;
+            if start.is_none() && sum >= 0.05 {
+                start = Some(elapsed);
+            }
+            if end.is_none() && sum >= 0.95 {
+                end = Some(elapsed);
+            }
+        }
+        let duration = end? - start?;
+        let rate_ppm = 0.9 * accumulated / duration * 1e6;
+        if duration <= 0.0 || !(MIN_SMEAR_PPM..=MAX_SMEAR_PPM).contains(&rate_ppm.abs()) {
+            return None;
+        }
+
+        Some(LeapSmear {
+            leap,
+            rate_ppm,
+            accumulated: SignedDuration::from_nanos((accumulated * 1e9) as i128),
+            samples: samples
+                .iter()
+                .filter(|&&(_, network_time, _)| inside_window(network_time))
+                .count(),
+        })
+    }
+}
+
+impl Default for LeapSmearDetector {
+    /// A detector keeping the last 4096 samples, enough for a day of
+    /// 64-second polling on either side of the leap.
+    fn default() -> Self {
+        Self::new(4096)
+    }
+}
+
+/// The start of the month nearest to `time`, UTC.
+fn nearest_month_boundary(time: SystemTime) -> SystemTime {
+    let half_month = Duration::from_secs(15 * 24 * 60 * 60);
+    next_leap_second(time.checked_sub(half_month).unwrap_or(time))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html>.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ServerInfo;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// 2017-01-01T00:00:00Z, after the last leap second so far.
+    const LEAP_2016: u64 = 1_483_228_800;
+
+    fn unix(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn snapshot(network_time: SystemTime, leap_indicator: LeapIndicator) -> TimeSnapshot {
+        TimeSnapshot {
+            round_trip_delay: Duration::from_millis(20),
+            server: "192.0.2.1:123".to_string(),
+            server_info: ServerInfo {
+                leap_indicator,
+                stratum: 1,
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(network_time, SignedDuration::ZERO)
+        }
+    }
+
+    #[test]
+    fn test_next_leap_second() {
+        // Known leap seconds: end of June 2015 and of December 2016
+        assert_eq!(next_leap_second(unix(1_435_000_000)), unix(1_435_708_800));
+        assert_eq!(next_leap_second(unix(LEAP_2016 - 1)), unix(LEAP_2016));
+        // The instant itself belongs to the next month
+        assert_eq!(next_leap_second(unix(LEAP_2016)), unix(1_485_907_200));
+        // End of February in a leap year (2024-03-01)
+        assert_eq!(next_leap_second(unix(1_708_000_000)), unix(1_709_251_200));
+        // After the 2036 NTP era rollover (2036-03-01)
+        assert_eq!(next_leap_second(unix(2_085_978_496)), unix(2_087_942_400));
+        assert_eq!(next_leap_second(UNIX_EPOCH), unix(2_678_400));
+    }
+
+    #[test]
+    fn test_civil_roundtrip() {
+        for days in (-800_000..800_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_report_leaves_snapshot() {
+        let mut s = snapshot(unix(LEAP_2016), LeapIndicator::InsertSecond);
+        apply(&mut s, LeapSecondHandling::Report).unwrap();
+        assert_eq!(s.network_time, unix(LEAP_2016));
+        assert_eq!(s.clock_offset, SignedDuration::ZERO);
+    }
+
+    #[test]
+    fn test_apply_inserted_leap_second() {
+        // Sent 5 ms before the leap, extrapolated 5 ms past it: the network
+        // clock repeated a second
+        let before = unix(LEAP_2016 - 1);
+        let after = unix(LEAP_2016) + Duration::from_millis(5);
+
+        let mut s = snapshot(before, LeapIndicator::InsertSecond);
+        apply(&mut s, LeapSecondHandling::Apply).unwrap();
+        assert_eq!(s.network_time, before);
+
+        let mut s = snapshot(after, LeapIndicator::InsertSecond);
+        apply(&mut s, LeapSecondHandling::Apply).unwrap();
+        assert_eq!(s.network_time, after - Duration::from_secs(1));
+        assert_eq!(s.clock_offset.as_nanos(), 1_000_000_000);
+        assert!(s.is_ahead());
+    }
+
+    #[test]
+    fn test_apply_deleted_leap_second() {
+        // 23:59:59 does not exist, so 23:59:58 is followed by 00:00:00
+        let mut s = snapshot(unix(LEAP_2016 - 2), LeapIndicator::DeleteSecond);
+        apply(&mut s, LeapSecondHandling::Apply).unwrap();
+        assert_eq!(s.network_time, unix(LEAP_2016 - 2));
+
+        let mut s = snapshot(unix(LEAP_2016 - 1), LeapIndicator::DeleteSecond);
+        apply(&mut s, LeapSecondHandling::Apply).unwrap();
+        assert_eq!(s.network_time, unix(LEAP_2016));
+        assert_eq!(s.clock_offset.as_nanos(), -1_000_000_000);
+    }
+
+    #[test]
+    fn test_reject_window() {
+        let handling = LeapSecondHandling::Reject(Duration::from_secs(10));
+
+        let mut s = snapshot(unix(LEAP_2016 - 5), LeapIndicator::InsertSecond);
+        let result = apply(&mut s, handling);
+        assert!(matches!(result, Err(Error::LeapSecond { at }) if at == unix(LEAP_2016)));
+
+        let mut s = snapshot(unix(LEAP_2016 - 60), LeapIndicator::InsertSecond);
+        assert!(apply(&mut s, handling).is_ok());
+
+        // Without an announcement nothing is rejected
+        let mut s = snapshot(unix(LEAP_2016 - 5), LeapIndicator::NoWarning);
+        assert!(apply(&mut s, handling).is_ok());
+    }
+
+    /// Samples every `interval` seconds for `span` seconds on either side of
+    /// the 2016 leap second, from a server smearing the leap at `smear`
+    /// (start and duration in seconds relative to the leap, `None` for a
+    /// server that steps), read by a local clock drifting at `drift_ppm`
+    /// that steps at the leap. `sign` is 1 for an inserted second and -1
+    /// for a deleted one.
+    fn smear_samples(
+        span: i64,
+        interval: i64,
+        smear: Option<(f64, f64)>,
+        drift_ppm: f64,
+        sign: f64,
+        jitter: &mut dyn FnMut() -> f64,
+    ) -> LeapSmearDetector {
+        let leap = LEAP_2016 as f64;
+        let mut detector = LeapSmearDetector::default();
+        for i in (-span..=span).step_by(interval as usize) {
+            // Elapsed seconds, and UTC as a Unix timestamp
+            let t = leap + i as f64;
+            let utc = if t >= leap { t - sign } else { t };
+            let local = utc + drift_ppm * 1e-6 * (i + span) as f64;
+            let server = match smear {
+                Some((start, duration)) => {
+                    t - sign * ((t - leap - start) / duration).clamp(0.0, 1.0)
+                }
+                None => utc,
+            } + jitter();
+
+            let offset = local - server;
+            let mut s = snapshot(
+                UNIX_EPOCH + Duration::from_secs_f64(server),
+                LeapIndicator::NoWarning,
+            );
+            s.system_time = UNIX_EPOCH + Duration::from_secs_f64(local);
+            s.clock_offset = SignedDuration::from_nanos((offset * 1e9) as i128);
+            detector.add(&s);
+        }
+        detector
+    }
+
+    #[test]
+    fn test_detects_24_hour_smear() {
+        // Noon to noon, as used by large public services, with a drifting
+        // local clock and noisy measurements
+        let day = 86_400.0;
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut jitter = || rng.gen_range(-0.002..0.002);
+        let detector = smear_samples(
+            3 * 86_400,
+            600,
+            Some((-day / 2.0, day)),
+            10.0,
+            1.0,
+            &mut jitter,
+        );
+        let smear = detector.detect().unwrap();
+        assert_eq!(smear.leap, unix(LEAP_2016));
+        assert_eq!(smear.leap_indicator(), LeapIndicator::InsertSecond);
+        let duration = smear.smear_duration().as_secs_f64();
+        assert!((duration - day).abs() < 0.02 * day, "{} s", duration);
+        assert!((smear.accumulated.as_secs_f64() - 1.0).abs() < 0.02);
+        assert!((286..=290).contains(&smear.samples));
+
+        // A deleted leap second speeds the server up
+        let detector = smear_samples(3 * 86_400, 600, Some((-day, day)), -5.0, -1.0, &mut || 0.0);
+        let smear = detector.detect().unwrap();
+        assert_eq!(smear.leap_indicator(), LeapIndicator::DeleteSecond);
+        assert!(
+            (smear.rate_ppm + 1e6 / day).abs() < 0.2,
+            "{} ppm",
+            smear.rate_ppm
+        );
+    }
+
+    #[test]
+    fn test_detects_short_smear() {
+        // UTC-SLS smears over the last 1000 s before the leap
+        let detector = smear_samples(
+            2 * 86_400,
+            60,
+            Some((-1000.0, 1000.0)),
+            0.0,
+            1.0,
+            &mut || 0.0,
+        );
+        let smear = detector.detect().unwrap();
+        assert!(
+            (smear.rate_ppm - 1000.0).abs() < 100.0,
+            "{} ppm",
+            smear.rate_ppm
+        );
+
+        // Too little of a 24-hour smear falls within a 10-minute window
+        let day = 86_400.0;
+        let detector = smear_samples(86_400, 60, Some((-day / 2.0, day)), 0.0, 1.0, &mut || 0.0)
+            .with_window(Duration::from_secs(600));
+        assert!(detector.detect().is_none());
+    }
+
+    #[test]
+    fn test_ignores_stepping_servers() {
+        // A drifting local clock and a server stepping at the leap
+        let detector = smear_samples(3 * 86_400, 600, None, 20.0, 1.0, &mut || 0.0);
+        assert!(detector.detect().is_none());
+
+        // Noise does not look like a smear
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut jitter = || rng.gen_range(-0.005..0.005);
+        let detector = smear_samples(3 * 86_400, 600, None, -15.0, 1.0, &mut jitter);
+        assert!(detector.detect().is_none());
+    }
+
+    #[test]
+    fn test_smear_needs_baseline() {
+        // Without samples away from the leap, the local drift is unknown
+        let day = 86_400.0;
+        let detector = smear_samples(43_200, 600, Some((-day / 2.0, day)), 0.0, 1.0, &mut || 0.0);
+        assert!(detector.detect().is_none());
+
+        let mut detector = LeapSmearDetector::new(1);
+        assert!(detector.is_empty());
+        detector.add(&snapshot(unix(LEAP_2016), LeapIndicator::NoWarning));
+        detector.add(&snapshot(unix(LEAP_2016 + 1), LeapIndicator::NoWarning));
+        detector.add(&snapshot(unix(LEAP_2016 + 2), LeapIndicator::NoWarning));
+        assert_eq!(detector.len(), 2);
+        detector.clear();
+        assert!(detector.detect().is_none());
+    }
+}
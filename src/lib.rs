@@ -10,7 +10,9 @@
 //!
 //! - **Simple API**: Easy-to-use client interface with sensible defaults
 //! - **NTS Support**: Full Network Time Security implementation for authenticated time
-//! - **Async/Await**: Built on Tokio for efficient async I/O
+//! - **Async/Await**: Runs on Tokio by default, on smol or async-std with
+//!   the `smol` and `async-std` features; other runtimes plug in through
+//!   [`Runtime`], [`Connector`] and [`Resolver`]
 //! - **Configurable**: Flexible configuration options for advanced use cases
 //! - **Based on ntpd-rs**: Built on the battle-tested ntpd-rs implementation from Project Pendulum
 //!
@@ -25,7 +27,7 @@
 //!     let config = NtsClientConfig::new("time.cloudflare.com");
 //!
 //!     // Create and connect the client
-//!     let mut client = NtsClient::new(config);
+//!     let client = NtsClient::new(config);
 //!     client.connect().await?;
 //!
 //!     // Query the current time
@@ -33,7 +35,7 @@
 //!
 //!     println!("Network time: {:?}", time.network_time);
 //!     println!("System time:  {:?}", time.system_time);
-//!     println!("Offset:       {:?}", time.offset);
+//!     println!("Offset:       {}", time.clock_offset);
 //!     println!("Authenticated: {}", time.authenticated);
 //!
 //!     Ok(())
@@ -61,15 +63,113 @@
 
 #![deny(missing_docs)]
 #![warn(rust_2018_idioms)]
+// Without a runtime or the `blocking` feature, the socket code has no user
+#![cfg_attr(
+    not(any(
+        feature = "tokio",
+        feature = "smol",
+        feature = "async-std",
+        feature = "blocking"
+    )),
+    allow(unused)
+)]
 
+pub mod blacklist;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod capabilities;
+pub mod circuit;
 pub mod client;
+#[cfg(feature = "clock-adjust")]
+pub mod clock;
 pub mod config;
+pub mod cookies;
+pub mod diagnostics;
+pub mod drift;
 pub mod error;
+pub mod events;
+#[cfg(feature = "export-keys")]
+pub mod export;
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod health;
+mod ke_records;
+pub mod leap;
+pub mod metrics;
+pub mod ntp_packet;
+#[cfg(feature = "ntpd-rs-config")]
+pub mod ntpd_rs;
 mod nts_ke;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod pool;
+pub mod proxy;
+pub mod query;
+pub mod resolver;
+pub mod retry;
+pub mod runtime;
+#[cfg(feature = "tokio")]
+pub mod service;
+pub mod sim;
+pub mod sink;
+mod socket;
+#[cfg(feature = "persistence")]
+mod state;
+pub mod stats;
+pub mod stream;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod time_source;
+pub mod transport;
 pub mod types;
+mod x509;
 
 // Re-export main types for convenience
-pub use client::NtsClient;
-pub use config::NtsClientConfig;
-pub use error::{Error, Result};
-pub use types::{NtsKeResult, TimeSnapshot};
+pub use blacklist::{BlacklistEntry, BlacklistPolicy};
+pub use capabilities::{CapabilityReport, CapabilityStatus};
+pub use circuit::{CircuitBreakerPolicy, CircuitState};
+pub use client::{ConnectedNtsClient, NtsClient, NtsClientBuilder};
+pub use config::{
+    AddressFamily, CertificateDer, ClientAuth, ConfigError, NtsClientConfig, PrivateKeyDer,
+    QueryOptions, RedirectPolicy,
+};
+pub use cookies::{Association, CookieStore, MemoryCookieStore};
+pub use diagnostics::DiagnosticsReport;
+pub use drift::{DriftEstimate, DriftEstimator};
+pub use error::{Error, ErrorKind, ErrorSummary, Result};
+pub use events::{ClientEvent, EventHandler};
+#[cfg(feature = "export-keys")]
+pub use export::NtsMaterial;
+pub use extension::ExtensionField;
+pub use health::HealthStatus;
+pub use leap::{LeapSecondHandling, LeapSmear, LeapSmearDetector};
+pub use metrics::MetricsSink;
+#[cfg(feature = "pcap")]
+pub use pcap::PcapWriter;
+#[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+pub use pool::{query_many, query_many_with};
+pub use pool::{NtsPool, SelectionStrategy};
+pub use proxy::ProxyConfig;
+pub use resolver::Resolver;
+#[cfg(feature = "tokio")]
+pub use resolver::SystemResolver;
+#[cfg(feature = "async-std")]
+pub use runtime::AsyncStdRuntime;
+pub use runtime::Runtime;
+#[cfg(feature = "smol")]
+pub use runtime::SmolRuntime;
+#[cfg(feature = "tokio")]
+pub use runtime::TokioRuntime;
+#[cfg(feature = "tokio")]
+pub use service::NtsSyncService;
+pub use sink::SampleSink;
+pub use stats::{AllanDeviation, RollingStats, SampleStatistics, ServerStats};
+pub use stream::TimeStream;
+pub use time_source::{Clock, SystemClock};
+pub use transport::{Connector, KeTransport, NtpTransport};
+pub use types::{
+    CertificateInfo, DebugSnapshot, FilteredTime, LeapIndicator, NtsKeRecord, NtsKeResult, NtsKeys,
+    PacketCapture, RateLimitState, RedirectDecision, ServerInfo, SignedDuration, TimeSnapshot,
+    TimingBreakdown, TlsDetails,
+};
@@ -0,0 +1,111 @@
+//! Metrics collection hooks.
+
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::types::TimeSnapshot;
+
+/// Receives measurements from an [`NtsClient`](crate::NtsClient).
+///
+/// All methods have no-op default implementations, so implementors only need
+/// to override the measurements they care about.
+pub trait MetricsSink: Send + Sync {
+    /// Called after a successful NTS key exchange.
+    fn record_key_exchange(&self, _duration: Duration) {}
+
+    /// Called after a failed NTS key exchange.
+    fn record_key_exchange_failure(&self, _error: &Error) {}
+
+    /// Called after a successful time query.
+    fn record_query(&self, _snapshot: &TimeSnapshot) {}
+
+    /// Called after a failed time query.
+    fn record_query_failure(&self, _error: &Error) {}
+
+    /// Called after each successful time query with the number of cookies
+    /// left for the current server.
+    fn record_cookies_remaining(&self, _remaining: usize) {}
+}
+
+/// Publishes measurements through the [`metrics`](::metrics) crate facade.
+///
+/// Install a recorder such as `metrics-exporter-prometheus` to scrape them.
+/// The following metrics are emitted:
+///
+/// | Name | Type | Labels |
+/// |------|------|--------|
+/// | `nts_key_exchanges_total` | counter | |
+/// | `nts_key_exchange_failures_total` | counter | `kind` |
+/// | `nts_key_exchange_duration_seconds` | histogram | |
+/// | `nts_queries_total` | counter | |
+/// | `nts_query_failures_total` | counter | `kind` |
+/// | `nts_offset_seconds` | gauge | `server` |
+/// | `nts_round_trip_seconds` | gauge | `server` |
+/// | `nts_cookies_remaining` | gauge | |
+///
+/// Every key exchange after the first one is a re-key, so
+/// `nts_key_exchanges_total` also counts re-keying.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::metrics::PrometheusMetrics;
+/// use rkik_nts::NtsClient;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::builder()
+///     .with_server("time.cloudflare.com")
+///     .with_metrics(PrometheusMetrics)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrometheusMetrics;
+
+#[cfg(feature = "metrics")]
+impl MetricsSink for PrometheusMetrics {
+    fn record_key_exchange(&self, duration: Duration) {
+        ::metrics::counter!("nts_key_exchanges_total").increment(1);
+        ::metrics::histogram!("nts_key_exchange_duration_seconds").record(duration.as_secs_f64());
+    }
+
+    fn record_key_exchange_failure(&self, error: &Error) {
+        ::metrics::counter!("nts_key_exchange_failures_total", "kind" => error.kind().as_str())
+            .increment(1);
+    }
+
+    fn record_query(&self, snapshot: &TimeSnapshot) {
+        ::metrics::counter!("nts_queries_total").increment(1);
+        ::metrics::gauge!("nts_offset_seconds", "server" => snapshot.server.clone())
+            .set(snapshot.clock_offset.as_secs_f64());
+        ::metrics::gauge!("nts_round_trip_seconds", "server" => snapshot.server.clone())
+            .set(snapshot.round_trip_delay.as_secs_f64());
+    }
+
+    fn record_query_failure(&self, error: &Error) {
+        ::metrics::counter!("nts_query_failures_total", "kind" => error.kind().as_str())
+            .increment(1);
+    }
+
+    fn record_cookies_remaining(&self, remaining: usize) {
+        ::metrics::gauge!("nts_cookies_remaining").set(remaining as f64);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_without_recorder() {
+        let error = Error::RetriesExhausted {
+            attempts: 3,
+            source: Box::new(Error::Timeout),
+        };
+        // Without a recorder installed, recording is a no-op
+        PrometheusMetrics.record_query_failure(&error);
+        PrometheusMetrics.record_cookies_remaining(0);
+    }
+}
@@ -0,0 +1,402 @@
+//! NTP packet parser.
+//!
+//! [`parse`] decodes the 48-byte NTP header (RFC 5905, section 7.3) of a
+//! packet without interpreting it: it checks only the length, never
+//! allocates and never panics, whatever the input. Whether a response is
+//! acceptable (mode, stratum, leap indicator, origin timestamp) is decided
+//! by [`NtpQuery`](crate::query::NtpQuery).
+//!
+//! The parser is exercised by the cargo-fuzz targets in `fuzz/`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::types::LeapIndicator;
+
+/// Size of the NTP header.
+pub const HEADER_LEN: usize = 48;
+
+/// Seconds between the NTP epoch (1900-01-01) and the Unix epoch (1970-01-01).
+pub(crate) const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// The header fields of an NTP packet.
+///
+/// Timestamps are kept in their raw 64-bit form (32-bit seconds and a 32-bit
+/// fraction, without the era); [`to_system_time`] converts them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedPacket<'a> {
+    /// Leap indicator.
+    pub leap_indicator: LeapIndicator,
+
+    /// Protocol version number.
+    pub version: u8,
+
+    /// Association mode (3 = client, 4 = server).
+    pub mode: u8,
+
+    /// Stratum, 0 for a Kiss-o'-Death packet.
+    pub stratum: u8,
+
+    /// Poll interval exponent (log2 seconds).
+    pub poll: i8,
+
+    /// Clock precision exponent (log2 seconds).
+    pub precision: i8,
+
+    /// Total round-trip delay to the primary reference.
+    pub root_delay: Duration,
+
+    /// Total dispersion to the primary reference.
+    pub root_dispersion: Duration,
+
+    /// Reference identifier, or the kiss code of a Kiss-o'-Death packet.
+    pub reference_id: [u8; 4],
+
+    /// Time the server clock was last set or corrected.
+    pub reference_timestamp: u64,
+
+    /// Transmit timestamp of the request this packet answers.
+    pub origin_timestamp: u64,
+
+    /// Time the server received the request (T2).
+    pub receive_timestamp: u64,
+
+    /// Time the server sent this packet (T3).
+    pub transmit_timestamp: u64,
+
+    /// Everything after the header: extension fields and any MAC.
+    pub extensions: &'a [u8],
+}
+
+impl ParsedPacket<'_> {
+    /// The kiss code of a Kiss-o'-Death packet (stratum 0), with trailing
+    /// NULs removed. `None` for other packets, or if the code is not ASCII.
+    pub fn kiss_code(&self) -> Option<&str> {
+        if self.stratum != 0 || !self.reference_id.is_ascii() {
+            return None;
+        }
+        let code = std::str::from_utf8(&self.reference_id).ok()?;
+        Some(code.trim_end_matches('\0'))
+    }
+}
+
+/// Parse the header of the NTP packet `packet`.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidResponse`] if `packet` is shorter than an NTP
+/// header.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::ntp_packet;
+///
+/// let mut packet = [0u8; 48];
+/// packet[0] = 0x24; // VN = 4, mode = 4 (server)
+/// packet[1] = 2; // stratum
+///
+/// let parsed = ntp_packet::parse(&packet)?;
+/// assert_eq!((parsed.version, parsed.mode, parsed.stratum), (4, 4, 2));
+/// assert!(parsed.extensions.is_empty());
+///
+/// assert!(ntp_packet::parse(&packet[..47]).is_err());
+/// # Ok::<(), rkik_nts::Error>(())
+/// ```
+pub fn parse(packet: &[u8]) -> Result<ParsedPacket<'_>> {
+    let Some((header, extensions)) = split_header(packet) else {
+        return Err(Error::InvalidResponse(format!(
+            "NTP packet too small ({} bytes)",
+            packet.len()
+        )));
+    };
+
+    let timestamp = |offset: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&header[offset..offset + 8]);
+        u64::from_be_bytes(bytes)
+    };
+    let short = |offset: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&header[offset..offset + 4]);
+        decode_ntp_short(bytes)
+    };
+
+    Ok(ParsedPacket {
+        leap_indicator: LeapIndicator::from_bits(header[0] >> 6),
+        version: (header[0] >> 3) & 0x07,
+        mode: header[0] & 0x07,
+        stratum: header[1],
+        poll: header[2] as i8,
+        precision: header[3] as i8,
+        root_delay: short(4),
+        root_dispersion: short(8),
+        reference_id: [header[12], header[13], header[14], header[15]],
+        reference_timestamp: timestamp(16),
+        origin_timestamp: timestamp(24),
+        receive_timestamp: timestamp(32),
+        transmit_timestamp: timestamp(40),
+        extensions,
+    })
+}
+
+/// Split `packet` into its fixed-size header and the remaining bytes.
+fn split_header(packet: &[u8]) -> Option<(&[u8; HEADER_LEN], &[u8])> {
+    if packet.len() < HEADER_LEN {
+        return None;
+    }
+    let (header, rest) = packet.split_at(HEADER_LEN);
+    Some((header.try_into().ok()?, rest))
+}
+
+/// Convert a raw 64-bit NTP timestamp to a system time.
+///
+/// NTP timestamps only count seconds modulo 2^32, so they wrap every 136
+/// years, first on 2036-02-07 (RFC 5905, section 6). The era is chosen to
+/// put the result within 68 years of `reference`, normally the local clock.
+/// Saturates to `reference` if the result is not representable.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use rkik_nts::ntp_packet::to_system_time;
+///
+/// // 10 s into NTP era 1, read by a clock just before the rollover
+/// let reference = UNIX_EPOCH + Duration::from_secs(2_085_978_490);
+/// assert_eq!(
+///     to_system_time(10 << 32, reference),
+///     UNIX_EPOCH + Duration::from_secs(2_085_978_506)
+/// );
+/// ```
+pub fn to_system_time(timestamp: u64, reference: SystemTime) -> SystemTime {
+    let reference_secs = unix_seconds(reference) + NTP_UNIX_OFFSET as i64;
+
+    // Signed distance from the reference, modulo the era length
+    let delta = ((timestamp >> 32) as u32).wrapping_sub(reference_secs as u32) as i32;
+    let unix_secs = reference_secs + delta as i64 - NTP_UNIX_OFFSET as i64;
+    let nanos = ((timestamp & 0xFFFF_FFFF) * 1_000_000_000) >> 32;
+
+    let time = if unix_secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(unix_secs as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(unix_secs.unsigned_abs()))
+    };
+    time.and_then(|time| time.checked_add(Duration::from_nanos(nanos)))
+        .unwrap_or(reference)
+}
+
+/// Whole seconds since the Unix epoch, rounded down.
+pub(crate) fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => {
+            let d = e.duration();
+            -(d.as_secs() as i64) - i64::from(d.subsec_nanos() > 0)
+        }
+    }
+}
+
+/// Encode a system time as a 64-bit NTP timestamp (32.32 fixed point).
+///
+/// The era is dropped: times after the 2036 rollover encode into era 1, as
+/// RFC 5905 requires.
+pub(crate) fn encode_ntp_timestamp(time: SystemTime) -> Result<[u8; 8]> {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::Other(format!("System time error: {}", e)))?;
+
+    let secs = (since_epoch.as_secs() + NTP_UNIX_OFFSET) as u32;
+    let frac = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+
+    let mut buf = [0u8; 8];
+    buf[..4].copy_from_slice(&secs.to_be_bytes());
+    buf[4..].copy_from_slice(&(frac as u32).to_be_bytes());
+    Ok(buf)
+}
+
+/// Decode a 32-bit NTP short format value (16.16 fixed point seconds).
+fn decode_ntp_short(bytes: [u8; 4]) -> Duration {
+    let value = u32::from_be_bytes(bytes) as u64;
+    Duration::from_nanos((value * 1_000_000_000) >> 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_parse_header_fields() {
+        let mut packet = vec![0u8; 52];
+        packet[0] = 0x64; // LI = 1, VN = 4, mode = 4 (server)
+        packet[1] = 2;
+        packet[2] = 6;
+        packet[3] = 0xE9; // -23
+        packet[4..8].copy_from_slice(&0x0000_8000u32.to_be_bytes()); // 0.5 s
+        packet[8..12].copy_from_slice(&0x0001_0000u32.to_be_bytes()); // 1 s
+        packet[12..16].copy_from_slice(&[192, 0, 2, 1]);
+        for (i, offset) in [16, 24, 32, 40].into_iter().enumerate() {
+            packet[offset..offset + 8].copy_from_slice(&(i as u64 + 1).to_be_bytes());
+        }
+        packet[48..].copy_from_slice(&[1, 2, 3, 4]);
+
+        let parsed = parse(&packet).unwrap();
+        assert_eq!(parsed.leap_indicator, LeapIndicator::InsertSecond);
+        assert_eq!((parsed.version, parsed.mode, parsed.stratum), (4, 4, 2));
+        assert_eq!((parsed.poll, parsed.precision), (6, -23));
+        assert_eq!(parsed.root_delay, Duration::from_millis(500));
+        assert_eq!(parsed.root_dispersion, Duration::from_secs(1));
+        assert_eq!(parsed.reference_id, [192, 0, 2, 1]);
+        assert_eq!(
+            [
+                parsed.reference_timestamp,
+                parsed.origin_timestamp,
+                parsed.receive_timestamp,
+                parsed.transmit_timestamp
+            ],
+            [1, 2, 3, 4]
+        );
+        assert_eq!(parsed.extensions, &[1, 2, 3, 4]);
+        assert_eq!(parsed.kiss_code(), None);
+    }
+
+    #[test]
+    fn test_kiss_code() {
+        let mut packet = [0u8; HEADER_LEN];
+        packet[12..16].copy_from_slice(b"RATE");
+        assert_eq!(parse(&packet).unwrap().kiss_code(), Some("RATE"));
+
+        packet[12..16].copy_from_slice(b"DN\0\0");
+        assert_eq!(parse(&packet).unwrap().kiss_code(), Some("DN"));
+
+        packet[12..16].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(parse(&packet).unwrap().kiss_code(), None);
+    }
+
+    /// Unix time of the last second of NTP era 0 (2036-02-07T06:28:15Z).
+    const ERA_0_END: u64 = (1 << 32) - 1 - NTP_UNIX_OFFSET;
+
+    fn unix(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn encode(time: SystemTime) -> u64 {
+        u64::from_be_bytes(encode_ntp_timestamp(time).unwrap())
+    }
+
+    #[test]
+    fn test_ntp_timestamp_roundtrip() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 500_000_000);
+        let decoded = to_system_time(encode(time), time);
+        let diff = decoded
+            .duration_since(time)
+            .unwrap_or_else(|e| e.duration());
+        assert!(diff < Duration::from_nanos(10), "diff {:?}", diff);
+    }
+
+    #[test]
+    fn test_era_rollover() {
+        // The last second of era 0 and the first of era 1
+        assert_eq!(encode(unix(ERA_0_END)) >> 32, 0xFFFF_FFFF);
+        assert_eq!(encode(unix(ERA_0_END + 1)) >> 32, 0);
+
+        // Timestamps on either side of the rollover, read by clocks on
+        // either side of it
+        for reference in [ERA_0_END - 60, ERA_0_END, ERA_0_END + 1, ERA_0_END + 60] {
+            for time in [ERA_0_END - 5, ERA_0_END, ERA_0_END + 1, ERA_0_END + 5] {
+                let decoded = to_system_time(encode(unix(time)), unix(reference));
+                assert_eq!(decoded, unix(time), "time {} read at {}", time, reference);
+            }
+        }
+
+        // A year into era 1 is not mistaken for 1900
+        let time = unix(ERA_0_END + 365 * 86_400);
+        assert_eq!(to_system_time(encode(time), time), time);
+    }
+
+    #[test]
+    fn test_era_window() {
+        // Eras are chosen within 68 years of the reference (2065), in both
+        // directions
+        let reference = unix(3_000_000_000);
+        let half_era = 1u64 << 31;
+        for delta in [0, 1, 86_400, half_era - 1] {
+            let later = unix(3_000_000_000 + delta);
+            let earlier = unix(3_000_000_000 - delta);
+            assert_eq!(to_system_time(encode(later), reference), later);
+            assert_eq!(to_system_time(encode(earlier), reference), earlier);
+        }
+
+        // Beyond that, the nearer era wins
+        let far = unix(3_000_000_000 + half_era + 1);
+        assert_eq!(
+            to_system_time(encode(far), reference),
+            unix(3_000_000_000 - half_era + 1)
+        );
+    }
+
+    #[test]
+    fn test_era_roundtrip_properties() {
+        // Any time from 1970 to 2200 survives encoding, read by a clock
+        // that is off by up to a decade
+        let mut rng = StdRng::seed_from_u64(4);
+        let decade = 10 * 365 * 86_400i64;
+        for _ in 0..10_000 {
+            let time =
+                UNIX_EPOCH + Duration::from_nanos(rng.gen_range(0..7_258_118_400_000_000_000));
+            let skew = rng.gen_range(-decade..decade);
+            let reference = if skew >= 0 {
+                time + Duration::from_secs(skew as u64)
+            } else {
+                time.checked_sub(Duration::from_secs(skew.unsigned_abs()))
+                    .unwrap_or(UNIX_EPOCH)
+            };
+
+            let decoded = to_system_time(encode(time), reference);
+            let diff = decoded
+                .duration_since(time)
+                .unwrap_or_else(|e| e.duration());
+            assert!(
+                diff < Duration::from_nanos(10),
+                "{:?} decoded as {:?}",
+                time,
+                decoded
+            );
+        }
+    }
+
+    #[test]
+    fn test_pre_epoch_reference() {
+        // 1969 decodes before the Unix epoch rather than wrapping
+        let time = UNIX_EPOCH - Duration::from_secs(86_400);
+        let timestamp = (NTP_UNIX_OFFSET - 86_400) << 32;
+        assert_eq!(to_system_time(timestamp, time), time);
+        assert_eq!(unix_seconds(UNIX_EPOCH - Duration::from_millis(1)), -1);
+    }
+
+    #[test]
+    fn test_arbitrary_input() {
+        // Any input parses if and only if it holds a header, without panicking
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..128);
+            let packet: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            match parse(&packet) {
+                Ok(parsed) => {
+                    assert!(len >= HEADER_LEN);
+                    assert_eq!(parsed.extensions.len(), len - HEADER_LEN);
+                    assert!(parsed.version < 8 && parsed.mode < 8);
+                    assert!(parsed.root_delay < Duration::from_secs(1 << 16));
+                    to_system_time(parsed.transmit_timestamp, UNIX_EPOCH);
+                }
+                Err(e) => {
+                    assert!(len < HEADER_LEN);
+                    assert!(matches!(e, Error::InvalidResponse(_)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Loading NTS sources from ntpd-rs configuration files.
+//!
+//! This module is only available with the `ntpd-rs-config` feature. It reads
+//! the `[[source]]` tables of an ntpd-rs `ntp.toml` and turns every source
+//! with `mode = "nts"` into an [`NtsClientConfig`], so a daemon and an
+//! application can share one list of servers:
+//!
+//! ```toml
+//! [[source]]
+//! mode = "nts"
+//! address = "time.cloudflare.com"
+//!
+//! [[source]]
+//! mode = "nts"
+//! address = "nts.internal.example:4461"
+//! certificate-authority = "/etc/ntpd-rs/internal-ca.pem"
+//! ```
+//!
+//! Sources in other modes and all other sections of the file are ignored.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::NtsClientConfig;
+use crate::error::{Error, Result};
+
+/// The parts of an ntpd-rs configuration file used here.
+#[derive(Deserialize)]
+struct DaemonConfig {
+    #[serde(default)]
+    source: Vec<Source>,
+}
+
+/// A `[[source]]` table.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Source {
+    mode: String,
+    address: Option<String>,
+    certificate_authority: Option<PathBuf>,
+    ntp_version: Option<NtpVersion>,
+}
+
+/// `ntp-version`: a version number or `"auto"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NtpVersion {
+    Fixed(u8),
+    Named(String),
+}
+
+/// Parse the NTS sources of an ntpd-rs configuration.
+///
+/// `address` may carry a port as `host:port`; `certificate-authority` and
+/// `ntp-version` are honoured, with `"auto"` keeping the default version.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidConfig`] if `config` is not valid TOML, if an
+/// NTS source has no `address`, or if a resulting configuration is invalid.
+///
+/// # Examples
+///
+/// ```
+/// let sources = rkik_nts::ntpd_rs::sources_from_str(
+///     r#"
+///     [[source]]
+///     mode = "nts"
+///     address = "time.cloudflare.com"
+///
+///     [[source]]
+///     mode = "pool"
+///     address = "pool.ntp.org"
+///     "#,
+/// )?;
+/// assert_eq!(sources.len(), 1);
+/// assert_eq!(sources[0].nts_ke_server, "time.cloudflare.com");
+/// # Ok::<(), rkik_nts::Error>(())
+/// ```
+pub fn sources_from_str(config: &str) -> Result<Vec<NtsClientConfig>> {
+    let daemon: DaemonConfig = toml::from_str(config)
+        .map_err(|e| Error::InvalidConfig(format!("Invalid ntpd-rs configuration: {}", e)))?;
+
+    daemon
+        .source
+        .into_iter()
+        .filter(|source| source.mode == "nts")
+        .map(into_config)
+        .collect()
+}
+
+/// Read and parse the NTS sources of the ntpd-rs configuration file at
+/// `path`. See [`sources_from_str`].
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the file cannot be read, and the errors of
+/// [`sources_from_str`].
+pub fn sources_from_file(path: impl AsRef<Path>) -> Result<Vec<NtsClientConfig>> {
+    sources_from_str(&std::fs::read_to_string(path)?)
+}
+
+fn into_config(source: Source) -> Result<NtsClientConfig> {
+    let address = source
+        .address
+        .ok_or_else(|| Error::InvalidConfig("ntpd-rs NTS source has no address".to_string()))?;
+
+    let mut config = NtsClientConfig::new(address);
+    if let Some(path) = source.certificate_authority {
+        config = config.with_ca_file(path);
+    }
+    match source.ntp_version {
+        None => {}
+        Some(NtpVersion::Fixed(version)) => config = config.with_ntp_version(version),
+        Some(NtpVersion::Named(name)) if name == "auto" => {}
+        Some(NtpVersion::Named(name)) => {
+            return Err(Error::InvalidConfig(format!(
+                "Unsupported ntpd-rs ntp-version: {}",
+                name
+            )));
+        }
+    }
+
+    config.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_from_str() {
+        let sources = sources_from_str(
+            r#"
+            [observability]
+            log-level = "info"
+
+            [[source]]
+            mode = "server"
+            address = "ntp.example"
+
+            [[source]]
+            mode = "nts"
+            address = "[2001:db8::1]:4461"
+            certificate-authority = "/etc/ntpd-rs/ca.pem"
+            ntp-version = 4
+
+            [[source]]
+            mode = "nts"
+            address = "nts.example"
+            ntp-version = "auto"
+
+            [synchronization]
+            minimum-agreeing-sources = 1
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].nts_ke_server, "2001:db8::1");
+        assert_eq!(sources[0].nts_ke_port, 4461);
+        assert_eq!(
+            sources[0].ca_file.as_deref(),
+            Some(Path::new("/etc/ntpd-rs/ca.pem"))
+        );
+        assert_eq!(sources[1].nts_ke_server, "nts.example");
+        assert_eq!(sources[1].nts_ke_port, 4460);
+        assert!(sources[1].ca_file.is_none());
+    }
+
+    #[test]
+    fn test_invalid_sources() {
+        assert!(sources_from_str("").unwrap().is_empty());
+        for invalid in [
+            "[[source]\nmode = \"nts\"",
+            "[[source]]\nmode = \"nts\"",
+            "[[source]]\nmode = \"nts\"\naddress = \"a.example\"\nntp-version = \"latest\"",
+            "[[source]]\nmode = \"nts\"\naddress = \"a.example\"\nntp-version = 9",
+        ] {
+            assert!(
+                matches!(sources_from_str(invalid), Err(Error::InvalidConfig(_))),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! Recording of NTS traffic as a pcapng capture file.
+//!
+//! A [`PcapWriter`] attached with
+//! [`NtsClientBuilder::with_pcap_writer`](crate::NtsClientBuilder::with_pcap_writer)
+//! records every NTP request and response as an IPv4 or IPv6 UDP packet,
+//! and every successful key exchange as the opening and closing segments of
+//! its TCP connection, annotated with the negotiated parameters. The TLS
+//! payload of the key exchange is not recorded. The resulting file can be
+//! opened in Wireshark, which decodes the NTS extension fields.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::types::NtsKeResult;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// Raw IP packets, version given by the first nibble.
+const LINKTYPE_RAW: u16 = 101;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const TTL: u8 = 64;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_ACK: u8 = 0x10;
+
+/// Writes a pcapng capture with a single raw IP interface and microsecond
+/// timestamps.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::pcap::PcapWriter;
+/// use rkik_nts::NtsClient;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::builder()
+///     .with_server("time.cloudflare.com")
+///     .with_pcap_writer(PcapWriter::create("nts.pcapng")?)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PcapWriter {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl PcapWriter {
+    /// Create or truncate the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write to `writer`, starting with the section header and interface
+    /// description blocks.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // major version
+        header.extend_from_slice(&0u16.to_le_bytes()); // minor version
+        header.extend_from_slice(&(-1i64).to_le_bytes()); // section length unknown
+        writer.write_all(&block(BLOCK_SECTION_HEADER, &header))?;
+
+        let mut interface = Vec::with_capacity(8);
+        interface.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes()); // reserved
+        interface.extend_from_slice(&0u32.to_le_bytes()); // no snapshot length limit
+        writer.write_all(&block(BLOCK_INTERFACE_DESCRIPTION, &interface))?;
+
+        writer.flush()?;
+        Ok(Self {
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Record a UDP datagram sent from `src` to `dst` at `time`.
+    pub(crate) fn write_udp(
+        &self,
+        time: SystemTime,
+        src: SocketAddr,
+        dst: SocketAddr,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let (src, dst) = same_family(src, dst);
+        let mut udp = Vec::with_capacity(8 + payload.len());
+        udp.extend_from_slice(&src.port().to_be_bytes());
+        udp.extend_from_slice(&dst.port().to_be_bytes());
+        udp.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]); // checksum
+        udp.extend_from_slice(payload);
+        let checksum = match transport_checksum(src.ip(), dst.ip(), IPPROTO_UDP, &udp) {
+            0 => 0xffff,
+            sum => sum,
+        };
+        udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+        self.write_packet(
+            time,
+            &ip_packet(src.ip(), dst.ip(), IPPROTO_UDP, &udp),
+            None,
+        )
+    }
+
+    /// Record the key exchange of `result` with the NTS-KE server `server`,
+    /// finished at `finished`, as the SYN and FIN segments of a TCP
+    /// connection from an unspecified local address.
+    pub(crate) fn write_key_exchange(
+        &self,
+        server: &str,
+        result: &NtsKeResult,
+        finished: SystemTime,
+    ) -> io::Result<()> {
+        let remote = result.ke_server;
+        let local = match remote {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+        };
+        let comment = format!(
+            "NTS-KE with {} ({}): AEAD {}, {} cookies, NTP server {}, protocol version {}, {} ms",
+            server,
+            remote,
+            result.aead_algorithm,
+            result.cookie_count(),
+            result.ntp_server,
+            result.protocol_version(),
+            result.ke_duration().as_millis(),
+        );
+        let started = finished
+            .checked_sub(result.ke_duration())
+            .unwrap_or(finished);
+
+        let syn = tcp_segment(local, remote, TCP_SYN);
+        self.write_packet(
+            started,
+            &ip_packet(local.ip(), remote.ip(), IPPROTO_TCP, &syn),
+            Some(&comment),
+        )?;
+        let fin = tcp_segment(local, remote, TCP_FIN | TCP_ACK);
+        self.write_packet(
+            finished,
+            &ip_packet(local.ip(), remote.ip(), IPPROTO_TCP, &fin),
+            None,
+        )
+    }
+
+    /// Write an enhanced packet block for `packet`.
+    fn write_packet(
+        &self,
+        time: SystemTime,
+        packet: &[u8],
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+
+        let mut body = Vec::with_capacity(20 + padded(packet.len()) + 32);
+        body.extend_from_slice(&0u32.to_le_bytes()); // interface
+        body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(micros as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // captured
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // original
+        body.extend_from_slice(packet);
+        body.resize(padded(body.len()), 0);
+        if let Some(comment) = comment {
+            body.extend_from_slice(&OPT_COMMENT.to_le_bytes());
+            body.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+            body.extend_from_slice(comment.as_bytes());
+            body.resize(padded(body.len()), 0);
+            body.extend_from_slice(&OPT_END_OF_OPT.to_le_bytes());
+            body.extend_from_slice(&0u16.to_le_bytes());
+        }
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.write_all(&block(BLOCK_ENHANCED_PACKET, &body))?;
+        writer.flush()
+    }
+}
+
+/// `len` rounded up to a multiple of 4.
+fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Frame `body` as a pcapng block. `body` must be padded to 4 bytes.
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let total = (12 + body.len()) as u32;
+    let mut block = Vec::with_capacity(total as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&total.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&total.to_le_bytes());
+    block
+}
+
+/// Map both addresses to IPv6 if their families differ.
+fn same_family(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let to_v6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    };
+    if src.is_ipv4() == dst.is_ipv4() {
+        (src, dst)
+    } else {
+        (to_v6(src), to_v6(dst))
+    }
+}
+
+/// A TCP header without payload or options.
+fn tcp_segment(src: SocketAddr, dst: SocketAddr, flags: u8) -> Vec<u8> {
+    let mut tcp = Vec::with_capacity(20);
+    tcp.extend_from_slice(&src.port().to_be_bytes());
+    tcp.extend_from_slice(&dst.port().to_be_bytes());
+    tcp.extend_from_slice(&0u32.to_be_bytes()); // sequence number
+    tcp.extend_from_slice(&0u32.to_be_bytes()); // acknowledgment number
+    tcp.push(5 << 4); // data offset
+    tcp.push(flags);
+    tcp.extend_from_slice(&0xffffu16.to_be_bytes()); // window
+    tcp.extend_from_slice(&[0, 0]); // checksum
+    tcp.extend_from_slice(&[0, 0]); // urgent pointer
+    let checksum = transport_checksum(src.ip(), dst.ip(), IPPROTO_TCP, &tcp);
+    tcp[16..18].copy_from_slice(&checksum.to_be_bytes());
+    tcp
+}
+
+/// Wrap `payload` in an IPv4 or IPv6 header. Both addresses must be of the
+/// same family.
+fn ip_packet(src: IpAddr, dst: IpAddr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let mut packet = Vec::with_capacity(20 + payload.len());
+            packet.push(0x45); // version 4, 20-byte header
+            packet.push(0);
+            packet.extend_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0]); // identification
+            packet.extend_from_slice(&0x4000u16.to_be_bytes()); // don't fragment
+            packet.push(TTL);
+            packet.push(protocol);
+            packet.extend_from_slice(&[0, 0]); // checksum
+            packet.extend_from_slice(&src.octets());
+            packet.extend_from_slice(&dst.octets());
+            let checksum = internet_checksum(0, &packet);
+            packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+            packet.extend_from_slice(payload);
+            packet
+        }
+        (src, dst) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            let mut packet = Vec::with_capacity(40 + payload.len());
+            packet.extend_from_slice(&0x6000_0000u32.to_be_bytes());
+            packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            packet.push(protocol);
+            packet.push(TTL);
+            packet.extend_from_slice(&v6(src).octets());
+            packet.extend_from_slice(&v6(dst).octets());
+            packet.extend_from_slice(payload);
+            packet
+        }
+    }
+}
+
+/// Checksum of a TCP or UDP `segment` including the IP pseudo-header.
+fn transport_checksum(src: IpAddr, dst: IpAddr, protocol: u8, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40);
+    match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&[0, protocol]);
+            pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        }
+        (src, dst) => {
+            for ip in [src, dst] {
+                match ip {
+                    IpAddr::V4(ip) => pseudo.extend_from_slice(&ip.to_ipv6_mapped().octets()),
+                    IpAddr::V6(ip) => pseudo.extend_from_slice(&ip.octets()),
+                }
+            }
+            pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+    }
+    internet_checksum(sum_words(0, &pseudo), segment)
+}
+
+/// One's complement sum of `data` as big-endian 16-bit words, added to
+/// `initial`, without folding.
+fn sum_words(initial: u32, data: &[u8]) -> u32 {
+    data.chunks(2).fold(initial, |sum, chunk| {
+        let word = u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]);
+        sum + word as u32
+    })
+}
+
+/// The RFC 1071 checksum of `data`, starting from the partial sum `initial`.
+fn internet_checksum(initial: u32, data: &[u8]) -> u16 {
+    let mut sum = sum_words(initial, data);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::NtsKeys;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// A writer whose output stays readable after being moved into a
+    /// [`PcapWriter`].
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Split a capture into (block type, body) pairs, checking the framing.
+    fn blocks(mut data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut blocks = Vec::new();
+        while !data.is_empty() {
+            let len = u32_at(data, 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(u32_at(data, len - 4) as usize, len);
+            blocks.push((u32_at(data, 0), data[8..len - 4].to_vec()));
+            data = &data[len..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_udp_capture() {
+        let buffer = SharedBuffer::default();
+        let writer = PcapWriter::from_writer(buffer.clone()).unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(0x1_0000_0002);
+        let local: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let server: SocketAddr = "198.51.100.7:123".parse().unwrap();
+        let payload = [0x23u8; 49];
+        writer.write_udp(time, local, server, &payload).unwrap();
+
+        let data = buffer.0.lock().unwrap().clone();
+        let blocks = blocks(&data);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].0, BLOCK_SECTION_HEADER);
+        assert_eq!(u32_at(&blocks[0].1, 0), BYTE_ORDER_MAGIC);
+        assert_eq!(blocks[1].0, BLOCK_INTERFACE_DESCRIPTION);
+        assert_eq!(&blocks[1].1[..2], &LINKTYPE_RAW.to_le_bytes());
+
+        let (block_type, body) = &blocks[2];
+        assert_eq!(*block_type, BLOCK_ENHANCED_PACKET);
+        assert_eq!(u32_at(body, 4), 1); // timestamp, high
+        assert_eq!(u32_at(body, 8), 2); // timestamp, low
+        let len = u32_at(body, 12) as usize;
+        assert_eq!(len, 20 + 8 + payload.len());
+        assert_eq!(body.len(), 20 + padded(len));
+
+        let packet = &body[20..20 + len];
+        assert_eq!(packet[0], 0x45);
+        assert_eq!(packet[9], IPPROTO_UDP);
+        assert_eq!(internet_checksum(0, &packet[..20]), 0);
+        let udp = &packet[20..];
+        assert_eq!(&udp[..4], &[0x9c, 0x40, 0, 123]);
+        assert_eq!(
+            transport_checksum(local.ip(), server.ip(), IPPROTO_UDP, udp),
+            0
+        );
+        assert_eq!(&udp[8..], &payload[..]);
+    }
+
+    #[test]
+    fn test_key_exchange_capture() {
+        let buffer = SharedBuffer::default();
+        let writer = PcapWriter::from_writer(buffer.clone()).unwrap();
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![1; 32], vec![2; 32]).unwrap();
+        let result = NtsKeResult::new(
+            "[2001:db8::2]:123".parse().unwrap(),
+            "[2001:db8::1]:4460".parse().unwrap(),
+            vec![vec![0xAA; 64]],
+            Duration::from_millis(25),
+            keys,
+        );
+        let finished = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        writer
+            .write_key_exchange("time.example.com", &result, finished)
+            .unwrap();
+
+        let data = buffer.0.lock().unwrap().clone();
+        let blocks = blocks(&data);
+        assert_eq!(blocks.len(), 4);
+        let (syn, fin) = (&blocks[2].1, &blocks[3].1);
+        let micros = |body: &[u8]| (u32_at(body, 4) as u64) << 32 | u32_at(body, 8) as u64;
+        assert_eq!(micros(fin) - micros(syn), 25_000);
+
+        let len = u32_at(syn, 12) as usize;
+        assert_eq!(len, 40 + 20);
+        let packet = &syn[20..20 + len];
+        assert_eq!(packet[0] >> 4, 6);
+        assert_eq!(packet[6], IPPROTO_TCP);
+        let tcp = &packet[40..];
+        assert_eq!(&tcp[2..4], &4460u16.to_be_bytes());
+        assert_eq!(tcp[13], TCP_SYN);
+        assert_eq!(
+            transport_checksum(
+                "::".parse().unwrap(),
+                result.ke_server.ip(),
+                IPPROTO_TCP,
+                tcp
+            ),
+            0
+        );
+
+        let options = &syn[20 + padded(len)..];
+        assert_eq!(&options[..2], &OPT_COMMENT.to_le_bytes());
+        let comment_len = u16::from_le_bytes([options[2], options[3]]) as usize;
+        let comment = std::str::from_utf8(&options[4..4 + comment_len]).unwrap();
+        assert!(comment.starts_with("NTS-KE with time.example.com ([2001:db8::1]:4460)"));
+        assert!(comment.ends_with("25 ms"));
+        assert_eq!(&options[options.len() - 4..], &[0, 0, 0, 0]);
+        assert_eq!(fin[20 + 40 + 13], TCP_FIN | TCP_ACK);
+    }
+
+    #[test]
+    fn test_mixed_address_families() {
+        let (src, dst) = same_family(
+            "192.0.2.1:1000".parse().unwrap(),
+            "[2001:db8::1]:123".parse().unwrap(),
+        );
+        assert_eq!(src, "[::ffff:192.0.2.1]:1000".parse().unwrap());
+        assert_eq!(dst, "[2001:db8::1]:123".parse().unwrap());
+    }
+}
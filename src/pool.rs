@@ -0,0 +1,498 @@
+//! Querying several NTS servers and selecting the best answer.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::blacklist::{Blacklist, BlacklistEntry, BlacklistPolicy};
+use crate::client::NtsClient;
+#[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+use crate::config::NtsClientConfig;
+use crate::error::{Error, Result};
+use crate::runtime::join_bounded;
+use crate::types::TimeSnapshot;
+
+/// How [`NtsPool::get_time`] picks one answer among the servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStrategy {
+    /// The answer with the shortest round-trip delay.
+    BestRtt,
+    /// The answer with the lowest root distance, i.e. the tightest error
+    /// bound.
+    #[default]
+    LowestRootDistance,
+    /// Among the answers whose error bounds (offset ± root distance) overlap
+    /// for a majority of the servers, the one with the lowest root distance.
+    /// Fails if no majority agrees.
+    Majority,
+}
+
+/// A set of NTS clients, one per NTS-KE server.
+///
+/// Servers are connected and queried concurrently. Servers that fail to
+/// connect are retried on the next query.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::{NtsClientConfig, NtsPool, SelectionStrategy};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut pool = NtsPool::new([
+///     NtsClientConfig::new("time.cloudflare.com"),
+///     NtsClientConfig::new("nts.ntp.se"),
+///     NtsClientConfig::new("ntppool1.time.nl"),
+/// ])
+/// .with_strategy(SelectionStrategy::Majority);
+///
+/// pool.connect().await?;
+/// let time = pool.get_time().await?;
+/// println!("Offset: {} (from {})", time.clock_offset, time.server);
+/// # Ok(())
+/// # }
+/// ```
+pub struct NtsPool {
+    clients: Vec<NtsClient>,
+    strategy: SelectionStrategy,
+    blacklist: Blacklist,
+}
+
+impl NtsPool {
+    /// Create a pool with one client per configuration.
+    #[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+    pub fn new(configs: impl IntoIterator<Item = NtsClientConfig>) -> Self {
+        Self::from_clients(configs.into_iter().map(NtsClient::new))
+    }
+
+    /// Create a pool from already built clients.
+    pub fn from_clients(clients: impl IntoIterator<Item = NtsClient>) -> Self {
+        Self {
+            clients: clients.into_iter().collect(),
+            strategy: SelectionStrategy::default(),
+            blacklist: Blacklist::new(BlacklistPolicy::default()),
+        }
+    }
+
+    /// Set the strategy used to select an answer.
+    pub fn with_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set when servers that keep failing are temporarily skipped.
+    ///
+    /// Blacklisted servers are not queried as long as another server of
+    /// the pool is available.
+    pub fn with_blacklist_policy(mut self, policy: BlacklistPolicy) -> Self {
+        self.blacklist = Blacklist::new(policy);
+        self
+    }
+
+    /// The servers currently blacklisted, for diagnostic purposes.
+    pub fn blacklist(&self) -> Vec<BlacklistEntry> {
+        self.blacklist.entries(Instant::now())
+    }
+
+    /// The clients in the pool, in the order they were added.
+    pub fn clients(&self) -> &[NtsClient] {
+        &self.clients
+    }
+
+    /// Connect all clients concurrently.
+    ///
+    /// Returns the number of connected clients.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pool is empty or no client could connect. In
+    /// the latter case the error of the first client is returned.
+    pub async fn connect(&mut self) -> Result<usize> {
+        let results = self
+            .run_all(|_, client| async move {
+                let result = client.connect().await;
+                (client, result)
+            })
+            .await?;
+
+        let mut first_error = None;
+        let mut connected = 0;
+        for result in results {
+            match result {
+                Ok(()) => connected += 1,
+                Err(e) => {
+                    warn!("Pool server failed to connect: {}", e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match (connected, first_error) {
+            (0, Some(e)) => Err(e),
+            _ => Ok(connected),
+        }
+    }
+
+    /// Query all servers concurrently.
+    ///
+    /// Clients that are not connected are connected first. Blacklisted
+    /// servers are skipped unless all servers are blacklisted. Results are in
+    /// the order of [`clients`](Self::clients).
+    pub async fn query_all(&mut self) -> Vec<Result<TimeSnapshot>> {
+        let now = Instant::now();
+        let mut skipped: Vec<bool> = self
+            .clients
+            .iter()
+            .map(|client| self.blacklist.contains(&client.config().nts_ke_server, now))
+            .collect();
+        if skipped.iter().all(|&skip| skip) {
+            skipped.fill(false);
+        }
+
+        let results = self
+            .run_all(|index, client| {
+                let skip = skipped[index];
+                async move {
+                    let result = async {
+                        if skip {
+                            return Err(Error::ServerUnavailable(format!(
+                                "{} is blacklisted",
+                                client.config().nts_ke_server
+                            )));
+                        }
+                        if !client.is_connected() {
+                            client.connect().await?;
+                        }
+                        client.get_time().await
+                    }
+                    .await;
+                    (client, result)
+                }
+            })
+            .await
+            .unwrap_or_default();
+
+        let now = Instant::now();
+        for ((client, result), skip) in self.clients.iter().zip(&results).zip(skipped) {
+            let server = &client.config().nts_ke_server;
+            match result {
+                _ if skip => {}
+                Ok(_) => self.blacklist.record_success(server),
+                Err(Error::KissOfDeath { code }) if code == "DENY" || code == "RSTR" => {
+                    self.blacklist.ban(server, now)
+                }
+                Err(_) => self.blacklist.record_failure(server, now),
+            }
+        }
+        results
+    }
+
+    /// Query all servers and select one answer with the configured
+    /// [`SelectionStrategy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ServerUnavailable`] if no server answered, or
+    /// [`Error::NoMajority`] if the [`SelectionStrategy::Majority`] strategy
+    /// found no majority of agreeing servers.
+    pub async fn get_time(&mut self) -> Result<TimeSnapshot> {
+        let total = self.clients.len();
+        let snapshots: Vec<TimeSnapshot> = self
+            .query_all()
+            .await
+            .into_iter()
+            .filter_map(|result| match result {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    debug!("Pool server query failed: {}", e);
+                    None
+                }
+            })
+            .collect();
+        select(self.strategy, snapshots, total)
+    }
+
+    /// Run `operation` on every client concurrently and put the clients back.
+    async fn run_all<T, F, Fut>(&mut self, operation: F) -> Result<Vec<Result<T>>>
+    where
+        F: Fn(usize, NtsClient) -> Fut,
+        Fut: Future<Output = (NtsClient, Result<T>)> + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.clients.is_empty() {
+            return Err(Error::InvalidConfig("NTS pool has no servers".to_string()));
+        }
+
+        let clients = std::mem::take(&mut self.clients);
+        let tasks = clients
+            .into_iter()
+            .enumerate()
+            .map(|(index, client)| operation(index, client));
+        let finished = join_bounded(tasks, usize::MAX).await;
+
+        let mut results = Vec::with_capacity(finished.len());
+        for (client, result) in finished {
+            self.clients.push(client);
+            results.push(result);
+        }
+        Ok(results)
+    }
+}
+
+/// Number of servers [`query_many`] queries at the same time.
+pub const DEFAULT_PARALLELISM: usize = 8;
+
+/// Perform a key exchange and one time query against each of `servers`.
+///
+/// Servers are queried concurrently, at most [`DEFAULT_PARALLELISM`] at a
+/// time, with the default configuration. Results are in the order of
+/// `servers`. Use [`query_many_with`] to customize the configuration.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// let servers = ["time.cloudflare.com", "nts.ntp.se"];
+/// for (server, result) in servers.iter().zip(rkik_nts::query_many(&servers).await) {
+///     match result {
+///         Ok(time) => println!("{}: offset {}", server, time.clock_offset),
+///         Err(e) => println!("{}: {}", server, e),
+///     }
+/// }
+/// # }
+/// ```
+#[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+pub async fn query_many(servers: &[&str]) -> Vec<Result<TimeSnapshot>> {
+    query_many_with(servers, &NtsClientConfig::default(), DEFAULT_PARALLELISM).await
+}
+
+/// Like [`query_many`], using `template` for every server (with its NTS-KE
+/// server replaced) and querying at most `parallelism` servers at a time.
+#[cfg(any(feature = "tokio", feature = "smol", feature = "async-std"))]
+pub async fn query_many_with(
+    servers: &[&str],
+    template: &NtsClientConfig,
+    parallelism: usize,
+) -> Vec<Result<TimeSnapshot>> {
+    let tasks = servers.iter().map(|server| {
+        let config = NtsClientConfig {
+            nts_ke_server: server.to_string(),
+            ..template.clone()
+        };
+        async move {
+            let client = NtsClient::new(config);
+            client.connect().await?;
+            client.get_time().await
+        }
+    });
+    join_bounded(tasks, parallelism).await
+}
+
+/// Select one of `snapshots`, answered by `total` queried servers.
+fn select(
+    strategy: SelectionStrategy,
+    snapshots: Vec<TimeSnapshot>,
+    total: usize,
+) -> Result<TimeSnapshot> {
+    if snapshots.is_empty() {
+        return Err(Error::ServerUnavailable(
+            "No server in the NTS pool answered".to_string(),
+        ));
+    }
+
+    let candidates = match strategy {
+        SelectionStrategy::BestRtt => {
+            return Ok(snapshots
+                .into_iter()
+                .min_by_key(|s| s.round_trip_delay)
+                .expect("snapshots is not empty"))
+        }
+        SelectionStrategy::LowestRootDistance => snapshots,
+        SelectionStrategy::Majority => {
+            let agreeing = majority(&snapshots);
+            if agreeing.len() * 2 <= total {
+                return Err(Error::NoMajority {
+                    agreeing: agreeing.len(),
+                    total,
+                });
+            }
+            snapshots
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| agreeing.contains(i))
+                .map(|(_, s)| s)
+                .collect()
+        }
+    };
+    Ok(candidates
+        .into_iter()
+        .min_by_key(TimeSnapshot::root_distance)
+        .expect("candidates is not empty"))
+}
+
+/// Indices of the largest set of snapshots whose correctness intervals
+/// (offset ± root distance) share a common point, as in Marzullo's algorithm.
+fn majority(snapshots: &[TimeSnapshot]) -> Vec<usize> {
+    let intervals: Vec<(i128, i128)> = snapshots
+        .iter()
+        .map(|s| {
+            let offset = s.offset_nanos();
+            let distance = s.root_distance().max(Duration::from_nanos(1)).as_nanos() as i128;
+            (offset - distance, offset + distance)
+        })
+        .collect();
+
+    // The best common point is always the lower bound of some interval.
+    intervals
+        .iter()
+        .map(|&(point, _)| {
+            intervals
+                .iter()
+                .enumerate()
+                .filter(|(_, &(low, high))| low <= point && point <= high)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        })
+        .max_by_key(Vec::len)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ServerInfo, SignedDuration};
+    use std::time::SystemTime;
+
+    fn snapshot(server: &str, offset_ms: i64, rtt_ms: u64, dispersion_ms: u64) -> TimeSnapshot {
+        let offset = SignedDuration::from_nanos(-(offset_ms as i128) * 1_000_000);
+        TimeSnapshot {
+            round_trip_delay: Duration::from_millis(rtt_ms),
+            server: server.to_string(),
+            server_info: ServerInfo {
+                root_dispersion: Duration::from_millis(dispersion_ms),
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(SystemTime::now(), offset)
+        }
+    }
+
+    #[test]
+    fn test_select_best_rtt() {
+        let selected = select(
+            SelectionStrategy::BestRtt,
+            vec![snapshot("a", 0, 30, 1), snapshot("b", 0, 10, 50)],
+            2,
+        )
+        .unwrap();
+        assert_eq!(selected.server, "b");
+    }
+
+    #[test]
+    fn test_select_lowest_root_distance() {
+        let selected = select(
+            SelectionStrategy::LowestRootDistance,
+            vec![snapshot("a", 0, 30, 1), snapshot("b", 0, 10, 50)],
+            2,
+        )
+        .unwrap();
+        assert_eq!(selected.server, "a");
+    }
+
+    #[test]
+    fn test_select_majority_ignores_falseticker() {
+        let snapshots = vec![
+            snapshot("a", 5, 20, 2),
+            snapshot("b", -3, 20, 2),
+            snapshot("liar", 5_000, 2, 0),
+        ];
+        let selected = select(SelectionStrategy::Majority, snapshots.clone(), 3).unwrap();
+        assert_eq!(selected.server, "a");
+
+        // The lowest root distance alone would pick the falseticker
+        let selected = select(SelectionStrategy::LowestRootDistance, snapshots, 3).unwrap();
+        assert_eq!(selected.server, "liar");
+    }
+
+    #[test]
+    fn test_select_majority_requires_more_than_half() {
+        let snapshots = vec![snapshot("a", 0, 10, 1), snapshot("b", 1_000, 10, 1)];
+        assert!(matches!(
+            select(SelectionStrategy::Majority, snapshots, 2),
+            Err(Error::NoMajority {
+                agreeing: 1,
+                total: 2
+            })
+        ));
+
+        // Servers that did not answer count against the majority
+        let snapshots = vec![snapshot("a", 0, 10, 1), snapshot("b", 2, 10, 1)];
+        assert!(select(SelectionStrategy::Majority, snapshots, 4).is_err());
+    }
+
+    #[test]
+    fn test_select_without_answers() {
+        assert!(matches!(
+            select(SelectionStrategy::BestRtt, Vec::new(), 3),
+            Err(Error::ServerUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_empty_pool() {
+        let mut pool = NtsPool::new(Vec::<NtsClientConfig>::new());
+        assert!(matches!(pool.connect().await, Err(Error::InvalidConfig(_))));
+        assert!(pool.get_time().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_many_keeps_order() {
+        let template = NtsClientConfig::default().with_ntp_version(2);
+        let results = query_many_with(&["a.example", "b.example", "c.example"], &template, 2).await;
+        assert_eq!(results.len(), 3);
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(Error::InvalidConfig(_)))));
+        assert!(query_many(&[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_all_blacklists_failing_servers() {
+        let mut pool = NtsPool::new([
+            NtsClientConfig::new("a.example").with_ntp_version(2),
+            NtsClientConfig::new("b.example").with_ntp_version(2),
+        ])
+        .with_blacklist_policy(BlacklistPolicy {
+            max_failures: 1,
+            duration: Duration::from_secs(60),
+        });
+        pool.blacklist.ban("b.example", Instant::now());
+
+        let results = pool.query_all().await;
+        assert!(matches!(results[0], Err(Error::InvalidConfig(_))));
+        assert!(matches!(results[1], Err(Error::ServerUnavailable(_))));
+
+        // Now that all servers are blacklisted, they are queried again
+        let servers: Vec<_> = pool.blacklist().into_iter().map(|e| e.server).collect();
+        assert_eq!(servers, ["a.example", "b.example"]);
+        let results = pool.query_all().await;
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(Error::InvalidConfig(_)))));
+    }
+
+    #[tokio::test]
+    async fn test_connect_keeps_client_order() {
+        let mut pool = NtsPool::new([
+            NtsClientConfig::new("a.example").with_ntp_version(2),
+            NtsClientConfig::new("b.example").with_ntp_version(2),
+        ]);
+        assert!(pool.connect().await.is_err());
+        let servers: Vec<_> = pool
+            .clients()
+            .iter()
+            .map(|c| c.config().nts_ke_server.as_str())
+            .collect();
+        assert_eq!(servers, ["a.example", "b.example"]);
+    }
+}
@@ -0,0 +1,602 @@
+//! SOCKS5 proxy support for the NTS-KE connection (RFC 1928).
+//!
+//! Only the TCP key exchange goes through the proxy; NTP queries are sent
+//! over UDP directly to the NTP server. The NTS-KE hostname is resolved
+//! locally and the proxy is asked to connect to the resulting address.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+use tokio::net::TcpStream;
+
+use crate::socket::SocketOptions;
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+const USERNAME_PASSWORD_VERSION: u8 = 0x01;
+const COMMAND_CONNECT: u8 = 0x01;
+const ADDRESS_IPV4: u8 = 0x01;
+const ADDRESS_DOMAIN: u8 = 0x03;
+const ADDRESS_IPV6: u8 = 0x04;
+
+/// A SOCKS5 proxy for the NTS-KE connection, set with
+/// [`NtsClientConfig::with_proxy`](crate::NtsClientConfig::with_proxy).
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::{NtsClientConfig, ProxyConfig};
+///
+/// let config = NtsClientConfig::new("time.cloudflare.com")
+///     .with_proxy(ProxyConfig::socks5("proxy.example.com:1080").with_credentials("user", "secret"));
+/// assert!(config.validation_errors().is_empty());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProxyConfig {
+    /// Proxy address as `host:port`.
+    pub address: String,
+
+    /// Optional: Username and password for the proxy (RFC 1929).
+    pub credentials: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    /// A SOCKS5 proxy at `address` (`host:port`), without authentication.
+    pub fn socks5(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticate to the proxy with a username and password.
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Describe what is wrong with this proxy configuration, if anything.
+    pub(crate) fn validation_error(&self) -> Option<String> {
+        let port = self.address.rsplit_once(':').map(|(_, port)| port);
+        if !port.is_some_and(|port| port.parse::<u16>().is_ok_and(|port| port != 0)) {
+            return Some(format!(
+                "Invalid proxy address {:?}: expected host:port",
+                self.address
+            ));
+        }
+        if let Some((username, password)) = &self.credentials {
+            if username.is_empty() || username.len() > 255 || password.len() > 255 {
+                return Some(
+                    "Proxy username must be 1 to 255 bytes and password at most 255 bytes"
+                        .to_string(),
+                );
+            }
+        }
+        None
+    }
+
+    /// Open a TCP connection to `target` through the proxy.
+    #[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+    pub(crate) async fn connect(
+        &self,
+        options: &SocketOptions,
+        target: SocketAddr,
+    ) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for proxy in tokio::net::lookup_host(&self.address).await? {
+            match options.connect_tcp(proxy).await {
+                Ok(mut stream) => {
+                    handshake(&mut TokioIo(&mut stream), target, self.credentials.as_ref()).await?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| self.not_resolved()))
+    }
+
+    /// Open a blocking TCP connection to `target` through the proxy,
+    /// waiting at most `timeout` for each step.
+    #[cfg(any(
+        feature = "blocking",
+        feature = "smol",
+        feature = "async-std",
+        target_os = "wasi"
+    ))]
+    pub(crate) async fn connect_std(
+        &self,
+        options: &SocketOptions,
+        target: SocketAddr,
+        timeout: Option<std::time::Duration>,
+    ) -> io::Result<std::net::TcpStream> {
+        use std::net::ToSocketAddrs;
+
+        let mut last_error = None;
+        for proxy in self.address.to_socket_addrs()? {
+            match options.connect_tcp_std(proxy, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(timeout)?;
+                    let mut stream = BlockingIo(stream);
+                    handshake(&mut stream, target, self.credentials.as_ref()).await?;
+                    stream.0.set_read_timeout(None)?;
+                    return Ok(stream.0);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| self.not_resolved()))
+    }
+
+    fn not_resolved(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Proxy {} did not resolve", self.address),
+        )
+    }
+}
+
+/// Async I/O over a blocking stream, completing every operation in place.
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+struct BlockingIo<S>(S);
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+impl<S: std::io::Read + Unpin> AsyncRead for BlockingIo<S> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::task::Poll::Ready(self.0.read(buf))
+    }
+}
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+impl<S: std::io::Write + Unpin> AsyncWrite for BlockingIo<S> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::task::Poll::Ready(self.0.write(buf))
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(self.0.flush())
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// The futures I/O traits over a Tokio stream.
+#[cfg(any(test, all(feature = "tokio", not(target_os = "wasi"))))]
+struct TokioIo<S>(S);
+
+#[cfg(any(test, all(feature = "tokio", not(target_os = "wasi"))))]
+impl<S: tokio::io::AsyncRead + Unpin> AsyncRead for TokioIo<S> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        std::pin::Pin::new(&mut self.0)
+            .poll_read(cx, &mut buf)
+            .map_ok(|()| buf.filled().len())
+    }
+}
+
+#[cfg(any(test, all(feature = "tokio", not(target_os = "wasi"))))]
+impl<S: tokio::io::AsyncWrite + Unpin> AsyncWrite for TokioIo<S> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::pin::Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("address", &self.address)
+            .field(
+                "credentials",
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(|(username, _)| (username, "<redacted>")),
+            )
+            .finish()
+    }
+}
+
+/// Ask the SOCKS5 proxy at the other end of `stream` to connect to
+/// `target`.
+async fn handshake<S>(
+    stream: &mut S,
+    target: SocketAddr,
+    credentials: Option<&(String, String)>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Method negotiation
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[SOCKS_VERSION, 2, METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD],
+        None => &[SOCKS_VERSION, 1, METHOD_NO_AUTH],
+    };
+    stream.write_all(greeting).await?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(protocol_error("not a SOCKS5 proxy"));
+    }
+    match (reply[1], credentials) {
+        (METHOD_NO_AUTH, _) => {}
+        (METHOD_USERNAME_PASSWORD, Some((username, password))) => {
+            let mut request = vec![USERNAME_PASSWORD_VERSION, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "SOCKS5 proxy rejected the credentials",
+                ));
+            }
+        }
+        (METHOD_NONE_ACCEPTABLE, _) => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "SOCKS5 proxy accepts none of the offered authentication methods",
+            ))
+        }
+        (method, _) => {
+            return Err(protocol_error(&format!(
+                "proxy chose unoffered authentication method {}",
+                method
+            )))
+        }
+    }
+
+    // Connect request
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0x00];
+    match target {
+        SocketAddr::V4(addr) => {
+            request.push(ADDRESS_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            request.push(ADDRESS_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(protocol_error("invalid reply version"));
+    }
+    if reply[1] != 0 {
+        return Err(reply_error(reply[1], target));
+    }
+
+    // Skip the address the proxy bound for the connection
+    let address_len = match reply[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        other => {
+            return Err(protocol_error(&format!("unknown address type {}", other)));
+        }
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("SOCKS5 protocol error: {}", message),
+    )
+}
+
+/// The error for a failed SOCKS5 connect request (RFC 1928, section 6).
+fn reply_error(code: u8, target: SocketAddr) -> io::Error {
+    let (kind, reason) = match code {
+        0x02 => (
+            io::ErrorKind::PermissionDenied,
+            "connection not allowed by ruleset",
+        ),
+        0x03 => (io::ErrorKind::Other, "network unreachable"),
+        0x04 => (io::ErrorKind::Other, "host unreachable"),
+        0x05 => (io::ErrorKind::ConnectionRefused, "connection refused"),
+        0x06 => (io::ErrorKind::TimedOut, "TTL expired"),
+        0x07 => (io::ErrorKind::Unsupported, "command not supported"),
+        0x08 => (io::ErrorKind::Unsupported, "address type not supported"),
+        _ => (io::ErrorKind::Other, "general failure"),
+    };
+    io::Error::new(
+        kind,
+        format!("SOCKS5 proxy could not connect to {}: {}", target, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Run the server side of a SOCKS5 handshake on `stream`, returning the
+    /// requested target. Accepts `credentials` if given, otherwise no
+    /// authentication, and answers the connect request with `reply`.
+    async fn serve_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        credentials: Option<(&str, &str)>,
+        reply: u8,
+    ) -> io::Result<SocketAddr> {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await?;
+        let mut methods = vec![0u8; header[1] as usize];
+        stream.read_exact(&mut methods).await?;
+
+        let method = match credentials {
+            Some(_) if methods.contains(&METHOD_USERNAME_PASSWORD) => METHOD_USERNAME_PASSWORD,
+            Some(_) => METHOD_NONE_ACCEPTABLE,
+            None => METHOD_NO_AUTH,
+        };
+        stream.write_all(&[SOCKS_VERSION, method]).await?;
+
+        if let Some((username, password)) = credentials {
+            if method == METHOD_NONE_ACCEPTABLE {
+                return Err(io::ErrorKind::PermissionDenied.into());
+            }
+            let mut buf = [0u8; 2];
+            stream.read_exact(&mut buf).await?;
+            let mut user = vec![0u8; buf[1] as usize];
+            stream.read_exact(&mut user).await?;
+            stream.read_exact(&mut buf[..1]).await?;
+            let mut pass = vec![0u8; buf[0] as usize];
+            stream.read_exact(&mut pass).await?;
+            let ok = user == username.as_bytes() && pass == password.as_bytes();
+            stream
+                .write_all(&[USERNAME_PASSWORD_VERSION, u8::from(!ok)])
+                .await?;
+            if !ok {
+                return Err(io::ErrorKind::PermissionDenied.into());
+            }
+        }
+
+        let mut request = [0u8; 4];
+        stream.read_exact(&mut request).await?;
+        assert_eq!(request[..3], [SOCKS_VERSION, COMMAND_CONNECT, 0]);
+        let target = if request[3] == ADDRESS_IPV4 {
+            let mut addr = [0u8; 6];
+            stream.read_exact(&mut addr).await?;
+            SocketAddr::from((
+                [addr[0], addr[1], addr[2], addr[3]],
+                u16::from_be_bytes([addr[4], addr[5]]),
+            ))
+        } else {
+            let mut addr = [0u8; 18];
+            stream.read_exact(&mut addr).await?;
+            let ip: [u8; 16] = addr[..16].try_into().unwrap();
+            SocketAddr::from((ip, u16::from_be_bytes([addr[16], addr[17]])))
+        };
+
+        // Bound address as a domain name, to exercise that reply form
+        let mut response = vec![SOCKS_VERSION, reply, 0, ADDRESS_DOMAIN, 5];
+        response.extend_from_slice(b"proxy");
+        response.extend_from_slice(&1080u16.to_be_bytes());
+        stream.write_all(&response).await?;
+        Ok(target)
+    }
+
+    /// A SOCKS5 proxy on localhost relaying one connection.
+    async fn spawn_proxy(credentials: Option<(&'static str, &'static str)>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            if let Ok(target) = serve_handshake(&mut TokioIo(&mut client), credentials, 0).await {
+                let mut upstream = TcpStream::connect(target).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_connect_through_proxy() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            TokioIo(&mut stream).write_all(b"hello").await.unwrap();
+        });
+
+        let proxy = spawn_proxy(Some(("user", "secret"))).await;
+        let config = ProxyConfig::socks5(proxy.to_string()).with_credentials("user", "secret");
+        let mut stream = config
+            .connect(&SocketOptions::default(), target_addr)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 5];
+        TokioIo(&mut stream).read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_key_exchange_through_proxy() {
+        let server = crate::test_util::MockServer::start().unwrap();
+        let proxy = spawn_proxy(None).await;
+        let client = crate::NtsClient::new(
+            server
+                .client_config()
+                .with_max_retries(0)
+                .with_proxy(ProxyConfig::socks5(proxy.to_string())),
+        );
+        client.connect().await.unwrap();
+        assert!(client.get_time().await.unwrap().authenticated);
+
+        // Nothing listens on the proxy port any more
+        let client = crate::NtsClient::new(
+            server
+                .client_config()
+                .with_max_retries(0)
+                .with_proxy(ProxyConfig::socks5(proxy.to_string())),
+        );
+        assert!(client.connect().await.is_err());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocking_key_exchange_through_proxy() {
+        let server = crate::test_util::MockServer::start().unwrap();
+        let proxy = spawn_proxy(Some(("user", "secret"))).await;
+        let config = server
+            .client_config()
+            .with_max_retries(0)
+            .with_proxy(ProxyConfig::socks5(proxy.to_string()).with_credentials("user", "secret"));
+        tokio::task::spawn_blocking(move || {
+            let client = crate::blocking::NtsClient::new(config);
+            client.connect().unwrap();
+            assert!(client.get_time().unwrap().authenticated);
+        })
+        .await
+        .unwrap();
+    }
+
+    /// Both ends of an in-memory stream.
+    fn duplex() -> (
+        TokioIo<tokio::io::DuplexStream>,
+        TokioIo<tokio::io::DuplexStream>,
+    ) {
+        let (client, server) = tokio::io::duplex(256);
+        (TokioIo(client), TokioIo(server))
+    }
+
+    #[tokio::test]
+    async fn test_handshake_errors() {
+        let target: SocketAddr = "[2001:db8::1]:4460".parse().unwrap();
+
+        // Wrong password
+        let (mut client, mut server) = duplex();
+        let credentials = ("user".to_string(), "wrong".to_string());
+        let (result, _) = tokio::join!(
+            handshake(&mut client, target, Some(&credentials)),
+            serve_handshake(&mut server, Some(("user", "secret")), 0)
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        // Authentication required but no credentials configured
+        let (mut client, mut server) = duplex();
+        let (result, _) = tokio::join!(
+            handshake(&mut client, target, None),
+            serve_handshake(&mut server, Some(("user", "secret")), 0)
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        // Target refused the connection, requested as an IPv6 address
+        let (mut client, mut server) = duplex();
+        let (result, requested) = tokio::join!(
+            handshake(&mut client, target, None),
+            serve_handshake(&mut server, None, 0x05)
+        );
+        assert_eq!(requested.unwrap(), target);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(err.to_string().contains("connection refused"));
+
+        // Not a SOCKS5 proxy
+        let (mut client, mut server) = duplex();
+        server
+            .write_all(b"HTTP/1.1 400 Bad Request\r\n")
+            .await
+            .unwrap();
+        let err = handshake(&mut client, target, None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(ProxyConfig::socks5("proxy:1080")
+            .validation_error()
+            .is_none());
+        assert!(ProxyConfig::socks5("[::1]:1080")
+            .validation_error()
+            .is_none());
+        assert!(ProxyConfig::socks5("proxy").validation_error().is_some());
+        assert!(ProxyConfig::socks5("proxy:0").validation_error().is_some());
+        assert!(ProxyConfig::socks5("proxy:1080")
+            .with_credentials("", "secret")
+            .validation_error()
+            .is_some());
+
+        let debug = format!(
+            "{:?}",
+            ProxyConfig::socks5("p:1").with_credentials("u", "secret")
+        );
+        assert!(debug.contains("<redacted>") && !debug.contains("secret"));
+    }
+}
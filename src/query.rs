@@ -0,0 +1,759 @@
+//! NTP query state machine.
+//!
+//! [`NtpQuery`] builds a client request and turns a server response into a
+//! [`TimeSnapshot`] without any I/O: packets and timestamps go in, packets
+//! and measurements come out. [`NtsClient`](crate::NtsClient) drives it over
+//! its transport; the [`sim`](crate::sim) module drives it from recorded
+//! sessions.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::extension::{decode_fields, ExtensionField};
+use crate::ntp_packet::{self, encode_ntp_timestamp, to_system_time, ParsedPacket, HEADER_LEN};
+use crate::types::{LeapIndicator, NtsKeys, ServerInfo, SignedDuration, TimeSnapshot};
+
+/// A time query to one NTP server.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, Instant, UNIX_EPOCH};
+///
+/// use rkik_nts::query::NtpQuery;
+///
+/// let t1 = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// let query = NtpQuery::new("192.0.2.1:123".parse()?, 4, t1, None)?;
+/// assert_eq!(query.request().len(), 48);
+///
+/// // A server 1 s ahead, answering at once
+/// let mut response = query.request().to_vec();
+/// response[0] = 0x24; // VN = 4, mode = 4 (server)
+/// response[1] = 1; // stratum
+/// let mut server_time = query.transmit();
+/// server_time[3] += 1;
+/// response[24..32].copy_from_slice(&query.transmit());
+/// response[32..40].copy_from_slice(&server_time);
+/// response[40..48].copy_from_slice(&server_time);
+///
+/// assert!(query.matches(&response));
+/// let snapshot = query.parse_response(&response, t1, Instant::now())?;
+/// assert_eq!(snapshot.offset_signed(), -1000);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct NtpQuery {
+    server: SocketAddr,
+    version: u8,
+    /// Transmit timestamp field as sent in the request.
+    transmit: [u8; 8],
+    /// Local time at which the request was sent (T1).
+    t1: SystemTime,
+    request: Vec<u8>,
+    /// Set when the request is protected with NTS.
+    nts: Option<NtsRequest>,
+}
+
+/// What authenticates the response to an NTS request.
+#[derive(Debug, Clone)]
+struct NtsRequest {
+    unique_id: Vec<u8>,
+    keys: NtsKeys,
+}
+
+impl NtpQuery {
+    /// Start a query to `server` with NTP version `version`, sent at local
+    /// time `t1`.
+    ///
+    /// The transmit timestamp field carries `nonce` if given, or T1
+    /// otherwise. T1 itself is only kept locally either way.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Other`] if `nonce` is `None` and `t1` precedes the
+    /// Unix epoch.
+    pub fn new(
+        server: SocketAddr,
+        version: u8,
+        t1: SystemTime,
+        nonce: Option<[u8; 8]>,
+    ) -> Result<Self> {
+        let transmit = match nonce {
+            Some(nonce) => nonce,
+            None => encode_ntp_timestamp(t1)?,
+        };
+
+        let mut request = vec![0u8; HEADER_LEN];
+        // LI (2 bits) = 0, VN (3 bits) = version, Mode (3 bits) = 3 (client)
+        request[0] = (version & 0x07) << 3 | 0x03;
+        // Poll interval
+        request[2] = 6;
+        request[40..48].copy_from_slice(&transmit);
+
+        Ok(Self {
+            server,
+            version,
+            transmit,
+            t1,
+            request,
+            nts: None,
+        })
+    }
+
+    /// Protect the request with NTS (RFC 8915, section 5.7).
+    ///
+    /// Appends a random Unique Identifier, `cookie`, `placeholders` Cookie
+    /// Placeholders each asking the server for one more cookie, and an NTS
+    /// Authenticator computed with the C2S key of `keys`. The response must
+    /// then be authenticated with the S2C key of `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Other`] if the authenticator cannot be computed.
+    pub fn with_nts(mut self, keys: &NtsKeys, cookie: &[u8], placeholders: usize) -> Result<Self> {
+        let unique_id = rand::random::<[u8; 32]>().to_vec();
+        ExtensionField::UniqueIdentifier {
+            id: unique_id.clone(),
+        }
+        .encode(&mut self.request);
+        ExtensionField::NtsCookie {
+            cookie: cookie.to_vec(),
+        }
+        .encode(&mut self.request);
+        for _ in 0..placeholders {
+            ExtensionField::NtsCookiePlaceholder {
+                length: cookie.len(),
+            }
+            .encode(&mut self.request);
+        }
+        let (nonce, ciphertext) = keys.seal(&self.request, &[])?;
+        ExtensionField::NtsAuthenticator {
+            nonce: nonce.to_vec(),
+            ciphertext,
+        }
+        .encode(&mut self.request);
+
+        self.nts = Some(NtsRequest {
+            unique_id,
+            keys: keys.clone(),
+        });
+        Ok(self)
+    }
+
+    /// Resume a query from a request already sent at local time `t1`, for
+    /// example one read from a recorded session.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Other`] if `request` is shorter than an NTP header.
+    pub fn from_request(server: SocketAddr, request: &[u8], t1: SystemTime) -> Result<Self> {
+        let parsed = ntp_packet::parse(request).map_err(|_| {
+            Error::Other(format!("NTP request too small ({} bytes)", request.len()))
+        })?;
+        Ok(Self {
+            server,
+            version: parsed.version,
+            transmit: parsed.transmit_timestamp.to_be_bytes(),
+            t1,
+            request: request.to_vec(),
+            nts: None,
+        })
+    }
+
+    /// Like [`from_request`](Self::from_request), for an NTS request
+    /// protected with `keys`. The response must then be authenticated with
+    /// the S2C key of `keys` and echo the request's Unique Identifier.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Other`] if `request` is shorter than an NTP header
+    /// or carries no Unique Identifier.
+    pub fn from_nts_request(
+        server: SocketAddr,
+        request: &[u8],
+        t1: SystemTime,
+        keys: &NtsKeys,
+    ) -> Result<Self> {
+        let mut query = Self::from_request(server, request, t1)?;
+        let unique_id = decode_fields(&request[HEADER_LEN..], true)?
+            .into_iter()
+            .find_map(|(_, field)| match field {
+                ExtensionField::UniqueIdentifier { id } => Some(id),
+                _ => None,
+            })
+            .ok_or_else(|| Error::Other("NTS request has no Unique Identifier".to_string()))?;
+        query.nts = Some(NtsRequest {
+            unique_id,
+            keys: keys.clone(),
+        });
+        Ok(query)
+    }
+
+    /// The server queried.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// The request packet to send.
+    pub fn request(&self) -> &[u8] {
+        &self.request
+    }
+
+    /// The transmit timestamp field of the request, which the response must
+    /// echo as its origin timestamp.
+    pub fn transmit(&self) -> [u8; 8] {
+        self.transmit
+    }
+
+    /// The local time the request was sent (T1).
+    pub fn t1(&self) -> SystemTime {
+        self.t1
+    }
+
+    /// Check whether `packet`'s origin timestamp (bytes 24-31) echoes our
+    /// transmit timestamp, i.e. whether it answers this query.
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        packet.get(24..32) == Some(&self.transmit[..])
+    }
+
+    /// Parse the server response `packet`, received at local time `t4`.
+    ///
+    /// `measured_at` is the monotonic time of reception, recorded in the
+    /// snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ReplayDetected`] if `packet` does not answer this
+    /// query, [`Error::AuthenticationFailed`] if the query uses NTS and the
+    /// response is not authenticated, [`Error::KissOfDeath`] for a
+    /// Kiss-o'-Death packet, and [`Error::InvalidResponse`],
+    /// [`Error::InvalidMode`], [`Error::InvalidStratum`] or
+    /// [`Error::ServerUnsynchronized`] for invalid or unusable responses.
+    pub fn parse_response(
+        &self,
+        packet: &[u8],
+        t4: SystemTime,
+        measured_at: Instant,
+    ) -> Result<TimeSnapshot> {
+        self.parse_response_with_cookies(packet, t4, measured_at)
+            .map(|(snapshot, _)| snapshot)
+    }
+
+    /// Like [`parse_response`](Self::parse_response), also returning the
+    /// new NTS cookies of the response. Empty for queries without NTS.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`parse_response`](Self::parse_response).
+    pub fn parse_response_with_cookies(
+        &self,
+        packet: &[u8],
+        t4: SystemTime,
+        measured_at: Instant,
+    ) -> Result<(TimeSnapshot, Vec<Vec<u8>>)> {
+        let parsed = ntp_packet::parse(packet)?;
+
+        // The origin timestamp must echo the transmit timestamp we sent
+        let expected = u64::from_be_bytes(self.transmit);
+        if parsed.origin_timestamp != expected {
+            return Err(Error::ReplayDetected {
+                expected,
+                received: parsed.origin_timestamp,
+            });
+        }
+
+        // Authenticate before looking at the header, Kiss-o'-Death codes
+        // included
+        let cookies = match &self.nts {
+            Some(nts) => nts.verify(packet, &parsed)?,
+            None => Vec::new(),
+        };
+
+        // The server must answer with the version we asked for
+        if parsed.version != self.version {
+            return Err(Error::InvalidResponse(format!(
+                "NTP version mismatch: requested {}, got {}",
+                self.version, parsed.version
+            )));
+        }
+
+        // Only accept server mode (4) responses
+        if parsed.mode != 4 {
+            return Err(Error::InvalidMode(parsed.mode));
+        }
+
+        // Stratum 0 is a Kiss-o'-Death packet; the kiss code is in the reference ID
+        if parsed.stratum == 0 {
+            let code = String::from_utf8_lossy(&parsed.reference_id)
+                .trim_end_matches('\0')
+                .to_string();
+            return Err(Error::KissOfDeath { code });
+        }
+        if parsed.stratum > 15 {
+            return Err(Error::InvalidStratum(parsed.stratum));
+        }
+
+        if parsed.leap_indicator == LeapIndicator::Unsynchronized {
+            return Err(Error::ServerUnsynchronized);
+        }
+
+        let server_info = ServerInfo {
+            leap_indicator: parsed.leap_indicator,
+            stratum: parsed.stratum,
+            reference_id: parsed.reference_id,
+            root_delay: parsed.root_delay,
+            root_dispersion: parsed.root_dispersion,
+        };
+
+        // Server receive (T2) and transmit (T3) timestamps, in the NTP era
+        // nearest our send time
+        let t2 = to_system_time(parsed.receive_timestamp, self.t1);
+        let t3 = to_system_time(parsed.transmit_timestamp, self.t1);
+
+        let (t1, t2, t3, t4_nanos) = (
+            signed_nanos(self.t1),
+            signed_nanos(t2),
+            signed_nanos(t3),
+            signed_nanos(t4),
+        );
+
+        // Clock offset of the server relative to us: ((T2 - T1) + (T3 - T4)) / 2.
+        // Positive means the server is ahead of the local clock.
+        let theta = ((t2 - t1) + (t3 - t4_nanos)) / 2;
+        let offset = nanos_to_duration(theta.abs());
+
+        // Network time as of T4, corrected for the symmetric path delay
+        let system_time = t4;
+        let network_time = if theta >= 0 {
+            system_time.checked_add(offset)
+        } else {
+            system_time.checked_sub(offset)
+        }
+        .ok_or_else(|| Error::InvalidResponse("NTP clock offset out of range".to_string()))?;
+
+        // Round-trip delay: (T4 - T1) - (T3 - T2), excluding server processing time
+        let round_trip_delay = nanos_to_duration((t4_nanos - t1) - (t3 - t2));
+
+        #[allow(deprecated)]
+        let snapshot = TimeSnapshot {
+            system_time,
+            measured_at,
+            network_time,
+            clock_offset: SignedDuration::from_nanos(-theta),
+            offset,
+            round_trip_delay,
+            server: self.server.to_string(),
+            authenticated: self.nts.is_some(),
+            server_info,
+            bootstrap: false,
+        };
+
+        Ok((snapshot, cookies))
+    }
+}
+
+impl NtsRequest {
+    /// Check that `packet` is authenticated with the S2C key and echoes our
+    /// Unique Identifier, returning the cookies it carries.
+    ///
+    /// Only an NTS NAK (Kiss-o'-Death `NTSN`) comes unauthenticated, as the
+    /// server could not read our cookie: it is accepted if it echoes the
+    /// Unique Identifier.
+    fn verify(&self, packet: &[u8], parsed: &ParsedPacket<'_>) -> Result<Vec<Vec<u8>>> {
+        let fields = decode_fields(parsed.extensions, true)?;
+        let echoes_id = |fields: &[(usize, ExtensionField)]| {
+            fields.iter().any(|(_, field)| {
+                matches!(field, ExtensionField::UniqueIdentifier { id } if *id == self.unique_id)
+            })
+        };
+
+        let authenticator = fields
+            .iter()
+            .enumerate()
+            .find_map(|(index, (offset, field))| match field {
+                ExtensionField::NtsAuthenticator { nonce, ciphertext } => {
+                    Some((index, *offset, nonce, ciphertext))
+                }
+                _ => None,
+            });
+        let Some((index, offset, nonce, ciphertext)) = authenticator else {
+            if parsed.kiss_code() == Some("NTSN") && echoes_id(&fields) {
+                return Err(Error::KissOfDeath {
+                    code: "NTSN".to_string(),
+                });
+            }
+            return Err(Error::AuthenticationFailed(
+                "NTP response has no NTS authenticator".to_string(),
+            ));
+        };
+        // The authenticator covers everything before it
+        let associated_data = &packet[..HEADER_LEN + offset];
+        let plaintext = self
+            .keys
+            .open(nonce, ciphertext, associated_data)
+            .ok_or_else(|| {
+                Error::AuthenticationFailed("NTS authenticator does not verify".to_string())
+            })?;
+        if !echoes_id(&fields[..index]) {
+            return Err(Error::AuthenticationFailed(
+                "NTP response does not echo the Unique Identifier".to_string(),
+            ));
+        }
+
+        let cookies = decode_fields(&plaintext, false)?
+            .into_iter()
+            .filter_map(|(_, field)| match field {
+                ExtensionField::NtsCookie { cookie } => Some(cookie),
+                _ => None,
+            })
+            .collect();
+        Ok(cookies)
+    }
+}
+
+/// Queries awaiting a response, keyed by the transmit timestamp the
+/// response must echo.
+///
+/// The client and [`sim::replay`](crate::sim::replay) both hand each
+/// received datagram to the query it answers through this map, so a
+/// recorded session is routed the way the client routed it.
+#[derive(Debug)]
+pub(crate) struct PendingQueries<T> {
+    queries: HashMap<[u8; 8], T>,
+}
+
+impl<T> Default for PendingQueries<T> {
+    fn default() -> Self {
+        Self {
+            queries: HashMap::new(),
+        }
+    }
+}
+
+impl<T> PendingQueries<T> {
+    /// Wait for the response to the query with `transmit`, replacing an
+    /// earlier query with the same transmit timestamp.
+    pub(crate) fn insert(&mut self, transmit: [u8; 8], query: T) {
+        self.queries.insert(transmit, query);
+    }
+
+    /// Stop waiting for the response to the query with `transmit`.
+    pub(crate) fn remove(&mut self, transmit: &[u8; 8]) -> Option<T> {
+        self.queries.remove(transmit)
+    }
+
+    /// Keep only the queries for which `f` returns `true`.
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.queries.retain(|_, query| f(query));
+    }
+
+    /// Take the query `packet` answers, found by its origin timestamp
+    /// (bytes 24-31). `None` if no pending query matches.
+    pub(crate) fn take_answered(&mut self, packet: &[u8]) -> Option<T> {
+        let origin = <[u8; 8]>::try_from(packet.get(24..32)?).ok()?;
+        self.queries.remove(&origin)
+    }
+}
+
+/// Convert a signed nanosecond count to a duration, clamping negatives to zero.
+fn nanos_to_duration(nanos: i128) -> Duration {
+    Duration::from_nanos(nanos.clamp(0, u64::MAX as i128) as u64)
+}
+
+/// Nanoseconds since the Unix epoch, negative for earlier times.
+fn signed_nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn test_query(t1: SystemTime) -> NtpQuery {
+        NtpQuery::new("127.0.0.1:123".parse().unwrap(), 4, t1, None).unwrap()
+    }
+
+    /// Build an NTPv4 server response answering a request sent at `t1`.
+    fn test_response(t1: SystemTime, t2: SystemTime, t3: SystemTime) -> Vec<u8> {
+        let mut response = vec![0u8; 48];
+        response[0] = 0x24; // VN = 4, mode = 4 (server)
+        response[1] = 2; // stratum
+        response[24..32].copy_from_slice(&encode_ntp_timestamp(t1).unwrap());
+        response[32..40].copy_from_slice(&encode_ntp_timestamp(t2).unwrap());
+        response[40..48].copy_from_slice(&encode_ntp_timestamp(t3).unwrap());
+        response
+    }
+
+    #[test]
+    fn test_response_version_mismatch_rejected() {
+        let mut response = vec![0u8; 48];
+        response[0] = 0x1C; // VN = 3, mode = 4 (server)
+
+        let now = SystemTime::now();
+        let query = test_query(now);
+        response[24..32].copy_from_slice(&query.transmit());
+        let result = query.parse_response(&response, now, Instant::now());
+        assert!(matches!(result, Err(Error::InvalidResponse(_))));
+
+        response[0] = 0x24; // VN = 4, mode = 4 (server)
+        response[1] = 2; // stratum
+        assert!(query.parse_response(&response, now, Instant::now()).is_ok());
+    }
+
+    #[test]
+    fn test_origin_mismatch_rejected() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let query = test_query(base);
+
+        let mut response = test_response(base, base, base);
+        assert!(query.matches(&response));
+
+        response[31] ^= 0xFF;
+        assert!(!query.matches(&response));
+        let result = query.parse_response(&response, base, Instant::now());
+        assert!(matches!(
+            result,
+            Err(Error::ReplayDetected { expected, received })
+                if expected == u64::from_be_bytes(query.transmit()) && expected ^ received == 0xFF
+        ));
+    }
+
+    #[test]
+    fn test_kiss_of_death_parsed() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut response = test_response(base, base, base);
+        response[1] = 0;
+        response[12..16].copy_from_slice(b"RATE");
+
+        match test_query(base).parse_response(&response, base, Instant::now()) {
+            Err(Error::KissOfDeath { code }) => assert_eq!(code, "RATE"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_nts_response_authenticated() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let query = test_query(base).with_nts(&keys, &[0xAB; 64], 2).unwrap();
+        let mut header = test_response(base, base, base);
+        header[24..32].copy_from_slice(&query.transmit());
+        let response = crate::test_util::nts_response(query.request(), &header, &keys).unwrap();
+
+        let (snapshot, cookies) = query
+            .parse_response_with_cookies(&response, base, Instant::now())
+            .unwrap();
+        assert!(snapshot.authenticated);
+        assert_eq!(cookies, vec![vec![0xAB; 64]; 3]);
+
+        // Any change to the authenticated part is detected
+        for i in 48..response.len() - 40 {
+            let mut forged = response.clone();
+            forged[i] ^= 1;
+            let result = query.parse_response(&forged, base, Instant::now());
+            assert!(result.is_err(), "byte {} not authenticated", i);
+        }
+        let mut forged = response.clone();
+        forged[2] ^= 1;
+        let result = query.parse_response(&forged, base, Instant::now());
+        assert!(matches!(result, Err(Error::AuthenticationFailed(_))));
+
+        // A plain response, or one answering another request, is rejected
+        let result = query.parse_response(&header, base, Instant::now());
+        assert!(matches!(result, Err(Error::AuthenticationFailed(_))));
+        let other = test_query(base).with_nts(&keys, &[0xAB; 64], 0).unwrap();
+        let response = crate::test_util::nts_response(other.request(), &header, &keys).unwrap();
+        let result = query.parse_response(&response, base, Instant::now());
+        assert!(matches!(result, Err(Error::AuthenticationFailed(_))));
+    }
+
+    #[test]
+    fn test_nts_nak_unauthenticated() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let query = test_query(base).with_nts(&keys, &[0xAB; 64], 0).unwrap();
+        let mut response = test_response(base, base, base);
+        response[1] = 0;
+        response[12..16].copy_from_slice(b"NTSN");
+        response[24..32].copy_from_slice(&query.transmit());
+        let unique_id = &query.request()[HEADER_LEN..HEADER_LEN + 36];
+        assert_eq!(unique_id[..2], [0x01, 0x04]);
+
+        // The NAK must echo the Unique Identifier
+        let result = query.parse_response(&response, base, Instant::now());
+        assert!(matches!(result, Err(Error::AuthenticationFailed(_))));
+        response.extend_from_slice(unique_id);
+        match query.parse_response(&response, base, Instant::now()) {
+            Err(Error::KissOfDeath { code }) => assert_eq!(code, "NTSN"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Other Kiss-o'-Death codes must be authenticated
+        response[12..16].copy_from_slice(b"DENY");
+        let result = query.parse_response(&response, base, Instant::now());
+        assert!(matches!(result, Err(Error::AuthenticationFailed(_))));
+    }
+
+    #[test]
+    fn test_invalid_header_rejected() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let query = test_query(base);
+        let parse = |response: &[u8]| query.parse_response(response, base, Instant::now());
+
+        let mut response = test_response(base, base, base);
+        response[0] = 0x23; // mode 3 (client)
+        assert!(matches!(parse(&response), Err(Error::InvalidMode(3))));
+
+        let mut response = test_response(base, base, base);
+        response[1] = 16;
+        assert!(matches!(parse(&response), Err(Error::InvalidStratum(16))));
+
+        let mut response = test_response(base, base, base);
+        response[0] |= 0xC0; // LI = 3
+        assert!(matches!(parse(&response), Err(Error::ServerUnsynchronized)));
+    }
+
+    #[test]
+    fn test_server_info_parsed() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut response = test_response(base, base, base);
+        response[0] |= 0x40; // LI = 1
+        response[4..8].copy_from_slice(&0x0000_8000u32.to_be_bytes()); // 0.5 s
+        response[8..12].copy_from_slice(&0x0001_0000u32.to_be_bytes()); // 1 s
+        response[12..16].copy_from_slice(&[192, 0, 2, 1]);
+
+        let snapshot = test_query(base)
+            .parse_response(&response, base, Instant::now())
+            .unwrap();
+        let info = snapshot.server_info;
+        assert_eq!(info.leap_indicator, LeapIndicator::InsertSecond);
+        assert_eq!(info.stratum, 2);
+        assert_eq!(info.reference_id_string(), "192.0.2.1");
+        assert_eq!(info.root_delay, Duration::from_millis(500));
+        assert_eq!(info.root_dispersion, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_round_trip_delay_excludes_server_time() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let t1 = base;
+        let t2 = base + Duration::from_millis(20);
+        let t3 = base + Duration::from_millis(30);
+        let t4 = base + Duration::from_millis(50);
+
+        let response = test_response(t1, t2, t3);
+        let snapshot = test_query(t1)
+            .parse_response(&response, t4, Instant::now())
+            .unwrap();
+        let rtt_ms = snapshot.round_trip_delay.as_secs_f64() * 1000.0;
+        assert!((rtt_ms - 40.0).abs() < 0.001, "rtt {} ms", rtt_ms);
+    }
+
+    #[test]
+    fn test_offset_uses_four_timestamps() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        // Server is 100 ms ahead, 10 ms one-way delay, 5 ms processing
+        let t1 = base;
+        let t2 = base + Duration::from_millis(110);
+        let t3 = base + Duration::from_millis(115);
+        let t4 = base + Duration::from_millis(25);
+
+        let response = test_response(t1, t2, t3);
+        let snapshot = test_query(t1)
+            .parse_response(&response, t4, Instant::now())
+            .unwrap();
+        let offset_ms = snapshot.clock_offset.as_secs_f64() * 1000.0;
+        assert!((offset_ms + 100.0).abs() < 0.001, "offset {} ms", offset_ms);
+        assert!(snapshot.is_behind());
+        assert!((-100..=-99).contains(&snapshot.offset_signed()));
+    }
+
+    #[test]
+    fn test_offset_across_era_rollover() {
+        // Sent 1 s before the 2036 rollover to a server 2 s ahead, whose
+        // timestamps are already in NTP era 1
+        let rollover = UNIX_EPOCH + Duration::from_secs((1 << 32) - 2_208_988_800);
+        let t1 = rollover - Duration::from_secs(1);
+        let t2 = rollover + Duration::from_millis(1_010);
+        let t3 = rollover + Duration::from_millis(1_020);
+        let t4 = t1 + Duration::from_millis(30);
+
+        let response = test_response(t1, t2, t3);
+        assert_eq!(response[32..36], [0, 0, 0, 1]);
+        let snapshot = test_query(t1)
+            .parse_response(&response, t4, Instant::now())
+            .unwrap();
+        let offset_ms = snapshot.clock_offset.as_secs_f64() * 1000.0;
+        assert!(
+            (offset_ms + 2000.0).abs() < 0.001,
+            "offset {} ms",
+            offset_ms
+        );
+        let rtt_ms = snapshot.round_trip_delay.as_secs_f64() * 1000.0;
+        assert!((rtt_ms - 20.0).abs() < 0.001, "rtt {} ms", rtt_ms);
+    }
+
+    #[test]
+    fn test_offset_and_delay_properties() {
+        // Random exchanges with asymmetric paths: the measurement satisfies
+        // the NTP equations, up to the resolution of NTP timestamps
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let t1 = UNIX_EPOCH + Duration::from_nanos(rng.gen_range(1 << 60..1 << 61));
+            let server_offset = rng.gen_range(-10_000_000_000i128..10_000_000_000);
+            let outbound = rng.gen_range(0..500_000_000i128);
+            let processing = rng.gen_range(0..10_000_000i128);
+            let inbound = rng.gen_range(0..500_000_000i128);
+
+            let at =
+                |nanos: i128| UNIX_EPOCH + Duration::from_nanos((signed_nanos(t1) + nanos) as u64);
+            let t2 = at(outbound + server_offset);
+            let t3 = at(outbound + server_offset + processing);
+            let t4 = at(outbound + processing + inbound);
+
+            let response = test_response(t1, t2, t3);
+            let snapshot = test_query(t1)
+                .parse_response(&response, t4, Instant::now())
+                .unwrap();
+
+            let expected_offset = server_offset + (outbound - inbound) / 2;
+            let rtt = snapshot.round_trip_delay.as_nanos() as i128;
+            assert!((rtt - (outbound + inbound)).abs() <= 2, "rtt {}", rtt);
+            assert!(
+                (snapshot.offset_nanos() + expected_offset).abs() <= 2,
+                "offset {} ns, expected {} ns",
+                snapshot.offset_nanos(),
+                -expected_offset
+            );
+            assert_eq!(
+                signed_nanos(snapshot.network_time) - signed_nanos(snapshot.system_time),
+                -snapshot.offset_nanos()
+            );
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_responses() {
+        let t1 = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let query = test_query(t1);
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..96);
+            let mut packet: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            if len >= 32 && rng.gen_bool(0.5) {
+                packet[24..32].copy_from_slice(&query.transmit());
+            }
+            if let Ok(snapshot) = query.parse_response(&packet, t1, Instant::now()) {
+                assert!(query.matches(&packet));
+                assert!((1..=15).contains(&snapshot.server_info.stratum));
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Pluggable hostname resolution for NTS-KE servers.
+
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+use std::net::ToSocketAddrs;
+use std::pin::Pin;
+
+use crate::error::{Error, Result};
+
+/// Future returned by [`Resolver::resolve`].
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Resolves a hostname and port into socket addresses.
+///
+/// Implement this trait to plug in a custom DNS resolver (for example a
+/// DNS-over-HTTPS client or a static lookup table).
+pub trait Resolver: Send + Sync {
+    /// Resolve `host:port` into one or more socket addresses.
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+/// The default resolver, backed by the operating system.
+///
+/// Lookups run on Tokio's blocking thread pool via [`tokio::net::lookup_host`],
+/// so they never block the async runtime. On WASI, which has no Tokio
+/// networking, lookups block the calling task.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+#[cfg(all(feature = "tokio", target_os = "wasi"))]
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        StdResolver.resolve(host, port)
+    }
+}
+
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| Error::Dns {
+                    host: host.to_string(),
+                    reason: e.to_string(),
+                })?
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(Error::Dns {
+                    host: host.to_string(),
+                    reason: "no addresses resolved".to_string(),
+                });
+            }
+
+            Ok(addrs)
+        })
+    }
+}
+
+/// Resolves names with [`ToSocketAddrs`], blocking the calling thread.
+#[cfg(any(feature = "blocking", target_os = "wasi"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct StdResolver;
+
+#[cfg(any(feature = "blocking", target_os = "wasi"))]
+impl Resolver for StdResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(std::future::ready(lookup(host, port)))
+    }
+}
+
+/// Resolves names with [`ToSocketAddrs`] on the thread pool of the
+/// `blocking` crate, which smol and async-std share.
+///
+/// The default resolver when the `tokio` feature is disabled.
+#[cfg(any(feature = "smol", feature = "async-std"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockingResolver;
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl Resolver for BlockingResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        let host = host.to_string();
+        Box::pin(blocking::unblock(move || lookup(&host, port)))
+    }
+}
+
+/// Resolve `host:port` with [`ToSocketAddrs`], blocking the calling thread.
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+fn lookup(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let dns_error = |reason: String| Error::Dns {
+        host: host.to_string(),
+        reason,
+    };
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| dns_error(e.to_string()))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(dns_error("no addresses resolved".to_string()));
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_system_resolver_ip_literal() {
+        let addrs = SystemResolver.resolve("127.0.0.1", 4460).await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:4460".parse().unwrap()]);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[tokio::test]
+    async fn test_std_resolver() {
+        let addrs = StdResolver.resolve("127.0.0.1", 4460).await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:4460".parse().unwrap()]);
+        let err = StdResolver
+            .resolve("invalid..host", 4460)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Dns { .. }));
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_blocking_resolver() {
+        let addrs = async_io::block_on(BlockingResolver.resolve("127.0.0.1", 4460)).unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:4460".parse().unwrap()]);
+        let err = async_io::block_on(BlockingResolver.resolve("invalid..host", 4460)).unwrap_err();
+        assert!(matches!(err, Error::Dns { .. }));
+    }
+}
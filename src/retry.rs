@@ -0,0 +1,321 @@
+//! Retry policies for key exchanges and time queries.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::runtime::{self, Runtime};
+use crate::time_source::Clock;
+
+/// Decides whether and when a failed operation is retried.
+///
+/// Only errors classified as retryable (timeouts, transient network errors)
+/// are passed to the policy; fatal errors such as certificate failures are
+/// never retried. Implement this trait to plug in a custom backoff.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::retry::RetryPolicy;
+/// use rkik_nts::{Error, NtsClientConfig};
+/// use std::time::Duration;
+///
+/// /// Retry timeouts only, once, after one second.
+/// #[derive(Debug)]
+/// struct RetryTimeoutsOnce;
+///
+/// impl RetryPolicy for RetryTimeoutsOnce {
+///     fn next_delay(&self, retry: u32, error: &Error) -> Option<Duration> {
+///         (retry == 0 && matches!(error, Error::Timeout)).then_some(Duration::from_secs(1))
+///     }
+/// }
+///
+/// let config = NtsClientConfig::new("time.cloudflare.com").with_retry_policy(RetryTimeoutsOnce);
+/// ```
+pub trait RetryPolicy: Debug + Send + Sync {
+    /// Delay before retry number `retry` (0-based) after `error`, or `None`
+    /// to give up.
+    fn next_delay(&self, retry: u32, error: &Error) -> Option<Duration>;
+}
+
+/// Retry up to `max_retries` times with the same delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedBackoff {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay between two attempts.
+    pub delay: Duration,
+}
+
+impl RetryPolicy for FixedBackoff {
+    fn next_delay(&self, retry: u32, _error: &Error) -> Option<Duration> {
+        (retry < self.max_retries).then_some(self.delay)
+    }
+}
+
+/// Retry up to `max_retries` times, doubling the delay each time.
+///
+/// This is the default policy, with `max_retries` taken from the
+/// configuration, an initial delay of 100ms and a maximum of 5s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    /// Maximum number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between two attempts.
+    pub max_delay: Duration,
+    /// Randomize each delay between half and all of its value, so that
+    /// many clients failing at once do not retry in lockstep.
+    pub jitter: bool,
+}
+
+impl ExponentialBackoff {
+    /// Exponential backoff with the default delays and no jitter.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_delay: INITIAL_BACKOFF,
+            max_delay: MAX_BACKOFF,
+            jitter: false,
+        }
+    }
+
+    /// Enable or disable jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn next_delay(&self, retry: u32, _error: &Error) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        if self.jitter {
+            Some(rand::thread_rng().gen_range(delay / 2..=delay))
+        } else {
+            Some(delay)
+        }
+    }
+}
+
+/// Delay before the first retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Upper bound for the delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Run `operation` until it succeeds, fails with a non-retryable error, or
+/// `policy` gives up.
+///
+/// When retries were made and all attempts failed, the last error is
+/// wrapped in [`Error::RetriesExhausted`] so callers can see how many
+/// attempts were made.
+pub(crate) async fn with_retries<T, F, Fut>(
+    name: &str,
+    policy: &dyn RetryPolicy,
+    runtime: &dyn Runtime,
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt: u32 = 0;
+    loop {
+        attempt += 1;
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if !e.is_retryable() => {
+                debug!("{} failed with non-retryable error: {}", name, e);
+                return Err(e);
+            }
+            Err(e) => match policy.next_delay(attempt - 1, &e) {
+                None if attempt == 1 => return Err(e),
+                None => {
+                    return Err(Error::RetriesExhausted {
+                        attempts: attempt,
+                        source: Box::new(e),
+                    })
+                }
+                Some(delay) => {
+                    warn!(
+                        "{} attempt {} failed: {}. Retrying in {:?}",
+                        name, attempt, e, delay
+                    );
+                    runtime.sleep(delay).await;
+                }
+            },
+        }
+    }
+}
+
+/// Run `operation`, failing with [`Error::Timeout`] if `deadline` passes
+/// first on `clock`.
+pub(crate) async fn within<T>(
+    runtime: &dyn Runtime,
+    clock: &dyn Clock,
+    deadline: Option<Instant>,
+    operation: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match deadline {
+        Some(deadline) => runtime::timeout_at(runtime, clock, deadline, operation)
+            .await
+            .unwrap_or(Err(Error::Timeout)),
+        None => operation.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::TokioRuntime;
+    use crate::time_source::SystemClock;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_backoff_delay_grows_and_caps() {
+        let policy = ExponentialBackoff::new(u32::MAX);
+        let delay = |retry| policy.next_delay(retry, &Error::Timeout);
+        assert_eq!(delay(0), Some(Duration::from_millis(100)));
+        assert_eq!(delay(1), Some(Duration::from_millis(200)));
+        assert_eq!(delay(2), Some(Duration::from_millis(400)));
+        assert_eq!(delay(10), Some(MAX_BACKOFF));
+        assert_eq!(delay(u32::MAX - 1), Some(MAX_BACKOFF));
+        assert_eq!(
+            ExponentialBackoff::new(2).next_delay(2, &Error::Timeout),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let policy = ExponentialBackoff::new(10).with_jitter(true);
+        for _ in 0..100 {
+            let delay = policy.next_delay(1, &Error::Timeout).unwrap();
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn test_fixed_backoff() {
+        let policy = FixedBackoff {
+            max_retries: 2,
+            delay: Duration::from_millis(50),
+        };
+        assert_eq!(
+            policy.next_delay(1, &Error::Timeout),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(policy.next_delay(2, &Error::Timeout), None);
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let calls = Cell::new(0);
+        let result = with_retries("test", &ExponentialBackoff::new(3), &TokioRuntime, || {
+            calls.set(calls.get() + 1);
+            let n = calls.get();
+            async move {
+                if n < 2 {
+                    Err(Error::Timeout)
+                } else {
+                    Ok(n)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_is_not_retried() {
+        let calls = Cell::new(0);
+        let result: Result<()> =
+            with_retries("test", &ExponentialBackoff::new(3), &TokioRuntime, || {
+                calls.set(calls.get() + 1);
+                async {
+                    Err(Error::Tls {
+                        message: "bad certificate".to_string(),
+                        source: None,
+                    })
+                }
+            })
+            .await;
+
+        assert!(matches!(result, Err(Error::Tls { .. })));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_report_attempts() {
+        let calls = Cell::new(0);
+        let result: Result<()> =
+            with_retries("test", &ExponentialBackoff::new(1), &TokioRuntime, || {
+                calls.set(calls.get() + 1);
+                async { Err(Error::Timeout) }
+            })
+            .await;
+
+        match result {
+            Err(Error::RetriesExhausted { attempts, source }) => {
+                assert_eq!(attempts, 2);
+                assert!(matches!(*source, Error::Timeout));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_within_deadline() {
+        let deadline = Some(Instant::now() + Duration::from_millis(20));
+        let result: Result<()> = within(&TokioRuntime, &SystemClock, deadline, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Timeout)));
+
+        assert_eq!(
+            within(&TokioRuntime, &SystemClock, None, async { Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_sees_error() {
+        #[derive(Debug)]
+        struct OnlyUnavailable;
+
+        impl RetryPolicy for OnlyUnavailable {
+            fn next_delay(&self, retry: u32, error: &Error) -> Option<Duration> {
+                (retry < 5 && matches!(error, Error::ServerUnavailable(_)))
+                    .then_some(Duration::ZERO)
+            }
+        }
+
+        let calls = Cell::new(0);
+        let result: Result<()> = with_retries("test", &OnlyUnavailable, &TokioRuntime, || {
+            calls.set(calls.get() + 1);
+            async { Err(Error::Timeout) }
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(calls.get(), 1);
+    }
+}
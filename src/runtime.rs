@@ -0,0 +1,311 @@
+//! Pluggable async runtime for the client.
+//!
+//! Besides network I/O, which goes through a [`Connector`](crate::Connector),
+//! and name lookups, which go through a [`Resolver`](crate::Resolver), the
+//! client needs only two things from its executor: timers and a thread on
+//! which the key exchange may block. Both go through a [`Runtime`], so that
+//! with a custom runtime, connector and resolver the client runs on
+//! executors other than Tokio without starting a Tokio runtime next to
+//! them.
+//!
+//! The `tokio` feature, enabled by default, provides [`TokioRuntime`]. The
+//! `smol` and `async-std` features provide [`SmolRuntime`] and
+//! [`AsyncStdRuntime`], which are the defaults when the `tokio` feature is
+//! disabled, together with
+//! [`AsyncIoConnector`](crate::transport::AsyncIoConnector) and
+//! [`BlockingResolver`](crate::resolver::BlockingResolver).
+//! [`NtsSyncService`](crate::NtsSyncService) is built on Tokio tasks and
+//! requires the `tokio` feature.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use crate::time_source::Clock;
+
+/// Future returned by [`Runtime::sleep`].
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Timers and blocking threads for the client.
+///
+/// Register a custom runtime with
+/// [`NtsClientBuilder::with_runtime`](crate::NtsClientBuilder::with_runtime).
+///
+/// # Examples
+///
+/// An adapter for a runtime with its own timer, here a thread per sleep to
+/// keep the example self-contained:
+///
+/// ```
+/// use std::time::Duration;
+/// use rkik_nts::runtime::{Runtime, SleepFuture};
+///
+/// struct ThreadRuntime;
+///
+/// impl Runtime for ThreadRuntime {
+///     fn sleep(&self, duration: Duration) -> SleepFuture {
+///         let (tx, rx) = futures_channel::oneshot::channel::<()>();
+///         std::thread::spawn(move || {
+///             std::thread::sleep(duration);
+///             let _ = tx.send(());
+///         });
+///         Box::pin(async move {
+///             let _ = rx.await;
+///         })
+///     }
+///
+///     fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+///         std::thread::spawn(task);
+///     }
+/// }
+///
+/// let client = rkik_nts::NtsClient::builder()
+///     .with_server("time.cloudflare.com")
+///     .with_runtime(ThreadRuntime)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait Runtime: Send + Sync {
+    /// A future that completes after `duration`.
+    fn sleep(&self, duration: Duration) -> SleepFuture;
+
+    /// Run `task` on a thread where blocking is allowed.
+    ///
+    /// The key exchange runs this way; the task reports its outcome itself.
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>);
+}
+
+/// The default runtime: Tokio's timer and blocking thread pool.
+///
+/// Must be used from within a Tokio runtime. On WASI, which has no threads,
+/// blocking tasks run on the calling task instead.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        #[cfg(not(target_os = "wasi"))]
+        tokio::task::spawn_blocking(task);
+        #[cfg(target_os = "wasi")]
+        task();
+    }
+}
+
+/// The runtime for smol: async-io timers and the `blocking` thread pool.
+///
+/// Works on any executor, as async-io runs its own reactor thread.
+#[cfg(feature = "smol")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl Runtime for SmolRuntime {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let timer = async_io::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        blocking::unblock(task).detach();
+    }
+}
+
+/// The runtime for async-std: its timers and blocking thread pool.
+#[cfg(feature = "async-std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(task);
+    }
+}
+
+/// Run `task` with [`Runtime::spawn_blocking`] and wait for its result.
+///
+/// Returns None if the task panicked.
+pub(crate) async fn run_blocking<T, F>(runtime: &dyn Runtime, task: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = futures_channel::oneshot::channel();
+    runtime.spawn_blocking(Box::new(move || {
+        let _ = sender.send(task());
+    }));
+    receiver.await.ok()
+}
+
+/// Run `future`, giving up after `duration`.
+///
+/// Returns None on timeout.
+pub(crate) async fn timeout<F: Future>(
+    runtime: &dyn Runtime,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    let mut sleep = runtime.sleep(duration);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        sleep.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// Run `future`, giving up at `deadline` as read on `clock`.
+pub(crate) async fn timeout_at<F: Future>(
+    runtime: &dyn Runtime,
+    clock: &dyn Clock,
+    deadline: Instant,
+    future: F,
+) -> Option<F::Output> {
+    timeout(
+        runtime,
+        deadline.saturating_duration_since(clock.instant()),
+        future,
+    )
+    .await
+}
+
+/// Run `futures` concurrently on the current task, at most `limit` at a
+/// time, returning their outputs in order.
+pub(crate) async fn join_bounded<F: Future>(
+    futures: impl IntoIterator<Item = F>,
+    limit: usize,
+) -> Vec<F::Output> {
+    let mut pending = futures.into_iter().enumerate();
+    let mut running: Vec<(usize, Pin<Box<F>>)> = Vec::new();
+    let mut outputs: Vec<Option<F::Output>> = Vec::new();
+    loop {
+        while running.len() < limit.max(1) {
+            match pending.next() {
+                Some((index, future)) => {
+                    outputs.push(None);
+                    running.push((index, Box::pin(future)));
+                }
+                None => break,
+            }
+        }
+        if running.is_empty() {
+            break;
+        }
+        let (slot, output) = std::future::poll_fn(|cx| {
+            for (slot, (_, future)) in running.iter_mut().enumerate() {
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    return Poll::Ready((slot, output));
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        let (index, _) = running.swap_remove(slot);
+        outputs[index] = Some(output);
+    }
+    outputs
+        .into_iter()
+        .map(|output| output.expect("every future completed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let runtime = TokioRuntime;
+        let fast = timeout(&runtime, Duration::from_secs(5), async { 1 }).await;
+        assert_eq!(fast, Some(1));
+        let slow = timeout(
+            &runtime,
+            Duration::from_millis(10),
+            std::future::pending::<()>(),
+        )
+        .await;
+        assert_eq!(slow, None);
+
+        let past = Instant::now() - Duration::from_millis(1);
+        let expired = timeout_at(
+            &runtime,
+            &crate::SystemClock,
+            past,
+            std::future::pending::<()>(),
+        )
+        .await;
+        assert_eq!(expired, None);
+    }
+
+    /// Check the timers and blocking tasks of `runtime`.
+    async fn check_runtime(runtime: &dyn Runtime) {
+        assert_eq!(run_blocking(runtime, || 7).await, Some(7));
+        let panicked: Option<()> = run_blocking(runtime, || panic!("task failed")).await;
+        assert_eq!(panicked, None);
+
+        let start = Instant::now();
+        runtime.sleep(Duration::from_millis(20)).await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        let slow = timeout(
+            runtime,
+            Duration::from_millis(10),
+            std::future::pending::<()>(),
+        );
+        assert_eq!(slow.await, None);
+    }
+
+    #[tokio::test]
+    async fn test_tokio_runtime() {
+        check_runtime(&TokioRuntime).await;
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_smol_runtime() {
+        async_io::block_on(check_runtime(&SmolRuntime));
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn test_async_std_runtime() {
+        async_std::task::block_on(check_runtime(&AsyncStdRuntime));
+    }
+
+    #[tokio::test]
+    async fn test_join_bounded() {
+        let runtime = TokioRuntime;
+        let running = std::sync::atomic::AtomicUsize::new(0);
+        let max_running = std::sync::atomic::AtomicUsize::new(0);
+        let futures = (0..10u64).map(|i| {
+            let (running, max_running) = (&running, &max_running);
+            let runtime = &runtime;
+            async move {
+                use std::sync::atomic::Ordering;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                // Later futures finish first
+                runtime.sleep(Duration::from_millis(20 - 2 * i)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        });
+        let outputs = join_bounded(futures, 3).await;
+        assert_eq!(outputs, (0..10).collect::<Vec<_>>());
+        assert_eq!(max_running.into_inner(), 3);
+    }
+}
@@ -0,0 +1,166 @@
+//! Background time synchronization.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::client::NtsClient;
+use crate::stats::{allan_deviations, AllanDeviation, SampleStatistics};
+use crate::types::TimeSnapshot;
+
+/// Number of successful samples kept for [`NtsSyncService::statistics`].
+pub const HISTORY_LEN: usize = 64;
+
+/// Keeps a fresh [`TimeSnapshot`] available to the rest of an application.
+///
+/// The service runs a [`TimeStream`](crate::TimeStream) in a tokio task and
+/// publishes every successful sample in a [`watch`] channel. Failed samples
+/// are logged and the last good snapshot is kept; connection and re-keying
+/// are handled by the stream. The last [`HISTORY_LEN`] snapshots are kept
+/// for stability statistics. The task stops when the service is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rkik_nts::{NtsClient, NtsClientConfig, NtsSyncService};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// let service = NtsSyncService::spawn(client, Duration::from_secs(64));
+///
+/// let mut updates = service.subscribe();
+/// updates.changed().await?;
+/// if let Some(time) = service.latest() {
+///     println!("Offset: {} ms", time.offset_signed());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct NtsSyncService {
+    receiver: watch::Receiver<Option<TimeSnapshot>>,
+    history: Arc<Mutex<VecDeque<TimeSnapshot>>>,
+    task: JoinHandle<()>,
+}
+
+impl NtsSyncService {
+    /// Start sampling with `client` every `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a tokio runtime or if `period` is zero.
+    pub fn spawn(client: NtsClient, period: Duration) -> Self {
+        let (sender, receiver) = watch::channel(None);
+        let history = Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_LEN)));
+        let mut samples = client.time_stream(period);
+        let task_history = Arc::clone(&history);
+        let task = tokio::spawn(async move {
+            while let Some(sample) = samples.next().await {
+                match sample {
+                    Ok(snapshot) => {
+                        let mut history = task_history.lock().unwrap_or_else(|e| e.into_inner());
+                        if history.len() == HISTORY_LEN {
+                            history.pop_front();
+                        }
+                        history.push_back(snapshot.clone());
+                        drop(history);
+                        sender.send_replace(Some(snapshot));
+                    }
+                    Err(e) => warn!("Background time sample failed: {}", e),
+                }
+            }
+        });
+        Self {
+            receiver,
+            history,
+            task,
+        }
+    }
+
+    /// The most recent snapshot, or `None` before the first successful
+    /// sample.
+    pub fn latest(&self) -> Option<TimeSnapshot> {
+        self.receiver.borrow().clone()
+    }
+
+    /// Borrow the most recent snapshot without cloning it.
+    ///
+    /// Holding the reference blocks the service from publishing new samples,
+    /// so keep it short-lived.
+    pub fn borrow(&self) -> watch::Ref<'_, Option<TimeSnapshot>> {
+        self.receiver.borrow()
+    }
+
+    /// A receiver notified of every new snapshot.
+    pub fn subscribe(&self) -> watch::Receiver<Option<TimeSnapshot>> {
+        self.receiver.clone()
+    }
+
+    /// The last successful snapshots, oldest first.
+    pub fn history(&self) -> Vec<TimeSnapshot> {
+        self.history
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Offset, jitter and wander statistics over the
+    /// [`history`](Self::history), or `None` before the first successful
+    /// sample.
+    pub fn statistics(&self) -> Option<SampleStatistics> {
+        SampleStatistics::from_samples(&self.history())
+    }
+
+    /// Allan deviations of the system clock over the
+    /// [`history`](Self::history), at 1, 2, 4, ... sampling periods. Empty
+    /// with fewer than three samples.
+    pub fn allan_deviations(&self) -> Vec<AllanDeviation> {
+        allan_deviations(&self.history())
+    }
+}
+
+impl Drop for NtsSyncService {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NtsClientConfig;
+
+    #[tokio::test]
+    async fn test_failed_samples_publish_nothing() {
+        let config = NtsClientConfig::new("test.server.com")
+            .with_ke_addr("127.0.0.1:9".parse().unwrap())
+            .with_max_retries(0);
+        let service = NtsSyncService::spawn(NtsClient::new(config), Duration::from_millis(10));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(service.latest().is_none());
+        assert!(!service.subscribe().has_changed().unwrap());
+        assert!(service.history().is_empty());
+        assert!(service.statistics().is_none());
+        assert!(service.allan_deviations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_task() {
+        let client = NtsClient::new(NtsClientConfig::new("test.server.com"));
+        let service = NtsSyncService::spawn(client, Duration::from_secs(60));
+        let mut updates = service.subscribe();
+        drop(service);
+
+        // The sender is dropped with the aborted task
+        assert!(updates.changed().await.is_err());
+    }
+}
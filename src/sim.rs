@@ -0,0 +1,493 @@
+//! Deterministic replay of recorded NTP sessions.
+//!
+//! A [`Session`] lists the datagrams exchanged with one NTP server, with the
+//! local time each was sent or received, and the NTS keys if the requests
+//! were protected with NTS. [`replay`] feeds them through the query state
+//! machine the client uses ([`NtpQuery`]), including the routing of
+//! responses among concurrent queries, so the outcome depends on nothing
+//! but the session. NTS responses are authenticated as the client would. This makes server quirks reproducible as regression
+//! fixtures, and lets property tests generate sessions.
+//!
+//! Sessions have a line-based text form. Times are seconds since the Unix
+//! epoch, packets are hex-encoded, and `#` starts a comment:
+//!
+//! ```text
+//! # A server 250 ms ahead, answering after 20 ms
+//! server 192.0.2.1:123
+//! keys 15 0000...0000 0101...0101
+//! send 1700000000.000000000 23000600000000000000...0102030405060708
+//! recv 1700000000.020000000 24020000000000000000...e8fe3f80c0000000
+//! ```
+//!
+//! The optional `keys` line holds the AEAD algorithm and the hex-encoded C2S
+//! and S2C keys. Sessions with keys are as secret as the keys: they let
+//! anyone forge responses for the association they were recorded from.
+//!
+//! The client's receive buffer limit is not simulated: oversized responses
+//! are parsed like any other.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::query::{NtpQuery, PendingQueries};
+use crate::types::{NtsKeys, PacketCapture, TimeSnapshot};
+
+/// Datagrams exchanged with one NTP server.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use rkik_nts::sim::{replay, Session};
+///
+/// let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// let mut request = vec![0u8; 48];
+/// request[0] = 0x23; // VN = 4, mode = 3 (client)
+/// request[40..48].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+///
+/// let session = Session::new("192.0.2.1:123".parse()?).send(sent, request);
+/// let outcomes = replay(&session, Duration::from_secs(5));
+/// assert!(matches!(outcomes[..], [Err(rkik_nts::Error::Timeout)]));
+///
+/// let text = session.to_string();
+/// assert_eq!(text.parse::<Session>()?, session);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// The NTP server.
+    pub server: SocketAddr,
+
+    /// The keys the requests are protected with, if they use NTS.
+    pub keys: Option<NtsKeys>,
+
+    /// The datagrams sent and received, in order.
+    pub events: Vec<SessionEvent>,
+}
+
+/// A datagram of a [`Session`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// A request sent to the server.
+    Sent {
+        /// Local time the request was sent (T1).
+        time: SystemTime,
+        /// The request.
+        packet: Vec<u8>,
+    },
+
+    /// A datagram received from the server.
+    Received {
+        /// Local time the datagram was received (T4).
+        time: SystemTime,
+        /// The datagram.
+        packet: Vec<u8>,
+    },
+}
+
+impl Session {
+    /// Create an empty session with `server`.
+    pub fn new(server: SocketAddr) -> Self {
+        Self {
+            server,
+            keys: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Authenticate the responses with `keys`, as for requests built with
+    /// [`NtpQuery::with_nts`].
+    pub fn with_keys(mut self, keys: NtsKeys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Append a request sent at `time`.
+    pub fn send(mut self, time: SystemTime, packet: impl Into<Vec<u8>>) -> Self {
+        self.events.push(SessionEvent::Sent {
+            time,
+            packet: packet.into(),
+        });
+        self
+    }
+
+    /// Append a datagram received at `time`.
+    pub fn receive(mut self, time: SystemTime, packet: impl Into<Vec<u8>>) -> Self {
+        self.events.push(SessionEvent::Received {
+            time,
+            packet: packet.into(),
+        });
+        self
+    }
+}
+
+impl From<&PacketCapture> for Session {
+    /// The query recorded by
+    /// [`NtsClient::last_packets`](crate::NtsClient::last_packets). Add the
+    /// connection's keys with [`Session::with_keys`] to authenticate an NTS
+    /// response.
+    fn from(capture: &PacketCapture) -> Self {
+        let session = Session::new(capture.server).send(capture.sent_at, capture.request.clone());
+        match (&capture.response, capture.received_at) {
+            (Some(response), Some(time)) => session.receive(time, response.clone()),
+            _ => session,
+        }
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "server {}", self.server)?;
+        if let Some(keys) = &self.keys {
+            writeln!(
+                f,
+                "keys {} {} {}",
+                keys.aead_algorithm(),
+                hex(keys.c2s()),
+                hex(keys.s2c())
+            )?;
+        }
+        for event in &self.events {
+            let (keyword, time, packet) = match event {
+                SessionEvent::Sent { time, packet } => ("send", time, packet),
+                SessionEvent::Received { time, packet } => ("recv", time, packet),
+            };
+            let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            write!(
+                f,
+                "{} {}.{:09} ",
+                keyword,
+                since_epoch.as_secs(),
+                since_epoch.subsec_nanos()
+            )?;
+            writeln!(f, "{}", hex(packet))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut session: Option<Session> = None;
+        for (index, line) in s.lines().enumerate() {
+            let invalid =
+                |reason: &str| Error::Other(format!("Session line {}: {}", index + 1, reason));
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            match (keyword, &mut session) {
+                ("server", None) => {
+                    let server = words
+                        .next()
+                        .and_then(|addr| addr.parse().ok())
+                        .ok_or_else(|| invalid("expected a socket address"))?;
+                    session = Some(Session::new(server));
+                }
+                ("send" | "recv", Some(session)) => {
+                    let time = words
+                        .next()
+                        .and_then(parse_time)
+                        .ok_or_else(|| invalid("expected a time in seconds"))?;
+                    let packet = words
+                        .next()
+                        .and_then(parse_hex)
+                        .ok_or_else(|| invalid("expected a hex-encoded packet"))?;
+                    session.events.push(if keyword == "send" {
+                        SessionEvent::Sent { time, packet }
+                    } else {
+                        SessionEvent::Received { time, packet }
+                    });
+                }
+                ("keys", Some(session)) if session.keys.is_none() && session.events.is_empty() => {
+                    let aead_algorithm = words
+                        .next()
+                        .and_then(|id| id.parse().ok())
+                        .ok_or_else(|| invalid("expected an AEAD algorithm identifier"))?;
+                    let mut key = || {
+                        words
+                            .next()
+                            .and_then(parse_hex)
+                            .ok_or_else(|| invalid("expected a hex-encoded key"))
+                    };
+                    let (c2s, s2c) = (key()?, key()?);
+                    session.keys = Some(
+                        NtsKeys::new(aead_algorithm, c2s, s2c)
+                            .map_err(|e| invalid(&e.to_string()))?,
+                    );
+                }
+                ("keys", Some(_)) => return Err(invalid("keys must follow the server")),
+                ("server", Some(_)) => return Err(invalid("duplicate server")),
+                (_, None) => return Err(invalid("expected the server first")),
+                (other, Some(_)) => {
+                    return Err(invalid(&format!("unknown keyword {:?}", other)));
+                }
+            }
+            if words.next().is_some() {
+                return Err(invalid("trailing data"));
+            }
+        }
+        session.ok_or_else(|| Error::Other("Session without server".to_string()))
+    }
+}
+
+/// Parse seconds since the Unix epoch with up to 9 decimals.
+fn parse_time(s: &str) -> Option<SystemTime> {
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = if frac.is_empty() {
+        0
+    } else {
+        frac.parse::<u32>().ok()? * 10u32.pow(9 - frac.len() as u32)
+    };
+    UNIX_EPOCH.checked_add(Duration::new(secs.parse().ok()?, nanos))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Replay `session`, returning the outcome of each request in the order
+/// they were sent.
+///
+/// Each datagram received goes to the pending query whose transmit
+/// timestamp it echoes, and is dropped if there is none, through the same
+/// routing as the client. A query is pending for `query_timeout` after it
+/// was sent; queries left without a response fail with [`Error::Timeout`].
+/// With [`Session::keys`], requests are read as NTS requests and their
+/// responses must authenticate.
+pub fn replay(session: &Session, query_timeout: Duration) -> Vec<Result<TimeSnapshot>> {
+    let measured_at = Instant::now();
+    let mut pending: PendingQueries<(usize, NtpQuery)> = PendingQueries::default();
+    let mut outcomes: Vec<Option<Result<TimeSnapshot>>> = Vec::new();
+    for event in &session.events {
+        match event {
+            SessionEvent::Sent { time, packet } => {
+                let query = match &session.keys {
+                    Some(keys) => NtpQuery::from_nts_request(session.server, packet, *time, keys),
+                    None => NtpQuery::from_request(session.server, packet, *time),
+                };
+                match query {
+                    Ok(query) => {
+                        pending.insert(query.transmit(), (outcomes.len(), query));
+                        outcomes.push(None);
+                    }
+                    Err(e) => outcomes.push(Some(Err(e))),
+                }
+            }
+            SessionEvent::Received { time, packet } => {
+                pending.retain(|(_, query)| {
+                    time.duration_since(query.t1())
+                        .map_or(true, |elapsed| elapsed <= query_timeout)
+                });
+                if let Some((index, query)) = pending.take_answered(packet) {
+                    outcomes[index] = Some(query.parse_response(packet, *time, measured_at));
+                }
+            }
+        }
+    }
+    outcomes
+        .into_iter()
+        .map(|outcome| outcome.unwrap_or(Err(Error::Timeout)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp_packet::encode_ntp_timestamp;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_millis(millis)
+    }
+
+    fn request(nonce: u64) -> Vec<u8> {
+        let mut request = vec![0u8; 48];
+        request[0] = 0x23;
+        request[40..48].copy_from_slice(&nonce.to_be_bytes());
+        request
+    }
+
+    /// A response to the request with `nonce`, from a server clock reading
+    /// `server_time`.
+    fn response(nonce: u64, server_time: SystemTime) -> Vec<u8> {
+        let mut response = vec![0u8; 48];
+        response[0] = 0x24;
+        response[1] = 2;
+        response[24..32].copy_from_slice(&nonce.to_be_bytes());
+        response[32..40].copy_from_slice(&encode_ntp_timestamp(server_time).unwrap());
+        response[40..48].copy_from_slice(&encode_ntp_timestamp(server_time).unwrap());
+        response
+    }
+
+    fn offsets(outcomes: &[Result<TimeSnapshot>]) -> Vec<Option<i64>> {
+        outcomes
+            .iter()
+            .map(|outcome| outcome.as_ref().ok().map(TimeSnapshot::offset_signed))
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_routes_responses() {
+        let session = Session::new("192.0.2.1:123".parse().unwrap())
+            .send(at(0), request(1))
+            .send(at(10), request(2))
+            .send(at(20), request(3))
+            .receive(at(30), response(2, at(20))) // answers the second query
+            .receive(at(35), response(9, at(20))) // answers nothing
+            .receive(at(40), response(1, at(20)))
+            .receive(at(45), response(1, at(20))) // duplicate
+            .receive(at(6000), response(3, at(20))); // too late
+
+        let outcomes = replay(&session, TIMEOUT);
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(
+            offsets(&outcomes),
+            [Some(0), Some(0), None],
+            "{:?}",
+            outcomes
+        );
+        assert!(matches!(outcomes[2], Err(Error::Timeout)));
+        assert_eq!(outcomes[0].as_ref().unwrap().system_time, at(40));
+    }
+
+    #[test]
+    fn test_replay_authenticates_nts() {
+        let server = "192.0.2.1:123".parse().unwrap();
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let nts_request = |nonce: u64| {
+            NtpQuery::new(server, 4, at(0), Some(nonce.to_be_bytes()))
+                .unwrap()
+                .with_nts(&keys, &[0xAB; 64], 0)
+                .unwrap()
+                .request()
+                .to_vec()
+        };
+        let (first, second) = (nts_request(1), nts_request(2));
+        let answer = |request: &[u8], nonce: u64| {
+            crate::test_util::nts_response(request, &response(nonce, at(20)), &keys).unwrap()
+        };
+        let mut forged = answer(&second, 2);
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+
+        let session = Session::new(server)
+            .with_keys(keys.clone())
+            .send(at(0), first.clone())
+            .send(at(0), second)
+            .receive(at(40), answer(&first, 1))
+            .receive(at(40), forged);
+        let outcomes = replay(&session, TIMEOUT);
+        assert!(outcomes[0].as_ref().unwrap().authenticated);
+        assert!(matches!(outcomes[1], Err(Error::AuthenticationFailed(_))));
+
+        // Plain responses to NTS requests do not authenticate
+        let session = Session::new(server)
+            .with_keys(keys)
+            .send(at(0), first)
+            .receive(at(40), response(1, at(20)));
+        assert!(matches!(
+            replay(&session, TIMEOUT)[0],
+            Err(Error::AuthenticationFailed(_))
+        ));
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+    }
+
+    #[test]
+    fn test_session_text_form() {
+        let text = "\
+# Comment
+server [2001:db8::1]:123
+
+send 1700000000.5 2300  # trailing comment
+recv 1700000001.000000001 24ff
+";
+        let session: Session = text.parse().unwrap();
+        assert_eq!(
+            session,
+            Session::new("[2001:db8::1]:123".parse().unwrap())
+                .send(at(500), [0x23, 0x00])
+                .receive(at(1000) + Duration::from_nanos(1), [0x24, 0xff])
+        );
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+
+        for invalid in [
+            "",
+            "send 1 00",
+            "server 192.0.2.1:123\nserver 192.0.2.1:123",
+            "server 192.0.2.1:123\nsend 1 0",
+            "server 192.0.2.1:123\nsend 1.0000000001 00",
+            "server 192.0.2.1:123\nrecv 1 00 00",
+            "server 192.0.2.1:123\nwait 1",
+            "server 192.0.2.1:123\nkeys 15 00 01",
+            "server 192.0.2.1:123\nsend 1 00\nkeys 15 00 01",
+        ] {
+            assert!(invalid.parse::<Session>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_replay_independent_of_response_order() {
+        // However responses to concurrent queries are reordered, duplicated
+        // or mixed with strays, each query gets the same outcome
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let n = rng.gen_range(1..8u64);
+            let mut session = Session::new("192.0.2.1:123".parse().unwrap());
+            let mut responses = Vec::new();
+            for nonce in 0..n {
+                session = session.send(at(nonce), request(nonce));
+                let server_time = at(rng.gen_range(0..1000));
+                let received = at(rng.gen_range(100..200));
+                if rng.gen_bool(0.8) {
+                    responses.push((received, response(nonce, server_time)));
+                }
+            }
+            let in_order = responses
+                .iter()
+                .fold(session.clone(), |session, (time, packet)| {
+                    session.receive(*time, packet.clone())
+                });
+            let in_order = replay(&in_order, TIMEOUT);
+
+            let mut shuffled = responses.clone();
+            shuffled.extend(responses.iter().filter(|_| rng.gen_bool(0.3)).cloned());
+            shuffled.push((at(150), response(n + 1, at(0))));
+            shuffled.shuffle(&mut rng);
+            for (time, packet) in shuffled {
+                session = session.receive(time, packet);
+            }
+            let outcomes = replay(&session, TIMEOUT);
+
+            assert_eq!(offsets(&outcomes), offsets(&in_order));
+            assert_eq!(
+                outcomes.iter().filter(|o| o.is_err()).count(),
+                n as usize - responses.len()
+            );
+        }
+    }
+}
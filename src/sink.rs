@@ -0,0 +1,264 @@
+//! Recording of time samples.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::types::TimeSnapshot;
+
+/// Receives every successful sample of an [`NtsClient`](crate::NtsClient).
+///
+/// Register sinks with
+/// [`NtsClientBuilder::with_sample_sink`](crate::NtsClientBuilder::with_sample_sink).
+/// Errors are logged and do not fail the query.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::sink::CsvSink;
+/// use rkik_nts::NtsClient;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::builder()
+///     .with_server("time.cloudflare.com")
+///     .with_sample_sink(CsvSink::create("samples.csv")?)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait SampleSink: Send + Sync {
+    /// Record a sample.
+    fn record(&self, snapshot: &TimeSnapshot) -> io::Result<()>;
+}
+
+/// Writes samples as comma-separated values, one line per sample after a
+/// header line.
+///
+/// Times are Unix timestamps and durations are seconds; the offset is
+/// positive when the system clock is ahead.
+pub struct CsvSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Columns written by [`CsvSink`].
+const CSV_HEADER: &str = "system_time,network_time,offset,round_trip_delay,server,authenticated,\
+                          stratum,leap_indicator,reference_id,root_delay,root_dispersion";
+
+impl CsvSink {
+    /// Create or truncate the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_writer(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write to `writer`, starting with the header line.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+        writeln!(writer, "{}", CSV_HEADER)?;
+        writer.flush()?;
+        Ok(Self {
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl SampleSink for CsvSink {
+    fn record(&self, snapshot: &TimeSnapshot) -> io::Result<()> {
+        let info = &snapshot.server_info;
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{:?},{},{},{}",
+            unix_secs(snapshot.system_time),
+            unix_secs(snapshot.network_time),
+            offset_secs(snapshot),
+            secs(snapshot.round_trip_delay),
+            csv_field(&snapshot.server),
+            snapshot.authenticated,
+            info.stratum,
+            info.leap_indicator,
+            csv_field(&info.reference_id_string()),
+            secs(info.root_delay),
+            secs(info.root_dispersion),
+        )?;
+        writer.flush()
+    }
+}
+
+/// Writes samples as JSON Lines: one JSON object per line, with the same
+/// fields and units as [`CsvSink`].
+pub struct JsonLinesSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesSink {
+    /// Create or truncate the file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Write to `writer`.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+}
+
+impl SampleSink for JsonLinesSink {
+    fn record(&self, snapshot: &TimeSnapshot) -> io::Result<()> {
+        let info = &snapshot.server_info;
+        let line = format!(
+            "{{\"system_time\":{},\"network_time\":{},\"offset\":{},\
+             \"round_trip_delay\":{},\"server\":{},\"authenticated\":{},\"stratum\":{},\
+             \"leap_indicator\":\"{:?}\",\"reference_id\":{},\"root_delay\":{},\
+             \"root_dispersion\":{}}}",
+            unix_secs(snapshot.system_time),
+            unix_secs(snapshot.network_time),
+            offset_secs(snapshot),
+            secs(snapshot.round_trip_delay),
+            json_string(&snapshot.server),
+            snapshot.authenticated,
+            info.stratum,
+            info.leap_indicator,
+            json_string(&info.reference_id_string()),
+            secs(info.root_delay),
+            secs(info.root_dispersion),
+        );
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{}", line)?;
+        writer.flush()
+    }
+}
+
+/// Signed seconds from `from` to `to`, with nanosecond precision.
+fn signed_secs(from: SystemTime, to: SystemTime) -> String {
+    match to.duration_since(from) {
+        Ok(d) => secs(d),
+        Err(e) => format!("-{}", secs(e.duration())),
+    }
+}
+
+/// Seconds with nanosecond precision.
+fn secs(duration: Duration) -> String {
+    format!("{}.{:09}", duration.as_secs(), duration.subsec_nanos())
+}
+
+/// Seconds since the Unix epoch.
+fn unix_secs(time: SystemTime) -> String {
+    signed_secs(UNIX_EPOCH, time)
+}
+
+/// Signed offset in seconds, positive when the system clock is ahead.
+fn offset_secs(snapshot: &TimeSnapshot) -> String {
+    signed_secs(snapshot.network_time, snapshot.system_time)
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Encode a JSON string literal.
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ServerInfo, SignedDuration};
+    use std::sync::Arc;
+
+    /// A writer whose output can be inspected after it was moved into a sink.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn snapshot() -> TimeSnapshot {
+        let system_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        TimeSnapshot {
+            round_trip_delay: Duration::from_millis(20),
+            server: "[::1]:123".to_string(),
+            server_info: ServerInfo {
+                stratum: 1,
+                reference_id: *b"GPS\0",
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(system_time, SignedDuration::from_nanos(-5_000_000))
+        }
+    }
+
+    #[test]
+    fn test_csv_sink() {
+        let buffer = SharedBuffer::default();
+        let sink = CsvSink::from_writer(buffer.clone()).unwrap();
+        sink.record(&snapshot()).unwrap();
+
+        let contents = buffer.contents();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert_eq!(
+            lines[1],
+            "1700000000.000000000,1700000000.005000000,-0.005000000,0.020000000,[::1]:123,true,\
+             1,NoWarning,GPS,0.000000000,0.000000000"
+        );
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let buffer = SharedBuffer::default();
+        let sink = JsonLinesSink::from_writer(buffer.clone());
+        sink.record(&snapshot()).unwrap();
+        sink.record(&snapshot()).unwrap();
+
+        let contents = buffer.contents();
+        assert_eq!(contents.lines().count(), 2);
+        let line = contents.lines().next().unwrap();
+        assert!(line.starts_with("{\"system_time\":1700000000.000000000,"));
+        assert!(line.contains("\"offset\":-0.005000000,"));
+        assert!(line.contains("\"server\":\"[::1]:123\","));
+        assert!(line.contains("\"reference_id\":\"GPS\","));
+        assert!(line.ends_with('}'));
+        assert_eq!(json_string("a\"\\\u{1}"), "\"a\\\"\\\\\\u0001\"");
+    }
+}
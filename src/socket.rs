@@ -0,0 +1,436 @@
+//! Socket creation with the configured local binding and IP options.
+//!
+//! Sockets are created with socket2 and driven by Tokio or async-io, except
+//! on WASI, where neither is available and only the std sockets exist.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+use std::time::Duration;
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+use std::time::SystemTime;
+
+#[cfg(not(target_os = "wasi"))]
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::config::NtsClientConfig;
+use crate::proxy::ProxyConfig;
+
+/// Local socket settings applied to both the NTS-KE and NTP sockets.
+#[derive(Debug, Clone, Default)]
+// The configuration rejects the options WASI cannot apply
+#[cfg_attr(target_os = "wasi", allow(dead_code))]
+pub(crate) struct SocketOptions {
+    bind_address: Option<SocketAddr>,
+    interface: Option<String>,
+    dscp: Option<u8>,
+    ttl: Option<u32>,
+    proxy: Option<ProxyConfig>,
+}
+
+impl SocketOptions {
+    pub(crate) fn from_config(config: &NtsClientConfig) -> Self {
+        Self {
+            bind_address: config.bind_address,
+            interface: config.interface.clone(),
+            dscp: config.dscp,
+            ttl: config.ttl,
+            proxy: config.proxy.clone(),
+        }
+    }
+
+    /// Open a blocking NTS-KE connection to `remote`, through the proxy if
+    /// one is configured, waiting at most `timeout` to connect.
+    #[cfg(any(
+        feature = "blocking",
+        feature = "smol",
+        feature = "async-std",
+        target_os = "wasi"
+    ))]
+    pub(crate) async fn connect_ke_std(
+        &self,
+        remote: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> io::Result<std::net::TcpStream> {
+        match &self.proxy {
+            Some(proxy) => proxy.connect_std(self, remote, timeout).await,
+            None => self.connect_tcp_std(remote, timeout),
+        }
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl SocketOptions {
+    /// Create a socket for talking to `remote` with these options applied.
+    fn new_socket(&self, remote: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(remote), ty, Some(protocol))?;
+
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(interface) = &self.interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+
+        if let Some(dscp) = self.dscp {
+            set_traffic_class(&socket, remote, u32::from(dscp) << 2)?;
+        }
+
+        if let Some(addr) = self.bind_address {
+            socket.bind(&addr.into())?;
+        }
+
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    /// Open a TCP connection to `remote`.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn connect_tcp(&self, remote: SocketAddr) -> io::Result<TcpStream> {
+        let socket = self.new_socket(remote, Type::STREAM, Protocol::TCP)?;
+        tokio::net::TcpSocket::from_std_stream(socket.into())
+            .connect(remote)
+            .await
+    }
+
+    /// Open the NTS-KE connection to `remote`, through the proxy if one is
+    /// configured.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn connect_ke(&self, remote: SocketAddr) -> io::Result<TcpStream> {
+        match &self.proxy {
+            Some(proxy) => proxy.connect(self, remote).await,
+            None => self.connect_tcp(remote).await,
+        }
+    }
+
+    /// Bind a UDP socket and connect it to `remote`.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn connect_udp(&self, remote: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::from_std(self.bind_udp(remote)?.into())?;
+        socket.connect(remote).await?;
+        Ok(socket)
+    }
+
+    /// Open a blocking TCP connection to `remote`, waiting at most
+    /// `timeout`.
+    #[cfg(any(feature = "blocking", feature = "smol", feature = "async-std"))]
+    pub(crate) fn connect_tcp_std(
+        &self,
+        remote: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> io::Result<std::net::TcpStream> {
+        let socket = self.new_socket(remote, Type::STREAM, Protocol::TCP)?;
+        socket.set_nonblocking(false)?;
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&remote.into(), timeout)?,
+            None => socket.connect(&remote.into())?,
+        }
+        Ok(socket.into())
+    }
+
+    /// Bind a blocking UDP socket and connect it to `remote`.
+    #[cfg(any(feature = "blocking", feature = "smol", feature = "async-std"))]
+    pub(crate) fn connect_udp_std(&self, remote: SocketAddr) -> io::Result<std::net::UdpSocket> {
+        let socket = self.bind_udp(remote)?;
+        socket.set_nonblocking(false)?;
+        socket.connect(&remote.into())?;
+        Ok(socket.into())
+    }
+
+    /// Create a UDP socket for talking to `remote`, bound to a local address.
+    fn bind_udp(&self, remote: SocketAddr) -> io::Result<Socket> {
+        let socket = self.new_socket(remote, Type::DGRAM, Protocol::UDP)?;
+
+        if let Some(ttl) = self.ttl {
+            if remote.is_ipv6() {
+                socket.set_unicast_hops_v6(ttl)?;
+            } else {
+                socket.set_ttl_v4(ttl)?;
+            }
+        }
+
+        if self.bind_address.is_none() {
+            socket.bind(&unspecified(remote).into())?;
+        }
+        Ok(socket)
+    }
+}
+
+/// Sockets on WASI. The configuration rejects the options std cannot
+/// apply: the interface, DSCP and the local address.
+#[cfg(target_os = "wasi")]
+impl SocketOptions {
+    /// Open a blocking TCP connection to `remote`, waiting at most
+    /// `timeout`.
+    pub(crate) fn connect_tcp_std(
+        &self,
+        remote: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> io::Result<std::net::TcpStream> {
+        match timeout {
+            Some(timeout) => std::net::TcpStream::connect_timeout(&remote, timeout),
+            None => std::net::TcpStream::connect(remote),
+        }
+    }
+
+    /// Bind a blocking UDP socket and connect it to `remote`.
+    pub(crate) fn connect_udp_std(&self, remote: SocketAddr) -> io::Result<std::net::UdpSocket> {
+        let socket = std::net::UdpSocket::bind(unspecified(remote))?;
+        if let Some(ttl) = self.ttl {
+            socket.set_ttl(ttl)?;
+        }
+        socket.connect(remote)?;
+        Ok(socket)
+    }
+}
+
+/// The wildcard address of `remote`'s family, with an ephemeral port.
+fn unspecified(remote: SocketAddr) -> SocketAddr {
+    let ip = if remote.is_ipv6() {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    };
+    SocketAddr::new(ip, 0)
+}
+
+/// Ask the kernel to timestamp received packets on `socket`.
+///
+/// Returns whether kernel timestamps are active. Without the
+/// `kernel-timestamps` feature, or on platforms other than Linux, this is a
+/// no-op and received packets are timestamped in userspace.
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+pub(crate) fn enable_kernel_timestamps(socket: &UdpSocket) -> bool {
+    #[cfg(all(feature = "kernel-timestamps", target_os = "linux"))]
+    match kernel::enable_timestamps(socket) {
+        Ok(()) => return true,
+        Err(e) => {
+            tracing::warn!(
+                "Kernel timestamps unavailable, falling back to userspace: {}",
+                e
+            );
+            return false;
+        }
+    }
+
+    #[allow(unreachable_code)]
+    {
+        let _ = socket;
+        false
+    }
+}
+
+/// Receive a datagram together with its receive timestamp.
+///
+/// The timestamp comes from the kernel when kernel timestamps are enabled,
+/// otherwise it is read from the system clock right after the receive.
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+pub(crate) async fn recv_timestamped(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SystemTime)> {
+    #[cfg(all(feature = "kernel-timestamps", target_os = "linux"))]
+    {
+        let (len, timestamp) = kernel::recv_with_timestamp(socket, buf).await?;
+        return Ok((len, timestamp.unwrap_or_else(SystemTime::now)));
+    }
+
+    #[allow(unreachable_code)]
+    {
+        let len = socket.recv(buf).await?;
+        Ok((len, SystemTime::now()))
+    }
+}
+
+#[cfg(all(feature = "kernel-timestamps", feature = "tokio", target_os = "linux"))]
+mod kernel {
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use tokio::io::Interest;
+    use tokio::net::UdpSocket;
+
+    /// Enable `SO_TIMESTAMPNS` on the socket.
+    pub(super) fn enable_timestamps(socket: &UdpSocket) -> io::Result<()> {
+        let enable: libc::c_int = 1;
+        // SAFETY: the fd is valid for the lifetime of `socket` and the option
+        // value points to a live c_int of the advertised size.
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMPNS,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Receive a datagram and extract the `SCM_TIMESTAMPNS` control message.
+    pub(super) async fn recv_with_timestamp(
+        socket: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, Option<SystemTime>)> {
+        socket
+            .async_io(Interest::READABLE, || recvmsg(socket.as_raw_fd(), buf))
+            .await
+    }
+
+    fn recvmsg(fd: libc::c_int, buf: &mut [u8]) -> io::Result<(usize, Option<SystemTime>)> {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // u64 elements keep the control buffer aligned for cmsghdr
+        let mut control = [0u64; 16];
+
+        // SAFETY: msghdr is a plain C struct for which all-zeroes is valid.
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        // SAFETY: msg points to valid iovec and control buffers that outlive the call.
+        let len = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_DONTWAIT) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut timestamp = None;
+        // SAFETY: the CMSG_* macros only walk the control buffer filled in by recvmsg.
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+                {
+                    let ts =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                    timestamp =
+                        Some(UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Ok((len as usize, timestamp))
+    }
+}
+
+/// Set the IPv4 TOS byte or IPv6 traffic class, depending on `remote`'s family.
+#[cfg(not(target_os = "wasi"))]
+fn set_traffic_class(socket: &Socket, remote: SocketAddr, value: u32) -> io::Result<()> {
+    if remote.is_ipv4() {
+        return socket.set_tos_v4(value);
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "fuchsia",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    return socket.set_tclass_v6(value);
+
+    #[allow(unreachable_code)]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Setting the IPv6 traffic class is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_udp_with_bind_address() {
+        let options = SocketOptions {
+            bind_address: Some("127.0.0.1:0".parse().unwrap()),
+            ..Default::default()
+        };
+        let socket = options
+            .connect_udp("127.0.0.1:123".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.local_addr().unwrap().ip(),
+            "127.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dscp_sets_tos() {
+        let options = SocketOptions {
+            dscp: Some(48), // CS6
+            ..Default::default()
+        };
+        let remote: SocketAddr = "127.0.0.1:123".parse().unwrap();
+        let socket = options
+            .new_socket(remote, Type::DGRAM, Protocol::UDP)
+            .unwrap();
+        assert_eq!(socket.tos_v4().unwrap(), 48 << 2);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_applied_to_udp() {
+        let options = SocketOptions {
+            ttl: Some(3),
+            ..Default::default()
+        };
+        let socket = options
+            .connect_udp("127.0.0.1:123".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(socket.ttl().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_recv_timestamped() {
+        let receiver = SocketOptions::default()
+            .connect_udp("127.0.0.1:9".parse().unwrap())
+            .await
+            .unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        receiver
+            .connect(sender.local_addr().unwrap())
+            .await
+            .unwrap();
+        let kernel = enable_kernel_timestamps(&receiver);
+        assert_eq!(
+            kernel,
+            cfg!(all(feature = "kernel-timestamps", target_os = "linux"))
+        );
+
+        let before = SystemTime::now();
+        sender.send_to(b"ping", receiver_addr).await.unwrap();
+        let mut buf = [0u8; 16];
+        let (len, timestamp) = recv_timestamped(&receiver, &mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"ping");
+        assert!(timestamp >= before);
+    }
+
+    #[tokio::test]
+    async fn test_connect_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stream = SocketOptions::default().connect_tcp(addr).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+}
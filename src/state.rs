@@ -0,0 +1,277 @@
+//! Persistence of NTS state across process restarts.
+//!
+//! This module is only available with the `persistence` feature. Saving the
+//! negotiated keys and cookies lets a restarted daemon resume querying the
+//! NTP server without a new NTS key exchange, like ntpd-rs does with its
+//! cookie files.
+//!
+//! State files are JSON. They can optionally be encrypted with a 32-byte key
+//! using ChaCha20-Poly1305; encrypted files start with the magic `RKNTSENC`
+//! followed by a format version byte, a 12-byte nonce and the ciphertext.
+
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::{Error, Result};
+use crate::types::{NtsKeResult, NtsKeys};
+
+/// Magic bytes at the start of an encrypted state file.
+const ENCRYPTED_MAGIC: &[u8; 8] = b"RKNTSENC";
+
+/// Version of the state file format.
+const FORMAT_VERSION: u8 = 1;
+
+/// Serialized form of a connected client's NTS state.
+#[derive(Serialize, Deserialize)]
+struct PersistedState {
+    version: u8,
+    saved_at: u64,
+    ntp_server: SocketAddr,
+    ke_server: SocketAddr,
+    protocol_version: u8,
+    aead_algorithm: u16,
+    c2s_key: Zeroizing<Vec<u8>>,
+    s2c_key: Zeroizing<Vec<u8>>,
+    cookies: Vec<Vec<u8>>,
+}
+
+/// Write the keys of `result` and the unused `cookies` to `path`,
+/// encrypting them if `key` is given.
+///
+/// The file is written to a temporary path first and then renamed, so a
+/// crash never leaves a truncated state file behind. On Unix the file is
+/// only readable by its owner.
+pub(crate) fn save(
+    path: &Path,
+    result: &NtsKeResult,
+    cookies: Vec<Vec<u8>>,
+    key: Option<&[u8; 32]>,
+) -> Result<()> {
+    let state = PersistedState {
+        version: FORMAT_VERSION,
+        saved_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        ntp_server: result.ntp_server,
+        ke_server: result.ke_server,
+        protocol_version: result.protocol_version,
+        aead_algorithm: result.keys.aead_algorithm(),
+        c2s_key: Zeroizing::new(result.keys.c2s().to_vec()),
+        s2c_key: Zeroizing::new(result.keys.s2c().to_vec()),
+        cookies,
+    };
+    let json = Zeroizing::new(
+        serde_json::to_vec(&state)
+            .map_err(|e| Error::Protocol(format!("Failed to serialize NTS state: {}", e)))?,
+    );
+    let contents = match key {
+        Some(key) => encrypt(key, &json)?,
+        None => json,
+    };
+
+    let tmp = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&tmp)?;
+    file.write_all(&contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Read NTS state written by [`save`] from `path`.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidConfig`] if the file cannot be decrypted or
+/// parsed, or if it is encrypted and no key is given.
+pub(crate) fn load(path: &Path, key: Option<&[u8; 32]>) -> Result<NtsKeResult> {
+    let contents = Zeroizing::new(std::fs::read(path)?);
+    let json = match (contents.starts_with(ENCRYPTED_MAGIC), key) {
+        (true, Some(key)) => decrypt(key, &contents)?,
+        (true, None) => {
+            return Err(Error::InvalidConfig(
+                "NTS state file is encrypted but no key was given".to_string(),
+            ))
+        }
+        (false, _) => contents,
+    };
+
+    let state: PersistedState = serde_json::from_slice(&json)
+        .map_err(|e| Error::InvalidConfig(format!("Invalid NTS state file: {}", e)))?;
+    if state.version != FORMAT_VERSION {
+        return Err(Error::InvalidConfig(format!(
+            "Unsupported NTS state file version: {}",
+            state.version
+        )));
+    }
+    if state.cookies.is_empty() {
+        return Err(Error::InvalidConfig(
+            "NTS state file contains no cookies".to_string(),
+        ));
+    }
+
+    let keys = NtsKeys::new(
+        state.aead_algorithm,
+        state.c2s_key.to_vec(),
+        state.s2c_key.to_vec(),
+    )?;
+    let mut result = NtsKeResult::new(
+        state.ntp_server,
+        state.ke_server,
+        state.cookies,
+        std::time::Duration::ZERO,
+        keys,
+    );
+    result.protocol_version = state.protocol_version;
+    Ok(result)
+}
+
+fn cipher(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("key length is fixed"))
+}
+
+fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| Error::Protocol("Failed to generate nonce".to_string()))?;
+
+    let mut header = ENCRYPTED_MAGIC.to_vec();
+    header.push(FORMAT_VERSION);
+    let mut sealed = plaintext.to_vec();
+    cipher(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&header),
+            &mut sealed,
+        )
+        .map_err(|_| Error::Protocol("Failed to encrypt NTS state".to_string()))?;
+
+    let mut out = Zeroizing::new(header);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+fn decrypt(key: &[u8; 32], contents: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let header_len = ENCRYPTED_MAGIC.len() + 1;
+    if contents.len() < header_len + NONCE_LEN {
+        return Err(Error::InvalidConfig(
+            "Encrypted NTS state file is truncated".to_string(),
+        ));
+    }
+    let (header, rest) = contents.split_at(header_len);
+    if header[ENCRYPTED_MAGIC.len()] != FORMAT_VERSION {
+        return Err(Error::InvalidConfig(format!(
+            "Unsupported NTS state file version: {}",
+            header[ENCRYPTED_MAGIC.len()]
+        )));
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| Error::InvalidConfig("Invalid NTS state file nonce".to_string()))?;
+
+    let mut buf = Zeroizing::new(ciphertext.to_vec());
+    let plaintext_len = cipher(key)
+        .open_in_place(nonce, Aad::from(header), &mut buf)
+        .map_err(|_| {
+            Error::InvalidConfig("Failed to decrypt NTS state file (wrong key?)".to_string())
+        })?
+        .len();
+    buf.truncate(plaintext_len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_result() -> NtsKeResult {
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![1; 32], vec![2; 32]).unwrap();
+        let mut result = NtsKeResult::new(
+            "192.0.2.1:123".parse().unwrap(),
+            "192.0.2.2:4460".parse().unwrap(),
+            vec![vec![0xAA; 64], vec![0xBB; 64]],
+            std::time::Duration::from_millis(50),
+            keys,
+        );
+        result.protocol_version = 5;
+        result
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rkik-nts-{}-{}.json", name, std::process::id()))
+    }
+
+    fn assert_restored(restored: &NtsKeResult) {
+        let original = test_result();
+        assert_eq!(restored.ntp_server, original.ntp_server);
+        assert_eq!(restored.ke_server, original.ke_server);
+        assert_eq!(restored.protocol_version(), 5);
+        assert_eq!(restored.aead_algorithm, "AEAD_AES_SIV_CMAC_256");
+        assert_eq!(restored.keys.c2s(), original.keys.c2s());
+        assert_eq!(restored.keys.s2c(), original.keys.s2c());
+        assert_eq!(restored.cookies, original.cookies);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("plain");
+        save(&path, &test_result(), test_result().cookies, None).unwrap();
+        assert_restored(&load(&path, None).unwrap());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_load_encrypted() {
+        let path = temp_path("encrypted");
+        let key = [7u8; 32];
+        save(&path, &test_result(), test_result().cookies, Some(&key)).unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        assert!(contents.starts_with(ENCRYPTED_MAGIC));
+        assert!(!contents.windows(4).any(|w| w == b"ntp_"));
+
+        assert_restored(&load(&path, Some(&key)).unwrap());
+        assert!(matches!(load(&path, None), Err(Error::InvalidConfig(_))));
+        assert!(matches!(
+            load(&path, Some(&[8u8; 32])),
+            Err(Error::InvalidConfig(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_files() {
+        let path = temp_path("invalid");
+        std::fs::write(&path, b"not json").unwrap();
+        assert!(matches!(load(&path, None), Err(Error::InvalidConfig(_))));
+
+        std::fs::write(&path, b"RKNTSENC\x01short").unwrap();
+        assert!(matches!(
+            load(&path, Some(&[0u8; 32])),
+            Err(Error::InvalidConfig(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(load(&path, None), Err(Error::Io(_))));
+    }
+}
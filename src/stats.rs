@@ -0,0 +1,380 @@
+//! Stability statistics over a history of time samples.
+//!
+//! Offsets follow the sign convention of [`TimeSnapshot::offset_signed`]
+//! (positive when the system clock is ahead) and are expressed in seconds,
+//! frequencies in parts per million.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use crate::types::TimeSnapshot;
+
+/// Summary statistics of a series of samples.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rkik_nts::{NtsClient, NtsClientConfig, SampleStatistics};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// client.connect().await?;
+/// let mut samples = Vec::new();
+/// for _ in 0..8 {
+///     samples.push(client.get_time().await?);
+/// }
+/// if let Some(stats) = SampleStatistics::from_samples(&samples) {
+///     println!("Offset: {:.3} ms ± {:.3} ms", stats.mean_offset * 1e3, stats.std_dev * 1e3);
+///     println!("Jitter: {:.3} ms", stats.jitter * 1e3);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleStatistics {
+    /// Number of samples.
+    pub samples: usize,
+
+    /// Mean offset, in seconds.
+    pub mean_offset: f64,
+
+    /// Standard deviation of the offsets, in seconds.
+    pub std_dev: f64,
+
+    /// Mean round-trip delay.
+    pub mean_delay: Duration,
+
+    /// Root mean square of the differences between successive offsets, in
+    /// seconds. Zero with a single sample.
+    pub jitter: f64,
+
+    /// Root mean square of the differences between successive frequency
+    /// measurements, in ppm, or `None` with fewer than three samples.
+    pub wander_ppm: Option<f64>,
+}
+
+impl SampleStatistics {
+    /// Compute the statistics of `samples`, taken in chronological order.
+    /// Returns `None` if `samples` is empty.
+    pub fn from_samples(samples: &[TimeSnapshot]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let count = samples.len() as f64;
+        let offsets: Vec<f64> = samples.iter().map(offset_secs).collect();
+        let mean_offset = offsets.iter().sum::<f64>() / count;
+        let variance = offsets
+            .iter()
+            .map(|x| (x - mean_offset).powi(2))
+            .sum::<f64>()
+            / count;
+        let total_delay: Duration = samples.iter().map(|s| s.round_trip_delay).sum();
+
+        let jitter = rms(offsets.windows(2).map(|w| w[1] - w[0]));
+        let frequencies: Vec<f64> = samples
+            .windows(2)
+            .filter_map(|w| {
+                let elapsed = seconds_between(w[0].system_time, w[1].system_time);
+                (elapsed > 0.0).then(|| (offset_secs(&w[1]) - offset_secs(&w[0])) / elapsed)
+            })
+            .collect();
+        let wander_ppm = (frequencies.len() >= 2)
+            .then(|| rms(frequencies.windows(2).map(|w| w[1] - w[0])) * 1e6);
+
+        Some(Self {
+            samples: samples.len(),
+            mean_offset,
+            std_dev: variance.sqrt(),
+            mean_delay: total_delay / samples.len() as u32,
+            jitter,
+            wander_ppm,
+        })
+    }
+}
+
+/// Count, mean, standard deviation, extremes and percentiles of the last
+/// `window` values pushed.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::RollingStats;
+///
+/// let mut rtt = RollingStats::new(4);
+/// for ms in [12.0, 10.0, 30.0, 11.0, 13.0] {
+///     rtt.push(ms);
+/// }
+/// assert_eq!(rtt.len(), 4);
+/// assert_eq!(rtt.min(), Some(10.0));
+/// assert_eq!(rtt.max(), Some(30.0));
+/// assert_eq!(rtt.median(), Some(12.0));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollingStats {
+    window: usize,
+    values: VecDeque<f64>,
+}
+
+impl RollingStats {
+    /// Create an aggregate keeping the last `window` values (at least 1).
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            values: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Add a value, evicting the oldest one if the window is full.
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// Number of values in the window.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the window is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Maximum number of values kept.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Drop all values.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Mean of the values.
+    pub fn mean(&self) -> Option<f64> {
+        (!self.is_empty()).then(|| self.values.iter().sum::<f64>() / self.len() as f64)
+    }
+
+    /// Population standard deviation of the values.
+    pub fn std_dev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance =
+            self.values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / self.len() as f64;
+        Some(variance.sqrt())
+    }
+
+    /// Smallest value.
+    pub fn min(&self) -> Option<f64> {
+        self.values.iter().copied().reduce(f64::min)
+    }
+
+    /// Largest value.
+    pub fn max(&self) -> Option<f64> {
+        self.values.iter().copied().reduce(f64::max)
+    }
+
+    /// The `p`th percentile (0 to 100, clamped), interpolating linearly
+    /// between the closest values.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut sorted: Vec<f64> = self.values.iter().copied().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f64::total_cmp);
+        let rank = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+        let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+        Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+    }
+
+    /// The 50th percentile.
+    pub fn median(&self) -> Option<f64> {
+        self.percentile(50.0)
+    }
+}
+
+/// Rolling offset and round-trip statistics of one server, maintained by
+/// the client when [`stats_window`](crate::NtsClientConfig::stats_window)
+/// is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStats {
+    /// Signed offsets, in seconds.
+    pub offset: RollingStats,
+
+    /// Round-trip delays, in seconds.
+    pub round_trip: RollingStats,
+}
+
+impl ServerStats {
+    /// Empty statistics keeping the last `window` samples.
+    pub fn new(window: usize) -> Self {
+        Self {
+            offset: RollingStats::new(window),
+            round_trip: RollingStats::new(window),
+        }
+    }
+
+    /// Add a sample.
+    pub fn record(&mut self, snapshot: &TimeSnapshot) {
+        self.offset.push(offset_secs(snapshot));
+        self.round_trip
+            .push(snapshot.round_trip_delay.as_secs_f64());
+    }
+}
+
+/// Allan deviation of the system clock at one averaging time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AllanDeviation {
+    /// Averaging time.
+    pub tau: Duration,
+
+    /// Allan deviation, as a fractional frequency (1e-6 is 1 ppm).
+    pub deviation: f64,
+}
+
+/// Allan deviation of `samples` at `m` times their mean spacing.
+///
+/// The samples must be in chronological order and roughly evenly spaced, as
+/// produced by a [`TimeStream`](crate::TimeStream). Returns `None` if `m` is
+/// zero or there are fewer than `2 * m + 1` samples.
+pub fn allan_deviation(samples: &[TimeSnapshot], m: usize) -> Option<AllanDeviation> {
+    let n = samples.len();
+    if m == 0 || n < 2 * m + 1 {
+        return None;
+    }
+    let span = seconds_between(samples[0].system_time, samples[n - 1].system_time);
+    let tau = span / (n - 1) as f64 * m as f64;
+    if tau <= 0.0 {
+        return None;
+    }
+
+    let offsets: Vec<f64> = samples.iter().map(offset_secs).collect();
+    let terms = n - 2 * m;
+    let sum: f64 = (0..terms)
+        .map(|i| (offsets[i + 2 * m] - 2.0 * offsets[i + m] + offsets[i]).powi(2))
+        .sum();
+    Some(AllanDeviation {
+        tau: Duration::from_secs_f64(tau),
+        deviation: (sum / (2.0 * tau * tau * terms as f64)).sqrt(),
+    })
+}
+
+/// Allan deviations at octave-spaced averaging times (1, 2, 4, ... times the
+/// sample spacing), as far as `samples` allow.
+pub fn allan_deviations(samples: &[TimeSnapshot]) -> Vec<AllanDeviation> {
+    std::iter::successors(Some(1usize), |m| m.checked_mul(2))
+        .map_while(|m| allan_deviation(samples, m))
+        .collect()
+}
+
+/// Signed offset in seconds, positive when the system clock is ahead.
+fn offset_secs(snapshot: &TimeSnapshot) -> f64 {
+    seconds_between(snapshot.network_time, snapshot.system_time)
+}
+
+/// Signed seconds from `from` to `to`.
+fn seconds_between(from: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(from) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+/// Root mean square of `values`, or zero if there are none.
+fn rms(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v * v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SignedDuration;
+    use std::time::UNIX_EPOCH;
+
+    fn snapshot(secs: u64, offset_secs: f64, delay_ms: u64) -> TimeSnapshot {
+        let system_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs);
+        let offset = SignedDuration::from_nanos((offset_secs * 1e9).round() as i128);
+        TimeSnapshot {
+            round_trip_delay: Duration::from_millis(delay_ms),
+            ..TimeSnapshot::for_test(system_time, offset)
+        }
+    }
+
+    #[test]
+    fn test_sample_statistics() {
+        assert!(SampleStatistics::from_samples(&[]).is_none());
+
+        let single = SampleStatistics::from_samples(&[snapshot(0, 0.002, 10)]).unwrap();
+        assert_eq!(single.jitter, 0.0);
+        assert_eq!(single.wander_ppm, None);
+
+        // Alternating offsets, 16s apart
+        let samples: Vec<_> = (0..4)
+            .map(|i| snapshot(16 * i, if i % 2 == 0 { 0.001 } else { -0.001 }, 10 + i))
+            .collect();
+        let stats = SampleStatistics::from_samples(&samples).unwrap();
+        assert_eq!(stats.samples, 4);
+        assert!(stats.mean_offset.abs() < 1e-9);
+        assert!((stats.std_dev - 0.001).abs() < 1e-9);
+        assert_eq!(stats.mean_delay, Duration::from_micros(11_500));
+        assert!((stats.jitter - 0.002).abs() < 1e-9);
+        // Frequencies alternate between -125 and +125 ppm
+        assert!((stats.wander_ppm.unwrap() - 250.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_rolling_stats() {
+        let stats = RollingStats::new(0);
+        assert_eq!(stats.window(), 1);
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.percentile(50.0), None);
+
+        let mut stats = RollingStats::new(5);
+        for v in [100.0, 4.0, 1.0, 3.0, 2.0, 5.0] {
+            stats.push(v);
+        }
+        assert_eq!(stats.len(), 5);
+        assert_eq!(stats.mean(), Some(3.0));
+        assert!((stats.std_dev().unwrap() - 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(stats.min(), Some(1.0));
+        assert_eq!(stats.max(), Some(5.0));
+        assert_eq!(stats.percentile(0.0), Some(1.0));
+        assert_eq!(stats.percentile(100.0), Some(5.0));
+        assert_eq!(stats.percentile(90.0), Some(4.6));
+        assert_eq!(stats.median(), Some(3.0));
+
+        let mut server = ServerStats::new(3);
+        server.record(&snapshot(0, -0.002, 20));
+        assert_eq!(server.offset.mean(), Some(-0.002));
+        assert_eq!(server.round_trip.max(), Some(0.02));
+    }
+
+    #[test]
+    fn test_allan_deviation() {
+        // A constant frequency error has no Allan deviation
+        let linear: Vec<_> = (0..9)
+            .map(|i| snapshot(10 * i, 1e-5 * i as f64, 10))
+            .collect();
+        let adev = allan_deviation(&linear, 1).unwrap();
+        assert_eq!(adev.tau, Duration::from_secs(10));
+        assert!(adev.deviation < 1e-12);
+        assert_eq!(allan_deviations(&linear).len(), 3);
+        assert!(allan_deviation(&linear, 0).is_none());
+        assert!(allan_deviation(&linear, 5).is_none());
+
+        // Alternating phase: second differences of ±4ms over 1s
+        let noisy: Vec<_> = (0..5)
+            .map(|i| snapshot(i, if i % 2 == 0 { 0.001 } else { -0.001 }, 10))
+            .collect();
+        let adev = allan_deviation(&noisy, 1).unwrap();
+        assert!((adev.deviation - 0.004 / 2f64.sqrt()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,157 @@
+//! Periodic time samples as a [`Stream`].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tracing::debug;
+
+use crate::client::NtsClient;
+use crate::error::{Error, Result};
+use crate::runtime::{Runtime, SleepFuture};
+use crate::types::TimeSnapshot;
+
+type Sample = Pin<Box<dyn Future<Output = Result<TimeSnapshot>> + Send>>;
+
+/// A stream of time samples taken at a fixed interval.
+///
+/// Created with [`NtsClient::time_stream`]. The stream never ends; each item
+/// is the outcome of one `get_time()` call, so retries, backoff and
+/// Kiss-o'-Death `RATE` spacing apply as usual. The client connects before
+/// the first sample if needed, and performs a fresh key exchange before the
+/// next sample when a query failed in a way new keys may fix (no response,
+/// or a Kiss-o'-Death `NTSN`).
+///
+/// Samples start one interval apart, timed by the client's
+/// [`Runtime`]. If a sample takes longer than the interval, the next one
+/// starts as soon as it completes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rkik_nts::{NtsClient, NtsClientConfig};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = NtsClient::new(NtsClientConfig::new("time.cloudflare.com"));
+/// let mut samples = client.time_stream(Duration::from_secs(64));
+/// while let Some(sample) = samples.next().await {
+///     match sample {
+///         Ok(time) => println!("Offset: {} ms", time.offset_signed()),
+///         Err(e) => eprintln!("Sample failed: {}", e),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TimeStream {
+    client: NtsClient,
+    runtime: Arc<dyn Runtime>,
+    period: Duration,
+    /// Elapses when the next sample may start; `None` before the first.
+    next_start: Option<SleepFuture>,
+    rekey: bool,
+    pending: Option<Sample>,
+}
+
+impl TimeStream {
+    pub(crate) fn new(client: NtsClient, runtime: Arc<dyn Runtime>, period: Duration) -> Self {
+        assert!(!period.is_zero(), "`period` must be non-zero");
+        Self {
+            client,
+            runtime,
+            period,
+            next_start: None,
+            rekey: false,
+            pending: None,
+        }
+    }
+
+    /// Wait for the next sample.
+    ///
+    /// This is equivalent to `StreamExt::next`, without requiring an
+    /// extension trait. It never returns `None`.
+    pub async fn next(&mut self) -> Option<Result<TimeSnapshot>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for TimeStream {
+    type Item = Result<TimeSnapshot>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(pending) = &mut this.pending {
+                let result = ready!(pending.as_mut().poll(cx));
+                this.pending = None;
+                this.rekey = result.as_ref().err().is_some_and(needs_rekey);
+                return Poll::Ready(Some(result));
+            }
+
+            if let Some(next_start) = &mut this.next_start {
+                ready!(next_start.as_mut().poll(cx));
+            }
+            this.next_start = Some(this.runtime.sleep(this.period));
+            let client = this.client.clone();
+            let rekey = this.rekey;
+            this.pending = Some(Box::pin(async move {
+                if rekey {
+                    debug!("Last sample failed, performing a fresh key exchange");
+                    client.reconnect().await?;
+                } else if !client.is_connected() {
+                    client.connect().await?;
+                }
+                client.get_time().await
+            }));
+        }
+    }
+}
+
+/// Whether a fresh key exchange may fix a failed query.
+pub(crate) fn needs_rekey(error: &Error) -> bool {
+    match error {
+        Error::KissOfDeath { code } => code == "NTSN",
+        Error::Timeout
+        | Error::RetriesExhausted { .. }
+        | Error::Io(_)
+        | Error::CookieExhausted { .. } => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_rekey() {
+        assert!(needs_rekey(&Error::Timeout));
+        assert!(needs_rekey(&Error::KissOfDeath {
+            code: "NTSN".to_string()
+        }));
+        assert!(!needs_rekey(&Error::KissOfDeath {
+            code: "RATE".to_string()
+        }));
+        assert!(!needs_rekey(&Error::CircuitOpen {
+            retry_after: Duration::from_secs(1)
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_connect_errors() {
+        let config = crate::NtsClientConfig::new("test.server.com")
+            .with_ke_addr("127.0.0.1:9".parse().unwrap())
+            .with_max_retries(0);
+        let mut samples = NtsClient::new(config).time_stream(Duration::from_millis(10));
+
+        for _ in 0..2 {
+            let sample = samples.next().await.unwrap();
+            assert!(sample.is_err());
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Pluggable time source for the client.
+//!
+//! The client reads the wall clock for the timestamps it sends (T1) and
+//! receives (T4), and the monotonic clock for round trips, pacing, the
+//! circuit breaker and the blacklist. Both go through a [`Clock`], so tests
+//! can substitute a skewed or stepping clock and check the offset math
+//! exactly.
+
+use std::time::{Instant, SystemTime};
+
+/// A source of wall-clock and monotonic time.
+///
+/// Register a custom clock with
+/// [`NtsClientBuilder::with_clock`](crate::NtsClientBuilder::with_clock).
+/// Timeouts and retry delays run on the client's
+/// [`Runtime`](crate::Runtime).
+pub trait Clock: Send + Sync {
+    /// The current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// The current monotonic time.
+    fn instant(&self) -> Instant;
+
+    /// The time a packet was received, given the receive timestamp reported
+    /// by the transport.
+    ///
+    /// Transport timestamps are taken from the system clock (possibly by
+    /// the kernel), so the default ignores them and reads this clock.
+    fn receive_time(&self, reported: SystemTime) -> SystemTime {
+        let _ = reported;
+        self.now()
+    }
+}
+
+/// The default clock: the system wall clock and [`Instant::now`].
+///
+/// Receive times reported by the transport, including kernel timestamps,
+/// are used as is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn receive_time(&self, reported: SystemTime) -> SystemTime {
+        reported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedClock(SystemTime, Instant);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+
+        fn instant(&self) -> Instant {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_receive_time() {
+        let reported = SystemTime::UNIX_EPOCH;
+        assert_eq!(SystemClock.receive_time(reported), reported);
+
+        let now = SystemTime::now();
+        let clock = FixedClock(now, Instant::now());
+        assert_eq!(clock.receive_time(reported), now);
+    }
+}
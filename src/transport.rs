@@ -0,0 +1,413 @@
+//! Pluggable network I/O for key exchanges and NTP queries.
+//!
+//! The client opens all its connections through a [`Connector`]. The
+//! default [`SocketConnector`] uses TCP and UDP sockets; a custom connector
+//! can substitute in-memory transports, for example to test how the client
+//! copes with lost, corrupted or reordered packets without touching the
+//! network.
+//!
+//! On WASI, which has no Tokio networking, [`SocketConnector`] uses blocking
+//! std sockets instead. With the `smol` or `async-std` feature,
+//! [`AsyncIoConnector`] drives the sockets with async-io rather than Tokio.
+
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::pin::Pin;
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+use std::task::Poll;
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+use std::time::Duration;
+use std::time::SystemTime;
+
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+use tokio::net::UdpSocket;
+
+use crate::config::NtsClientConfig;
+use crate::socket::SocketOptions;
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+use crate::socket::{enable_kernel_timestamps, recv_timestamped};
+
+/// Future returned by the transport traits.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A datagram channel to one NTP server.
+///
+/// Concurrent queries share the transport, so `send` and `recv` may be
+/// called from several tasks at once.
+pub trait NtpTransport: Send + Sync {
+    /// Send one NTP packet to the server.
+    fn send<'a>(&'a self, packet: &'a [u8]) -> TransportFuture<'a, ()>;
+
+    /// Receive one packet from the server into `buf`, together with the
+    /// time it was received. Returns the number of bytes written; longer
+    /// packets are truncated to the length of `buf`.
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SystemTime)>;
+
+    /// The local address packets are sent from.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Whether receive times are taken by the kernel rather than read from
+    /// the system clock after the fact.
+    fn kernel_timestamps(&self) -> bool {
+        false
+    }
+}
+
+/// A byte stream to an NTS-KE server.
+///
+/// The key exchange runs on a blocking thread and polls the stream, so
+/// reads and writes should fail with [`io::ErrorKind::WouldBlock`] rather
+/// than block. Implemented for every such stream type, including a
+/// non-blocking [`std::net::TcpStream`].
+pub trait KeTransport: Read + Write + Send {}
+
+impl<T: Read + Write + Send + ?Sized> KeTransport for T {}
+
+/// Opens transports to NTS-KE and NTP servers.
+///
+/// Register a custom connector with
+/// [`NtsClientBuilder::with_connector`](crate::NtsClientBuilder::with_connector).
+pub trait Connector: Send + Sync {
+    /// Open a stream to the NTS-KE server at `server`.
+    fn connect_ke<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn KeTransport>>;
+
+    /// Open a datagram channel to the NTP server at `server`.
+    fn connect_ntp<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn NtpTransport>>;
+}
+
+/// The default connector: TCP and UDP sockets with the local binding and IP
+/// options of the client configuration.
+#[cfg(any(feature = "tokio", target_os = "wasi"))]
+#[derive(Debug, Clone, Default)]
+pub struct SocketConnector {
+    #[cfg(not(target_os = "wasi"))]
+    options: SocketOptions,
+    #[cfg(target_os = "wasi")]
+    inner: StdConnector,
+}
+
+#[cfg(any(feature = "tokio", target_os = "wasi"))]
+impl SocketConnector {
+    /// Create a connector applying the socket options of `config`.
+    pub fn new(config: &NtsClientConfig) -> Self {
+        Self {
+            #[cfg(not(target_os = "wasi"))]
+            options: SocketOptions::from_config(config),
+            #[cfg(target_os = "wasi")]
+            inner: StdConnector::new(config),
+        }
+    }
+}
+
+#[cfg(target_os = "wasi")]
+impl Connector for SocketConnector {
+    fn connect_ke<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn KeTransport>> {
+        self.inner.connect_ke(server)
+    }
+
+    fn connect_ntp<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn NtpTransport>> {
+        self.inner.connect_ntp(server)
+    }
+}
+
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+impl Connector for SocketConnector {
+    fn connect_ke<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn KeTransport>> {
+        Box::pin(async move {
+            // The std stream stays in non-blocking mode
+            let stream = self.options.connect_ke(server).await?.into_std()?;
+            Ok(Box::new(stream) as Box<dyn KeTransport>)
+        })
+    }
+
+    fn connect_ntp<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn NtpTransport>> {
+        Box::pin(async move {
+            let socket = self.options.connect_udp(server).await?;
+            Ok(Box::new(UdpTransport::new(socket)) as Box<dyn NtpTransport>)
+        })
+    }
+}
+
+/// An [`NtpTransport`] over a connected UDP socket, using kernel receive
+/// timestamps where available.
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+    kernel_timestamps: bool,
+}
+
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+impl UdpTransport {
+    /// Wrap a UDP socket connected to the NTP server.
+    pub fn new(socket: UdpSocket) -> Self {
+        let kernel_timestamps = enable_kernel_timestamps(&socket);
+        Self {
+            socket,
+            kernel_timestamps,
+        }
+    }
+}
+
+#[cfg(all(feature = "tokio", not(target_os = "wasi")))]
+impl NtpTransport for UdpTransport {
+    fn send<'a>(&'a self, packet: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.socket.send(packet).await?;
+            Ok(())
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SystemTime)> {
+        Box::pin(recv_timestamped(&self.socket, buf))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn kernel_timestamps(&self) -> bool {
+        self.kernel_timestamps
+    }
+}
+
+/// How long a receive on a std socket blocks before the future yields.
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+const STD_RECV_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Opens blocking std sockets with the configured socket options, for
+/// callers without Tokio networking.
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StdConnector {
+    options: SocketOptions,
+    /// Limit for establishing a TCP connection.
+    timeout: Option<Duration>,
+}
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+impl StdConnector {
+    pub(crate) fn new(config: &NtsClientConfig) -> Self {
+        Self {
+            options: SocketOptions::from_config(config),
+            timeout: Some(config.effective_ke_timeout()),
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+impl Connector for StdConnector {
+    fn connect_ke<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn KeTransport>> {
+        Box::pin(async move {
+            let stream = self.options.connect_ke_std(server, self.timeout).await?;
+            stream.set_nonblocking(true)?;
+            Ok(Box::new(stream) as Box<dyn KeTransport>)
+        })
+    }
+
+    fn connect_ntp<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn NtpTransport>> {
+        let result = self.options.connect_udp_std(server).and_then(|socket| {
+            socket.set_read_timeout(Some(STD_RECV_TIMEOUT))?;
+            Ok(Box::new(StdUdpTransport { socket }) as Box<dyn NtpTransport>)
+        });
+        Box::pin(std::future::ready(result))
+    }
+}
+
+/// An [`NtpTransport`] over a blocking UDP socket.
+///
+/// A receive waits on the socket for up to [`STD_RECV_TIMEOUT`] per poll,
+/// so that responses are timestamped as they arrive.
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+struct StdUdpTransport {
+    socket: std::net::UdpSocket,
+}
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "smol",
+    feature = "async-std",
+    target_os = "wasi"
+))]
+impl NtpTransport for StdUdpTransport {
+    fn send<'a>(&'a self, packet: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(std::future::ready(self.socket.send(packet).map(drop)))
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SystemTime)> {
+        Box::pin(std::future::poll_fn(move |cx| {
+            match self.socket.recv(buf) {
+                Ok(len) => Poll::Ready(Ok((len, SystemTime::now()))),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Ask to be polled again right away
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        }))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+/// A connector for smol and async-std: TCP and UDP sockets with the
+/// options of the client configuration, driven by async-io.
+///
+/// The NTS-KE connection, and the SOCKS5 handshake if a proxy is
+/// configured, are set up on a thread of the `blocking` pool. Receive times
+/// are read from the system clock, without kernel timestamps.
+#[cfg(any(feature = "smol", feature = "async-std"))]
+#[derive(Debug, Clone, Default)]
+pub struct AsyncIoConnector {
+    inner: StdConnector,
+}
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl AsyncIoConnector {
+    /// Create a connector applying the socket options of `config`.
+    pub fn new(config: &NtsClientConfig) -> Self {
+        Self {
+            inner: StdConnector::new(config),
+        }
+    }
+}
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl Connector for AsyncIoConnector {
+    fn connect_ke<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn KeTransport>> {
+        let inner = self.inner.clone();
+        Box::pin(blocking::unblock(move || {
+            async_io::block_on(inner.connect_ke(server))
+        }))
+    }
+
+    fn connect_ntp<'a>(&'a self, server: SocketAddr) -> TransportFuture<'a, Box<dyn NtpTransport>> {
+        let result = self
+            .inner
+            .options
+            .connect_udp_std(server)
+            .and_then(async_io::Async::new)
+            .map(|socket| Box::new(AsyncUdpTransport { socket }) as Box<dyn NtpTransport>);
+        Box::pin(std::future::ready(result))
+    }
+}
+
+/// An [`NtpTransport`] over a connected async-io UDP socket.
+#[cfg(any(feature = "smol", feature = "async-std"))]
+struct AsyncUdpTransport {
+    socket: async_io::Async<std::net::UdpSocket>,
+}
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl NtpTransport for AsyncUdpTransport {
+    fn send<'a>(&'a self, packet: &'a [u8]) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            self.socket.send(packet).await?;
+            Ok(())
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SystemTime)> {
+        Box::pin(async move {
+            let len = self.socket.recv(buf).await?;
+            Ok((len, SystemTime::now()))
+        })
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.get_ref().local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_udp_transport() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let transport = SocketConnector::default()
+            .connect_ntp(server.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        transport.send(&[1, 2, 3]).await.unwrap();
+        let mut buf = [0u8; 8];
+        let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], &[1, 2, 3]);
+        assert_eq!(peer, transport.local_addr().unwrap());
+
+        // Longer datagrams are truncated
+        let before = SystemTime::now();
+        server.send_to(&[7; 6], peer).await.unwrap();
+        let (len, received) = transport.recv(&mut buf[..4]).await.unwrap();
+        assert_eq!(len, 4);
+        assert!(received >= before);
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn test_async_io_transport() {
+        async_io::block_on(async {
+            let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let transport = AsyncIoConnector::default()
+                .connect_ntp(server.local_addr().unwrap())
+                .await
+                .unwrap();
+
+            transport.send(&[1, 2, 3]).await.unwrap();
+            let mut buf = [0u8; 8];
+            let (len, peer) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..len], &[1, 2, 3]);
+            assert_eq!(peer, transport.local_addr().unwrap());
+
+            server.send_to(&[7; 3], peer).unwrap();
+            let (len, _) = transport.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], &[7; 3]);
+        });
+    }
+}
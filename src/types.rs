@@ -1,22 +1,48 @@
 //! Common types used throughout the library.
 
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::error::{Error, Result};
+use crate::time_source::Clock;
 
 /// Result of a time synchronization query.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TimeSnapshot {
     /// The current system time when the measurement was taken.
+    ///
+    /// With the `serde` and `chrono` features, serialized as an RFC 3339
+    /// string.
+    #[cfg_attr(all(feature = "serde", feature = "chrono"), serde(with = "rfc3339"))]
     pub system_time: SystemTime,
 
-    /// The network time received from the NTP server.
+    /// Monotonic clock reading taken with `system_time`, unaffected by later
+    /// steps of the system clock.
+    ///
+    /// Not serialized; deserialized snapshots get the time of
+    /// deserialization.
+    #[cfg_attr(feature = "serde", serde(skip, default = "Instant::now"))]
+    pub measured_at: Instant,
+
+    /// The network time at `system_time`, corrected for path delay.
+    #[cfg_attr(all(feature = "serde", feature = "chrono"), serde(with = "rfc3339"))]
     pub network_time: SystemTime,
 
-    /// The offset between system time and network time.
-    /// Positive means the system clock is ahead.
+    /// Offset of the system clock from the network time,
+    /// `((T1 - T2) + (T4 - T3)) / 2`: the standard NTP offset
+    /// `((T2 - T1) + (T3 - T4)) / 2` negated. Positive when the system clock
+    /// is ahead.
+    pub clock_offset: SignedDuration,
+
+    /// The absolute value of [`clock_offset`](Self::clock_offset).
+    #[deprecated(
+        since = "0.3.0",
+        note = "use `clock_offset`, which carries the direction of the offset"
+    )]
     pub offset: std::time::Duration,
 
     /// Round-trip delay to the server.
@@ -27,16 +53,68 @@ pub struct TimeSnapshot {
 
     /// Whether the response was authenticated via NTS.
     pub authenticated: bool,
+
+    /// Header fields describing the server's synchronization quality.
+    pub server_info: ServerInfo,
+
+    /// Whether this is a bootstrap measurement: the key exchange accepted a
+    /// certificate outside its validity period because the system clock
+    /// looked wrong. Set the clock from it, then reconnect.
+    pub bootstrap: bool,
 }
 
 impl TimeSnapshot {
-    /// Calculate the clock offset as a signed duration.
-    /// Positive means system clock is ahead of network time.
+    /// Calculate the clock offset in whole milliseconds, truncated towards
+    /// zero. Positive means system clock is ahead of network time.
     pub fn offset_signed(&self) -> i64 {
-        match self.system_time.duration_since(self.network_time) {
-            Ok(duration) => duration.as_millis() as i64,
-            Err(e) => -(e.duration().as_millis() as i64),
+        (self.clock_offset.as_nanos() / 1_000_000) as i64
+    }
+
+    /// Clock offset in nanoseconds. Positive means system clock is ahead of
+    /// network time.
+    pub fn offset_nanos(&self) -> i128 {
+        self.clock_offset.as_nanos()
+    }
+
+    /// Round-trip delay in nanoseconds.
+    pub fn rtt_nanos(&self) -> u128 {
+        self.round_trip_delay.as_nanos()
+    }
+
+    /// Time elapsed since the measurement, on the monotonic time of
+    /// `clock`, which should be the clock of the client that took it.
+    pub fn age(&self, clock: &dyn Clock) -> Duration {
+        clock.instant().saturating_duration_since(self.measured_at)
+    }
+
+    /// Root distance: `root_delay / 2 + root_dispersion + round_trip_delay / 2`.
+    ///
+    /// This is an upper bound on the error of the network time.
+    pub fn root_distance(&self) -> std::time::Duration {
+        self.server_info.root_delay / 2
+            + self.server_info.root_dispersion
+            + self.round_trip_delay / 2
+    }
+
+    /// The leap indicator announced by the server.
+    pub fn leap_indicator(&self) -> LeapIndicator {
+        self.server_info.leap_indicator
+    }
+
+    /// The announced leap second, if any: the end of the UTC month in which
+    /// the server sent its response.
+    ///
+    /// The server's transmit time is `network_time - round_trip_delay / 2`,
+    /// so this stays correct for measurements extrapolated past the leap.
+    pub fn next_leap_second(&self) -> Option<SystemTime> {
+        if !self.leap_indicator().is_pending() {
+            return None;
         }
+        let sent = self
+            .network_time
+            .checked_sub(self.round_trip_delay / 2)
+            .unwrap_or(self.network_time);
+        Some(crate::leap::next_leap_second(sent))
     }
 
     /// Check if the system clock is ahead of network time.
@@ -48,10 +126,765 @@ impl TimeSnapshot {
     pub fn is_behind(&self) -> bool {
         self.system_time < self.network_time
     }
+
+    /// The network time as a `chrono` date-time.
+    #[cfg(feature = "chrono")]
+    pub fn network_datetime(&self) -> chrono::DateTime<chrono::Utc> {
+        self.network_time.into()
+    }
+
+    /// The system time of the measurement as a `chrono` date-time.
+    #[cfg(feature = "chrono")]
+    pub fn system_datetime(&self) -> chrono::DateTime<chrono::Utc> {
+        self.system_time.into()
+    }
+}
+
+/// Serde adapter writing a [`SystemTime`] as an RFC 3339 string with
+/// nanosecond precision.
+#[cfg(all(feature = "serde", feature = "chrono"))]
+mod rfc3339 {
+    use std::time::SystemTime;
+
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        time: &SystemTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let datetime: DateTime<Utc> = (*time).into();
+        serializer.serialize_str(&datetime.to_rfc3339_opts(SecondsFormat::Nanos, true))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SystemTime, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(SystemTime::from)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A duration that may be negative, such as a clock offset.
+///
+/// # Examples
+///
+/// ```
+/// use rkik_nts::SignedDuration;
+/// use std::time::Duration;
+///
+/// let offset = -SignedDuration::from(Duration::from_micros(1500));
+/// assert!(offset.is_negative());
+/// assert_eq!(offset.as_nanos(), -1_500_000);
+/// assert_eq!(offset.abs(), Duration::from_micros(1500));
+/// assert_eq!(offset.to_string(), "-1.500000ms");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SignedDuration {
+    nanos: i128,
+}
+
+impl SignedDuration {
+    /// A zero duration.
+    pub const ZERO: Self = Self { nanos: 0 };
+
+    /// Create a duration from signed nanoseconds.
+    pub const fn from_nanos(nanos: i128) -> Self {
+        Self { nanos }
+    }
+
+    /// The signed time from `from` to `to`: positive when `to` is later.
+    pub fn between(from: SystemTime, to: SystemTime) -> Self {
+        match to.duration_since(from) {
+            Ok(d) => Self::from(d),
+            Err(e) => -Self::from(e.duration()),
+        }
+    }
+
+    /// Signed nanoseconds.
+    pub const fn as_nanos(&self) -> i128 {
+        self.nanos
+    }
+
+    /// Signed seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.nanos as f64 / 1e9
+    }
+
+    /// Absolute value, saturating at [`Duration::MAX`].
+    pub fn abs(&self) -> Duration {
+        let nanos = self.nanos.unsigned_abs();
+        let secs = u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX);
+        Duration::new(secs, (nanos % 1_000_000_000) as u32)
+    }
+
+    /// Whether the duration is below zero.
+    pub const fn is_negative(&self) -> bool {
+        self.nanos < 0
+    }
+
+    /// Whether the duration is above zero.
+    pub const fn is_positive(&self) -> bool {
+        self.nanos > 0
+    }
+}
+
+impl From<Duration> for SignedDuration {
+    fn from(duration: Duration) -> Self {
+        Self::from_nanos(duration.as_nanos() as i128)
+    }
+}
+
+impl std::ops::Neg for SignedDuration {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_nanos(-self.nanos)
+    }
+}
+
+/// Formats as signed milliseconds, e.g. `-1.500000ms`.
+impl std::fmt::Display for SignedDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        write!(f, "{}{:.6}ms", sign, self.abs().as_secs_f64() * 1e3)
+    }
+}
+
+/// A multi-line, human-readable report of the measurement.
+///
+/// ```text
+/// Server:          162.159.200.1:123
+/// Offset:          -1.234 ms (system clock behind)
+/// Round-trip:      12.345 ms
+/// Root distance:   6.789 ms
+/// Stratum:         3 (reference 10.21.8.4)
+/// Leap indicator:  NoWarning
+/// Authenticated:   yes
+/// Network time:    1700000000.123456789 (Unix)
+/// ```
+impl std::fmt::Display for TimeSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offset = self.clock_offset.as_secs_f64();
+        let direction = if self.clock_offset.is_positive() {
+            "system clock ahead"
+        } else if self.clock_offset.is_negative() {
+            "system clock behind"
+        } else {
+            "synchronized"
+        };
+        writeln!(f, "Server:          {}", self.server)?;
+        writeln!(f, "Offset:          {:.3} ms ({})", offset * 1e3, direction)?;
+        writeln!(f, "Round-trip:      {}", Millis(self.round_trip_delay))?;
+        writeln!(f, "Root distance:   {}", Millis(self.root_distance()))?;
+        writeln!(
+            f,
+            "Stratum:         {} (reference {})",
+            self.server_info.stratum,
+            self.server_info.reference_id_string()
+        )?;
+        writeln!(f, "Leap indicator:  {:?}", self.server_info.leap_indicator)?;
+        writeln!(f, "Authenticated:   {}", yes_no(self.authenticated))?;
+        if self.bootstrap {
+            writeln!(f, "Bootstrap:       yes (certificate validity ignored)")?;
+        }
+        write!(f, "Network time:    {} (Unix)", UnixTime(self.network_time))
+    }
+}
+
+/// Formats a duration in milliseconds with microsecond precision.
+struct Millis(std::time::Duration);
+
+impl std::fmt::Display for Millis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.3} ms", self.0.as_secs_f64() * 1e3)
+    }
+}
+
+/// Formats a time as Unix seconds with nanosecond precision.
+struct UnixTime(SystemTime);
+
+impl std::fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(d) => write!(f, "{}.{:09}", d.as_secs(), d.subsec_nanos()),
+            Err(e) => write!(f, "-{:?}", e.duration()),
+        }
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Duration of each phase of `connect()` and `get_time()`, for diagnostics.
+///
+/// Phases that did not run (for example DNS resolution when pre-resolved
+/// addresses are used) are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimingBreakdown {
+    /// Time spent resolving the NTS-KE server hostname.
+    pub dns_resolution: Option<std::time::Duration>,
+
+    /// Time spent establishing the TCP connection to the NTS-KE server.
+    pub tcp_connect: Option<std::time::Duration>,
+
+    /// Time until the server's TLS handshake flight was received.
+    pub tls_handshake: Option<std::time::Duration>,
+
+    /// Time from the end of the TLS handshake until the NTS-KE records were received.
+    pub ke_records: Option<std::time::Duration>,
+
+    /// Wall-clock duration of the last NTP request/response exchange.
+    pub ntp_round_trip: Option<std::time::Duration>,
+}
+
+/// The raw packets of the last query, see
+/// [`NtsClient::last_packets`](crate::NtsClient::last_packets).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PacketCapture {
+    /// The NTP server queried.
+    pub server: std::net::SocketAddr,
+
+    /// When the request was sent.
+    pub sent_at: SystemTime,
+
+    /// The request as sent.
+    pub request: Vec<u8>,
+
+    /// The response as received, `None` if no response arrived.
+    pub response: Option<Vec<u8>>,
+
+    /// When the response was received, `None` if no response arrived.
+    pub received_at: Option<SystemTime>,
+}
+
+/// Result of [`NtsClient::get_time_debug`](crate::NtsClient::get_time_debug):
+/// a snapshot with the protocol details of the response.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DebugSnapshot {
+    /// The time measurement.
+    pub snapshot: TimeSnapshot,
+
+    /// Extension fields of the response, in the order they were received.
+    pub extension_fields: Vec<crate::extension::ExtensionField>,
+}
+
+/// Result of [`NtsClient::get_time_filtered`](crate::NtsClient::get_time_filtered).
+///
+/// Like the NTP clock filter, the sample with the smallest round-trip delay
+/// is chosen: it is the least affected by queuing delays, so its offset is
+/// the most accurate.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FilteredTime {
+    /// The sample with the minimum round-trip delay.
+    pub chosen: TimeSnapshot,
+
+    /// All successful samples, in the order they were taken.
+    pub samples: Vec<TimeSnapshot>,
+
+    /// Number of samples that failed.
+    pub failures: usize,
+}
+
+impl FilteredTime {
+    /// Pick the minimum-delay sample, or `None` if `samples` is empty.
+    pub(crate) fn from_samples(samples: Vec<TimeSnapshot>, failures: usize) -> Option<Self> {
+        let chosen = samples.iter().min_by_key(|s| s.round_trip_delay)?.clone();
+        Some(Self {
+            chosen,
+            samples,
+            failures,
+        })
+    }
+
+    /// The sample with the median signed offset, which is more robust than
+    /// [`chosen`](Self::chosen) when delays are symmetric but offsets are
+    /// noisy. With an even number of samples, the lower median is returned.
+    pub fn median_offset(&self) -> &TimeSnapshot {
+        let mut sorted: Vec<&TimeSnapshot> = self.samples.iter().collect();
+        sorted.sort_by_key(|s| s.offset_nanos());
+        sorted[(sorted.len() - 1) / 2]
+    }
+}
+
+/// Leap indicator from the NTP packet header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LeapIndicator {
+    /// No leap second pending.
+    #[default]
+    NoWarning,
+
+    /// The last minute of the day has 61 seconds.
+    InsertSecond,
+
+    /// The last minute of the day has 59 seconds.
+    DeleteSecond,
+
+    /// The server clock is not synchronized.
+    Unsynchronized,
+}
+
+impl LeapIndicator {
+    /// Decode the two leap indicator bits.
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => LeapIndicator::NoWarning,
+            1 => LeapIndicator::InsertSecond,
+            2 => LeapIndicator::DeleteSecond,
+            _ => LeapIndicator::Unsynchronized,
+        }
+    }
+
+    /// Whether a leap second is announced for the end of the current month.
+    pub fn is_pending(self) -> bool {
+        matches!(
+            self,
+            LeapIndicator::InsertSecond | LeapIndicator::DeleteSecond
+        )
+    }
+}
+
+/// Server quality information from the NTP response header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ServerInfo {
+    /// Leap second warning and synchronization status.
+    pub leap_indicator: LeapIndicator,
+
+    /// Stratum of the server (1 = primary reference, 2-15 = secondary).
+    pub stratum: u8,
+
+    /// Raw reference identifier.
+    pub reference_id: [u8; 4],
+
+    /// Total round-trip delay from the server to the primary reference.
+    pub root_delay: std::time::Duration,
+
+    /// Total dispersion from the server to the primary reference.
+    pub root_dispersion: std::time::Duration,
+}
+
+impl ServerInfo {
+    /// Format the reference identifier for display.
+    ///
+    /// For stratum 0 and 1 this is an ASCII code (e.g. `GPS`, `RATE`);
+    /// for higher strata it is shown as an IPv4 address.
+    pub fn reference_id_string(&self) -> String {
+        if self.stratum <= 1 {
+            String::from_utf8_lossy(&self.reference_id)
+                .trim_end_matches('\0')
+                .to_string()
+        } else {
+            std::net::Ipv4Addr::from(self.reference_id).to_string()
+        }
+    }
+}
+
+/// Query rate-limiting state, driven by Kiss-o'-Death `RATE` responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RateLimitState {
+    /// Minimum interval the client waits between two queries.
+    pub min_interval: std::time::Duration,
+
+    /// Number of `RATE` kiss codes received so far.
+    pub rate_kisses: u32,
+}
+
+impl RateLimitState {
+    /// Interval used after the first `RATE` kiss code.
+    const INITIAL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+    /// Upper bound for the backoff interval (the NTP maximum poll of 2^10 s).
+    const MAX_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1024);
+
+    /// Record a `RATE` kiss code, doubling the minimum interval.
+    pub(crate) fn record_rate_kiss(&mut self) {
+        self.rate_kisses += 1;
+        self.min_interval = (self.min_interval * 2)
+            .max(Self::INITIAL_INTERVAL)
+            .min(Self::MAX_INTERVAL);
+    }
+}
+
+/// Metadata of an X.509 certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CertificateInfo {
+    /// Subject name, e.g. `C=US, O=Example, CN=time.example.com`.
+    pub subject: String,
+
+    /// Issuer name, in the same format as the subject.
+    pub issuer: String,
+
+    /// DNS names, IP addresses, emails and URIs from the subjectAltName extension.
+    pub subject_alt_names: Vec<String>,
+
+    /// Start of the validity period.
+    pub not_before: std::time::SystemTime,
+
+    /// End of the validity period.
+    pub not_after: std::time::SystemTime,
+}
+
+impl CertificateInfo {
+    /// Check if the certificate expires within `window` from now.
+    ///
+    /// Already expired certificates are included.
+    pub fn expires_within(&self, window: std::time::Duration) -> bool {
+        SystemTime::now()
+            .checked_add(window)
+            .map_or(true, |deadline| self.not_after <= deadline)
+    }
+}
+
+/// Details of the TLS session used for the NTS key exchange.
+///
+/// The serialized form omits the DER-encoded certificate chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TlsDetails {
+    /// Negotiated TLS version (e.g. `TLSv1_3`), if it could be determined.
+    pub protocol_version: Option<String>,
+
+    /// Negotiated cipher suite (e.g. `TLS13_AES_128_GCM_SHA256`), if it
+    /// could be determined.
+    pub cipher_suite: Option<String>,
+
+    /// ALPN protocol selected by the server, `None` if it selected none.
+    /// Only `ntske/1` is offered, and the handshake fails if the server
+    /// selects anything else.
+    pub alpn_protocol: Option<String>,
+
+    /// Whether an earlier TLS session was resumed.
+    pub session_resumed: bool,
+
+    /// DER-encoded certificate chain presented by the server, leaf first.
+    /// Empty when the session was resumed, as no certificate is sent then.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub peer_certificates: Vec<rustls::pki_types::CertificateDer<'static>>,
+
+    /// Metadata of the server's leaf certificate, if one was presented.
+    pub leaf_certificate: Option<CertificateInfo>,
+
+    /// Whether the leaf certificate expires within the configured
+    /// [`cert_expiry_warning`](crate::NtsClientConfig::cert_expiry_warning) window.
+    pub certificate_expiring: bool,
+
+    /// Whether the certificate was only accepted by ignoring its validity
+    /// period, see [`NtsClientConfig::with_clock_skew_tolerance`](crate::NtsClientConfig::with_clock_skew_tolerance).
+    pub validity_ignored: bool,
+}
+
+/// How the key exchange handled the NTP server named by the NTS-KE server,
+/// see [`RedirectPolicy`](crate::RedirectPolicy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RedirectDecision {
+    /// The NTP server is the NTS-KE server, on the standard port.
+    #[default]
+    NotRedirected,
+
+    /// The redirect was followed under [`RedirectPolicy::Permissive`](crate::RedirectPolicy::Permissive).
+    Followed,
+
+    /// The redirect target is on the [`RedirectPolicy::Allowlist`](crate::RedirectPolicy::Allowlist).
+    Allowed,
+}
+
+impl RedirectDecision {
+    /// A short label: `not_redirected`, `followed` or `allowed`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RedirectDecision::NotRedirected => "not_redirected",
+            RedirectDecision::Followed => "followed",
+            RedirectDecision::Allowed => "allowed",
+        }
+    }
+}
+
+/// A record of the server's NTS-KE response (RFC 8915, section 4).
+///
+/// Only kept when [`NtsClientConfig::capture_ke_records`](crate::NtsClientConfig::capture_ke_records)
+/// is enabled, see [`NtsKeResult::ke_records`]. Cookies are not retained,
+/// only their length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum NtsKeRecord {
+    /// End of Message (type 0).
+    EndOfMessage,
+
+    /// NTS Next Protocol Negotiation (type 1): the accepted NTP protocol IDs.
+    NextProtocol {
+        /// Protocol IDs (0 for NTPv4).
+        protocol_ids: Vec<u16>,
+    },
+
+    /// Error (type 2).
+    Error {
+        /// Error code (0 unrecognized critical record, 1 bad request,
+        /// 2 internal server error).
+        code: u16,
+    },
+
+    /// Warning (type 3).
+    Warning {
+        /// Warning code.
+        code: u16,
+    },
+
+    /// AEAD Algorithm Negotiation (type 4): the selected AEAD algorithms.
+    AeadAlgorithm {
+        /// Whether the critical bit was set.
+        critical: bool,
+        /// AEAD algorithm IDs from the IANA registry.
+        algorithm_ids: Vec<u16>,
+    },
+
+    /// New Cookie for NTPv4 (type 5).
+    NewCookie {
+        /// Cookie length in bytes.
+        length: usize,
+    },
+
+    /// NTPv4 Server Negotiation (type 6).
+    Server {
+        /// Whether the critical bit was set.
+        critical: bool,
+        /// The advertised NTP server name or address.
+        name: String,
+    },
+
+    /// NTPv4 Port Negotiation (type 7).
+    Port {
+        /// Whether the critical bit was set.
+        critical: bool,
+        /// The advertised NTP port.
+        port: u16,
+    },
+
+    /// A record type this crate does not interpret.
+    Unknown {
+        /// Record type, without the critical bit.
+        record_type: u16,
+        /// Whether the critical bit was set.
+        critical: bool,
+        /// Record body.
+        data: Vec<u8>,
+    },
+}
+
+/// One record per line, e.g. `AEAD Algorithm (critical): 15`.
+impl std::fmt::Display for NtsKeRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn ids(f: &mut std::fmt::Formatter<'_>, ids: &[u16]) -> std::fmt::Result {
+            let ids: Vec<String> = ids.iter().map(ToString::to_string).collect();
+            write!(f, "{}", ids.join(", "))
+        }
+        fn critical(critical: bool) -> &'static str {
+            if critical {
+                " (critical)"
+            } else {
+                ""
+            }
+        }
+
+        match self {
+            Self::EndOfMessage => write!(f, "End of Message"),
+            Self::NextProtocol { protocol_ids } => {
+                write!(f, "Next Protocol: ")?;
+                ids(f, protocol_ids)
+            }
+            Self::Error { code } => write!(f, "Error: {}", code),
+            Self::Warning { code } => write!(f, "Warning: {}", code),
+            Self::AeadAlgorithm {
+                critical: c,
+                algorithm_ids,
+            } => {
+                write!(f, "AEAD Algorithm{}: ", critical(*c))?;
+                ids(f, algorithm_ids)
+            }
+            Self::NewCookie { length } => write!(f, "New Cookie: {} bytes", length),
+            Self::Server { critical: c, name } => write!(f, "Server{}: {}", critical(*c), name),
+            Self::Port { critical: c, port } => write!(f, "Port{}: {}", critical(*c), port),
+            Self::Unknown {
+                record_type,
+                critical: c,
+                data,
+            } => write!(
+                f,
+                "Record type {}{}: {} bytes",
+                record_type,
+                critical(*c),
+                data.len()
+            ),
+        }
+    }
+}
+
+/// NTS keys protecting NTP packets in both directions (RFC 8915, section 5.1).
+///
+/// Key bytes are zeroed when dropped and never printed by `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct NtsKeys {
+    aead_algorithm: u16,
+    c2s: Zeroizing<Vec<u8>>,
+    s2c: Zeroizing<Vec<u8>>,
+}
+
+impl NtsKeys {
+    /// AEAD_AES_SIV_CMAC_256 in the IANA AEAD registry (32-byte keys).
+    pub const AEAD_AES_SIV_CMAC_256: u16 = 15;
+
+    /// AEAD_AES_SIV_CMAC_512 in the IANA AEAD registry (64-byte keys).
+    pub const AEAD_AES_SIV_CMAC_512: u16 = 17;
+
+    /// Create key material for the given AEAD algorithm.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the algorithm is not supported or
+    /// a key does not have the length the algorithm requires.
+    pub fn new(aead_algorithm: u16, c2s: Vec<u8>, s2c: Vec<u8>) -> Result<Self> {
+        let (c2s, s2c) = (Zeroizing::new(c2s), Zeroizing::new(s2c));
+        let key_len = Self::key_len(aead_algorithm).ok_or_else(|| {
+            Error::InvalidConfig(format!("Unsupported AEAD algorithm: {}", aead_algorithm))
+        })?;
+        if c2s.len() != key_len || s2c.len() != key_len {
+            return Err(Error::InvalidConfig(format!(
+                "{} requires {}-byte keys",
+                Self::algorithm_name(aead_algorithm),
+                key_len
+            )));
+        }
+        Ok(Self {
+            aead_algorithm,
+            c2s,
+            s2c,
+        })
+    }
+
+    /// Key length in bytes of a supported AEAD algorithm.
+    pub(crate) fn key_len(aead_algorithm: u16) -> Option<usize> {
+        match aead_algorithm {
+            Self::AEAD_AES_SIV_CMAC_256 => Some(32),
+            Self::AEAD_AES_SIV_CMAC_512 => Some(64),
+            _ => None,
+        }
+    }
+
+    fn algorithm_name(aead_algorithm: u16) -> &'static str {
+        match aead_algorithm {
+            Self::AEAD_AES_SIV_CMAC_256 => "AEAD_AES_SIV_CMAC_256",
+            Self::AEAD_AES_SIV_CMAC_512 => "AEAD_AES_SIV_CMAC_512",
+            _ => "unknown",
+        }
+    }
+
+    /// The AEAD algorithm identifier.
+    pub fn aead_algorithm(&self) -> u16 {
+        self.aead_algorithm
+    }
+
+    /// The AEAD algorithm name, e.g. `AEAD_AES_SIV_CMAC_256`.
+    pub fn aead_algorithm_name(&self) -> &'static str {
+        Self::algorithm_name(self.aead_algorithm)
+    }
+
+    /// Encrypt `plaintext` with the C2S key, authenticating
+    /// `associated_data` (RFC 8915, section 5.6). Returns the random nonce
+    /// and the ciphertext.
+    pub(crate) fn seal(
+        &self,
+        associated_data: &[u8],
+        plaintext: &[u8],
+    ) -> Result<([u8; 16], Vec<u8>)> {
+        let nonce: [u8; 16] = rand::random();
+        let ciphertext = self
+            .siv(&self.c2s, [associated_data, &nonce], plaintext, true)
+            .ok_or_else(|| Error::Other("NTS encryption failed".to_string()))?;
+        Ok((nonce, ciphertext))
+    }
+
+    /// Decrypt `ciphertext` with the S2C key, checking that it
+    /// authenticates `associated_data`. `None` if it does not verify.
+    pub(crate) fn open(
+        &self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        associated_data: &[u8],
+    ) -> Option<Vec<u8>> {
+        self.siv(&self.s2c, [associated_data, nonce], ciphertext, false)
+    }
+
+    fn siv(&self, key: &[u8], headers: [&[u8]; 2], data: &[u8], encrypt: bool) -> Option<Vec<u8>> {
+        use aes_siv::siv::{Aes128Siv, Aes256Siv};
+        use aes_siv::KeyInit;
+
+        // AEAD_AES_SIV_CMAC_256 is AES-SIV with a 128-bit AES key, and so on
+        let result = if self.aead_algorithm == Self::AEAD_AES_SIV_CMAC_512 {
+            let mut siv = Aes256Siv::new_from_slice(key).ok()?;
+            if encrypt {
+                siv.encrypt(headers, data)
+            } else {
+                siv.decrypt(headers, data)
+            }
+        } else {
+            let mut siv = Aes128Siv::new_from_slice(key).ok()?;
+            if encrypt {
+                siv.encrypt(headers, data)
+            } else {
+                siv.decrypt(headers, data)
+            }
+        };
+        result.ok()
+    }
+
+    /// The client-to-server key.
+    pub(crate) fn c2s(&self) -> &[u8] {
+        &self.c2s
+    }
+
+    /// The server-to-client key.
+    pub(crate) fn s2c(&self) -> &[u8] {
+        &self.s2c
+    }
+}
+
+impl std::fmt::Debug for NtsKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NtsKeys")
+            .field("aead_algorithm", &self.aead_algorithm_name())
+            .field("c2s", &"[redacted]")
+            .field("s2c", &"[redacted]")
+            .finish()
+    }
 }
 
 /// NTS key exchange result containing the negotiated parameters.
+///
+/// With the `serde` feature, this can be serialized for reports. Keys and
+/// cookies are never serialized; use `NtsClient::save_state` to persist
+/// them.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct NtsKeResult {
     /// The NTP server to use for time queries.
     pub ntp_server: std::net::SocketAddr,
@@ -59,38 +892,125 @@ pub struct NtsKeResult {
     /// The negotiated AEAD algorithm.
     pub aead_algorithm: String,
 
+    /// The NTS-KE server address the key exchange succeeded with.
+    pub ke_server: std::net::SocketAddr,
+
     /// Cookies for NTS authentication.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub(crate) cookies: Vec<Vec<u8>>,
 
     /// Duration of the NTS-KE handshake (for diagnostics).
     pub(crate) ke_duration: std::time::Duration,
 
-    /// The actual NTS data from ntp-proto (contains keys and cookies).
-    /// Note: Currently stored for future use with proper NTS authentication.
-    /// Will be used when transitioning from manual NTP packet construction
-    /// to ntp-proto's full client implementation.
-    #[allow(dead_code)]
-    pub(crate) nts_data: Box<ntp_proto::SourceNtsData>,
+    /// Per-phase timings of the key exchange.
+    pub(crate) timings: TimingBreakdown,
+
+    /// TLS session details of the key exchange.
+    pub(crate) tls: TlsDetails,
+
+    /// Negotiated NTP version number.
+    pub(crate) protocol_version: u8,
+
+    /// How the NTP server named by the NTS-KE server was handled.
+    pub(crate) redirect: RedirectDecision,
+
+    /// Records of the server's response, when captured.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub(crate) ke_records: Option<Vec<NtsKeRecord>>,
+
+    /// The C2S/S2C keys, either negotiated or pre-shared.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) keys: NtsKeys,
+}
+
+/// A multi-line, human-readable report of the key exchange.
+///
+/// ```text
+/// NTP server:      162.159.200.1:123
+/// NTS-KE server:   162.159.200.1:4460
+/// AEAD algorithm:  AEAD_AES_SIV_CMAC_256
+/// NTP version:     4
+/// Cookies:         8
+/// Duration:        123.456 ms
+/// TLS:             TLSv1_3, TLS13_AES_128_GCM_SHA256
+/// Certificate:     CN=time.cloudflare.com (issuer C=US, O=DigiCert Inc, CN=...)
+/// ```
+impl std::fmt::Display for NtsKeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "NTP server:      {}", self.ntp_server)?;
+        writeln!(f, "NTS-KE server:   {}", self.ke_server)?;
+        writeln!(f, "AEAD algorithm:  {}", self.aead_algorithm)?;
+        writeln!(f, "NTP version:     {}", self.protocol_version)?;
+        writeln!(f, "Cookies:         {}", self.cookies.len())?;
+        write!(f, "Duration:        {}", Millis(self.ke_duration))?;
+
+        let tls = &self.tls;
+        if let (Some(version), Some(suite)) = (&tls.protocol_version, &tls.cipher_suite) {
+            write!(f, "\nTLS:             {}, {}", version, suite)?;
+            if tls.session_resumed {
+                write!(f, " (resumed)")?;
+            }
+        }
+        if let Some(leaf) = &tls.leaf_certificate {
+            write!(
+                f,
+                "\nCertificate:     {} (issuer {})",
+                leaf.subject, leaf.issuer
+            )?;
+            if tls.certificate_expiring {
+                write!(
+                    f,
+                    "\n                 expires soon, not after {} (Unix)",
+                    UnixTime(leaf.not_after)
+                )?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl NtsKeResult {
     /// Create a new NtsKeResult from ntp-proto's KeyExchangeResult.
     pub(crate) fn new(
         ntp_server: std::net::SocketAddr,
-        aead_algorithm: String,
+        ke_server: std::net::SocketAddr,
         cookies: Vec<Vec<u8>>,
         ke_duration: std::time::Duration,
-        nts_data: Box<ntp_proto::SourceNtsData>,
+        keys: NtsKeys,
     ) -> Self {
         Self {
             ntp_server,
-            aead_algorithm,
+            aead_algorithm: keys.aead_algorithm_name().to_string(),
+            ke_server,
             cookies,
             ke_duration,
-            nts_data,
+            timings: TimingBreakdown::default(),
+            tls: TlsDetails::default(),
+            protocol_version: 4,
+            redirect: RedirectDecision::NotRedirected,
+            ke_records: None,
+            keys,
         }
     }
 
+    /// Create an NtsKeResult from pre-shared keys and cookies.
+    ///
+    /// There is no key exchange server in this mode; `ke_server` is set to
+    /// the NTP server.
+    pub(crate) fn from_fixed_keys(
+        ntp_server: std::net::SocketAddr,
+        keys: NtsKeys,
+        cookies: Vec<Vec<u8>>,
+    ) -> Self {
+        Self::new(
+            ntp_server,
+            ntp_server,
+            cookies,
+            std::time::Duration::ZERO,
+            keys,
+        )
+    }
+
     /// Get the number of available cookies.
     pub fn cookie_count(&self) -> usize {
         self.cookies.len()
@@ -116,6 +1036,41 @@ impl NtsKeResult {
         self.ke_duration
     }
 
+    /// Get the NTP protocol version negotiated during the key exchange
+    /// (4 or 5).
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol_version
+    }
+
+    /// Get the TLS session details of the key exchange.
+    ///
+    /// Includes the negotiated TLS version and cipher suite and the server's
+    /// certificate chain, for security reports.
+    pub fn tls_details(&self) -> &TlsDetails {
+        &self.tls
+    }
+
+    /// Get how the key exchange handled the NTP server named by the NTS-KE
+    /// server.
+    pub fn redirect(&self) -> RedirectDecision {
+        self.redirect
+    }
+
+    /// Get the key under which a [`CookieStore`](crate::CookieStore) keeps
+    /// the cookies of this key exchange.
+    pub fn association(&self) -> crate::Association {
+        crate::Association::new(self.ntp_server, &self.keys)
+    }
+
+    /// Get the records of the server's NTS-KE response, in the order they
+    /// were received.
+    ///
+    /// Returns `None` unless [`NtsClientConfig::with_ke_record_capture`](crate::NtsClientConfig::with_ke_record_capture)
+    /// was enabled, or if the response could not be decoded.
+    pub fn ke_records(&self) -> Option<&[NtsKeRecord]> {
+        self.ke_records.as_deref()
+    }
+
     /// Get a reference to the cookies (for diagnostic purposes).
     ///
     /// Returns cookie data as byte slices. Useful for verbose diagnostic
@@ -125,6 +1080,33 @@ impl NtsKeResult {
     }
 }
 
+#[cfg(test)]
+impl TimeSnapshot {
+    /// A snapshot taken at `system_time` with `clock_offset`, for tests.
+    /// The network time and the deprecated `offset` are derived from the
+    /// offset; set other fields with struct update syntax.
+    pub(crate) fn for_test(system_time: SystemTime, clock_offset: SignedDuration) -> Self {
+        let network_time = if clock_offset.is_negative() {
+            system_time + clock_offset.abs()
+        } else {
+            system_time - clock_offset.abs()
+        };
+        #[allow(deprecated)]
+        Self {
+            system_time,
+            measured_at: Instant::now(),
+            network_time,
+            clock_offset,
+            offset: clock_offset.abs(),
+            round_trip_delay: Duration::ZERO,
+            server: "127.0.0.1:123".to_string(),
+            authenticated: true,
+            server_info: ServerInfo::default(),
+            bootstrap: false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,17 +1114,10 @@ mod tests {
 
     #[test]
     fn test_time_snapshot_offset_signed_ahead() {
-        let network_time = SystemTime::now();
-        let system_time = network_time + Duration::from_secs(10);
-
-        let snapshot = TimeSnapshot {
-            system_time,
-            network_time,
-            offset: Duration::from_secs(10),
-            round_trip_delay: Duration::from_millis(50),
-            server: "test.server".to_string(),
-            authenticated: true,
-        };
+        let snapshot = TimeSnapshot::for_test(
+            SystemTime::now(),
+            SignedDuration::from_nanos(10_000_000_000),
+        );
 
         assert!(snapshot.offset_signed() > 0);
         assert!(snapshot.is_ahead());
@@ -150,29 +1125,143 @@ mod tests {
     }
 
     #[test]
-    fn test_time_snapshot_offset_signed_behind() {
-        let system_time = SystemTime::now();
-        let network_time = system_time + Duration::from_secs(5);
+    fn test_filtered_time_selection() {
+        let now = SystemTime::now();
+        let sample = |offset_ms: i64, delay_ms: u64| TimeSnapshot {
+            round_trip_delay: Duration::from_millis(delay_ms),
+            ..TimeSnapshot::for_test(
+                now,
+                SignedDuration::from_nanos(offset_ms as i128 * 1_000_000),
+            )
+        };
+
+        assert!(FilteredTime::from_samples(Vec::new(), 3).is_none());
+
+        let filtered = FilteredTime::from_samples(
+            vec![
+                sample(40, 30),
+                sample(-3, 5),
+                sample(2, 12),
+                sample(-90, 80),
+            ],
+            1,
+        )
+        .unwrap();
+        assert_eq!(filtered.chosen.round_trip_delay, Duration::from_millis(5));
+        assert_eq!(filtered.samples.len(), 4);
+        assert_eq!(filtered.failures, 1);
+        // Offsets sorted: -90, -3, 2, 40
+        assert_eq!(filtered.median_offset().offset_signed(), -3);
+    }
 
+    #[test]
+    fn test_display_reports() {
+        let system_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
         let snapshot = TimeSnapshot {
-            system_time,
-            network_time,
-            offset: Duration::from_secs(5),
-            round_trip_delay: Duration::from_millis(50),
-            server: "test.server".to_string(),
-            authenticated: true,
+            round_trip_delay: Duration::from_millis(20),
+            server_info: ServerInfo {
+                stratum: 1,
+                reference_id: *b"GPS\0",
+                ..ServerInfo::default()
+            },
+            ..TimeSnapshot::for_test(system_time, SignedDuration::from_nanos(-1_500_000))
         };
+        let report = snapshot.to_string();
+        assert!(report.starts_with("Server:          127.0.0.1:123\n"));
+        assert!(report.contains("Offset:          -1.500 ms (system clock behind)\n"));
+        assert!(report.contains("Round-trip:      20.000 ms\n"));
+        assert!(report.contains("Stratum:         1 (reference GPS)\n"));
+        assert!(report.ends_with("Network time:    1700000000.001500000 (Unix)"));
+
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 32], vec![1; 32]).unwrap();
+        let result =
+            NtsKeResult::from_fixed_keys("127.0.0.1:123".parse().unwrap(), keys, vec![vec![0; 64]]);
+        let report = result.to_string();
+        assert!(report.contains("AEAD algorithm:  AEAD_AES_SIV_CMAC_256\n"));
+        assert!(report.contains("Cookies:         1\n"));
+        assert!(!report.contains("TLS:"));
+    }
+
+    #[test]
+    fn test_time_snapshot_offset_signed_behind() {
+        let snapshot = TimeSnapshot::for_test(
+            SystemTime::now(),
+            SignedDuration::from_nanos(-5_000_000_000),
+        );
 
         assert!(snapshot.offset_signed() < 0);
         assert!(!snapshot.is_ahead());
         assert!(snapshot.is_behind());
     }
 
+    #[test]
+    fn test_time_snapshot_nanos() {
+        let snapshot = TimeSnapshot {
+            round_trip_delay: Duration::from_nanos(180_500),
+            ..TimeSnapshot::for_test(SystemTime::now(), SignedDuration::from_nanos(250_000))
+        };
+
+        assert_eq!(snapshot.offset_signed(), 0);
+        assert_eq!(snapshot.offset_nanos(), 250_000);
+        assert_eq!(snapshot.rtt_nanos(), 180_500);
+    }
+
+    #[test]
+    fn test_time_snapshot_age() {
+        let clock = crate::test_util::SimulatedClock::new(SystemTime::now());
+        let snapshot = TimeSnapshot {
+            measured_at: clock.instant(),
+            ..TimeSnapshot::for_test(clock.now(), SignedDuration::ZERO)
+        };
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(snapshot.age(&clock), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_reference_id_string() {
+        let mut info = ServerInfo {
+            stratum: 1,
+            reference_id: *b"GPS\0",
+            ..Default::default()
+        };
+        assert_eq!(info.reference_id_string(), "GPS");
+
+        info.stratum = 2;
+        info.reference_id = [192, 0, 2, 1];
+        assert_eq!(info.reference_id_string(), "192.0.2.1");
+    }
+
+    #[test]
+    fn test_leap_indicator_from_bits() {
+        assert_eq!(LeapIndicator::from_bits(0), LeapIndicator::NoWarning);
+        assert_eq!(LeapIndicator::from_bits(1), LeapIndicator::InsertSecond);
+        assert_eq!(LeapIndicator::from_bits(2), LeapIndicator::DeleteSecond);
+        assert_eq!(LeapIndicator::from_bits(3), LeapIndicator::Unsynchronized);
+    }
+
+    #[test]
+    fn test_rate_limit_backoff() {
+        let mut state = RateLimitState::default();
+        assert_eq!(state.min_interval, Duration::ZERO);
+
+        state.record_rate_kiss();
+        assert_eq!(state.min_interval, Duration::from_secs(2));
+        state.record_rate_kiss();
+        assert_eq!(state.min_interval, Duration::from_secs(4));
+        assert_eq!(state.rate_kisses, 2);
+
+        for _ in 0..20 {
+            state.record_rate_kiss();
+        }
+        assert_eq!(state.min_interval, Duration::from_secs(1024));
+    }
+
     #[test]
     fn test_nts_ke_result_cookie_count() {
         // Test cookie_count and has_cookies without creating full NtsKeResult
         // since SourceNtsData doesn't have a public constructor
-        let cookies = vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8, 9]];
+        let cookies = [vec![1, 2, 3, 4], vec![5, 6, 7, 8, 9]];
         assert_eq!(cookies.len(), 2);
         assert!(!cookies.is_empty());
 
@@ -186,4 +1275,118 @@ mod tests {
         assert_eq!(cookies.len(), 0);
         assert!(cookies.is_empty());
     }
+
+    #[test]
+    fn test_nts_keys_validation() {
+        let keys = NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_512, vec![7; 64], vec![9; 64]).unwrap();
+        assert_eq!(keys.aead_algorithm(), 17);
+        assert_eq!(keys.aead_algorithm_name(), "AEAD_AES_SIV_CMAC_512");
+        assert_eq!(keys.c2s(), &[7; 64][..]);
+        assert_eq!(keys.s2c(), &[9; 64][..]);
+
+        assert!(NtsKeys::new(NtsKeys::AEAD_AES_SIV_CMAC_256, vec![0; 64], vec![0; 32]).is_err());
+        assert!(NtsKeys::new(30, vec![0; 32], vec![0; 32]).is_err());
+    }
+
+    #[test]
+    fn test_nts_keys_debug_redacted() {
+        let keys = NtsKeys::new(
+            NtsKeys::AEAD_AES_SIV_CMAC_256,
+            vec![0xAA; 32],
+            vec![0xBB; 32],
+        )
+        .unwrap();
+        let debug = format!("{:?}", keys);
+        assert!(debug.contains("AEAD_AES_SIV_CMAC_256"));
+        assert!(debug.contains("redacted"));
+        assert!(!debug.contains("170"));
+    }
+
+    #[test]
+    fn test_nts_keys_seal_and_open() {
+        for (algorithm, len) in [
+            (NtsKeys::AEAD_AES_SIV_CMAC_256, 32),
+            (NtsKeys::AEAD_AES_SIV_CMAC_512, 64),
+        ] {
+            let client = NtsKeys::new(algorithm, vec![1; len], vec![2; len]).unwrap();
+            // The server encrypts with the S2C key and decrypts with C2S
+            let server = NtsKeys::new(algorithm, vec![2; len], vec![1; len]).unwrap();
+
+            let (nonce, ciphertext) = client.seal(b"header", b"secret").unwrap();
+            assert_eq!(ciphertext.len(), 16 + 6);
+            assert_eq!(
+                server.open(&nonce, &ciphertext, b"header").as_deref(),
+                Some(&b"secret"[..])
+            );
+            assert_eq!(server.open(&nonce, &ciphertext, b"Header"), None);
+            assert_eq!(client.open(&nonce, &ciphertext, b"header"), None);
+        }
+    }
+
+    #[test]
+    fn test_nts_ke_record_display() {
+        let aead = NtsKeRecord::AeadAlgorithm {
+            critical: true,
+            algorithm_ids: vec![15, 17],
+        };
+        assert_eq!(aead.to_string(), "AEAD Algorithm (critical): 15, 17");
+        let port = NtsKeRecord::Port {
+            critical: false,
+            port: 123,
+        };
+        assert_eq!(port.to_string(), "Port: 123");
+        let unknown = NtsKeRecord::Unknown {
+            record_type: 0x4008,
+            critical: false,
+            data: vec![0; 3],
+        };
+        assert_eq!(unknown.to_string(), "Record type 16392: 3 bytes");
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_ke_result_serializes_without_secrets() {
+        let keys = NtsKeys::new(
+            NtsKeys::AEAD_AES_SIV_CMAC_256,
+            vec![0xAA; 32],
+            vec![0xBB; 32],
+        )
+        .unwrap();
+        let result = NtsKeResult::from_fixed_keys(
+            "127.0.0.1:123".parse().unwrap(),
+            keys,
+            vec![vec![0xCC; 64]],
+        );
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains("\"aead_algorithm\":\"AEAD_AES_SIV_CMAC_256\""));
+        assert!(json.contains("\"tls\":{"));
+        assert!(!json.contains("keys"));
+        assert!(!json.contains("cookies"));
+        assert!(!json.contains("170") && !json.contains("204"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_datetimes() {
+        let system_time = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let snapshot = TimeSnapshot {
+            round_trip_delay: Duration::from_millis(20),
+            ..TimeSnapshot::for_test(system_time, SignedDuration::from_nanos(-1_000_000_000))
+        };
+        assert_eq!(snapshot.system_datetime().timestamp(), 1_700_000_000);
+        assert_eq!(snapshot.network_datetime().timestamp(), 1_700_000_001);
+        assert_eq!(
+            snapshot.network_datetime().timestamp_subsec_nanos(),
+            123_456_789
+        );
+
+        #[cfg(feature = "persistence")]
+        {
+            let json = serde_json::to_string(&snapshot).unwrap();
+            assert!(json.contains("\"system_time\":\"2023-11-14T22:13:20.123456789Z\""));
+            let restored: TimeSnapshot = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.network_time, snapshot.network_time);
+        }
+    }
 }
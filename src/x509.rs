@@ -0,0 +1,370 @@
+//! Minimal X.509 certificate parsing.
+//!
+//! Only the fields needed for pinning and diagnostics are extracted; the
+//! certificate is not validated here, rustls does that during the handshake.
+
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::types::CertificateInfo;
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_OID: u8 = 0x06;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_T61_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// id-ce-subjectAltName (2.5.29.17)
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// A single DER TLV and the input following it.
+struct Element<'a> {
+    tag: u8,
+    raw: &'a [u8],
+    content: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn parse(input: &[u8]) -> Option<Element<'_>> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let header = input.len() - rest.len();
+    Some(Element {
+        tag,
+        raw: &input[..header + len],
+        content: &rest[..len],
+        rest: &rest[len..],
+    })
+}
+
+/// Iterate over the elements of a constructed value.
+fn elements(mut input: &[u8]) -> impl Iterator<Item = Element<'_>> {
+    std::iter::from_fn(move || {
+        let element = parse(input)?;
+        input = element.rest;
+        Some(element)
+    })
+}
+
+/// The fields of a TBSCertificate up to and including the SubjectPublicKeyInfo.
+struct TbsCertificate<'a> {
+    issuer: &'a [u8],
+    validity: &'a [u8],
+    subject: &'a [u8],
+    spki: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn parse_tbs(cert: &[u8]) -> Option<TbsCertificate<'_>> {
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let certificate = parse(cert)?;
+    if certificate.tag != TAG_SEQUENCE {
+        return None;
+    }
+    let mut tbs = parse(certificate.content)?.content;
+
+    if tbs.first() == Some(&TAG_VERSION) {
+        tbs = parse(tbs)?.rest;
+    }
+    let serial = parse(tbs)?;
+    let signature = parse(serial.rest)?;
+    let issuer = parse(signature.rest)?;
+    let validity = parse(issuer.rest)?;
+    let subject = parse(validity.rest)?;
+    let spki = parse(subject.rest)?;
+    if spki.tag != TAG_SEQUENCE {
+        return None;
+    }
+
+    Some(TbsCertificate {
+        issuer: issuer.content,
+        validity: validity.content,
+        subject: subject.content,
+        spki: spki.raw,
+        rest: spki.rest,
+    })
+}
+
+/// Compute the SHA-256 hash of a certificate's DER-encoded SubjectPublicKeyInfo.
+///
+/// Returns None if the certificate cannot be parsed.
+pub(crate) fn spki_sha256(cert: &[u8]) -> Option<[u8; 32]> {
+    let tbs = parse_tbs(cert)?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, tbs.spki);
+    digest.as_ref().try_into().ok()
+}
+
+/// Extract subject, issuer, validity and subject alternative names.
+///
+/// Returns None if the certificate cannot be parsed.
+pub(crate) fn parse_certificate(cert: &[u8]) -> Option<CertificateInfo> {
+    let tbs = parse_tbs(cert)?;
+
+    let not_before = parse(tbs.validity)?;
+    let not_after = parse(not_before.rest)?;
+
+    let mut subject_alt_names = Vec::new();
+    // Skip the optional issuerUniqueID and subjectUniqueID
+    if let Some(extensions) = elements(tbs.rest).find(|e| e.tag == TAG_EXTENSIONS) {
+        for extension in elements(parse(extensions.content)?.content) {
+            let mut fields = elements(extension.content);
+            let oid = fields.next()?;
+            if oid.tag != TAG_OID || oid.content != OID_SUBJECT_ALT_NAME {
+                continue;
+            }
+            // Skip the optional critical flag
+            let value = fields.find(|e| e.tag == TAG_OCTET_STRING)?;
+            subject_alt_names = parse_general_names(parse(value.content)?.content);
+        }
+    }
+
+    Some(CertificateInfo {
+        subject: parse_name(tbs.subject)?,
+        issuer: parse_name(tbs.issuer)?,
+        subject_alt_names,
+        not_before: parse_time(&not_before)?,
+        not_after: parse_time(&not_after)?,
+    })
+}
+
+/// Format a Name as comma-separated `attribute=value` pairs.
+fn parse_name(name: &[u8]) -> Option<String> {
+    let mut parts = Vec::new();
+    for rdn in elements(name) {
+        for attribute in elements(rdn.content) {
+            let oid = parse(attribute.content)?;
+            let value = parse(oid.rest)?;
+            parts.push(format!(
+                "{}={}",
+                attribute_name(oid.content),
+                decode_string(&value)
+            ));
+        }
+    }
+    Some(parts.join(", "))
+}
+
+/// Short name of an X.520 attribute type, or its dotted OID.
+fn attribute_name(oid: &[u8]) -> String {
+    match oid {
+        [0x55, 0x04, 0x03] => "CN".to_string(),
+        [0x55, 0x04, 0x06] => "C".to_string(),
+        [0x55, 0x04, 0x07] => "L".to_string(),
+        [0x55, 0x04, 0x08] => "ST".to_string(),
+        [0x55, 0x04, 0x0a] => "O".to_string(),
+        [0x55, 0x04, 0x0b] => "OU".to_string(),
+        _ => format_oid(oid),
+    }
+}
+
+fn format_oid(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut value: u64 = 0;
+    for &byte in oid {
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (value / 40).min(2);
+                arcs.push(first);
+                arcs.push(value - first * 40);
+            } else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+    arcs.iter()
+        .map(|arc| arc.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn decode_string(value: &Element<'_>) -> String {
+    match value.tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => {
+            String::from_utf8_lossy(value.content).into_owned()
+        }
+        TAG_T61_STRING => value.content.iter().map(|&b| b as char).collect(),
+        TAG_BMP_STRING => {
+            let units: Vec<u16> = value
+                .content
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => value.content.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+/// Format the DNS names, IP addresses, emails and URIs of a GeneralNames value.
+fn parse_general_names(names: &[u8]) -> Vec<String> {
+    elements(names)
+        .filter_map(|name| match name.tag {
+            // rfc822Name, dNSName, uniformResourceIdentifier
+            0x81 | 0x82 | 0x86 => Some(String::from_utf8_lossy(name.content).into_owned()),
+            // iPAddress
+            0x87 => match name.content.len() {
+                4 => {
+                    let octets: [u8; 4] = name.content.try_into().ok()?;
+                    Some(IpAddr::from(octets).to_string())
+                }
+                16 => {
+                    let octets: [u8; 16] = name.content.try_into().ok()?;
+                    Some(IpAddr::from(octets).to_string())
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parse a UTCTime or GeneralizedTime in the `Z` form required by RFC 5280.
+fn parse_time(time: &Element<'_>) -> Option<SystemTime> {
+    let text = std::str::from_utf8(time.content).ok()?;
+    let text = text.strip_suffix('Z')?;
+    let (year, rest) = match time.tag {
+        TAG_UTC_TIME => {
+            let yy: i64 = text.get(..2)?.parse().ok()?;
+            (if yy < 50 { 2000 + yy } else { 1900 + yy }, text.get(2..)?)
+        }
+        TAG_GENERALIZED_TIME => (text.get(..4)?.parse().ok()?, text.get(4..)?),
+        _ => return None,
+    };
+    if rest.len() != 10 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize| rest[i..i + 2].parse::<i64>().ok();
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+
+    // Days since the epoch for a proleptic Gregorian date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+
+    const TEST_CERT: &str = "\
+-----BEGIN CERTIFICATE-----
+MIICBDCCAaqgAwIBAgIUPxEup5LlDe5/d2+QbnVnHJQJZ/owCgYIKoZIzj0EAwIw
+NzELMAkGA1UEBhMCRlIxEjAQBgNVBAoMCXJraWsgdGVzdDEUMBIGA1UEAwwLbnRz
+LmV4YW1wbGUwHhcNMjYxMDE3MDMzNDE3WhcNMzYxMDE0MDMzNDE3WjA3MQswCQYD
+VQQGEwJGUjESMBAGA1UECgwJcmtpayB0ZXN0MRQwEgYDVQQDDAtudHMuZXhhbXBs
+ZTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABGqWxr2KBL9Yl/z43RodKfmM4oAb
+l1PsO206eaEt+NTm1Zfx5oN+zsJKUm+Q6C9hqbAJbwIZqBpGj6UXzo4ZSJmjgZMw
+gZAwHQYDVR0OBBYEFAri/J1P0nBuv1o5eGwdMrancaa9MB8GA1UdIwQYMBaAFAri
+/J1P0nBuv1o5eGwdMrancaa9MA8GA1UdEwEB/wQFMAMBAf8wPQYDVR0RBDYwNIIL
+bnRzLmV4YW1wbGWCDSoubnRzLmV4YW1wbGWHBMAAAgGHECABDbgAAAAAAAAAAAAA
+AAEwCgYIKoZIzj0EAwIDSAAwRQIhAJ1xn7mEMK+qLkhycxEFoCi69mUdjgaY0+/e
+TYl8rA/2AiBNkNGZiK3nCeNwmHk2ZDUw5Egkh3/uIwendCz9P0RkOA==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_parse_certificate() {
+        let cert = CertificateDer::from_pem_slice(TEST_CERT.as_bytes()).unwrap();
+        let info = parse_certificate(&cert).unwrap();
+
+        assert_eq!(info.subject, "C=FR, O=rkik test, CN=nts.example");
+        assert_eq!(info.issuer, info.subject);
+        assert_eq!(
+            info.subject_alt_names,
+            vec!["nts.example", "*.nts.example", "192.0.2.1", "2001:db8::1"]
+        );
+        assert_eq!(
+            info.not_before,
+            UNIX_EPOCH + Duration::from_secs(1_792_208_057)
+        );
+        assert_eq!(
+            info.not_after,
+            UNIX_EPOCH + Duration::from_secs(2_107_568_057)
+        );
+    }
+
+    #[test]
+    fn test_parse_certificate_rejects_garbage() {
+        assert!(parse_certificate(&[]).is_none());
+        assert!(parse_certificate(&[0x30, 0x03, 0x02, 0x01, 0x00]).is_none());
+    }
+
+    #[test]
+    fn test_format_oid() {
+        // 1.2.840.113549 (RSA Data Security)
+        assert_eq!(
+            format_oid(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d]),
+            "1.2.840.113549"
+        );
+        assert_eq!(attribute_name(&[0x55, 0x04, 0x03]), "CN");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let utc = parse(&[
+            TAG_UTC_TIME,
+            13,
+            b'7',
+            b'0',
+            b'0',
+            b'1',
+            b'0',
+            b'2',
+            b'0',
+            b'0',
+            b'0',
+            b'0',
+            b'0',
+            b'0',
+            b'Z',
+        ])
+        .unwrap();
+        assert_eq!(
+            parse_time(&utc),
+            Some(UNIX_EPOCH + Duration::from_secs(86400))
+        );
+
+        let generalized = parse(b"\x18\x0f20000301000000Z").unwrap();
+        assert_eq!(
+            parse_time(&generalized),
+            Some(UNIX_EPOCH + Duration::from_secs(951_868_800))
+        );
+    }
+}
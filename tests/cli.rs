@@ -0,0 +1,125 @@
+//! Tests of the `rkik-nts` command-line tool against the mock server.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+use rkik_nts::test_util::{MockBehavior, MockServer};
+use rkik_nts::SignedDuration;
+use serde_json::Value;
+
+/// Run `rkik-nts` with `args`, trusting the mock server's CA.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rkik-nts"))
+        .args(args)
+        .arg("--ca-file")
+        .arg(ca_file())
+        .args(["--timeout", "2"])
+        .output()
+        .unwrap()
+}
+
+fn ca_file() -> &'static PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("rkik-nts-cli-{}.pem", std::process::id()));
+        std::fs::write(&path, MockServer::ca_certificate_pem()).unwrap();
+        path
+    })
+}
+
+fn server_arg(server: &MockServer) -> String {
+    format!("localhost:{}", server.ke_addr().port())
+}
+
+fn json_lines(output: &Output) -> Vec<Value> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_query() {
+    let server = MockServer::start().unwrap();
+    let output = run(&["query", &server_arg(&server)]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Authenticated:   yes"), "{stdout}");
+
+    let output = run(&["query", &server_arg(&server), "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let json = &json_lines(&output)[0];
+    assert_eq!(json["in_sync"], true);
+    assert_eq!(json["time"]["authenticated"], true);
+}
+
+#[test]
+fn test_query_out_of_sync() {
+    let server = MockServer::start_with(
+        MockBehavior::new().with_clock_offset(SignedDuration::from_nanos(500_000_000)),
+    )
+    .unwrap();
+    let output = run(&["query", &server_arg(&server), "--json"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(json_lines(&output)[0]["in_sync"], false);
+
+    let output = run(&["query", &server_arg(&server), "--max-offset", "1000"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_query_failure() {
+    let output = run(&["query", "127.0.0.1:1", "--json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(json_lines(&output)[0]["error"]["kind"], "io");
+
+    let output = run(&["query"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_key_exchange() {
+    let server = MockServer::start().unwrap();
+    let output = run(&["ke", &server_arg(&server), "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let json = &json_lines(&output)[0];
+    assert_eq!(
+        json["key_exchange"]["ntp_server"],
+        server.ntp_addr().to_string()
+    );
+}
+
+#[test]
+fn test_monitor() {
+    let server = MockServer::start().unwrap();
+    let output = run(&[
+        "monitor",
+        &server_arg(&server),
+        "--interval",
+        "0.05",
+        "--count",
+        "3",
+        "--json",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let samples = json_lines(&output);
+    assert_eq!(samples.len(), 3);
+    assert!(samples.iter().all(|sample| sample["in_sync"] == true));
+}
+
+#[test]
+fn test_compare() {
+    let server = MockServer::start().unwrap();
+    let output = run(&["compare", &server_arg(&server), "127.0.0.1:1", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let json = &json_lines(&output)[0];
+    let servers = json["servers"].as_array().unwrap();
+    assert_eq!(servers[0]["time"]["authenticated"], true);
+    assert_eq!(servers[1]["error"]["kind"], "io");
+    assert_eq!(json["spread_ms"], 0.0);
+
+    let output = run(&["compare", &server_arg(&server), &server_arg(&server)]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Spread:"));
+}
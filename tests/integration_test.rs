@@ -1,6 +1,10 @@
 //! Integration tests for rkik-nts library.
 
-use rkik_nts::{NtsClient, NtsClientConfig};
+use rkik_nts::sim::{replay, Session};
+use rkik_nts::test_util::{MockBehavior, MockServer};
+use rkik_nts::{
+    Error, LeapIndicator, LeapSecondHandling, NtsClient, NtsClientConfig, TimeSnapshot,
+};
 use std::time::Duration;
 
 #[test]
@@ -41,6 +45,104 @@ fn test_client_creation() {
 
     assert!(!client.is_connected());
     assert!(client.ntp_server().is_none());
+    assert_eq!(client.timings(), rkik_nts::TimingBreakdown::default());
+}
+
+#[tokio::test]
+async fn test_mock_server_session() {
+    let server = MockServer::start().unwrap();
+    let client = NtsClient::new(server.client_config().with_max_retries(0));
+
+    client.connect().await.unwrap();
+    assert_eq!(client.ntp_server(), Some(server.ntp_addr()));
+    assert!(client.nts_ke_info().unwrap().cookie_count() > 0);
+
+    for _ in 0..3 {
+        let time = client.get_time().await.unwrap();
+        assert!(time.authenticated);
+    }
+    assert_eq!(server.ntp_requests(), 3);
+}
+
+#[tokio::test]
+async fn test_mock_server_slow_response() {
+    let server =
+        MockServer::start_with(MockBehavior::new().with_ntp_delay(Duration::from_millis(300)))
+            .unwrap();
+    let client = NtsClient::new(
+        server
+            .client_config()
+            .with_query_timeout(Duration::from_millis(100))
+            .with_max_retries(0),
+    );
+    client.connect().await.unwrap();
+    assert!(client.get_time().await.is_err());
+
+    server.set_behavior(MockBehavior::new());
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(client.get_time().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_mock_server_leap_second() {
+    let server = MockServer::start_with(MockBehavior::new().with_leap_indicator(1)).unwrap();
+
+    let client = NtsClient::new(server.client_config().with_max_retries(0));
+    client.connect().await.unwrap();
+    let time = client.get_time().await.unwrap();
+    assert_eq!(time.leap_indicator(), LeapIndicator::InsertSecond);
+    let leap = time.next_leap_second().unwrap();
+    let until = leap.duration_since(time.network_time).unwrap();
+    assert!(until <= Duration::from_secs(31 * 86_400));
+
+    // Every measurement lies within a month of the announced leap second
+    let window = Duration::from_secs(32 * 86_400);
+    let client = NtsClient::new(
+        server
+            .client_config()
+            .with_max_retries(0)
+            .with_leap_second_handling(LeapSecondHandling::Reject(window)),
+    );
+    client.connect().await.unwrap();
+    let result = client.get_time().await;
+    assert!(matches!(result, Err(Error::LeapSecond { .. })));
+}
+
+/// Replay a recorded session from `tests/fixtures` with a 1 s query timeout.
+fn replay_fixture(name: &str) -> Vec<rkik_nts::Result<TimeSnapshot>> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let session: Session = std::fs::read_to_string(path).unwrap().parse().unwrap();
+    replay(&session, Duration::from_secs(1))
+}
+
+fn offset_ms(outcome: &rkik_nts::Result<TimeSnapshot>) -> f64 {
+    outcome.as_ref().unwrap().clock_offset.as_secs_f64() * 1e3
+}
+
+#[test]
+fn test_replay_fixtures() {
+    let outcomes = replay_fixture("duplicate_response.session");
+    assert_eq!(outcomes.len(), 1);
+    assert!((offset_ms(&outcomes[0]) + 250.0).abs() < 1e-3);
+    assert_eq!(
+        outcomes[0].as_ref().unwrap().round_trip_delay,
+        Duration::from_millis(20)
+    );
+
+    let outcomes = replay_fixture("late_response.session");
+    assert!(matches!(outcomes[0], Err(Error::Timeout)));
+    assert!(offset_ms(&outcomes[1]).abs() < 1e-3);
+
+    let outcomes = replay_fixture("interleaved_responses.session");
+    assert!((offset_ms(&outcomes[0]) - 500.0).abs() < 1e-3);
+    assert!(matches!(&outcomes[1], Err(Error::KissOfDeath { code }) if code == "RATE"));
+    assert!((offset_ms(&outcomes[2]) - 500.0).abs() < 1e-3);
+
+    let outcomes = replay_fixture("version_mismatch.session");
+    assert!(matches!(outcomes[0], Err(Error::InvalidResponse(_))));
+    assert!(matches!(outcomes[1], Err(Error::ServerUnsynchronized)));
 }
 
 // Note: The following tests require network connectivity and are marked as ignored by default.
@@ -51,7 +153,7 @@ fn test_client_creation() {
 async fn test_connect_to_cloudflare() {
     let config = NtsClientConfig::new("time.cloudflare.com").with_timeout(Duration::from_secs(10));
 
-    let mut client = NtsClient::new(config);
+    let client = NtsClient::new(config);
 
     match client.connect().await {
         Ok(_) => {
@@ -72,7 +174,7 @@ async fn test_connect_to_cloudflare() {
 async fn test_get_time() {
     let config = NtsClientConfig::new("time.cloudflare.com").with_timeout(Duration::from_secs(10));
 
-    let mut client = NtsClient::new(config);
+    let client = NtsClient::new(config);
 
     if client.connect().await.is_ok() {
         match client.get_time().await {